@@ -1,4 +1,4 @@
-use dcap_rs::types::quote::Quote;
+use tdx::quote::QuoteData;
 use tdx::Tdx;
 
 #[tokio::main]
@@ -9,9 +9,9 @@ async fn main() {
     // Retrieve an attestation report with default options passed to the hardware device
     // ================================================================================
     let (report_raw, _) = tdx.get_attestation_report_raw().unwrap();
-    let report = Quote::read(&mut report_raw.as_slice()).unwrap();
+    let report = QuoteData::from_bytes(&report_raw).unwrap();
 
-    println!("Attestation Report: {:?}", report);
+    println!("Attestation Report: {}", report.to_json().unwrap());
 
     // Verify the attestation report
     // ================================================================================
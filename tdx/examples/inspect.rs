@@ -2,18 +2,28 @@ use dcap_rs::types::quote::Quote;
 use std::path::PathBuf;
 
 use clap::Parser;
+use tdx::pck::get_pck_sgx_extensions;
+use tdx::quote::QuoteData;
 use tdx::utils::get_pck_fmspc_and_issuer;
 
 #[derive(Parser)]
 struct Opt {
     #[clap(long)]
     report: PathBuf,
+
+    /// Dump every field of the quote as JSON
+    #[clap(long)]
+    json: bool,
 }
 
 fn main() -> anyhow::Result<()> {
     let opt = Opt::parse();
     let report_path = opt.report;
     let report = std::fs::read(&report_path)?;
+    if opt.json {
+        println!("{}", QuoteData::from_bytes(&report)?.to_json()?);
+        return Ok(());
+    }
     let report = Quote::read(&mut report.as_slice())?;
     let report_version = u32::from(report.header.version);
 
@@ -26,5 +36,24 @@ fn main() -> anyhow::Result<()> {
     }
     println!("Report Version: V{}", report_version);
 
+    let extensions = get_pck_sgx_extensions(&report)?;
+    println!("PPID: {}", hex::encode(extensions.ppid));
+    println!("PCE-ID: {}", hex::encode(extensions.pce_id));
+    println!("SGX Type: {:?}", extensions.sgx_type);
+    println!("CPUSVN: {}", hex::encode(extensions.tcb.cpusvn));
+    println!("PCESVN: {}", extensions.tcb.pcesvn);
+    println!("SGX TCB Comp SVN: {:?}", extensions.tcb.sgx_tcb_comp_svn);
+    if let Some(platform_instance_id) = extensions.platform_instance_id {
+        println!(
+            "Platform Instance ID: {}",
+            hex::encode(platform_instance_id)
+        );
+    }
+    if let Some(configuration) = extensions.configuration {
+        println!("Dynamic Platform: {:?}", configuration.dynamic_platform);
+        println!("Cached Keys: {:?}", configuration.cached_keys);
+        println!("SMT Enabled: {:?}", configuration.smt_enabled);
+    }
+
     Ok(())
 }
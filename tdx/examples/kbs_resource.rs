@@ -0,0 +1,26 @@
+use std::sync::Arc;
+use tdx::kbs::KbsClient;
+
+// Usage: kbs_resource <KBS URL> <repository/type/tag> [recorded quote]
+//
+// With a recorded quote, the quote is sent as is instead of being generated on this TD, which
+// is only useful against a stub KBS that does not check the report data.
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        eprintln!("Usage: {} <KBS URL> <repository/type/tag> [quote]", args[0]);
+        std::process::exit(1);
+    }
+
+    let mut client = KbsClient::new(&args[1]);
+    if let Some(path) = args.get(3) {
+        let quote = std::fs::read(path).unwrap();
+        client = client
+            .with_quote_fn(Arc::new(move |_| Ok(quote.clone())))
+            .with_ccel_path(None);
+    }
+
+    let resource = client.get_resource(&args[2]).unwrap();
+    println!("Token: {}", client.token().unwrap_or_default());
+    println!("Resource: {}", hex::encode(resource));
+}
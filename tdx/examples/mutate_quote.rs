@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use clap::Parser;
+use p256::ecdsa::SigningKey;
+use tdx::quote::{QuoteBody, QuoteData};
+
+#[derive(Parser)]
+struct Opt {
+    /// Path to a raw SGX / TDX DCAP quote to use as a base
+    #[clap(long)]
+    report: PathBuf,
+
+    /// Directory where the mutated quotes are written
+    #[clap(long)]
+    out_dir: PathBuf,
+
+    /// Another quote whose QE report is swapped into the base quote
+    #[clap(long)]
+    qe_report_from: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let opt = Opt::parse();
+    let raw_quote = std::fs::read(&opt.report)?;
+    let quote = QuoteData::from_bytes(&raw_quote)?;
+    if quote.to_bytes()? != raw_quote {
+        bail!("Quote does not re-encode to the same bytes");
+    }
+    std::fs::create_dir_all(&opt.out_dir)?;
+    let write = |name: &str, quote: &QuoteData| -> anyhow::Result<()> {
+        let path = opt.out_dir.join(name);
+        std::fs::write(&path, quote.to_bytes()?)?;
+        println!("Wrote {}", path.display());
+        Ok(())
+    };
+
+    // Flip the first byte of MRTD (TDX) or MRENCLAVE (SGX): the body signature no longer matches
+    let mut flipped = quote.clone();
+    match &mut flipped.body {
+        QuoteBody::Sgx(report) => report.mr_enclave[0] ^= 0xff,
+        QuoteBody::Td10(report) => report.mr_td[0] ^= 0xff,
+        QuoteBody::Td15(report) => report.td10.mr_td[0] ^= 0xff,
+    }
+    write("flipped_measurement.bin", &flipped)?;
+
+    // Re-sign the flipped body with a test attestation key: the QE report binding no longer matches
+    let attestation_key = SigningKey::from_slice(&[0x11; 32])?;
+    let mut resigned = flipped.clone();
+    resigned.sign(&attestation_key)?;
+    write("resigned_body.bin", &resigned)?;
+
+    // Also re-sign the QE report with a test PCK key: the PCK certificate no longer matches
+    let pck_key = SigningKey::from_slice(&[0x22; 32])?;
+    let mut resigned_qe = resigned.clone();
+    resigned_qe.sign_qe_report(&pck_key);
+    write("resigned_qe_report.bin", &resigned_qe)?;
+
+    let mut truncated = quote.clone();
+    let len = truncated.signature.cert_data.len();
+    truncated.truncate_cert_data(len / 2);
+    write("truncated_cert_data.bin", &truncated)?;
+
+    if let Some(path) = &opt.qe_report_from {
+        let other = QuoteData::from_bytes(&std::fs::read(path)?)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        let mut swapped = quote.clone();
+        swapped.signature.qe_report = other.signature.qe_report;
+        swapped.signature.qe_report_signature = other.signature.qe_report_signature;
+        write("swapped_qe_report.bin", &swapped)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use alloy::primitives::Address;
+use alloy::signers::local::PrivateKeySigner;
+use clap::Parser;
+use tdx::onchain::OnchainOptions;
+use tdx::Tdx;
+
+#[derive(Parser)]
+struct Opt {
+    /// Path to a raw SGX / TDX DCAP quote
+    #[clap(long)]
+    report: PathBuf,
+
+    /// RPC endpoint, e.g. http://127.0.0.1:8545 for a local anvil node
+    #[clap(long)]
+    rpc_url: Option<String>,
+
+    /// Address of the DCAP Attestation contract
+    #[clap(long)]
+    dcap_attestation: Option<Address>,
+
+    /// Private key used to submit the verification transaction.
+    /// When omitted, the quote is verified with an `eth_call` only.
+    #[clap(long, env = "PRIVATE_KEY")]
+    private_key: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let opt = Opt::parse();
+    let raw_quote = std::fs::read(&opt.report)?;
+
+    let tdx = Tdx::new();
+    let options = OnchainOptions {
+        rpc_url: opt.rpc_url,
+        dcap_attestation: opt.dcap_attestation,
+        ..Default::default()
+    };
+
+    let verified_output = match opt.private_key {
+        Some(private_key) => {
+            let signer: PrivateKeySigner = private_key.parse()?;
+            let submission = tdx
+                .submit_attestation_report_onchain(&raw_quote, signer, &options)
+                .await?;
+            println!("Transaction: {}", submission.tx_hash);
+            submission.verified_output
+        }
+        None => {
+            tdx.verify_attestation_report_onchain(&raw_quote, &options)
+                .await?
+        }
+    };
+
+    println!("Quote Version: {}", verified_output.quote_version);
+    println!("TEE Type: {:#010x}", verified_output.tee_type);
+    println!("TCB Status: {:?}", verified_output.tcb_status);
+    println!("FMSPC: {}", verified_output.fmspc_hex());
+    println!("Advisory IDs: {:?}", verified_output.advisory_ids);
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+
+use alloy::signers::local::PrivateKeySigner;
+use clap::Parser;
+use tdx::pccs::{CollateralSource, PccsUploadOptions};
+use tdx::pcs::PcsClient;
+use tdx::Tdx;
+
+#[derive(Parser)]
+struct Opt {
+    /// Path to a raw SGX / TDX DCAP quote
+    #[clap(long)]
+    report: PathBuf,
+
+    /// Read the collaterals from this file instead of the Intel PCS
+    #[clap(long)]
+    collaterals: Option<PathBuf>,
+
+    /// PCS or PCCS base URL
+    #[clap(long, default_value = tdx::pcs::INTEL_PCS_URL)]
+    pcs_url: String,
+
+    /// RPC endpoint of the chain hosting the on-chain PCCS
+    #[clap(long)]
+    rpc_url: Option<String>,
+
+    /// Private key used to sign the upload transactions
+    #[clap(long, env = "PRIVATE_KEY")]
+    private_key: String,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let opt = Opt::parse();
+    let raw_quote = std::fs::read(&opt.report)?;
+    let signer: PrivateKeySigner = opt.private_key.parse()?;
+
+    let source = match opt.collaterals {
+        Some(path) => CollateralSource::File(path),
+        None => CollateralSource::Pcs(PcsClient::new(&opt.pcs_url)),
+    };
+    let options = PccsUploadOptions {
+        rpc_url: opt.rpc_url,
+        ..Default::default()
+    };
+
+    let tdx = Tdx::new();
+    let report = tdx
+        .upload_missing_collaterals(&raw_quote, &source, signer, &options)
+        .await?;
+
+    for item in &report.present {
+        println!("Present: {:?}", item);
+    }
+    for uploaded in &report.written {
+        println!("Written: {:?} in {}", uploaded.item, uploaded.tx_hash);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use tdx::Tdx;
+
+#[derive(Parser)]
+struct Opt {
+    /// Path to a raw SGX / TDX DCAP quote
+    #[clap(long)]
+    report: PathBuf,
+
+    /// Where to write the serialized guest input
+    #[clap(long)]
+    out: PathBuf,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let opt = Opt::parse();
+    let raw_quote = std::fs::read(&opt.report)?;
+
+    let tdx = Tdx::new();
+    let input = tdx.get_zk_guest_input(&raw_quote).await?;
+    std::fs::write(&opt.out, input.to_bytes()?)?;
+
+    println!("Current Time: {}", input.current_time);
+    println!("Input Hash: 0x{}", hex::encode(input.hash()?));
+
+    Ok(())
+}
@@ -0,0 +1,945 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+use base64_url::base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use serde_json::json;
+use tdx::collateral::RawCollaterals;
+use tdx::corim::{Corim, CorimEnvironment, CorimVerifier, ReferenceTriple};
+use tdx::device::DeviceOptions;
+use tdx::diff::{diff_quotes, DiffOptions};
+use tdx::pcs::PcsClient;
+use tdx::policy::{AppraisalPolicy, PolicyViolation};
+use tdx::quote::{QuoteBody, QuoteData};
+use tdx::rtmr::{
+    uefi_gpt_data, BootChain, BootFlow, BootVariable, GrubEvent, SecureBootConfig, ShimVariables,
+};
+use tdx::tdvf::TdvfImage;
+use tdx::utils::TeeType;
+use tdx::verified_output::VerifiedOutput;
+use tdx::Tdx;
+
+/// Generate, inspect and verify Intel TDX / SGX DCAP quotes.
+#[derive(Parser)]
+#[clap(name = "tdx", version)]
+struct Cli {
+    /// Print machine-readable JSON instead of text
+    #[clap(long, global = true)]
+    json: bool,
+
+    /// Base URL of the Intel PCS (or a PCCS), used for quotes carrying an encrypted PPID and
+    /// for `--source pcs`
+    #[clap(long, global = true)]
+    pcs_url: Option<String>,
+
+    /// Intel PCS API key
+    #[clap(long, global = true, env = "PCS_API_KEY")]
+    pcs_api_key: Option<String>,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a quote on this TD
+    Quote {
+        /// Report data as hex (up to 64 bytes, zero-padded)
+        #[clap(long, conflicts_with = "report_data_file")]
+        report_data: Option<String>,
+
+        /// Read the report data (up to 64 bytes, zero-padded) from a file, `-` for stdin
+        #[clap(long)]
+        report_data_file: Option<PathBuf>,
+
+        /// Output encoding
+        #[clap(long, value_enum, default_value = "raw")]
+        format: QuoteFormat,
+
+        /// Write the quote to a file instead of stdout
+        #[clap(long)]
+        out: Option<PathBuf>,
+    },
+    /// Print the fields of a quote
+    Inspect {
+        /// Path to a raw quote
+        #[clap(long)]
+        report: PathBuf,
+    },
+    /// Verify a quote and optionally appraise it against a policy and CoRIM reference values
+    Verify(VerifyArgs),
+    /// Compare two quotes field by field, exiting with status 1 when they differ
+    Diff {
+        /// Path to the first raw quote
+        left: PathBuf,
+
+        /// Path to the second raw quote
+        right: PathBuf,
+
+        /// Also compare report data, signatures, keys and certification data
+        #[clap(long)]
+        include_volatile: bool,
+    },
+    /// Compute the expected MRTD of a TD booted from a TDVF firmware image
+    Mrtd {
+        /// Path to the TDVF (OVMF) firmware image
+        #[clap(long)]
+        firmware: PathBuf,
+    },
+    /// Compute the expected RTMR0 to RTMR2 of a boot chain, printed as policy allowlists
+    Rtmr {
+        /// JSON boot chain configuration
+        #[clap(long)]
+        config: PathBuf,
+
+        /// TDVF firmware image, to take the CFV from and to also compute MRTD
+        #[clap(long)]
+        firmware: Option<PathBuf>,
+
+        /// Also print the replayed events
+        #[clap(long)]
+        events: bool,
+    },
+    /// Print the FMSPC and PCK CA of a quote, or of a quote generated on this TD
+    Fmspc {
+        /// Path to a raw quote
+        #[clap(long)]
+        report: Option<PathBuf>,
+    },
+    /// Retrieve the collaterals needed to verify a quote
+    Collateral {
+        #[clap(subcommand)]
+        command: CollateralCommand,
+    },
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    /// Path to a raw quote
+    #[clap(long)]
+    report: PathBuf,
+
+    /// Where the collaterals are taken from
+    #[clap(long, value_enum, default_value = "onchain")]
+    source: CollateralSourceArg,
+
+    /// Collaterals file saved with `tdx collateral save`, required for `--source file`
+    #[clap(long, required_if_eq("source", "file"))]
+    collaterals: Option<PathBuf>,
+
+    /// JSON appraisal policy
+    #[clap(long)]
+    policy: Option<PathBuf>,
+
+    /// CBOR CoRIM whose reference triples the TD must match
+    #[clap(long, requires_all = ["corim_vendor", "corim_model"])]
+    corim: Option<PathBuf>,
+
+    /// Vendor of the CoRIM environment class the TD belongs to
+    #[clap(long, requires = "corim")]
+    corim_vendor: Option<String>,
+
+    /// Model of the CoRIM environment class the TD belongs to
+    #[clap(long, requires = "corim")]
+    corim_model: Option<String>,
+
+    /// PEM public key the CoRIM signature is verified against, can be repeated
+    #[clap(long, requires = "corim")]
+    corim_key: Vec<PathBuf>,
+
+    /// Accept a CoRIM that is not signed
+    #[clap(long, requires = "corim")]
+    allow_unsigned_corim: bool,
+
+    /// Verification time as a UNIX timestamp in seconds, defaults to now
+    #[clap(long)]
+    time: Option<u64>,
+}
+
+#[derive(Subcommand)]
+enum CollateralCommand {
+    /// Print the collaterals as JSON
+    Fetch {
+        /// Path to a raw quote
+        #[clap(long)]
+        report: PathBuf,
+
+        #[clap(long, value_enum, default_value = "onchain")]
+        source: FetchSource,
+    },
+    /// Save the collaterals to a JSON file, for offline verification
+    Save {
+        /// Path to a raw quote
+        #[clap(long)]
+        report: PathBuf,
+
+        #[clap(long, value_enum, default_value = "onchain")]
+        source: FetchSource,
+
+        #[clap(long)]
+        out: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum QuoteFormat {
+    Raw,
+    Hex,
+    Base64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CollateralSourceArg {
+    /// Automata on-chain PCCS
+    Onchain,
+    /// Intel PCS, or the PCCS set with `--pcs-url`
+    Pcs,
+    /// A collaterals file
+    File,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FetchSource {
+    Onchain,
+    Pcs,
+}
+
+/// Boot chain description for `tdx rtmr`, with the inputs given as file paths.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BootChainConfig {
+    td_hob: PathBuf,
+    /// Defaults to the CFV of the `--firmware` image
+    cfv: Option<PathBuf>,
+    #[serde(default)]
+    secure_boot: SecureBootConfig,
+    /// ACPI blobs in measurement order
+    #[serde(default)]
+    acpi_tables: Vec<NamedFile>,
+    /// Boot variables in measurement order
+    #[serde(default)]
+    boot_variables: Vec<NamedFile>,
+    kernel: PathBuf,
+    initrd: Option<PathBuf>,
+    #[serde(default)]
+    cmdline: String,
+    /// Disk image (or its first sectors) whose GPT is measured when booting from a disk
+    disk: Option<PathBuf>,
+    /// Set both `shim` and `grub` for the shim and grub flow, neither for direct boot
+    shim: Option<PathBuf>,
+    grub: Option<PathBuf>,
+    /// Files with the contents of the variables measured by shim
+    #[serde(default)]
+    shim_variables: ShimVariablesConfig,
+    /// Files read, commands run, kernel and initrd loaded by grub, in measurement order
+    #[serde(default)]
+    grub_events: Vec<GrubEventConfig>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShimVariablesConfig {
+    sbat_level: Option<PathBuf>,
+    mok_list: Option<PathBuf>,
+    mok_list_x: Option<PathBuf>,
+    mok_list_trusted: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum GrubEventConfig {
+    /// A file read by grub: its path as seen by grub and the local copy
+    File {
+        path: String,
+        source: PathBuf,
+    },
+    Command(String),
+    Kernel(String),
+    Initrd(String),
+}
+
+#[derive(Deserialize)]
+struct NamedFile {
+    name: String,
+    path: PathBuf,
+}
+
+impl BootChainConfig {
+    fn into_boot_chain(self, cfv: Vec<u8>) -> anyhow::Result<BootChain> {
+        let read = |path: &Path| {
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+        };
+        let read_or_empty = |path: &Option<PathBuf>| {
+            path.as_deref()
+                .map(read)
+                .transpose()
+                .map(Option::unwrap_or_default)
+        };
+        let flow = match (&self.shim, &self.grub) {
+            (Some(shim), Some(grub)) => BootFlow::ShimGrub {
+                shim: read(shim)?,
+                shim_variables: ShimVariables {
+                    sbat_level: read_or_empty(&self.shim_variables.sbat_level)?,
+                    mok_list: read_or_empty(&self.shim_variables.mok_list)?,
+                    mok_list_x: read_or_empty(&self.shim_variables.mok_list_x)?,
+                    mok_list_trusted: self
+                        .shim_variables
+                        .mok_list_trusted
+                        .as_deref()
+                        .map(read)
+                        .transpose()?,
+                },
+                grub: read(grub)?,
+                grub_events: self
+                    .grub_events
+                    .into_iter()
+                    .map(|event| {
+                        Ok(match event {
+                            GrubEventConfig::File { path, source } => GrubEvent::File {
+                                path,
+                                data: read(&source)?,
+                            },
+                            GrubEventConfig::Command(command) => GrubEvent::Command(command),
+                            GrubEventConfig::Kernel(path) => GrubEvent::Kernel { path },
+                            GrubEventConfig::Initrd(path) => GrubEvent::Initrd { path },
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+            },
+            (None, None) => BootFlow::Direct,
+            _ => bail!("`shim` and `grub` must be set together"),
+        };
+        Ok(BootChain {
+            td_hob: read(&self.td_hob)?,
+            cfv,
+            secure_boot: self.secure_boot,
+            acpi_tables: self
+                .acpi_tables
+                .iter()
+                .map(|t| Ok((t.name.clone(), read(&t.path)?)))
+                .collect::<anyhow::Result<_>>()?,
+            boot_variables: self
+                .boot_variables
+                .iter()
+                .map(|v| {
+                    Ok(BootVariable {
+                        name: v.name.clone(),
+                        data: read(&v.path)?,
+                    })
+                })
+                .collect::<anyhow::Result<_>>()?,
+            kernel: read(&self.kernel)?,
+            initrd: self.initrd.as_deref().map(read).transpose()?,
+            cmdline: self.cmdline,
+            gpt: self.disk.as_deref().map(read_gpt).transpose()?,
+            flow,
+        })
+    }
+}
+
+/// Read the GPT of a disk image. Only the first MiB is read, which holds the partition
+/// table of disks partitioned with the usual layout.
+fn read_gpt(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut disk = Vec::new();
+    std::fs::File::open(path)
+        .and_then(|file| file.take(1 << 20).read_to_end(&mut disk))
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(uefi_gpt_data(&disk)?)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let pcs_client = pcs_client(&cli);
+    let tdx = match &pcs_client {
+        Some(client) => Tdx::new().with_pcs_client(client.clone()),
+        None => Tdx::new(),
+    };
+
+    match cli.command {
+        Command::Quote {
+            report_data,
+            report_data_file,
+            format,
+            out,
+        } => {
+            let report_data = match (report_data, report_data_file) {
+                (Some(hex), _) => Some(pad_report_data(
+                    &hex::decode(hex.trim_start_matches("0x")).context("Invalid report data")?,
+                )?),
+                (None, Some(path)) => Some(pad_report_data(&read_input(&path)?)?),
+                (None, None) => None,
+            };
+            let (raw_quote, _) =
+                tdx.get_attestation_report_raw_with_options(DeviceOptions { report_data })?;
+            let encoded = match format {
+                QuoteFormat::Raw => raw_quote,
+                QuoteFormat::Hex => hex::encode(&raw_quote).into_bytes(),
+                QuoteFormat::Base64 => BASE64.encode(&raw_quote).into_bytes(),
+            };
+            match out {
+                Some(path) => std::fs::write(path, encoded)?,
+                None => std::io::stdout().write_all(&encoded)?,
+            }
+        }
+        Command::Inspect { report } => {
+            let quote = QuoteData::from_bytes(&std::fs::read(report)?)?;
+            if cli.json {
+                println!("{}", quote.to_json()?);
+            } else {
+                print_quote(&quote);
+            }
+        }
+        Command::Verify(args) => {
+            let verification = verify(&tdx, pcs_client, args).await;
+            if cli.json {
+                let result = match &verification {
+                    Ok(verification) => json!({
+                        "verified": verification.violations.is_empty(),
+                        "verified_output": verification.output,
+                        "policy_violations": verification.violations,
+                        "corim_match": verification.corim_match,
+                    }),
+                    Err(e) => json!({
+                        "verified": false,
+                        "error": format!("{e:#}"),
+                    }),
+                };
+                println!("{}", serde_json::to_string_pretty(&result)?);
+                if !result["verified"].as_bool().unwrap_or_default() {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+
+            let Verification {
+                output,
+                violations,
+                corim_match,
+            } = verification?;
+            println!("Quote Version: {}", output.quote_version);
+            println!("TEE Type: {:#010x}", output.tee_type);
+            println!("TCB Status: {}", output.tcb_status);
+            println!("FMSPC: {}", output.fmspc_hex());
+            println!("Advisory IDs: {:?}", output.advisory_ids);
+            if let Some(triple) = &corim_match {
+                println!(
+                    "CoRIM Match: CoMID {} triple {} ({})",
+                    triple.comid_id,
+                    triple.index,
+                    triple.environment.model.as_deref().unwrap_or("no model")
+                );
+            }
+            for violation in &violations {
+                println!(
+                    "Policy violation: {}: {}",
+                    violation.field, violation.reason
+                );
+            }
+            if !violations.is_empty() {
+                std::process::exit(1);
+            }
+            println!("Verification successful!");
+        }
+        Command::Diff {
+            left,
+            right,
+            include_volatile,
+        } => {
+            let left = QuoteData::from_bytes(&std::fs::read(left)?)?;
+            let right = QuoteData::from_bytes(&std::fs::read(right)?)?;
+            let diff = diff_quotes(&left, &right, &DiffOptions { include_volatile });
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            } else if diff.is_empty() {
+                println!("Quotes are identical");
+            } else {
+                print!("{diff}");
+            }
+            if !diff.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::Mrtd { firmware } => {
+            let image = std::fs::read(firmware)?;
+            let tdvf = TdvfImage::parse(&image)?;
+            let mrtd = hex::encode(tdvf.compute_mrtd());
+            if cli.json {
+                let result = json!({
+                    "mrtd": mrtd,
+                    "sections": tdvf.sections(),
+                });
+                println!("{}", serde_json::to_string_pretty(&result)?);
+            } else {
+                for section in tdvf.sections() {
+                    println!(
+                        "Section: {:?} at {:#x} ({:#x} bytes, attributes {:#x})",
+                        section.section_type,
+                        section.memory_address,
+                        section.memory_data_size,
+                        section.attributes
+                    );
+                }
+                println!("MRTD: {mrtd}");
+            }
+        }
+        Command::Rtmr {
+            config,
+            firmware,
+            events,
+        } => {
+            let config: BootChainConfig = serde_json::from_slice(&std::fs::read(&config)?)
+                .with_context(|| format!("Failed to parse {}", config.display()))?;
+            let image = firmware.map(std::fs::read).transpose()?;
+            let tdvf = image.as_deref().map(TdvfImage::parse).transpose()?;
+            let cfv = match (&config.cfv, &tdvf) {
+                (Some(path), _) => std::fs::read(path)?,
+                (None, Some(tdvf)) => tdvf
+                    .cfv()
+                    .context("Firmware image has no CFV section")?
+                    .to_vec(),
+                (None, None) => bail!("Either `cfv` in the config or --firmware is required"),
+            };
+            let calc = config.into_boot_chain(cfv)?.compute()?;
+
+            let mut values = serde_json::Map::new();
+            if let Some(tdvf) = &tdvf {
+                values.insert("mr_td".into(), json!([hex::encode(tdvf.compute_mrtd())]));
+            }
+            for i in 0..3 {
+                values.insert(format!("rtmr{i}"), json!([hex::encode(calc.rtmr(i))]));
+            }
+            if cli.json {
+                let mut result = json!(values);
+                if events {
+                    result["events"] = json!(calc.events());
+                }
+                println!("{}", serde_json::to_string_pretty(&result)?);
+            } else {
+                if events {
+                    for event in calc.events() {
+                        println!(
+                            "RTMR{} {:#010x} {}: {}",
+                            event.rtmr,
+                            event.event_type,
+                            event.description,
+                            hex::encode(event.digest)
+                        );
+                    }
+                }
+                for (name, value) in &values {
+                    println!(
+                        "{}: {}",
+                        name.to_uppercase(),
+                        value[0].as_str().unwrap_or_default()
+                    );
+                }
+            }
+        }
+        Command::Fmspc { report } => {
+            let raw_quote = match report {
+                Some(path) => std::fs::read(path)?,
+                None => tdx.get_attestation_report_raw()?.0,
+            };
+            let quote = QuoteData::from_bytes(&raw_quote)?;
+            let (fmspc, pck_ca) = tdx.get_pck_fmspc_and_issuer(&raw_quote)?;
+            let platform = match TeeType::from_header(quote.header.tee_type)? {
+                TeeType::Sgx => "SGX",
+                TeeType::Tdx => "TDX",
+            };
+            if cli.json {
+                let result = json!({
+                    "fmspc": fmspc.to_uppercase(),
+                    "pck_ca": format!("{pck_ca:?}"),
+                    "platform": platform,
+                    "version": quote.header.version,
+                });
+                println!("{}", serde_json::to_string_pretty(&result)?);
+            } else {
+                println!("FMSPC: {}", fmspc.to_uppercase());
+                println!("PCK CA: {pck_ca:?}");
+                println!("Platform: {platform}");
+                println!("Version: {}", quote.header.version);
+            }
+        }
+        Command::Collateral { command } => {
+            let (report, source) = match &command {
+                CollateralCommand::Fetch { report, source }
+                | CollateralCommand::Save { report, source, .. } => (report, *source),
+            };
+            let raw_quote = std::fs::read(report)?;
+            let collaterals = match source {
+                FetchSource::Onchain => tdx.get_raw_collaterals(&raw_quote).await?,
+                FetchSource::Pcs => fetch_from_pcs(&tdx, &raw_quote, pcs_client)?,
+            };
+            match command {
+                CollateralCommand::Fetch { .. } => {
+                    println!("{}", serde_json::to_string_pretty(&collaterals)?);
+                }
+                CollateralCommand::Save { out, .. } => {
+                    collaterals.save(&out)?;
+                    if !cli.json {
+                        println!("Collaterals saved to {}", out.display());
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Outcome of `tdx verify` for a quote that passed DCAP verification.
+struct Verification {
+    output: VerifiedOutput,
+    violations: Vec<PolicyViolation>,
+    corim_match: Option<ReferenceTriple>,
+}
+
+async fn verify(
+    tdx: &Tdx,
+    pcs_client: Option<PcsClient>,
+    args: VerifyArgs,
+) -> anyhow::Result<Verification> {
+    let raw_quote = std::fs::read(args.report)?;
+    let collaterals = match args.source {
+        CollateralSourceArg::Onchain => tdx.get_raw_collaterals(&raw_quote).await?,
+        CollateralSourceArg::Pcs => fetch_from_pcs(tdx, &raw_quote, pcs_client)?,
+        CollateralSourceArg::File => {
+            RawCollaterals::load(args.collaterals.expect("required by clap"))?
+        }
+    };
+    let time = match args.time {
+        Some(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+        None => SystemTime::now(),
+    };
+    let output = tdx.verify_attestation_report_with_collaterals(&raw_quote, &collaterals, time)?;
+    let mut violations = match args.policy {
+        Some(path) => AppraisalPolicy::load(path)?.appraise(&output)?,
+        None => Vec::new(),
+    };
+    let corim_match = match args.corim {
+        Some(path) => {
+            let mut verifier = CorimVerifier::new().allow_unsigned(args.allow_unsigned_corim);
+            for key in args.corim_key {
+                verifier = verifier.with_pem_key_file(key)?;
+            }
+            let corim = Corim::load(path, &verifier)?;
+            let environment = CorimEnvironment::new(
+                args.corim_vendor.expect("required by clap"),
+                args.corim_model.expect("required by clap"),
+            );
+            match corim.appraise(&raw_quote, &output, &environment) {
+                Ok(triple) => Some(triple.clone()),
+                Err(e) => {
+                    violations.push(PolicyViolation {
+                        field: "corim".to_string(),
+                        reason: e.to_string(),
+                    });
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    Ok(Verification {
+        output,
+        violations,
+        corim_match,
+    })
+}
+
+fn pcs_client(cli: &Cli) -> Option<PcsClient> {
+    if cli.pcs_url.is_none() && cli.pcs_api_key.is_none() {
+        return None;
+    }
+    let client = match &cli.pcs_url {
+        Some(url) => PcsClient::new(url),
+        None => PcsClient::default(),
+    };
+    Some(match &cli.pcs_api_key {
+        Some(api_key) => client.with_api_key(api_key),
+        None => client,
+    })
+}
+
+fn fetch_from_pcs(
+    tdx: &Tdx,
+    raw_quote: &[u8],
+    pcs_client: Option<PcsClient>,
+) -> anyhow::Result<RawCollaterals> {
+    let quote = QuoteData::from_bytes(raw_quote)?;
+    let tee_type = TeeType::from_header(quote.header.tee_type)?;
+    let (fmspc, pck_ca) = tdx.get_pck_fmspc_and_issuer(raw_quote)?;
+    let client = pcs_client.unwrap_or_default();
+    Ok(client.get_collaterals(tee_type, &fmspc, pck_ca)?)
+}
+
+fn read_input(path: &Path) -> anyhow::Result<Vec<u8>> {
+    if path == Path::new("-") {
+        let mut data = Vec::new();
+        std::io::stdin().read_to_end(&mut data)?;
+        return Ok(data);
+    }
+    std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+fn pad_report_data(data: &[u8]) -> anyhow::Result<[u8; 64]> {
+    if data.len() > 64 {
+        bail!("Report data is {} bytes (at most 64)", data.len());
+    }
+    let mut report_data = [0u8; 64];
+    report_data[..data.len()].copy_from_slice(data);
+    Ok(report_data)
+}
+
+fn print_quote(quote: &QuoteData) {
+    let header = &quote.header;
+    println!("Version: {}", header.version);
+    println!("TEE Type: {:#010x}", header.tee_type);
+    println!("QE SVN: {}", header.qe_svn);
+    println!("PCE SVN: {}", header.pce_svn);
+    match &quote.body {
+        QuoteBody::Sgx(report) => {
+            println!("MRENCLAVE: {}", hex::encode(report.mr_enclave));
+            println!("MRSIGNER: {}", hex::encode(report.mr_signer));
+            println!("ISV Prod ID: {}", report.isv_prod_id);
+            println!("ISV SVN: {}", report.isv_svn);
+            println!("Attributes: {}", hex::encode(report.attributes));
+        }
+        body => {
+            let report = body.td_report().expect("TDX body");
+            println!("TEE TCB SVN: {}", hex::encode(report.tee_tcb_svn));
+            println!("MRSEAM: {}", hex::encode(report.mr_seam));
+            println!("MRTD: {}", hex::encode(report.mr_td));
+            println!("MRCONFIGID: {}", hex::encode(report.mr_config_id));
+            println!("MROWNER: {}", hex::encode(report.mr_owner));
+            println!("MROWNERCONFIG: {}", hex::encode(report.mr_owner_config));
+            for (i, rtmr) in report.rtmrs().iter().enumerate() {
+                println!("RTMR{i}: {}", hex::encode(rtmr));
+            }
+            println!("TD Attributes: {}", hex::encode(report.td_attributes));
+            println!("XFAM: {}", hex::encode(report.xfam));
+        }
+    }
+    println!("Report Data: {}", hex::encode(quote.report_data()));
+    println!(
+        "Certification Data Type: {}",
+        quote.signature.cert_data_type
+    );
+    if let Ok(certificates) = quote.certificates() {
+        for certificate in certificates {
+            println!("Certificate: {}", certificate.subject);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("tdx").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn defines_a_valid_command() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parses_quote_arguments() {
+        let cli = parse(&["quote", "--report-data", "0xdeadbeef", "--format", "base64"]).unwrap();
+        assert!(!cli.json);
+        match cli.command {
+            Command::Quote {
+                report_data,
+                report_data_file,
+                format,
+                out,
+            } => {
+                assert_eq!(report_data.as_deref(), Some("0xdeadbeef"));
+                assert!(report_data_file.is_none());
+                assert!(matches!(format, QuoteFormat::Base64));
+                assert!(out.is_none());
+            }
+            _ => panic!("expected the quote command"),
+        }
+        let cli = parse(&["quote"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Quote {
+                format: QuoteFormat::Raw,
+                ..
+            }
+        ));
+        assert!(parse(&["quote", "--report-data", "00", "--report-data-file", "-"]).is_err());
+        assert!(parse(&["quote", "--format", "der"]).is_err());
+    }
+
+    #[test]
+    fn parses_global_arguments_after_the_subcommand() {
+        let cli = parse(&[
+            "fmspc",
+            "--json",
+            "--pcs-url",
+            "https://pccs.example",
+            "--report",
+            "quote.bin",
+        ])
+        .unwrap();
+        assert!(cli.json);
+        assert_eq!(cli.pcs_url.as_deref(), Some("https://pccs.example"));
+        match cli.command {
+            Command::Fmspc { report } => assert_eq!(report, Some(PathBuf::from("quote.bin"))),
+            _ => panic!("expected the fmspc command"),
+        }
+    }
+
+    #[test]
+    fn parses_verify_arguments() {
+        let cli = parse(&["verify", "--report", "quote.bin"]).unwrap();
+        match cli.command {
+            Command::Verify(args) => {
+                assert!(args.source == CollateralSourceArg::Onchain);
+                assert!(args.collaterals.is_none());
+                assert!(args.time.is_none());
+            }
+            _ => panic!("expected the verify command"),
+        }
+
+        let cli = parse(&[
+            "verify",
+            "--report",
+            "quote.bin",
+            "--source",
+            "file",
+            "--collaterals",
+            "collaterals.json",
+            "--corim",
+            "reference.corim",
+            "--corim-vendor",
+            "Example",
+            "--corim-model",
+            "confidential-td",
+            "--corim-key",
+            "a.pem",
+            "--corim-key",
+            "b.pem",
+            "--time",
+            "1735689600",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Verify(args) => {
+                assert!(args.source == CollateralSourceArg::File);
+                assert_eq!(args.collaterals, Some(PathBuf::from("collaterals.json")));
+                assert_eq!(args.corim_vendor.as_deref(), Some("Example"));
+                assert_eq!(args.corim_model.as_deref(), Some("confidential-td"));
+                assert_eq!(args.corim_key.len(), 2);
+                assert!(!args.allow_unsigned_corim);
+                assert_eq!(args.time, Some(1_735_689_600));
+            }
+            _ => panic!("expected the verify command"),
+        }
+    }
+
+    #[test]
+    fn rejects_incomplete_verify_arguments() {
+        for args in [
+            &["verify"][..],
+            // --source file needs a collaterals file
+            &["verify", "--report", "quote.bin", "--source", "file"],
+            // a CoRIM needs the environment class
+            &[
+                "verify",
+                "--report",
+                "quote.bin",
+                "--corim",
+                "reference.corim",
+            ],
+            &[
+                "verify",
+                "--report",
+                "quote.bin",
+                "--corim",
+                "reference.corim",
+                "--corim-vendor",
+                "Example",
+            ],
+            // CoRIM options need a CoRIM
+            &["verify", "--report", "quote.bin", "--allow-unsigned-corim"],
+            &["verify", "--report", "quote.bin", "--corim-key", "a.pem"],
+            &["verify", "--report", "quote.bin", "--time", "-1"],
+        ] {
+            assert!(parse(args).is_err(), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn parses_diff_and_collateral_arguments() {
+        let cli = parse(&["diff", "a.bin", "b.bin", "--include-volatile"]).unwrap();
+        match cli.command {
+            Command::Diff {
+                left,
+                right,
+                include_volatile,
+            } => {
+                assert_eq!(left, PathBuf::from("a.bin"));
+                assert_eq!(right, PathBuf::from("b.bin"));
+                assert!(include_volatile);
+            }
+            _ => panic!("expected the diff command"),
+        }
+        assert!(parse(&["diff", "a.bin"]).is_err());
+
+        let cli = parse(&[
+            "collateral",
+            "save",
+            "--report",
+            "quote.bin",
+            "--source",
+            "pcs",
+            "--out",
+            "collaterals.json",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Collateral {
+                command: CollateralCommand::Save { source, out, .. },
+            } => {
+                assert!(source == FetchSource::Pcs);
+                assert_eq!(out, PathBuf::from("collaterals.json"));
+            }
+            _ => panic!("expected the collateral save command"),
+        }
+        assert!(parse(&["collateral", "save", "--report", "quote.bin"]).is_err());
+        // collaterals can only be fetched from the PCS or on-chain
+        assert!(parse(&[
+            "collateral",
+            "fetch",
+            "--report",
+            "q.bin",
+            "--source",
+            "file"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn pads_report_data() {
+        let report_data = pad_report_data(&[0xde, 0xad, 0xbe, 0xef]).unwrap();
+        assert_eq!(report_data[..4], [0xde, 0xad, 0xbe, 0xef]);
+        assert!(report_data[4..].iter().all(|&b| b == 0));
+
+        assert_eq!(pad_report_data(&[]).unwrap(), [0u8; 64]);
+        assert_eq!(pad_report_data(&[0xff; 64]).unwrap(), [0xff; 64]);
+        let err = pad_report_data(&[0; 65]).unwrap_err();
+        assert!(err.to_string().contains("65 bytes"), "{err}");
+    }
+}
@@ -0,0 +1,421 @@
+use crate::error::{Result, TdxError};
+use crate::hpke;
+#[cfg(feature = "coco-provider")]
+use crate::hpke::HpkeKeyPair;
+use crate::nonce::{
+    AttestationRequest, MemoryReplayStore, NonceIssuer, NonceResponse, ReplayStore,
+};
+use crate::policy::AppraisalPolicy;
+use crate::utils::serde_hex;
+use crate::verified_output::VerifiedOutput;
+use crate::verifier::{CollateralProvider, QuoteVerifier};
+use crate::Tdx;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// HPKE info string of released secrets. The secret name is used as associated data.
+pub const BROKER_HPKE_INFO: &[u8] = b"tdx-key-broker/v1";
+const STORE_AAD: &[u8] = b"tdx-key-broker-store/v1";
+const STORE_NONCE_LEN: usize = 12;
+
+/// A secret and the name of the policy a TD must satisfy to receive it.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretEntry {
+    pub policy: String,
+    #[serde(with = "serde_hex")]
+    pub value: Vec<u8>,
+}
+
+impl std::fmt::Debug for SecretEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretEntry")
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Secrets served by a [`KeyBroker`], by name.
+///
+/// Stored as JSON, `{"secrets": {"<name>": {"policy": "<policy>", "value": "<hex>"}}}`, either
+/// in clear or encrypted with ChaCha20Poly1305 under a 32-byte store key.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretStore {
+    pub secrets: HashMap<String, SecretEntry>,
+}
+
+impl SecretStore {
+    /// Load a store from a plain JSON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        Self::from_json(&data)
+    }
+
+    /// Load a store written by [`SecretStore::save_encrypted`].
+    pub fn load_encrypted(path: impl AsRef<Path>, key: &[u8; 32]) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        if data.len() < STORE_NONCE_LEN {
+            return Err(TdxError::Broker("Encrypted store is truncated".to_string()));
+        }
+        let (nonce, ciphertext) = data.split_at(STORE_NONCE_LEN);
+        let plaintext = ChaCha20Poly1305::new(key.into())
+            .decrypt(
+                nonce.into(),
+                Payload {
+                    msg: ciphertext,
+                    aad: STORE_AAD,
+                },
+            )
+            .map_err(|_| TdxError::Broker("Failed to decrypt the secret store".to_string()))?;
+        Self::from_json(&plaintext)
+    }
+
+    /// Encrypt the store under `key` and write it to `path`, as `nonce || ciphertext`.
+    pub fn save_encrypted(&self, path: impl AsRef<Path>, key: &[u8; 32]) -> Result<()> {
+        let plaintext = serde_json::to_vec(self)
+            .map_err(|e| TdxError::Broker(format!("Failed to encode the secret store: {e}")))?;
+        let mut nonce = [0u8; STORE_NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = ChaCha20Poly1305::new(key.into())
+            .encrypt(
+                &nonce.into(),
+                Payload {
+                    msg: &plaintext,
+                    aad: STORE_AAD,
+                },
+            )
+            .map_err(|_| TdxError::Broker("Failed to encrypt the secret store".to_string()))?;
+        std::fs::write(path, [&nonce[..], &ciphertext].concat())?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SecretEntry> {
+        self.secrets.get(name)
+    }
+
+    fn from_json(data: &[u8]) -> Result<Self> {
+        serde_json::from_slice(data)
+            .map_err(|e| TdxError::Broker(format!("Invalid secret store: {e}")))
+    }
+}
+
+/// Body of `POST /secret`: evidence answering a broker nonce, whose runtime data is the
+/// X25519 public key the secret is encrypted to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretRequest {
+    pub secret: String,
+    pub attestation: AttestationRequest,
+}
+
+/// Response of `POST /secret`: the secret sealed with HPKE to the attested key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecretResponse {
+    /// HPKE encapsulated key
+    #[serde(with = "serde_hex")]
+    pub enc: Vec<u8>,
+    #[serde(with = "serde_hex")]
+    pub ciphertext: Vec<u8>,
+}
+
+/// Releases secrets to TDs whose evidence satisfies the named policy of the secret.
+///
+/// The TD fetches a nonce (`GET /nonce`), then sends evidence whose report data binds the
+/// nonce and an ephemeral X25519 public key (`POST /secret`). The secret is returned sealed
+/// to that key with HPKE, so it is only readable inside the attested TD.
+pub struct KeyBroker {
+    issuer: NonceIssuer,
+    replay_store: Box<dyn ReplayStore>,
+    tdx: Tdx,
+    collaterals: CollateralProvider,
+    policies: HashMap<String, AppraisalPolicy>,
+    secrets: SecretStore,
+}
+
+impl KeyBroker {
+    /// Create a broker with a random nonce key and an in-memory replay store.
+    pub fn new(
+        secrets: SecretStore,
+        policies: HashMap<String, AppraisalPolicy>,
+        collaterals: CollateralProvider,
+    ) -> Self {
+        KeyBroker {
+            issuer: NonceIssuer::random(),
+            replay_store: Box::new(MemoryReplayStore::new()),
+            tdx: Tdx::new(),
+            collaterals,
+            policies,
+            secrets,
+        }
+    }
+
+    pub fn with_nonce_issuer(mut self, issuer: NonceIssuer) -> Self {
+        self.issuer = issuer;
+        self
+    }
+
+    pub fn with_replay_store(mut self, replay_store: Box<dyn ReplayStore>) -> Self {
+        self.replay_store = replay_store;
+        self
+    }
+
+    /// Use `tdx` to verify quotes, e.g. to resolve PCK certificates through a PCS client.
+    pub fn with_tdx(mut self, tdx: Tdx) -> Self {
+        self.tdx = tdx;
+        self
+    }
+
+    /// Issue a nonce, answering `GET /nonce`.
+    pub fn challenge(&self) -> NonceResponse {
+        let nonce = self.issuer.issue();
+        NonceResponse {
+            nonce,
+            expires_at: nonce.issued_at() + self.issuer.ttl().as_secs(),
+        }
+    }
+
+    /// Verify the request against the policy of the requested secret and return the secret
+    /// sealed to the attested key, along with the verified output for auditing.
+    pub fn release(&self, request: &SecretRequest) -> Result<(SecretResponse, VerifiedOutput)> {
+        let entry = self
+            .secrets
+            .get(&request.secret)
+            .ok_or_else(|| TdxError::Broker(format!("Unknown secret {}", request.secret)))?;
+        let policy = self.policies.get(&entry.policy).ok_or_else(|| {
+            TdxError::Broker(format!(
+                "Policy {} of secret {} is not defined",
+                entry.policy, request.secret
+            ))
+        })?;
+        let attestation = &request.attestation;
+        if attestation.runtime_data.len() != 32 {
+            return Err(TdxError::Broker(
+                "Runtime data must be a 32-byte X25519 public key".to_string(),
+            ));
+        }
+
+        // Verify before redeeming, so that a request failing verification cannot burn the
+        // nonce of the TD it was issued to
+        self.issuer
+            .validate_at(&attestation.nonce, SystemTime::now())?;
+        // Collaterals sent by the TD are only used, once validated, with the embedded provider
+        let evidence = &attestation.evidence;
+        let embedded = match self.collaterals {
+            CollateralProvider::Embedded(_) => evidence.collaterals.as_ref(),
+            _ => None,
+        };
+        let output = QuoteVerifier::new(self.collaterals.clone())
+            .with_tdx(self.tdx.clone())
+            .with_policy(policy.clone())
+            .verify(
+                &evidence.quote,
+                embedded,
+                &attestation.nonce.report_data(&attestation.runtime_data),
+            )?;
+        self.issuer
+            .redeem(&attestation.nonce, self.replay_store.as_ref())?;
+
+        let (enc, ciphertext) = hpke::seal(
+            &attestation.runtime_data,
+            BROKER_HPKE_INFO,
+            request.secret.as_bytes(),
+            &entry.value,
+        )?;
+        Ok((
+            SecretResponse {
+                enc: enc.to_vec(),
+                ciphertext,
+            },
+            output,
+        ))
+    }
+}
+
+/// Attester side of the key broker protocol, run in the TD.
+#[cfg(feature = "coco-provider")]
+#[derive(Debug, Clone)]
+pub struct BrokerClient {
+    base_url: String,
+    tdx: Tdx,
+    collaterals: Option<CollateralProvider>,
+}
+
+#[cfg(feature = "coco-provider")]
+impl BrokerClient {
+    /// Create a client for the broker at `base_url`, serving `GET /nonce` and `POST /secret`.
+    pub fn new(base_url: &str) -> Self {
+        BrokerClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            tdx: Tdx::new(),
+            collaterals: None,
+        }
+    }
+
+    /// Send the collaterals of the quote along with it.
+    pub fn with_collaterals(mut self, collaterals: CollateralProvider) -> Self {
+        self.collaterals = Some(collaterals);
+        self
+    }
+
+    /// Attest to the broker and retrieve the secret `name`.
+    pub fn fetch_secret(&self, name: &str) -> Result<Vec<u8>> {
+        let nonce: NonceResponse = ureq::get(&format!("{}/nonce", self.base_url))
+            .call()?
+            .into_json()?;
+        let keypair = HpkeKeyPair::generate();
+        let attestation = AttestationRequest::generate(
+            &self.tdx,
+            nonce.nonce,
+            keypair.public_key().to_vec(),
+            self.collaterals.as_ref(),
+        )?;
+        let request = SecretRequest {
+            secret: name.to_string(),
+            attestation,
+        };
+        let response: SecretResponse = ureq::post(&format!("{}/secret", self.base_url))
+            .send_json(&request)?
+            .into_json()?;
+        keypair.open(
+            &response.enc,
+            BROKER_HPKE_INFO,
+            name.as_bytes(),
+            &response.ciphertext,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collateral::RawCollaterals;
+    use crate::hpke::HpkeKeyPair;
+    use crate::test_pki;
+    use crate::verifier::{EmbeddedCollaterals, Evidence};
+    use std::sync::{Arc, Mutex};
+
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+    const COLLATERALS: &[u8] = include_bytes!("../tests/data/collaterals.json");
+
+    /// Replay store shared with the test, to observe redeemed nonces.
+    #[derive(Clone, Default)]
+    struct SharedStore(Arc<MemoryReplayStore>);
+
+    impl ReplayStore for SharedStore {
+        fn insert(&self, id: &[u8], expires_at: u64) -> Result<bool> {
+            self.0.insert(id, expires_at)
+        }
+    }
+
+    fn secrets() -> SecretStore {
+        let entry = SecretEntry {
+            policy: "default".to_string(),
+            value: b"disk key".to_vec(),
+        };
+        SecretStore {
+            secrets: HashMap::from([("disk-key".to_string(), entry)]),
+        }
+    }
+
+    fn request(broker: &KeyBroker) -> SecretRequest {
+        let collaterals: RawCollaterals = serde_json::from_slice(COLLATERALS).unwrap();
+        SecretRequest {
+            secret: "disk-key".to_string(),
+            attestation: AttestationRequest {
+                nonce: broker.challenge().nonce,
+                runtime_data: vec![9; 32],
+                evidence: Evidence {
+                    quote: TDX_V4_QUOTE.to_vec(),
+                    collaterals: Some(collaterals),
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn ignores_embedded_collaterals_and_keeps_nonce_on_failure() {
+        let issuer = NonceIssuer::new([7; 32]);
+        let store = SharedStore::default();
+        let calls = Arc::new(Mutex::new(0));
+        let provider = {
+            let calls = calls.clone();
+            CollateralProvider::Custom(Arc::new(move |_: &[u8]| {
+                *calls.lock().unwrap() += 1;
+                Err(TdxError::Dcap("no collaterals".to_string()))
+            }))
+        };
+        let policies = HashMap::from([("default".to_string(), AppraisalPolicy::default())]);
+        let broker = KeyBroker::new(secrets(), policies, provider)
+            .with_nonce_issuer(issuer.clone())
+            .with_replay_store(Box::new(store.clone()));
+
+        let request = request(&broker);
+        let err = broker.release(&request).unwrap_err();
+        assert!(err.to_string().contains("no collaterals"), "{err}");
+        assert_eq!(*calls.lock().unwrap(), 1);
+        assert!(issuer.redeem(&request.attestation.nonce, &store).is_ok());
+    }
+
+    #[test]
+    fn rejects_requests_before_verification() {
+        let policies = HashMap::from([("default".to_string(), AppraisalPolicy::default())]);
+        let broker = KeyBroker::new(secrets(), policies, CollateralProvider::embedded());
+
+        let mut unknown = request(&broker);
+        unknown.secret = "other".to_string();
+        assert!(broker.release(&unknown).is_err());
+
+        let mut short_key = request(&broker);
+        short_key.attestation.runtime_data.pop();
+        assert!(broker.release(&short_key).is_err());
+
+        // The test collaterals do not chain to the Intel root
+        let err = broker.release(&request(&broker)).unwrap_err();
+        assert!(err.to_string().contains("RootCaPinned"), "{err}");
+    }
+
+    #[test]
+    fn releases_secret_sealed_to_the_attested_key() {
+        let policies = HashMap::from([("default".to_string(), AppraisalPolicy::default())]);
+        let embedded = EmbeddedCollaterals::new().with_validator(test_pki::validator());
+        // Verified now: the test collaterals are valid until 2034
+        let broker = KeyBroker::new(secrets(), policies, CollateralProvider::Embedded(embedded));
+
+        let keypair = HpkeKeyPair::generate();
+        let mut request = request(&broker);
+        let attestation = &mut request.attestation;
+        attestation.runtime_data = keypair.public_key().to_vec();
+        attestation.evidence = Evidence {
+            quote: test_pki::quote_with_report_data(
+                attestation.nonce.report_data(&attestation.runtime_data),
+            ),
+            collaterals: Some(test_pki::collaterals()),
+        };
+
+        let (response, output) = broker.release(&request).unwrap();
+        assert_eq!(output.fmspc_hex(), "00806F050000");
+        let secret = keypair
+            .open(
+                &response.enc,
+                BROKER_HPKE_INFO,
+                b"disk-key",
+                &response.ciphertext,
+            )
+            .unwrap();
+        assert_eq!(secret, b"disk key");
+
+        // The nonce is redeemed
+        let err = broker.release(&request).unwrap_err();
+        assert!(err.to_string().contains("already"), "{err}");
+
+        // Evidence bound to another key
+        let mut other_key = request.clone();
+        other_key.attestation.nonce = broker.challenge().nonce;
+        other_key.attestation.runtime_data = HpkeKeyPair::generate().public_key().to_vec();
+        let err = broker.release(&other_key).unwrap_err();
+        assert!(err.to_string().contains("Report data"), "{err}");
+    }
+}
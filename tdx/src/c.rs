@@ -0,0 +1,1027 @@
+//! C API, built with the `clib` feature. The header is generated into `c/tdx.h`.
+//!
+//! Contexts (`TdxCtx`) hold per-handle options and results (`TdxReport`, `TdxVerifiedOutput`)
+//! own their bytes, so the library can be used from several threads at once. Async calls run
+//! on a runtime managed by the library. Functions that fail set a
+//! thread-local error message, retrieved with `tdx_last_error()`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr::{copy_nonoverlapping, null, null_mut};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::collateral::RawCollaterals;
+use crate::device::DeviceOptions;
+use crate::error::{Result, TdxError};
+use crate::pcs::PcsClient;
+use crate::utils::{runtime, PckCA};
+use crate::verified_output::VerifiedOutput;
+use crate::verifier::CollateralProvider;
+use crate::Tdx;
+
+/// Error codes returned by C FFI functions.
+pub const TDX_OK: i32 = 0;
+pub const TDX_ERR_NULL_POINTER: i32 = -1;
+pub const TDX_ERR_BUFFER_TOO_SMALL: i32 = -2;
+pub const TDX_ERR_NO_REPORT: i32 = -3;
+pub const TDX_ERR_ATTESTATION_FAILED: i32 = -4;
+pub const TDX_ERR_LOCK_POISONED: i32 = -5;
+pub const TDX_ERR_INVALID_ARGUMENT: i32 = -6;
+pub const TDX_ERR_PANIC: i32 = -7;
+
+/// PCK CA values returned by `tdx_get_fmspc()`.
+pub const TDX_PCK_CA_PLATFORM: i32 = 0;
+pub const TDX_PCK_CA_PROCESSOR: i32 = 1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl Into<String>) {
+    let message = message.into().replace('\0', " ");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+/// Run `f`, turning a panic into an error instead of unwinding into C.
+fn guard<T>(on_error: T, f: impl FnOnce() -> T) -> T {
+    clear_last_error();
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => value,
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            set_last_error(format!("panic: {message}"));
+            on_error
+        }
+    }
+}
+
+/// Copy `bytes` into `buf`, returning the number of bytes written or a negative error code.
+fn copy_out(bytes: &[u8], buf: *mut u8, buf_len: usize) -> i32 {
+    if buf.is_null() {
+        set_last_error("buf is null");
+        return TDX_ERR_NULL_POINTER;
+    }
+    let Ok(len) = i32::try_from(bytes.len()) else {
+        set_last_error("length exceeds i32::MAX");
+        return TDX_ERR_INVALID_ARGUMENT;
+    };
+    if buf_len < bytes.len() {
+        set_last_error(format!("buffer too small ({buf_len} < {})", bytes.len()));
+        return TDX_ERR_BUFFER_TOO_SMALL;
+    }
+    unsafe {
+        copy_nonoverlapping(bytes.as_ptr(), buf, bytes.len());
+    }
+    len
+}
+
+/// Return the message of the last error on the calling thread, or NULL if the last call
+/// succeeded.
+///
+/// The string is owned by the library and remains valid until the next call into the library
+/// on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn tdx_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(null(), |s| s.as_ptr()))
+}
+
+/// Options and state of a caller, created with `tdx_ctx_new()`.
+///
+/// A context can be shared between threads.
+pub struct TdxCtx {
+    options: Mutex<CtxOptions>,
+}
+
+#[derive(Clone, Default)]
+struct CtxOptions {
+    report_data: Option<[u8; 64]>,
+    pcs_client: Option<PcsClient>,
+}
+
+impl CtxOptions {
+    fn tdx(&self) -> Tdx {
+        match &self.pcs_client {
+            Some(client) => Tdx::new().with_pcs_client(client.clone()),
+            None => Tdx::new(),
+        }
+    }
+
+    /// Collaterals from the PCS if configured, from the Automata on-chain PCCS otherwise.
+    fn collaterals(&self, raw_quote: &[u8]) -> Result<RawCollaterals> {
+        let tdx = self.tdx();
+        match &self.pcs_client {
+            Some(client) => CollateralProvider::Pcs(client.clone()).collaterals(&tdx, raw_quote),
+            None => runtime()?.block_on(tdx.get_raw_collaterals(raw_quote)),
+        }
+    }
+}
+
+/// Read the options of `ctx`, or set the last error and return an error code.
+unsafe fn ctx_options(ctx: *const TdxCtx) -> std::result::Result<CtxOptions, i32> {
+    let Some(ctx) = (unsafe { ctx.as_ref() }) else {
+        set_last_error("ctx is null");
+        return Err(TDX_ERR_NULL_POINTER);
+    };
+    ctx.options
+        .lock()
+        .map(|options| options.clone())
+        .map_err(|e| {
+            set_last_error(format!("context lock poisoned: {e}"));
+            TDX_ERR_LOCK_POISONED
+        })
+}
+
+/// Update the options of `ctx`, returning `TDX_OK` or an error code.
+unsafe fn update_ctx_options(ctx: *const TdxCtx, f: impl FnOnce(&mut CtxOptions)) -> i32 {
+    let Some(ctx) = (unsafe { ctx.as_ref() }) else {
+        set_last_error("ctx is null");
+        return TDX_ERR_NULL_POINTER;
+    };
+    match ctx.options.lock() {
+        Ok(mut options) => {
+            f(&mut options);
+            TDX_OK
+        }
+        Err(e) => {
+            set_last_error(format!("context lock poisoned: {e}"));
+            TDX_ERR_LOCK_POISONED
+        }
+    }
+}
+
+/// An attestation report and its var data, owned by the caller and released with
+/// `tdx_report_free()`.
+pub struct TdxReport {
+    report: Vec<u8>,
+    var_data: Vec<u8>,
+}
+
+/// Create a context with default options. Release it with `tdx_ctx_free()`.
+#[unsafe(no_mangle)]
+pub extern "C" fn tdx_ctx_new() -> *mut TdxCtx {
+    guard(null_mut(), || {
+        Box::into_raw(Box::new(TdxCtx {
+            options: Mutex::new(CtxOptions::default()),
+        }))
+    })
+}
+
+/// Release a context. `ctx` may be NULL.
+///
+/// # Safety
+/// `ctx` must have been returned by `tdx_ctx_new()` and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_ctx_free(ctx: *mut TdxCtx) {
+    if !ctx.is_null() {
+        drop(unsafe { Box::from_raw(ctx) });
+    }
+}
+
+/// Set the report data of the reports generated with `ctx`, up to 64 bytes, zero-padded.
+/// Passing NULL restores the default of random report data.
+///
+/// Returns `TDX_OK`, or a negative error code.
+///
+/// # Safety
+/// `ctx` must be a valid context and `report_data` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_ctx_set_report_data(
+    ctx: *mut TdxCtx,
+    report_data: *const u8,
+    len: usize,
+) -> i32 {
+    guard(TDX_ERR_PANIC, || {
+        let value = if report_data.is_null() {
+            None
+        } else {
+            if len > 64 {
+                set_last_error(format!("report data exceeds 64 bytes ({len})"));
+                return TDX_ERR_INVALID_ARGUMENT;
+            }
+            let mut data = [0u8; 64];
+            unsafe {
+                copy_nonoverlapping(report_data, data.as_mut_ptr(), len);
+            }
+            Some(data)
+        };
+        unsafe { update_ctx_options(ctx, |options| options.report_data = value) }
+    })
+}
+
+/// Generate an attestation report with the options of `ctx`.
+///
+/// Returns a report to release with `tdx_report_free()`, or NULL on failure.
+///
+/// # Safety
+/// `ctx` must be a valid context.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_ctx_generate_report(ctx: *const TdxCtx) -> *mut TdxReport {
+    guard(null_mut(), || {
+        let Ok(options) = (unsafe { ctx_options(ctx) }) else {
+            return null_mut();
+        };
+        let tdx = options.tdx();
+        let result = match options.report_data {
+            Some(report_data) => tdx.get_attestation_report_raw_with_options(DeviceOptions {
+                report_data: Some(report_data),
+            }),
+            None => tdx.get_attestation_report_raw(),
+        };
+        match result {
+            Ok((report, var_data)) => Box::into_raw(Box::new(TdxReport {
+                report,
+                var_data: var_data.unwrap_or_default(),
+            })),
+            Err(e) => {
+                set_last_error(format!("failed to get attestation report: {e}"));
+                null_mut()
+            }
+        }
+    })
+}
+
+/// Length of the report in bytes, or 0 if `report` is NULL.
+///
+/// # Safety
+/// `report` must be a valid report or NULL.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_report_len(report: *const TdxReport) -> usize {
+    unsafe { report.as_ref() }.map_or(0, |report| report.report.len())
+}
+
+/// Copy the report into `buf`, of at least `tdx_report_len()` bytes.
+///
+/// Returns the number of bytes written, or a negative error code.
+///
+/// # Safety
+/// `report` must be a valid report and `buf` must point to `buf_len` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_report_copy(
+    report: *const TdxReport,
+    buf: *mut u8,
+    buf_len: usize,
+) -> i32 {
+    guard(TDX_ERR_PANIC, || match unsafe { report.as_ref() } {
+        Some(report) => copy_out(&report.report, buf, buf_len),
+        None => {
+            set_last_error("report is null");
+            TDX_ERR_NULL_POINTER
+        }
+    })
+}
+
+/// Length of the var data in bytes, 0 if there is none. Var data is only returned on Azure
+/// confidential VMs.
+///
+/// # Safety
+/// `report` must be a valid report or NULL.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_report_var_data_len(report: *const TdxReport) -> usize {
+    unsafe { report.as_ref() }.map_or(0, |report| report.var_data.len())
+}
+
+/// Copy the var data into `buf`, of at least `tdx_report_var_data_len()` bytes.
+///
+/// Returns the number of bytes written, or a negative error code.
+///
+/// # Safety
+/// `report` must be a valid report and `buf` must point to `buf_len` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_report_copy_var_data(
+    report: *const TdxReport,
+    buf: *mut u8,
+    buf_len: usize,
+) -> i32 {
+    guard(TDX_ERR_PANIC, || match unsafe { report.as_ref() } {
+        Some(report) => copy_out(&report.var_data, buf, buf_len),
+        None => {
+            set_last_error("report is null");
+            TDX_ERR_NULL_POINTER
+        }
+    })
+}
+
+/// Release a report. `report` may be NULL.
+///
+/// # Safety
+/// `report` must have been returned by the library and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_report_free(report: *mut TdxReport) {
+    if !report.is_null() {
+        drop(unsafe { Box::from_raw(report) });
+    }
+}
+
+/// Borrow `len` bytes at `data`, or set the last error and return an error code.
+unsafe fn bytes_arg<'a>(
+    data: *const u8,
+    len: usize,
+    name: &str,
+) -> std::result::Result<&'a [u8], i32> {
+    if data.is_null() {
+        set_last_error(format!("{name} is null"));
+        return Err(TDX_ERR_NULL_POINTER);
+    }
+    Ok(unsafe { std::slice::from_raw_parts(data, len) })
+}
+
+/// Borrow a NUL-terminated UTF-8 string, `None` if `s` is NULL.
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> std::result::Result<Option<&'a str>, i32> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map(Some)
+        .map_err(|e| {
+            set_last_error(format!("{name} is not valid UTF-8: {e}"));
+            TDX_ERR_INVALID_ARGUMENT
+        })
+}
+
+/// Return `s` as a string owned by the caller, released with `tdx_string_free()`.
+fn string_out(s: String) -> *mut c_char {
+    match CString::new(s) {
+        Ok(s) => s.into_raw(),
+        Err(e) => {
+            set_last_error(format!("string contains a NUL byte: {e}"));
+            null_mut()
+        }
+    }
+}
+
+/// Release a string returned by the library. `s` may be NULL.
+///
+/// # Safety
+/// `s` must have been returned by the library and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Retrieve collaterals from the Intel PCS, or the PCCS at `base_url`, instead of the
+/// Automata on-chain PCCS. The PCS is also used to resolve PCK certificates of quotes that
+/// carry an encrypted PPID. `base_url` and `api_key` may be NULL.
+///
+/// Returns `TDX_OK`, or a negative error code.
+///
+/// # Safety
+/// `ctx` must be a valid context, and `base_url` and `api_key` NULL or NUL-terminated.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_ctx_set_pcs(
+    ctx: *mut TdxCtx,
+    base_url: *const c_char,
+    api_key: *const c_char,
+) -> i32 {
+    guard(TDX_ERR_PANIC, || {
+        let (base_url, api_key) =
+            match unsafe { (str_arg(base_url, "base_url"), str_arg(api_key, "api_key")) } {
+                (Ok(base_url), Ok(api_key)) => (base_url, api_key),
+                (Err(code), _) | (_, Err(code)) => return code,
+            };
+        let mut client = base_url.map(PcsClient::new).unwrap_or_default();
+        if let Some(api_key) = api_key {
+            client = client.with_api_key(api_key);
+        }
+        unsafe { update_ctx_options(ctx, |options| options.pcs_client = Some(client)) }
+    })
+}
+
+/// The result of a successful quote verification, released with
+/// `tdx_verified_output_free()`.
+pub struct TdxVerifiedOutput {
+    output: VerifiedOutput,
+}
+
+/// Fixed-size fields of a verified output.
+#[repr(C)]
+pub struct TdxVerifiedInfo {
+    pub quote_version: u16,
+    /// 0x00 for SGX, 0x81 for TDX
+    pub tee_type: u32,
+    /// 0 UpToDate, 1 SWHardeningNeeded, 2 ConfigurationAndSWHardeningNeeded,
+    /// 3 ConfigurationNeeded, 4 OutOfDate, 5 OutOfDateConfigurationNeeded, 6 Revoked,
+    /// 7 Unrecognized
+    pub tcb_status: u8,
+    pub fmspc: [u8; 6],
+    pub quote_body_len: usize,
+    pub advisory_id_count: usize,
+}
+
+fn verified_output_out(result: Result<VerifiedOutput>) -> *mut TdxVerifiedOutput {
+    match result {
+        Ok(output) => Box::into_raw(Box::new(TdxVerifiedOutput { output })),
+        Err(e) => {
+            set_last_error(format!("quote verification failed: {e}"));
+            null_mut()
+        }
+    }
+}
+
+/// Verify a quote now, with collaterals from the source configured on `ctx`.
+///
+/// Returns the verified output, or NULL if the quote does not verify.
+///
+/// # Safety
+/// `ctx` must be a valid context and `quote` must point to `quote_len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_verify_quote(
+    ctx: *const TdxCtx,
+    quote: *const u8,
+    quote_len: usize,
+) -> *mut TdxVerifiedOutput {
+    guard(null_mut(), || {
+        let (Ok(options), Ok(quote)) = (unsafe { ctx_options(ctx) }, unsafe {
+            bytes_arg(quote, quote_len, "quote")
+        }) else {
+            return null_mut();
+        };
+        verified_output_out(options.collaterals(quote).and_then(|collaterals| {
+            options.tdx().verify_attestation_report_with_collaterals(
+                quote,
+                &collaterals,
+                SystemTime::now(),
+            )
+        }))
+    })
+}
+
+/// Verify a quote offline against `collaterals`, JSON as saved by `tdx collateral save`, at
+/// `time` in seconds since the UNIX epoch, or now if 0.
+///
+/// Returns the verified output, or NULL if the quote does not verify.
+///
+/// # Safety
+/// `ctx` must be a valid context, `quote` must point to `quote_len` readable bytes and
+/// `collaterals` must be NUL-terminated.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_verify_quote_with_collaterals(
+    ctx: *const TdxCtx,
+    quote: *const u8,
+    quote_len: usize,
+    collaterals: *const c_char,
+    time: u64,
+) -> *mut TdxVerifiedOutput {
+    guard(null_mut(), || {
+        let (Ok(options), Ok(quote), Ok(collaterals)) = (
+            unsafe { ctx_options(ctx) },
+            unsafe { bytes_arg(quote, quote_len, "quote") },
+            unsafe { str_arg(collaterals, "collaterals") },
+        ) else {
+            return null_mut();
+        };
+        let Some(collaterals) = collaterals else {
+            set_last_error("collaterals is null");
+            return null_mut();
+        };
+        let collaterals: RawCollaterals = match serde_json::from_str(collaterals) {
+            Ok(collaterals) => collaterals,
+            Err(e) => {
+                set_last_error(format!("invalid collaterals: {e}"));
+                return null_mut();
+            }
+        };
+        let time = match time {
+            0 => SystemTime::now(),
+            secs => match UNIX_EPOCH.checked_add(Duration::from_secs(secs)) {
+                Some(time) => time,
+                None => {
+                    set_last_error(format!("invalid verification time {secs}"));
+                    return null_mut();
+                }
+            },
+        };
+        verified_output_out(options.tdx().verify_attestation_report_with_collaterals(
+            quote,
+            &collaterals,
+            time,
+        ))
+    })
+}
+
+/// Fill `info` with the fixed-size fields of `output`.
+///
+/// Returns `TDX_OK`, or a negative error code.
+///
+/// # Safety
+/// `output` must be a valid verified output and `info` must point to a writable
+/// `TdxVerifiedInfo`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_verified_output_info(
+    output: *const TdxVerifiedOutput,
+    info: *mut TdxVerifiedInfo,
+) -> i32 {
+    guard(TDX_ERR_PANIC, || {
+        let (Some(output), false) = (unsafe { output.as_ref() }, info.is_null()) else {
+            set_last_error("output or info is null");
+            return TDX_ERR_NULL_POINTER;
+        };
+        let output = &output.output;
+        unsafe {
+            info.write(TdxVerifiedInfo {
+                quote_version: output.quote_version,
+                tee_type: output.tee_type,
+                tcb_status: output.tcb_status as u8,
+                fmspc: output.fmspc,
+                quote_body_len: output.quote_body.len(),
+                advisory_id_count: output.advisory_ids.len(),
+            });
+        }
+        TDX_OK
+    })
+}
+
+/// Copy the report body of the verified quote into `buf`, of at least `quote_body_len` bytes.
+///
+/// Returns the number of bytes written, or a negative error code.
+///
+/// # Safety
+/// `output` must be a valid verified output and `buf` must point to `buf_len` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_verified_output_copy_quote_body(
+    output: *const TdxVerifiedOutput,
+    buf: *mut u8,
+    buf_len: usize,
+) -> i32 {
+    guard(TDX_ERR_PANIC, || match unsafe { output.as_ref() } {
+        Some(output) => copy_out(&output.output.quote_body, buf, buf_len),
+        None => {
+            set_last_error("output is null");
+            TDX_ERR_NULL_POINTER
+        }
+    })
+}
+
+/// Return `output` as JSON, including the advisory IDs, to release with `tdx_string_free()`.
+///
+/// # Safety
+/// `output` must be a valid verified output.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_verified_output_to_json(
+    output: *const TdxVerifiedOutput,
+) -> *mut c_char {
+    guard(null_mut(), || {
+        let Some(output) = (unsafe { output.as_ref() }) else {
+            set_last_error("output is null");
+            return null_mut();
+        };
+        match serde_json::to_string(&output.output) {
+            Ok(json) => string_out(json),
+            Err(e) => {
+                set_last_error(format!("failed to encode the verified output: {e}"));
+                null_mut()
+            }
+        }
+    })
+}
+
+/// Release a verified output. `output` may be NULL.
+///
+/// # Safety
+/// `output` must have been returned by the library and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_verified_output_free(output: *mut TdxVerifiedOutput) {
+    if !output.is_null() {
+        drop(unsafe { Box::from_raw(output) });
+    }
+}
+
+/// Extract the FMSPC and the PCK CA of a quote.
+///
+/// Writes the FMSPC as 12 uppercase hex characters and a NUL terminator into `fmspc`, and
+/// `TDX_PCK_CA_PLATFORM` or `TDX_PCK_CA_PROCESSOR` into `pck_ca` unless it is NULL.
+///
+/// Returns `TDX_OK`, or a negative error code.
+///
+/// # Safety
+/// `ctx` must be a valid context, `quote` must point to `quote_len` readable bytes, `fmspc`
+/// to `fmspc_len` writable bytes and `pck_ca` must be NULL or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_get_fmspc(
+    ctx: *const TdxCtx,
+    quote: *const u8,
+    quote_len: usize,
+    fmspc: *mut c_char,
+    fmspc_len: usize,
+    pck_ca: *mut i32,
+) -> i32 {
+    guard(TDX_ERR_PANIC, || {
+        let options = match unsafe { ctx_options(ctx) } {
+            Ok(options) => options,
+            Err(code) => return code,
+        };
+        let quote = match unsafe { bytes_arg(quote, quote_len, "quote") } {
+            Ok(quote) => quote,
+            Err(code) => return code,
+        };
+        let (value, ca) = match options.tdx().get_pck_fmspc_and_issuer(quote) {
+            Ok(result) => result,
+            Err(e) => {
+                set_last_error(format!("failed to extract the FMSPC: {e}"));
+                return TDX_ERR_INVALID_ARGUMENT;
+            }
+        };
+        let value = CString::new(value.to_uppercase()).expect("hex has no NUL byte");
+        let written = copy_out(value.as_bytes_with_nul(), fmspc.cast(), fmspc_len);
+        if written < 0 {
+            return written;
+        }
+        if !pck_ca.is_null() {
+            let ca = match ca {
+                PckCA::Platform => TDX_PCK_CA_PLATFORM,
+                PckCA::Processor => TDX_PCK_CA_PROCESSOR,
+            };
+            unsafe { pck_ca.write(ca) };
+        }
+        TDX_OK
+    })
+}
+
+/// Retrieve the collaterals of a quote from the source configured on `ctx`.
+///
+/// Returns the collaterals as JSON, in the format of `tdx collateral save`, to release with
+/// `tdx_string_free()`, or NULL on failure.
+///
+/// # Safety
+/// `ctx` must be a valid context and `quote` must point to `quote_len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tdx_get_collaterals(
+    ctx: *const TdxCtx,
+    quote: *const u8,
+    quote_len: usize,
+) -> *mut c_char {
+    guard(null_mut(), || {
+        let (Ok(options), Ok(quote)) = (unsafe { ctx_options(ctx) }, unsafe {
+            bytes_arg(quote, quote_len, "quote")
+        }) else {
+            return null_mut();
+        };
+        let json = options.collaterals(quote).and_then(|collaterals| {
+            serde_json::to_string(&collaterals)
+                .map_err(|e| TdxError::Anyhow(format!("Failed to encode the collaterals: {e}")))
+        });
+        match json {
+            Ok(json) => string_out(json),
+            Err(e) => {
+                set_last_error(format!("failed to get collaterals: {e}"));
+                null_mut()
+            }
+        }
+    })
+}
+
+// Deprecated process-wide API, kept for existing callers. The report and var data are shared
+// by all threads, so concurrent callers see each other's output; use `TdxCtx` instead.
+
+static ATTESTATION_REPORT: LazyLock<Mutex<Vec<u8>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+static VAR_DATA: LazyLock<Mutex<Vec<u8>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+
+/// Helper to store report and var_data into the global statics.
+/// Returns the report length on success, or a negative error code on failure.
+fn store_report(result: Result<(Vec<u8>, Option<Vec<u8>>)>) -> i32 {
+    let (report_bytes, var_data) = match result {
+        Ok(r) => r,
+        Err(e) => {
+            set_last_error(format!("failed to get attestation report: {e}"));
+            return TDX_ERR_ATTESTATION_FAILED;
+        }
+    };
+    let report_len = match i32::try_from(report_bytes.len()) {
+        Ok(len) => len,
+        Err(_) => {
+            set_last_error("report length exceeds i32::MAX");
+            return TDX_ERR_ATTESTATION_FAILED;
+        }
+    };
+    match ATTESTATION_REPORT.lock() {
+        Ok(mut t) => *t = report_bytes,
+        Err(e) => {
+            set_last_error(format!("attestation report lock poisoned: {e}"));
+            return TDX_ERR_LOCK_POISONED;
+        }
+    }
+    // Always update var_data: clear it when None to avoid stale data from previous calls
+    match VAR_DATA.lock() {
+        Ok(mut t) => *t = var_data.unwrap_or_default(),
+        Err(e) => {
+            set_last_error(format!("var data lock poisoned: {e}"));
+            return TDX_ERR_LOCK_POISONED;
+        }
+    }
+    report_len
+}
+
+fn copy_global(global: &Mutex<Vec<u8>>, name: &str, buf: *mut u8, buf_len: usize) -> i32 {
+    if buf.is_null() {
+        set_last_error("buf is null");
+        return TDX_ERR_NULL_POINTER;
+    }
+    let bytes = match global.lock() {
+        Ok(t) => t,
+        Err(e) => {
+            set_last_error(format!("{name} lock poisoned: {e}"));
+            return TDX_ERR_LOCK_POISONED;
+        }
+    };
+    if bytes.is_empty() {
+        set_last_error(format!(
+            "no {name} found, call tdx_generate_attestation_report() first"
+        ));
+        return TDX_ERR_NO_REPORT;
+    }
+    copy_out(&bytes, buf, buf_len)
+}
+
+/// Generate the attestation report with default settings.
+///
+/// Returns the size of the report on success (>= 0), which you can use to malloc
+/// a buffer of suitable size before calling `tdx_get_attestation_report_raw()`.
+/// Returns a negative error code on failure.
+///
+/// Deprecated: not thread-safe, use `tdx_ctx_generate_report()`.
+#[unsafe(no_mangle)]
+pub extern "C" fn tdx_generate_attestation_report() -> i32 {
+    guard(TDX_ERR_PANIC, || {
+        store_report(Tdx::new().get_attestation_report_raw())
+    })
+}
+
+/// Generate the attestation report with custom report_data (64 bytes).
+///
+/// `report_data` must point to a buffer of at least 64 bytes.
+/// Returns the size of the report on success (>= 0), or a negative error code on failure.
+///
+/// Deprecated: not thread-safe, use `tdx_ctx_set_report_data()` and
+/// `tdx_ctx_generate_report()`.
+#[unsafe(no_mangle)]
+#[allow(clippy::not_unsafe_ptr_arg_deref)] // kept safe for existing callers
+pub extern "C" fn tdx_generate_attestation_report_with_options(report_data: *const u8) -> i32 {
+    guard(TDX_ERR_PANIC, || {
+        if report_data.is_null() {
+            set_last_error("report_data is null");
+            return TDX_ERR_NULL_POINTER;
+        }
+        let mut rust_report_data: [u8; 64] = [0; 64];
+        unsafe {
+            copy_nonoverlapping(report_data, rust_report_data.as_mut_ptr(), 64);
+        }
+        let device_options = DeviceOptions {
+            report_data: Some(rust_report_data),
+        };
+        store_report(Tdx::new().get_attestation_report_raw_with_options(device_options))
+    })
+}
+
+/// Copy the attestation report into the provided buffer.
+///
+/// `buf` must point to a buffer of at least `buf_len` bytes.
+/// Call `tdx_generate_attestation_report()` first to obtain the required size.
+///
+/// Returns the number of bytes written on success (>= 0), or a negative error code:
+/// - `TDX_ERR_NULL_POINTER` if `buf` is null
+/// - `TDX_ERR_BUFFER_TOO_SMALL` if `buf_len` is smaller than the report
+/// - `TDX_ERR_NO_REPORT` if no report has been generated yet
+/// - `TDX_ERR_LOCK_POISONED` if the internal mutex is poisoned
+///
+/// Deprecated: not thread-safe, use `tdx_report_copy()`.
+#[unsafe(no_mangle)]
+pub extern "C" fn tdx_get_attestation_report_raw(buf: *mut u8, buf_len: usize) -> i32 {
+    guard(TDX_ERR_PANIC, || {
+        copy_global(&ATTESTATION_REPORT, "attestation report", buf, buf_len)
+    })
+}
+
+/// Retrieve the length of var_data.
+///
+/// Call this only after `tdx_generate_attestation_report()`.
+/// Returns 0 if var_data is empty, or a negative error code on failure.
+///
+/// Deprecated: not thread-safe, use `tdx_report_var_data_len()`.
+#[unsafe(no_mangle)]
+pub extern "C" fn tdx_get_var_data_len() -> i32 {
+    guard(TDX_ERR_PANIC, || match VAR_DATA.lock() {
+        Ok(t) => t.len() as i32,
+        Err(e) => {
+            set_last_error(format!("var data lock poisoned: {e}"));
+            TDX_ERR_LOCK_POISONED
+        }
+    })
+}
+
+/// Copy var_data into the provided buffer.
+///
+/// `buf` must point to a buffer of at least `buf_len` bytes.
+/// Call `tdx_get_var_data_len()` first to obtain the required size.
+///
+/// Returns the number of bytes written on success (>= 0), or a negative error code.
+///
+/// Deprecated: not thread-safe, use `tdx_report_copy_var_data()`.
+#[unsafe(no_mangle)]
+pub extern "C" fn tdx_get_var_data(buf: *mut u8, buf_len: usize) -> i32 {
+    guard(TDX_ERR_PANIC, || {
+        copy_global(&VAR_DATA, "var data", buf, buf_len)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pki;
+
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+
+    fn last_error() -> Option<String> {
+        let message = tdx_last_error();
+        (!message.is_null()).then(|| {
+            unsafe { CStr::from_ptr(message) }
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+
+    fn collaterals() -> CString {
+        CString::new(test_pki::COLLATERALS).unwrap()
+    }
+
+    fn secs(time: SystemTime) -> u64 {
+        time.duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[test]
+    fn sets_report_data() {
+        let ctx = tdx_ctx_new();
+        let data = [7u8; 65];
+        unsafe {
+            assert_eq!(
+                tdx_ctx_set_report_data(ctx, data.as_ptr(), 65),
+                TDX_ERR_INVALID_ARGUMENT
+            );
+            assert!(last_error().unwrap().contains("exceeds 64 bytes"));
+            assert_eq!((*ctx).options.lock().unwrap().report_data, None);
+
+            assert_eq!(tdx_ctx_set_report_data(ctx, data.as_ptr(), 3), TDX_OK);
+            assert_eq!(last_error(), None);
+            let mut expected = [0; 64];
+            expected[..3].copy_from_slice(&data[..3]);
+            assert_eq!((*ctx).options.lock().unwrap().report_data, Some(expected));
+
+            assert_eq!(tdx_ctx_set_report_data(ctx, null(), 0), TDX_OK);
+            assert_eq!((*ctx).options.lock().unwrap().report_data, None);
+            assert_eq!(
+                tdx_ctx_set_report_data(null_mut(), data.as_ptr(), 3),
+                TDX_ERR_NULL_POINTER
+            );
+            tdx_ctx_free(ctx);
+        }
+    }
+
+    #[test]
+    fn copies_out_into_large_enough_buffers() {
+        let mut buf = [0u8; 4];
+        assert_eq!(
+            copy_out(b"12345", buf.as_mut_ptr(), buf.len()),
+            TDX_ERR_BUFFER_TOO_SMALL
+        );
+        assert!(last_error().unwrap().contains("buffer too small (4 < 5)"));
+        assert_eq!(buf, [0; 4]);
+        assert_eq!(copy_out(b"1234", buf.as_mut_ptr(), buf.len()), 4);
+        assert_eq!(&buf, b"1234");
+        assert_eq!(copy_out(b"1234", null_mut(), 4), TDX_ERR_NULL_POINTER);
+    }
+
+    #[test]
+    fn keeps_last_error_per_thread() {
+        let data = [0u8; 65];
+        unsafe {
+            assert_eq!(
+                tdx_ctx_set_report_data(null_mut(), data.as_ptr(), 65),
+                TDX_ERR_INVALID_ARGUMENT
+            );
+        }
+        assert!(last_error().is_some());
+        std::thread::spawn(|| assert_eq!(last_error(), None))
+            .join()
+            .unwrap();
+        assert!(last_error().is_some());
+
+        // Cleared by the next successful call
+        let ctx = tdx_ctx_new();
+        assert_eq!(last_error(), None);
+        unsafe { tdx_ctx_free(ctx) };
+    }
+
+    #[test]
+    fn gets_fmspc() {
+        let ctx = tdx_ctx_new();
+        let mut fmspc = [0 as c_char; 13];
+        let mut pck_ca = -1;
+        unsafe {
+            let code = tdx_get_fmspc(
+                ctx,
+                TDX_V4_QUOTE.as_ptr(),
+                TDX_V4_QUOTE.len(),
+                fmspc.as_mut_ptr(),
+                fmspc.len(),
+                &mut pck_ca,
+            );
+            assert_eq!(code, TDX_OK, "{:?}", last_error());
+            assert_eq!(CStr::from_ptr(fmspc.as_ptr()).to_str(), Ok("00806F050000"));
+            assert_eq!(pck_ca, TDX_PCK_CA_PLATFORM);
+
+            // No room for the NUL terminator
+            let code = tdx_get_fmspc(
+                ctx,
+                TDX_V4_QUOTE.as_ptr(),
+                TDX_V4_QUOTE.len(),
+                fmspc.as_mut_ptr(),
+                12,
+                null_mut(),
+            );
+            assert_eq!(code, TDX_ERR_BUFFER_TOO_SMALL);
+
+            let code = tdx_get_fmspc(
+                ctx,
+                TDX_V4_QUOTE.as_ptr(),
+                100,
+                fmspc.as_mut_ptr(),
+                fmspc.len(),
+                null_mut(),
+            );
+            assert_eq!(code, TDX_ERR_INVALID_ARGUMENT);
+            tdx_ctx_free(ctx);
+        }
+    }
+
+    #[test]
+    fn verifies_quote_with_collaterals() {
+        let ctx = tdx_ctx_new();
+        let quote = test_pki::QUOTE;
+        let time = secs(test_pki::now());
+        let collaterals = collaterals();
+        unsafe {
+            let output = tdx_verify_quote_with_collaterals(
+                ctx,
+                quote.as_ptr(),
+                quote.len(),
+                collaterals.as_ptr(),
+                time,
+            );
+            assert!(!output.is_null(), "{:?}", last_error());
+            let mut info = std::mem::MaybeUninit::<TdxVerifiedInfo>::uninit();
+            assert_eq!(tdx_verified_output_info(output, info.as_mut_ptr()), TDX_OK);
+            let info = info.assume_init();
+            assert_eq!(info.tee_type, 0x81);
+            assert_eq!(info.tcb_status, 0);
+            assert_eq!(info.fmspc, [0x00, 0x80, 0x6f, 0x05, 0x00, 0x00]);
+            let mut body = vec![0; info.quote_body_len];
+            assert_eq!(
+                tdx_verified_output_copy_quote_body(output, body.as_mut_ptr(), body.len()),
+                body.len() as i32
+            );
+            assert_eq!(body, quote[48..632]);
+            tdx_verified_output_free(output);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_verification_arguments() {
+        let ctx = tdx_ctx_new();
+        let quote = test_pki::QUOTE;
+        let time = secs(test_pki::now());
+        let collaterals = collaterals();
+        let verify = |ctx: *const TdxCtx, collaterals: *const c_char, time: u64| unsafe {
+            tdx_verify_quote_with_collaterals(ctx, quote.as_ptr(), quote.len(), collaterals, time)
+        };
+
+        let invalid = CString::new("{\"tcb_info\": 1}").unwrap();
+        assert!(verify(ctx, invalid.as_ptr(), time).is_null());
+        assert!(last_error().unwrap().contains("invalid collaterals"));
+
+        assert!(verify(null(), collaterals.as_ptr(), time).is_null());
+        assert_eq!(last_error().as_deref(), Some("ctx is null"));
+
+        assert!(verify(ctx, null(), time).is_null());
+        assert_eq!(last_error().as_deref(), Some("collaterals is null"));
+
+        assert!(verify(ctx, collaterals.as_ptr(), u64::MAX).is_null());
+        assert!(last_error().unwrap().contains("invalid verification time"));
+
+        // Collaterals expire in 2034
+        let expired = secs(test_pki::now() + Duration::from_secs(10 * 365 * 24 * 3600));
+        assert!(verify(ctx, collaterals.as_ptr(), expired).is_null());
+        assert!(last_error().unwrap().contains("quote verification failed"));
+        unsafe { tdx_ctx_free(ctx) };
+    }
+}
@@ -0,0 +1,343 @@
+use crate::error::{Result, TdxError};
+#[cfg(feature = "http")]
+use crate::pcs::PcsClient;
+#[cfg(feature = "http")]
+use std::borrow::Cow;
+
+const HEADER_LEN: usize = 48;
+const ENCLAVE_REPORT_LEN: usize = 384;
+const TD10_REPORT_LEN: usize = 584;
+const ECDSA_SIGNATURE_LEN: usize = 64;
+const ECDSA_KEY_LEN: usize = 64;
+const TEE_TYPE_TDX: u32 = 0x81;
+
+/// Certification data type of a PCK leaf certificate followed by the PCK CA and root CA.
+pub const CERT_DATA_TYPE_PCK_CERT_CHAIN: u16 = 5;
+/// Certification data type wrapping the QE report and the PCK certification data (quote v4+).
+pub const CERT_DATA_TYPE_QE_REPORT: u16 = 6;
+
+/// Platform identifiers carried instead of a PCK certificate chain (types 1 to 3).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PpidInfo {
+    /// Plaintext (type 1) or RSA-encrypted (types 2 and 3) PPID.
+    pub ppid: Vec<u8>,
+    pub cpusvn: [u8; 16],
+    pub pcesvn: u16,
+    pub pce_id: u16,
+}
+
+/// The certification data identifying the PCK key that certified the quoting enclave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CertificationData {
+    /// Type 1: plaintext PPID, CPUSVN, PCESVN and PCE-ID.
+    PpidCleartext(PpidInfo),
+    /// Type 2: RSA-2048-OAEP encrypted PPID, CPUSVN, PCESVN and PCE-ID.
+    PpidRsa2048Encrypted(PpidInfo),
+    /// Type 3: RSA-3072-OAEP encrypted PPID, CPUSVN, PCESVN and PCE-ID.
+    PpidRsa3072Encrypted(PpidInfo),
+    /// Type 5: PEM-encoded PCK certificate chain.
+    PckCertChain(Vec<u8>),
+    /// Any other certification data type.
+    Other(u16, Vec<u8>),
+}
+
+impl CertificationData {
+    fn parse(cert_data_type: u16, data: &[u8]) -> Result<Self> {
+        let ppid_len = match cert_data_type {
+            1 => 16,
+            2 => 256,
+            3 => 384,
+            CERT_DATA_TYPE_PCK_CERT_CHAIN => {
+                return Ok(CertificationData::PckCertChain(data.to_vec()))
+            }
+            _ => return Ok(CertificationData::Other(cert_data_type, data.to_vec())),
+        };
+        if data.len() != ppid_len + 20 {
+            return Err(TdxError::Dcap(format!(
+                "Certification data type {cert_data_type} has unexpected length {}",
+                data.len()
+            )));
+        }
+        let (ppid, rest) = data.split_at(ppid_len);
+        let info = PpidInfo {
+            ppid: ppid.to_vec(),
+            cpusvn: rest[..16].try_into().expect("length checked above"),
+            pcesvn: u16::from_le_bytes([rest[16], rest[17]]),
+            pce_id: u16::from_le_bytes([rest[18], rest[19]]),
+        };
+        Ok(match cert_data_type {
+            1 => CertificationData::PpidCleartext(info),
+            2 => CertificationData::PpidRsa2048Encrypted(info),
+            _ => CertificationData::PpidRsa3072Encrypted(info),
+        })
+    }
+}
+
+/// Offsets of the PCK certification data inside a raw quote.
+#[cfg_attr(not(feature = "http"), allow(dead_code))] // offsets used to rewrite the quote
+struct Location {
+    signature_len_offset: usize,
+    /// Size field of the type 6 QE report certification data (quote v4+).
+    outer_size_offset: Option<usize>,
+    /// Start of the certification data type field.
+    start: usize,
+    cert_data_type: u16,
+    data_start: usize,
+    end: usize,
+}
+
+/// Parse the PCK certification data of a raw SGX / TDX quote (v3, v4 or v5).
+pub fn get_certification_data(raw_quote: &[u8]) -> Result<CertificationData> {
+    let location = locate(raw_quote)?;
+    CertificationData::parse(
+        location.cert_data_type,
+        &raw_quote[location.data_start..location.end],
+    )
+}
+
+/// Returns the quote with its PCK certification data replaced by a PCK certificate chain
+/// (type 5), retrieving the PCK certificate from `pcs` when the quote only carries an
+/// encrypted PPID (types 2 and 3).
+///
+/// Only the unsigned certification data is rewritten, so the quote still verifies.
+///
+/// Plaintext PPIDs (type 1) are rejected: the PCS only serves PCK certificates by PPID
+/// encrypted with its registration key, so that PPIDs never leave the platform in the clear.
+/// Type 1 is meant for platforms registered with an on-premises PCCS, which resolves it
+/// itself.
+#[cfg(feature = "http")]
+pub fn with_pck_cert_chain<'a>(raw_quote: &'a [u8], pcs: &PcsClient) -> Result<Cow<'a, [u8]>> {
+    let location = locate(raw_quote)?;
+    let data = &raw_quote[location.data_start..location.end];
+    let chain = match CertificationData::parse(location.cert_data_type, data)? {
+        CertificationData::PckCertChain(_) => return Ok(Cow::Borrowed(raw_quote)),
+        CertificationData::PpidRsa2048Encrypted(info)
+        | CertificationData::PpidRsa3072Encrypted(info) => pcs.get_pck_cert_chain(&info)?,
+        CertificationData::PpidCleartext(_) => {
+            return Err(TdxError::Dcap(
+                "PCK certificates cannot be retrieved for a plaintext PPID (certification data type 1), the PCS requires an encrypted PPID".to_string(),
+            ))
+        }
+        CertificationData::Other(cert_data_type, _) => {
+            return Err(TdxError::Dcap(format!(
+                "Unsupported certification data type: {cert_data_type}"
+            )))
+        }
+    };
+
+    replace_certification_data(raw_quote, &location, CERT_DATA_TYPE_PCK_CERT_CHAIN, &chain)
+        .map(Cow::Owned)
+}
+
+/// Returns the quote with the certification data at `location` replaced by `data` of type
+/// `cert_data_type`, growing or shrinking the enclosing size fields accordingly.
+#[cfg(any(feature = "http", test))]
+fn replace_certification_data(
+    raw_quote: &[u8],
+    location: &Location,
+    cert_data_type: u16,
+    data: &[u8],
+) -> Result<Vec<u8>> {
+    let data_len = u32::try_from(data.len())
+        .map_err(|_| TdxError::Dcap("Certification data too large".to_string()))?;
+    let old_len = location.end - location.start;
+    let new_len = 6 + data.len();
+
+    let mut quote = Vec::with_capacity(raw_quote.len() + new_len);
+    quote.extend_from_slice(&raw_quote[..location.start]);
+    quote.extend_from_slice(&cert_data_type.to_le_bytes());
+    quote.extend_from_slice(&data_len.to_le_bytes());
+    quote.extend_from_slice(data);
+    quote.extend_from_slice(&raw_quote[location.end..]);
+
+    // Resize the enclosing size fields by the difference
+    let mut offsets = vec![location.signature_len_offset];
+    offsets.extend(location.outer_size_offset);
+    for offset in offsets {
+        let size = read_u32(&quote, offset)? as usize;
+        let size = (size + new_len)
+            .checked_sub(old_len)
+            .and_then(|size| u32::try_from(size).ok())
+            .ok_or_else(|| TdxError::Dcap("Invalid quote signature data size".to_string()))?;
+        quote[offset..offset + 4].copy_from_slice(&size.to_le_bytes());
+    }
+    Ok(quote)
+}
+
+fn locate(raw_quote: &[u8]) -> Result<Location> {
+    let version = read_u16(raw_quote, 0)?;
+    let tee_type = read_u32(raw_quote, 4)?;
+    let body_end = match version {
+        3 => HEADER_LEN + ENCLAVE_REPORT_LEN,
+        4 if tee_type == TEE_TYPE_TDX => HEADER_LEN + TD10_REPORT_LEN,
+        4 => HEADER_LEN + ENCLAVE_REPORT_LEN,
+        // v5: body type (u16) and body size (u32) precede the body
+        5 => HEADER_LEN + 6 + read_u32(raw_quote, HEADER_LEN + 2)? as usize,
+        _ => {
+            return Err(TdxError::Dcap(format!(
+                "Unsupported quote version: {version}"
+            )))
+        }
+    };
+
+    let signature_len_offset = body_end;
+    let signature_start = body_end + 4;
+    let mut offset = signature_start + ECDSA_SIGNATURE_LEN + ECDSA_KEY_LEN;
+    let mut outer_size_offset = None;
+    if version >= 4 {
+        let outer_type = read_u16(raw_quote, offset)?;
+        if outer_type != CERT_DATA_TYPE_QE_REPORT {
+            return Err(TdxError::Dcap(format!(
+                "Unexpected certification data type {outer_type} (expected {CERT_DATA_TYPE_QE_REPORT})"
+            )));
+        }
+        outer_size_offset = Some(offset + 2);
+        offset += 6;
+    }
+    // QE report and its signature, then the QE authentication data
+    offset += ENCLAVE_REPORT_LEN + ECDSA_SIGNATURE_LEN;
+    let auth_data_len = read_u16(raw_quote, offset)? as usize;
+    offset += 2 + auth_data_len;
+
+    let cert_data_type = read_u16(raw_quote, offset)?;
+    let cert_data_len = read_u32(raw_quote, offset + 2)? as usize;
+    let end = offset + 6 + cert_data_len;
+    if end > raw_quote.len() {
+        return Err(TdxError::Dcap(
+            "Certification data exceeds quote length".to_string(),
+        ));
+    }
+    Ok(Location {
+        signature_len_offset,
+        outer_size_offset,
+        start: offset,
+        cert_data_type,
+        data_start: offset + 6,
+        end,
+    })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| TdxError::Dcap("Quote is truncated".to_string()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| TdxError::Dcap("Quote is truncated".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SGX_V3_QUOTE: &[u8] = include_bytes!("../examples/testdata/sgx_v3_quote.bin");
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+    const TDX_V5_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v5_quote.bin");
+    const QUOTES: [&[u8]; 3] = [SGX_V3_QUOTE, TDX_V4_QUOTE, TDX_V5_QUOTE];
+
+    fn ppid_info(ppid_len: usize) -> PpidInfo {
+        PpidInfo {
+            ppid: (0..ppid_len).map(|i| i as u8).collect(),
+            cpusvn: [
+                0x08, 0x08, 0x02, 0x02, 0x04, 0x01, 0, 0x06, 0, 0, 0, 0, 0, 0, 0, 0,
+            ],
+            pcesvn: 11,
+            pce_id: 0,
+        }
+    }
+
+    fn encode(info: &PpidInfo) -> Vec<u8> {
+        let mut data = info.ppid.clone();
+        data.extend_from_slice(&info.cpusvn);
+        data.extend_from_slice(&info.pcesvn.to_le_bytes());
+        data.extend_from_slice(&info.pce_id.to_le_bytes());
+        data
+    }
+
+    /// Replace the PCK certificate chain of `raw_quote` with a PPID, returning the new quote
+    /// and the original chain.
+    fn with_ppid(raw_quote: &[u8], cert_data_type: u16, info: &PpidInfo) -> (Vec<u8>, Vec<u8>) {
+        let location = locate(raw_quote).unwrap();
+        let chain = raw_quote[location.data_start..location.end].to_vec();
+        let quote = replace_certification_data(raw_quote, &location, cert_data_type, &encode(info))
+            .unwrap();
+        (quote, chain)
+    }
+
+    #[test]
+    fn locates_pck_cert_chain() {
+        for raw_quote in QUOTES {
+            let CertificationData::PckCertChain(chain) = get_certification_data(raw_quote).unwrap()
+            else {
+                panic!("expected a PCK certificate chain");
+            };
+            let chain = String::from_utf8(chain).unwrap();
+            assert!(chain.starts_with("-----BEGIN CERTIFICATE-----"));
+            assert_eq!(chain.matches("-----BEGIN CERTIFICATE-----").count(), 3);
+        }
+    }
+
+    #[test]
+    fn parses_ppid_certification_data() {
+        for (cert_data_type, ppid_len) in [(1, 16), (2, 256), (3, 384)] {
+            let info = ppid_info(ppid_len);
+            let (quote, _) = with_ppid(TDX_V4_QUOTE, cert_data_type, &info);
+            let expected = match cert_data_type {
+                1 => CertificationData::PpidCleartext(info),
+                2 => CertificationData::PpidRsa2048Encrypted(info),
+                _ => CertificationData::PpidRsa3072Encrypted(info),
+            };
+            assert_eq!(get_certification_data(&quote).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn rejects_ppid_of_wrong_length() {
+        assert!(CertificationData::parse(2, &[0; 16 + 20]).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_certification_data() {
+        for raw_quote in QUOTES {
+            // The v4 test quote is zero-padded after its certification data
+            let end = locate(raw_quote).unwrap().end;
+            assert!(get_certification_data(&raw_quote[..end - 1]).is_err());
+        }
+    }
+
+    #[test]
+    fn replacing_certification_data_round_trips() {
+        for raw_quote in QUOTES {
+            let (quote, chain) = with_ppid(raw_quote, 2, &ppid_info(256));
+            let location = locate(&quote).unwrap();
+            let restored = replace_certification_data(
+                &quote,
+                &location,
+                CERT_DATA_TYPE_PCK_CERT_CHAIN,
+                &chain,
+            )
+            .unwrap();
+            assert_eq!(restored, raw_quote);
+        }
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn with_pck_cert_chain_keeps_quotes_with_a_chain() {
+        let pcs = PcsClient::default();
+        for raw_quote in QUOTES {
+            let resolved = with_pck_cert_chain(raw_quote, &pcs).unwrap();
+            assert!(matches!(resolved, Cow::Borrowed(quote) if quote == raw_quote));
+        }
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn with_pck_cert_chain_rejects_plaintext_ppid() {
+        let (quote, _) = with_ppid(TDX_V4_QUOTE, 1, &ppid_info(16));
+        assert!(with_pck_cert_chain(&quote, &PcsClient::default()).is_err());
+    }
+}
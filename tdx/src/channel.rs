@@ -0,0 +1,515 @@
+use crate::error::{Result, TdxError};
+use crate::verified_output::VerifiedOutput;
+#[cfg(feature = "coco-provider")]
+use crate::verifier::CollateralProvider;
+use crate::verifier::{Evidence, QuoteVerifier};
+#[cfg(feature = "coco-provider")]
+use crate::Tdx;
+use sha2::{Digest, Sha256};
+use snow::{Builder, HandshakeState, Keypair, TransportState};
+use std::io::{Read, Write};
+
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
+const PROLOGUE: &[u8] = b"tdx-attested-channel/v1";
+const MAX_RECORD_LEN: usize = 65535;
+const TAG_LEN: usize = 16;
+const MAX_RECORD_PAYLOAD: usize = MAX_RECORD_LEN - TAG_LEN;
+/// Upper bound on a single application message, to bound allocations driven by the peer.
+pub const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// Report data binding a channel static key: the SHA-256 of the X25519 public key,
+/// zero-padded.
+pub fn report_data_for_static_key(public_key: &[u8]) -> [u8; 64] {
+    let mut report_data = [0u8; 64];
+    report_data[..32].copy_from_slice(&Sha256::digest(public_key));
+    report_data
+}
+
+/// A Noise static key pair whose public key is bound into the report data of a quote.
+///
+/// The key pair only lives in memory, so a new identity (and quote) is generated for every
+/// process. It can be reused across channels.
+#[derive(Clone)]
+pub struct AttestedIdentity {
+    private_key: Vec<u8>,
+    public_key: Vec<u8>,
+    evidence: Evidence,
+}
+
+impl std::fmt::Debug for AttestedIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AttestedIdentity")
+            .field("public_key", &hex::encode(&self.public_key))
+            .finish_non_exhaustive()
+    }
+}
+
+impl AttestedIdentity {
+    /// Generate a static key pair on this TD and a quote binding its public key. When
+    /// `collaterals` is set, they are sent along with the quote so that peers can verify it
+    /// without network access.
+    #[cfg(feature = "coco-provider")]
+    pub fn generate(tdx: &Tdx, collaterals: Option<&CollateralProvider>) -> Result<Self> {
+        let keypair = generate_keypair()?;
+        let report_data = report_data_for_static_key(&keypair.public);
+        let evidence = Evidence::generate(tdx, report_data, collaterals)?;
+        Ok(AttestedIdentity {
+            private_key: keypair.private,
+            public_key: keypair.public,
+            evidence,
+        })
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    pub fn evidence(&self) -> &Evidence {
+        &self.evidence
+    }
+}
+
+/// An encrypted channel over a byte stream, established with a Noise XX handshake whose
+/// static keys are attested.
+///
+/// After the handshake, the responder always sends its evidence. The initiator sends its own
+/// evidence in mutual mode, or an empty message in one-way mode. Each side checks that the
+/// peer's quote verifies and binds the static key the peer proved possession of during the
+/// handshake.
+///
+/// Messages are split into Noise transport records of at most 65535 bytes, each prefixed with
+/// its big-endian u16 length. The first record of a message starts with the big-endian u32
+/// length of the message.
+pub struct AttestedChannel<S> {
+    stream: S,
+    transport: TransportState,
+    peer: Option<VerifiedOutput>,
+}
+
+impl<S: Read + Write> AttestedChannel<S> {
+    /// Establish a channel as the initiator (client), verifying the responder with `verifier`.
+    ///
+    /// With an `identity`, the initiator also attests itself (mutual mode). Without one, it
+    /// uses a throwaway static key and stays anonymous (one-way mode).
+    pub fn connect(
+        mut stream: S,
+        identity: Option<&AttestedIdentity>,
+        verifier: &QuoteVerifier,
+    ) -> Result<Self> {
+        let ephemeral;
+        let private_key = match identity {
+            Some(identity) => &identity.private_key,
+            None => {
+                ephemeral = generate_keypair()?;
+                &ephemeral.private
+            }
+        };
+        let mut handshake = builder()?
+            .local_private_key(private_key)
+            .build_initiator()
+            .map_err(channel_error)?;
+
+        // -> e
+        write_handshake(&mut stream, &mut handshake)?;
+        // <- e, ee, s, es
+        read_handshake(&mut stream, &mut handshake)?;
+        // -> s, se
+        write_handshake(&mut stream, &mut handshake)?;
+
+        let remote_static = remote_static(&handshake)?;
+        let mut channel = AttestedChannel {
+            stream,
+            transport: handshake.into_transport_mode().map_err(channel_error)?,
+            peer: None,
+        };
+        let evidence = channel
+            .recv_evidence()?
+            .ok_or_else(|| TdxError::Channel("Responder did not send its evidence".to_string()))?;
+        channel.peer =
+            Some(verifier.verify_evidence(&evidence, &report_data_for_static_key(&remote_static))?);
+        channel.send_evidence(identity.map(|identity| &identity.evidence))?;
+        Ok(channel)
+    }
+
+    /// Accept a channel as the responder (server), attesting with `identity`.
+    ///
+    /// With a `verifier`, the initiator must attest itself (mutual mode). Without one, any
+    /// initiator is accepted (one-way mode).
+    pub fn accept(
+        mut stream: S,
+        identity: &AttestedIdentity,
+        verifier: Option<&QuoteVerifier>,
+    ) -> Result<Self> {
+        let mut handshake = builder()?
+            .local_private_key(&identity.private_key)
+            .build_responder()
+            .map_err(channel_error)?;
+
+        // -> e
+        read_handshake(&mut stream, &mut handshake)?;
+        // <- e, ee, s, es
+        write_handshake(&mut stream, &mut handshake)?;
+        // -> s, se
+        read_handshake(&mut stream, &mut handshake)?;
+
+        let remote_static = remote_static(&handshake)?;
+        let mut channel = AttestedChannel {
+            stream,
+            transport: handshake.into_transport_mode().map_err(channel_error)?,
+            peer: None,
+        };
+        channel.send_evidence(Some(&identity.evidence))?;
+        let evidence = channel.recv_evidence()?;
+        if let Some(verifier) = verifier {
+            let evidence = evidence.ok_or_else(|| {
+                TdxError::Channel("Initiator did not send its evidence".to_string())
+            })?;
+            channel.peer = Some(
+                verifier.verify_evidence(&evidence, &report_data_for_static_key(&remote_static))?,
+            );
+        }
+        Ok(channel)
+    }
+
+    /// The verified output of the peer's quote, `None` for an anonymous initiator.
+    pub fn peer(&self) -> Option<&VerifiedOutput> {
+        self.peer.as_ref()
+    }
+
+    /// Encrypt and send a message.
+    pub fn send(&mut self, message: &[u8]) -> Result<()> {
+        if message.len() > MAX_MESSAGE_LEN {
+            return Err(TdxError::Channel(format!(
+                "Message of {} bytes exceeds {MAX_MESSAGE_LEN}",
+                message.len()
+            )));
+        }
+        let mut plaintext = Vec::with_capacity(4 + message.len());
+        plaintext.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        plaintext.extend_from_slice(message);
+
+        let mut record = vec![0u8; MAX_RECORD_LEN];
+        for chunk in plaintext.chunks(MAX_RECORD_PAYLOAD) {
+            let len = self
+                .transport
+                .write_message(chunk, &mut record)
+                .map_err(channel_error)?;
+            write_record(&mut self.stream, &record[..len])?;
+        }
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    /// Receive and decrypt a message.
+    pub fn recv(&mut self) -> Result<Vec<u8>> {
+        let mut plaintext = self.recv_record()?;
+        if plaintext.len() < 4 {
+            return Err(TdxError::Channel("Truncated message header".to_string()));
+        }
+        let len = u32::from_be_bytes(plaintext[..4].try_into().expect("4 bytes")) as usize;
+        if len > MAX_MESSAGE_LEN {
+            return Err(TdxError::Channel(format!(
+                "Message of {len} bytes exceeds {MAX_MESSAGE_LEN}"
+            )));
+        }
+        let mut message = plaintext.split_off(4);
+        while message.len() < len {
+            message.extend(self.recv_record()?);
+        }
+        if message.len() != len {
+            return Err(TdxError::Channel("Message length mismatch".to_string()));
+        }
+        Ok(message)
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    fn recv_record(&mut self) -> Result<Vec<u8>> {
+        let record = read_record(&mut self.stream)?;
+        let mut plaintext = vec![0u8; record.len()];
+        let len = self
+            .transport
+            .read_message(&record, &mut plaintext)
+            .map_err(channel_error)?;
+        plaintext.truncate(len);
+        Ok(plaintext)
+    }
+
+    fn send_evidence(&mut self, evidence: Option<&Evidence>) -> Result<()> {
+        let message = match evidence {
+            Some(evidence) => serde_json::to_vec(evidence)
+                .map_err(|e| TdxError::Channel(format!("Failed to encode evidence: {e}")))?,
+            None => Vec::new(),
+        };
+        self.send(&message)
+    }
+
+    fn recv_evidence(&mut self) -> Result<Option<Evidence>> {
+        let message = self.recv()?;
+        if message.is_empty() {
+            return Ok(None);
+        }
+        serde_json::from_slice(&message)
+            .map(Some)
+            .map_err(|e| TdxError::Channel(format!("Invalid evidence: {e}")))
+    }
+}
+
+fn builder<'a>() -> Result<Builder<'a>> {
+    let params = NOISE_PARAMS.parse().map_err(channel_error)?;
+    Ok(Builder::new(params).prologue(PROLOGUE))
+}
+
+fn generate_keypair() -> Result<Keypair> {
+    builder()?
+        .generate_keypair()
+        .map_err(|e| TdxError::Channel(format!("Failed to generate key pair: {e}")))
+}
+
+fn channel_error(e: snow::Error) -> TdxError {
+    TdxError::Channel(e.to_string())
+}
+
+fn remote_static(handshake: &HandshakeState) -> Result<Vec<u8>> {
+    handshake
+        .get_remote_static()
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| TdxError::Channel("Peer did not send a static key".to_string()))
+}
+
+fn write_handshake(stream: &mut impl Write, handshake: &mut HandshakeState) -> Result<()> {
+    let mut message = vec![0u8; MAX_RECORD_LEN];
+    let len = handshake
+        .write_message(&[], &mut message)
+        .map_err(channel_error)?;
+    write_record(stream, &message[..len])?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn read_handshake(stream: &mut impl Read, handshake: &mut HandshakeState) -> Result<()> {
+    let message = read_record(stream)?;
+    let mut payload = vec![0u8; message.len()];
+    handshake
+        .read_message(&message, &mut payload)
+        .map_err(channel_error)?;
+    Ok(())
+}
+
+fn write_record(stream: &mut impl Write, record: &[u8]) -> Result<()> {
+    stream.write_all(&(record.len() as u16).to_be_bytes())?;
+    stream.write_all(record)?;
+    Ok(())
+}
+
+fn read_record(stream: &mut impl Read) -> Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len)?;
+    let mut record = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut record)?;
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pki;
+    use crate::verifier::{CollateralProvider, EmbeddedCollaterals};
+    use std::sync::mpsc::{channel, Receiver, Sender};
+
+    /// One end of an in-memory byte stream.
+    struct Duplex {
+        tx: Sender<Vec<u8>>,
+        rx: Receiver<Vec<u8>>,
+        buf: Vec<u8>,
+    }
+
+    fn duplex() -> (Duplex, Duplex) {
+        let (a_tx, b_rx) = channel();
+        let (b_tx, a_rx) = channel();
+        let end = |tx, rx| Duplex {
+            tx,
+            rx,
+            buf: Vec::new(),
+        };
+        (end(a_tx, a_rx), end(b_tx, b_rx))
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.buf.is_empty() {
+                // EOF once the other end is dropped
+                self.buf = self.rx.recv().unwrap_or_default();
+            }
+            let len = buf.len().min(self.buf.len());
+            buf[..len].copy_from_slice(&self.buf[..len]);
+            self.buf.drain(..len);
+            Ok(len)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.tx
+                .send(buf.to_vec())
+                .map_err(|_| std::io::ErrorKind::BrokenPipe)?;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// An identity whose test quote binds `report_data`, or its own static key if `None`.
+    fn identity(report_data: Option<[u8; 64]>) -> AttestedIdentity {
+        let keypair = generate_keypair().unwrap();
+        let report_data = report_data.unwrap_or(report_data_for_static_key(&keypair.public));
+        AttestedIdentity {
+            private_key: keypair.private,
+            public_key: keypair.public,
+            evidence: Evidence {
+                quote: test_pki::quote_with_report_data(report_data),
+                collaterals: Some(test_pki::collaterals()),
+            },
+        }
+    }
+
+    fn verifier() -> QuoteVerifier {
+        let embedded = EmbeddedCollaterals::new().with_validator(test_pki::validator());
+        QuoteVerifier::new(CollateralProvider::Embedded(embedded)).with_time(test_pki::now())
+    }
+
+    type Channel = AttestedChannel<Duplex>;
+
+    /// Run the handshake of an initiator and a responder on two threads.
+    fn handshake(
+        client: Option<&AttestedIdentity>,
+        client_verifier: &QuoteVerifier,
+        server: &AttestedIdentity,
+        server_verifier: Option<&QuoteVerifier>,
+    ) -> (Result<Channel>, Result<Channel>) {
+        let (client_stream, server_stream) = duplex();
+        std::thread::scope(|scope| {
+            let server =
+                scope.spawn(|| AttestedChannel::accept(server_stream, server, server_verifier));
+            let client = AttestedChannel::connect(client_stream, client, client_verifier);
+            (client, server.join().unwrap())
+        })
+    }
+
+    fn connected() -> (Channel, Channel) {
+        let (client, server) = handshake(None, &verifier(), &identity(None), None);
+        (client.unwrap(), server.unwrap())
+    }
+
+    /// Send `plaintext` as a single raw transport record.
+    fn send_record(channel: &mut Channel, plaintext: &[u8]) {
+        let mut record = vec![0u8; MAX_RECORD_LEN];
+        let len = channel
+            .transport
+            .write_message(plaintext, &mut record)
+            .unwrap();
+        write_record(&mut channel.stream, &record[..len]).unwrap();
+    }
+
+    #[test]
+    fn establishes_one_way_channel() {
+        let (mut client, mut server) = connected();
+        assert!(client.peer().is_some());
+        assert!(server.peer().is_none());
+
+        client.send(b"ping").unwrap();
+        assert_eq!(server.recv().unwrap(), b"ping");
+        server.send(b"").unwrap();
+        assert_eq!(client.recv().unwrap(), b"");
+    }
+
+    #[test]
+    fn establishes_mutual_channel() {
+        let client_identity = identity(None);
+        let server_identity = identity(None);
+        let (client, server) = handshake(
+            Some(&client_identity),
+            &verifier(),
+            &server_identity,
+            Some(&verifier()),
+        );
+        let (client, server) = (client.unwrap(), server.unwrap());
+        let quote_body = |identity: &AttestedIdentity| identity.evidence.quote[48..632].to_vec();
+        assert_eq!(
+            client.peer().unwrap().quote_body,
+            quote_body(&server_identity)
+        );
+        assert_eq!(
+            server.peer().unwrap().quote_body,
+            quote_body(&client_identity)
+        );
+
+        // In mutual mode the initiator must attest itself
+        let (_, server) = handshake(None, &verifier(), &server_identity, Some(&verifier()));
+        let err = server.err().unwrap().to_string();
+        assert!(err.contains("Initiator did not send its evidence"), "{err}");
+    }
+
+    #[test]
+    fn rejects_quotes_not_binding_the_static_key() {
+        let unbound = identity(Some([0; 64]));
+        let (client, _) = handshake(None, &verifier(), &unbound, None);
+        let err = client.err().unwrap().to_string();
+        assert!(err.contains("Report data"), "{err}");
+
+        let (_, server) = handshake(
+            Some(&unbound),
+            &verifier(),
+            &identity(None),
+            Some(&verifier()),
+        );
+        let err = server.err().unwrap().to_string();
+        assert!(err.contains("Report data"), "{err}");
+    }
+
+    #[test]
+    fn verifies_at_the_verifier_time() {
+        // The test collaterals expire in 2034
+        let later = test_pki::now() + std::time::Duration::from_secs(10 * 365 * 24 * 3600);
+        let (client, _) = handshake(None, &verifier().with_time(later), &identity(None), None);
+        assert!(client.is_err());
+    }
+
+    #[test]
+    fn splits_messages_into_records() {
+        let (mut client, mut server) = connected();
+        let message: Vec<u8> = (0..3 * MAX_RECORD_PAYLOAD + 10).map(|i| i as u8).collect();
+        client.send(&message).unwrap();
+        assert_eq!(server.recv().unwrap(), message);
+
+        let err = client
+            .send(&vec![0u8; MAX_MESSAGE_LEN + 1])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("exceeds"), "{err}");
+    }
+
+    #[test]
+    fn rejects_malformed_records() {
+        let (mut client, mut server) = connected();
+        send_record(&mut client, &((MAX_MESSAGE_LEN + 1) as u32).to_be_bytes());
+        let err = server.recv().unwrap_err().to_string();
+        assert!(err.contains("exceeds"), "{err}");
+
+        send_record(&mut client, &[0, 0, 0, 3, 1, 2, 3, 4, 5]);
+        let err = server.recv().unwrap_err().to_string();
+        assert!(err.contains("Message length mismatch"), "{err}");
+
+        send_record(&mut client, &[0, 0]);
+        let err = server.recv().unwrap_err().to_string();
+        assert!(err.contains("Truncated message header"), "{err}");
+
+        // Records that do not decrypt
+        write_record(&mut client.stream, &[0; 32]).unwrap();
+        assert!(server.recv().is_err());
+    }
+}
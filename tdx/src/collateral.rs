@@ -0,0 +1,128 @@
+use crate::error::{Result, TdxError};
+use crate::utils::{der_to_pem_bytes, serde_hex};
+#[cfg(feature = "onchain")]
+use alloy::providers::ProviderBuilder;
+#[cfg(feature = "onchain")]
+use automata_dcap_network_registry::Network;
+use dcap_rs::types::collateral::Collateral;
+#[cfg(feature = "onchain")]
+use pccs_reader_rs::{find_missing_collaterals_from_quote, CollateralError};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "verifier")]
+use serde_json::value::RawValue;
+#[cfg(feature = "verifier")]
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The collaterals required to verify a quote, as fetched from the on-chain PCCS.
+///
+/// Certificates and CRLs are DER-encoded, TCB info and QE identity are the signed JSON documents.
+/// When serialized (e.g. to a JSON file), every field is hex-encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawCollaterals {
+    #[serde(with = "serde_hex")]
+    pub tcb_info: Vec<u8>,
+    #[serde(with = "serde_hex")]
+    pub qe_identity: Vec<u8>,
+    #[serde(with = "serde_hex")]
+    pub root_ca: Vec<u8>,
+    #[serde(with = "serde_hex")]
+    pub tcb_signing_ca: Vec<u8>,
+    #[serde(with = "serde_hex")]
+    pub root_ca_crl: Vec<u8>,
+    /// CRL of the PCK CA (Platform or Processor) that issued the quote's PCK certificate.
+    #[serde(with = "serde_hex")]
+    pub pck_crl: Vec<u8>,
+}
+
+impl RawCollaterals {
+    /// Load collaterals previously saved with [`RawCollaterals::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(&data)
+            .map_err(|e| TdxError::IO(format!("Failed to parse collaterals: {e}")))
+    }
+
+    /// Save the collaterals as a JSON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let data = serde_json::to_vec_pretty(self)
+            .map_err(|e| TdxError::IO(format!("Failed to serialize collaterals: {e}")))?;
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Convert into the dcap-rs `Collateral` used for off-chain verification.
+    pub fn to_collateral(&self) -> Result<Collateral> {
+        // dcap-rs expects PEM for the TCB signing cert chain
+        let mut combined_pem = der_to_pem_bytes(&self.tcb_signing_ca);
+        combined_pem.extend_from_slice(&der_to_pem_bytes(&self.root_ca));
+
+        Ok(Collateral::new(
+            &self.root_ca_crl,
+            &self.pck_crl,
+            &combined_pem,
+            &self.tcb_info,
+            &self.qe_identity,
+        )?)
+    }
+}
+
+/// Fetch the collaterals for `raw_quote` from the on-chain PCCS of the default network.
+#[cfg(feature = "onchain")]
+pub async fn fetch_raw_collaterals(raw_quote: &[u8]) -> Result<RawCollaterals> {
+    // Get network configuration (defaults to automata_testnet)
+    let network = Network::default_network(None)
+        .ok_or_else(|| TdxError::Http("Failed to get network config".to_string()))?;
+
+    // Get RPC endpoint from network registry
+    let rpc_url = network
+        .rpc_endpoints
+        .first()
+        .ok_or_else(|| TdxError::Http("No RPC endpoints available".to_string()))?
+        .parse()
+        .map_err(|e| TdxError::Http(format!("Failed to parse RPC URL: {}", e)))?;
+
+    let provider = ProviderBuilder::new().connect_http(rpc_url);
+
+    // Fetch collaterals from on-chain PCCS using the library
+    let collaterals = find_missing_collaterals_from_quote(
+        &provider, None, // deployment_version - uses default
+        raw_quote, false, // don't print to disk
+        None,  // tcb_eval_num
+    )
+    .await
+    .map_err(|e| match e {
+        CollateralError::Missing(report) => {
+            TdxError::Http(format!("Missing collaterals: {report:?}"))
+        }
+        CollateralError::Validation(msg) => TdxError::Http(format!("Validation error: {}", msg)),
+    })?;
+
+    Ok(RawCollaterals {
+        tcb_info: collaterals.tcb_info,
+        qe_identity: collaterals.qe_identity,
+        root_ca: collaterals.root_ca,
+        tcb_signing_ca: collaterals.tcb_signing_ca,
+        root_ca_crl: collaterals.root_ca_crl,
+        pck_crl: collaterals.pck_crl,
+    })
+}
+
+/// Split a signed Intel collateral JSON document (`{"<key>": {...}, "signature": "<hex>"}`)
+/// into the exact signed body and the raw signature.
+#[cfg(feature = "verifier")]
+pub(crate) fn split_signed_json(document: &[u8], key: &str) -> Result<(String, Vec<u8>)> {
+    let fields: HashMap<String, Box<RawValue>> = serde_json::from_slice(document)
+        .map_err(|e| TdxError::Dcap(format!("Invalid {key} document: {e}")))?;
+    let body = fields
+        .get(key)
+        .ok_or_else(|| TdxError::Dcap(format!("Collateral document is missing {key}")))?;
+    let signature = fields
+        .get("signature")
+        .ok_or_else(|| TdxError::Dcap(format!("{key} document is missing its signature")))?;
+    let signature: String = serde_json::from_str(signature.get())
+        .map_err(|e| TdxError::Dcap(format!("Invalid {key} signature: {e}")))?;
+    let signature = hex::decode(signature)
+        .map_err(|e| TdxError::Dcap(format!("Invalid {key} signature: {e}")))?;
+    Ok((body.get().to_string(), signature))
+}
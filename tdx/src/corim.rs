@@ -0,0 +1,902 @@
+use crate::error::{Result, TdxError};
+use crate::policy::MeasurementField;
+use crate::quote::QuoteData;
+use crate::verified_output::VerifiedOutput;
+use coset::cbor::value::Value;
+use coset::{iana, AsCborValue, CoseSign1};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use x509_parser::prelude::FromDer;
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+// CBOR tags of the CoRIM specification (draft-ietf-rats-corim)
+const TAG_COSE_SIGN1: u64 = 18;
+const TAG_UNSIGNED_CORIM: u64 = 501;
+const TAG_COMID: u64 = 506;
+const TAG_OID: u64 = 111;
+const TAG_UUID: u64 = 37;
+const TAG_BYTES: u64 = 560;
+const TAG_SVN: u64 = 552;
+const TAG_MIN_SVN: u64 = 553;
+
+// unsigned-corim-map
+const CORIM_ID: i128 = 0;
+const CORIM_TAGS: i128 = 1;
+// concise-mid-tag
+const COMID_TAG_IDENTITY: i128 = 1;
+const COMID_TRIPLES: i128 = 4;
+const TAG_IDENTITY_ID: i128 = 0;
+const TRIPLES_REFERENCE: i128 = 0;
+// environment-map / class-map
+const ENVIRONMENT_CLASS: i128 = 0;
+const CLASS_ID: i128 = 0;
+const CLASS_VENDOR: i128 = 1;
+const CLASS_MODEL: i128 = 2;
+// measurement-map / measurement-values-map
+const MEASUREMENT_KEY: i128 = 0;
+const MEASUREMENT_VALUES: i128 = 1;
+const MVAL_SVN: i128 = 1;
+const MVAL_DIGESTS: i128 = 2;
+const MVAL_RAW_VALUE: i128 = 4;
+const MVAL_INTEGRITY_REGISTERS: i128 = 14;
+
+// sha-384 in the IANA Named Information Hash Algorithm registry
+const ALG_SHA384: i128 = 7;
+
+/// Public keys CoRIM signatures are verified against.
+#[derive(Debug, Clone, Default)]
+pub struct CorimVerifier {
+    keys: Vec<CorimKey>,
+    allow_unsigned: bool,
+}
+
+#[derive(Debug, Clone)]
+enum CorimKey {
+    P256(p256::ecdsa::VerifyingKey),
+    P384(p384::ecdsa::VerifyingKey),
+}
+
+impl CorimVerifier {
+    pub fn new() -> Self {
+        CorimVerifier::default()
+    }
+
+    /// Trust a P-256 or P-384 public key, as a PEM encoded SubjectPublicKeyInfo.
+    pub fn with_pem_key(mut self, pem: &[u8]) -> Result<Self> {
+        let pem =
+            pem::parse(pem).map_err(|e| TdxError::Corim(format!("Invalid PEM public key: {e}")))?;
+        let (_, spki) = SubjectPublicKeyInfo::from_der(pem.contents())
+            .map_err(|e| TdxError::Corim(format!("Invalid public key: {e}")))?;
+        let point = &spki.subject_public_key.data;
+        let key = match point.len() {
+            65 => p256::ecdsa::VerifyingKey::from_sec1_bytes(point).map(CorimKey::P256),
+            97 => p384::ecdsa::VerifyingKey::from_sec1_bytes(point).map(CorimKey::P384),
+            len => {
+                return Err(TdxError::Corim(format!(
+                    "Unsupported public key of {len} bytes, expected P-256 or P-384"
+                )))
+            }
+        }
+        .map_err(|e| TdxError::Corim(format!("Invalid public key: {e}")))?;
+        self.keys.push(key);
+        Ok(self)
+    }
+
+    /// Trust the PEM public key stored at `path`.
+    pub fn with_pem_key_file(self, path: impl AsRef<Path>) -> Result<Self> {
+        let pem = std::fs::read(path)?;
+        self.with_pem_key(&pem)
+    }
+
+    /// Accept CoRIMs that are not wrapped in a COSE signature.
+    pub fn allow_unsigned(mut self, allow: bool) -> Self {
+        self.allow_unsigned = allow;
+        self
+    }
+
+    fn verify(&self, sign1: &CoseSign1) -> Result<()> {
+        let alg = match &sign1.protected.header.alg {
+            Some(coset::Algorithm::Assigned(alg)) => *alg,
+            _ => {
+                return Err(TdxError::Corim(
+                    "Missing or unsupported COSE signature algorithm".to_string(),
+                ))
+            }
+        };
+        let verified = self.keys.iter().any(|key| {
+            sign1
+                .verify_signature(b"", |signature, tbs| match (key, alg) {
+                    (CorimKey::P256(key), iana::Algorithm::ES256) => {
+                        use p256::ecdsa::signature::Verifier;
+                        let signature = p256::ecdsa::Signature::from_slice(signature)?;
+                        key.verify(tbs, &signature)
+                    }
+                    (CorimKey::P384(key), iana::Algorithm::ES384) => {
+                        use p384::ecdsa::signature::Verifier;
+                        let signature = p384::ecdsa::Signature::from_slice(signature)?;
+                        key.verify(tbs, &signature)
+                    }
+                    _ => Err(p256::ecdsa::Error::new()),
+                })
+                .is_ok()
+        });
+        if !verified {
+            return Err(TdxError::Corim(
+                "CoRIM signature does not verify against any configured key".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Environment a reference triple applies to, from the CoMID class map.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorimEnvironment {
+    /// Class identifier: an OID or UUID string, or hex for raw bytes.
+    pub class_id: Option<String>,
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+}
+
+impl CorimEnvironment {
+    /// Environment with the given vendor and model, the class an appraised TD must belong to.
+    pub fn new(vendor: impl Into<String>, model: impl Into<String>) -> Self {
+        CorimEnvironment {
+            class_id: None,
+            vendor: Some(vendor.into()),
+            model: Some(model.into()),
+        }
+    }
+
+    /// Whether this environment belongs to the `expected` class. Fields of `expected` set to
+    /// `None` accept any value.
+    pub fn matches(&self, expected: &CorimEnvironment) -> bool {
+        let field = |expected: &Option<String>, actual: &Option<String>| {
+            expected.is_none() || expected == actual
+        };
+        field(&expected.class_id, &self.class_id)
+            && field(&expected.vendor, &self.vendor)
+            && field(&expected.model, &self.model)
+    }
+}
+
+/// TDX reference values of a reference triple.
+///
+/// Each measurement is a list of accepted values, an empty list accepts any value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TdxReferenceValues {
+    #[serde(with = "crate::policy::hex_list")]
+    pub mr_td: Vec<Vec<u8>>,
+    #[serde(with = "crate::policy::hex_list")]
+    pub mr_seam: Vec<Vec<u8>>,
+    #[serde(with = "crate::policy::hex_list")]
+    pub rtmr0: Vec<Vec<u8>>,
+    #[serde(with = "crate::policy::hex_list")]
+    pub rtmr1: Vec<Vec<u8>>,
+    #[serde(with = "crate::policy::hex_list")]
+    pub rtmr2: Vec<Vec<u8>>,
+    #[serde(with = "crate::policy::hex_list")]
+    pub rtmr3: Vec<Vec<u8>>,
+    /// Minimum TEE_TCB_SVN, compared component by component. Empty accepts any value.
+    #[serde(with = "crate::utils::serde_hex")]
+    pub min_tee_tcb_svn: Vec<u8>,
+    /// Minimum SVN of the TDX module (SEAM), byte 0 of TEE_TCB_SVN.
+    pub min_seam_svn: Option<u64>,
+    /// Minimum ISV SVN of the Quoting Enclave that signed the quote.
+    pub min_qe_svn: Option<u64>,
+}
+
+/// A CoMID reference triple: an environment and the reference values it must report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReferenceTriple {
+    /// Tag ID of the CoMID the triple comes from.
+    pub comid_id: String,
+    /// Index of the triple in the CoMID.
+    pub index: usize,
+    pub environment: CorimEnvironment,
+    pub values: TdxReferenceValues,
+}
+
+impl ReferenceTriple {
+    /// Returns the names of the reference values `quote` does not match. SGX quotes match no
+    /// TDX reference values.
+    pub fn mismatches(&self, quote: &QuoteData) -> Vec<&'static str> {
+        let Some(report) = quote.td_report() else {
+            return vec!["tee_type"];
+        };
+        let values = &self.values;
+        let fields: [MeasurementField; 6] = [
+            ("mr_td", &values.mr_td, &report.mr_td),
+            ("mr_seam", &values.mr_seam, &report.mr_seam),
+            ("rtmr0", &values.rtmr0, &report.rtmr0),
+            ("rtmr1", &values.rtmr1, &report.rtmr1),
+            ("rtmr2", &values.rtmr2, &report.rtmr2),
+            ("rtmr3", &values.rtmr3, &report.rtmr3),
+        ];
+        let mut mismatches: Vec<&'static str> = fields
+            .into_iter()
+            .filter(|(_, accepted, value)| {
+                !accepted.is_empty() && !accepted.iter().any(|a| a == value)
+            })
+            .map(|(field, _, _)| field)
+            .collect();
+        if report
+            .tee_tcb_svn
+            .iter()
+            .zip(&values.min_tee_tcb_svn)
+            .any(|(svn, min)| svn < min)
+        {
+            mismatches.push("tee_tcb_svn");
+        }
+        let svns = [
+            (
+                "seam_svn",
+                values.min_seam_svn,
+                u64::from(report.tee_tcb_svn[0]),
+            ),
+            (
+                "qe_svn",
+                values.min_qe_svn,
+                u64::from(quote.signature.qe_report.isv_svn),
+            ),
+        ];
+        for (field, min, svn) in svns {
+            if min.is_some_and(|min| svn < min) {
+                mismatches.push(field);
+            }
+        }
+        mismatches
+    }
+
+    pub fn matches(&self, quote: &QuoteData) -> bool {
+        self.mismatches(quote).is_empty()
+    }
+}
+
+/// A CoRIM manifest and the TDX reference triples of its CoMIDs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Corim {
+    pub id: String,
+    pub signed: bool,
+    pub reference_triples: Vec<ReferenceTriple>,
+}
+
+impl Corim {
+    /// Parse a CBOR CoRIM. Signed CoRIMs (COSE_Sign1) must verify against one of the keys of
+    /// `verifier`, unsigned ones are only accepted if the verifier allows them.
+    pub fn from_bytes(data: &[u8], verifier: &CorimVerifier) -> Result<Self> {
+        let value: Value = coset::cbor::de::from_reader(data)
+            .map_err(|e| TdxError::Corim(format!("Invalid CBOR: {e}")))?;
+        match value {
+            Value::Tag(TAG_COSE_SIGN1, sign1) => {
+                let sign1 = CoseSign1::from_cbor_value(*sign1)
+                    .map_err(|e| TdxError::Corim(format!("Invalid COSE_Sign1: {e:?}")))?;
+                verifier.verify(&sign1)?;
+                let payload = sign1.payload.as_deref().ok_or_else(|| {
+                    TdxError::Corim("Detached CoRIM payloads are not supported".to_string())
+                })?;
+                let payload: Value = coset::cbor::de::from_reader(payload)
+                    .map_err(|e| TdxError::Corim(format!("Invalid CoRIM payload: {e}")))?;
+                Self::from_value(untag(payload, TAG_UNSIGNED_CORIM), true)
+            }
+            Value::Tag(TAG_UNSIGNED_CORIM, corim) if verifier.allow_unsigned => {
+                Self::from_value(*corim, false)
+            }
+            Value::Tag(TAG_UNSIGNED_CORIM, _) => Err(TdxError::Corim(
+                "CoRIM is not signed and unsigned CoRIMs are not allowed".to_string(),
+            )),
+            _ => Err(TdxError::Corim(
+                "Not a signed or unsigned CoRIM".to_string(),
+            )),
+        }
+    }
+
+    /// Read and parse a CBOR CoRIM file.
+    pub fn load(path: impl AsRef<Path>, verifier: &CorimVerifier) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        Self::from_bytes(&data, verifier)
+    }
+
+    fn from_value(value: Value, signed: bool) -> Result<Self> {
+        let map = as_map(&value, "corim-map")?;
+        let id = map_get(map, CORIM_ID)
+            .map(identifier)
+            .ok_or_else(|| TdxError::Corim("CoRIM has no id".to_string()))?;
+        let tags = map_get(map, CORIM_TAGS)
+            .and_then(Value::as_array)
+            .ok_or_else(|| TdxError::Corim("CoRIM has no tags".to_string()))?;
+
+        let mut reference_triples = Vec::new();
+        for tag in tags {
+            // CoSWID and CoTL tags carry no reference values
+            let Value::Tag(TAG_COMID, comid) = tag else {
+                continue;
+            };
+            let comid = comid
+                .as_bytes()
+                .ok_or_else(|| TdxError::Corim("CoMID is not a byte string".to_string()))?;
+            let comid: Value = coset::cbor::de::from_reader(comid.as_slice())
+                .map_err(|e| TdxError::Corim(format!("Invalid CoMID: {e}")))?;
+            reference_triples.extend(parse_comid(&comid)?);
+        }
+        Ok(Corim {
+            id,
+            signed,
+            reference_triples,
+        })
+    }
+
+    /// Returns the reference triples of the `environment` class that the verified quote
+    /// matches. SGX quotes match none.
+    pub fn matching_triples(
+        &self,
+        raw_quote: &[u8],
+        output: &VerifiedOutput,
+        environment: &CorimEnvironment,
+    ) -> Result<Vec<&ReferenceTriple>> {
+        let quote = verified_quote(raw_quote, output)?;
+        Ok(self
+            .reference_triples
+            .iter()
+            .filter(|triple| triple.environment.matches(environment) && triple.matches(&quote))
+            .collect())
+    }
+
+    /// Returns the first reference triple of the `environment` class that the verified quote
+    /// matches, or an error listing the mismatches of every triple. `output` must be the
+    /// verification output of `raw_quote`, which also provides the QE SVN.
+    ///
+    /// The environment must name a vendor and a model, so that reference values published for
+    /// other environments in the same CoRIM are never used.
+    pub fn appraise(
+        &self,
+        raw_quote: &[u8],
+        output: &VerifiedOutput,
+        environment: &CorimEnvironment,
+    ) -> Result<&ReferenceTriple> {
+        if environment.vendor.is_none() || environment.model.is_none() {
+            return Err(TdxError::Corim(
+                "CoRIM appraisal requires the vendor and model of the environment".to_string(),
+            ));
+        }
+        let quote = verified_quote(raw_quote, output)?;
+        if quote.td_report().is_none() {
+            return Err(TdxError::Corim(
+                "CoRIM appraisal requires a TDX quote".to_string(),
+            ));
+        }
+        let mut failures = Vec::new();
+        for triple in &self.reference_triples {
+            let mismatches = if triple.environment.matches(environment) {
+                triple.mismatches(&quote)
+            } else {
+                vec!["environment"]
+            };
+            if mismatches.is_empty() {
+                return Ok(triple);
+            }
+            failures.push(format!(
+                "{}#{}: {}",
+                triple.comid_id,
+                triple.index,
+                mismatches.join(", ")
+            ));
+        }
+        if failures.is_empty() {
+            return Err(TdxError::Corim(format!(
+                "CoRIM {} has no TDX reference triples",
+                self.id
+            )));
+        }
+        Err(TdxError::Corim(format!(
+            "No reference triple matched: {}",
+            failures.join("; ")
+        )))
+    }
+}
+
+/// Parse `raw_quote`, checking that it is the quote `output` was produced for.
+fn verified_quote(raw_quote: &[u8], output: &VerifiedOutput) -> Result<QuoteData> {
+    let quote = QuoteData::from_bytes(raw_quote)?;
+    if quote.body != output.body()? {
+        return Err(TdxError::Corim(
+            "Quote does not match the verified output".to_string(),
+        ));
+    }
+    Ok(quote)
+}
+
+fn parse_comid(comid: &Value) -> Result<Vec<ReferenceTriple>> {
+    let map = as_map(comid, "concise-mid-tag")?;
+    let comid_id = map_get(map, COMID_TAG_IDENTITY)
+        .and_then(|identity| map_get(as_map(identity, "tag-identity").ok()?, TAG_IDENTITY_ID))
+        .map(identifier)
+        .ok_or_else(|| TdxError::Corim("CoMID has no tag identity".to_string()))?;
+    let Some(triples) = map_get(map, COMID_TRIPLES) else {
+        return Ok(Vec::new());
+    };
+    let Some(reference) = map_get(as_map(triples, "triples-map")?, TRIPLES_REFERENCE) else {
+        return Ok(Vec::new());
+    };
+    let reference = reference
+        .as_array()
+        .ok_or_else(|| TdxError::Corim("Reference triples are not an array".to_string()))?;
+
+    let mut triples = Vec::new();
+    for (index, record) in reference.iter().enumerate() {
+        let record = match record.as_array().map(Vec::as_slice) {
+            Some([environment, measurements]) => (environment, measurements),
+            _ => {
+                return Err(TdxError::Corim(format!(
+                    "Malformed reference triple {index} in CoMID {comid_id}"
+                )))
+            }
+        };
+        let environment = parse_environment(record.0)?;
+        let measurements = record
+            .1
+            .as_array()
+            .ok_or_else(|| TdxError::Corim("Measurements are not an array".to_string()))?;
+        let mut values = TdxReferenceValues::default();
+        for measurement in measurements {
+            parse_measurement(measurement, &mut values)?;
+        }
+        if values == TdxReferenceValues::default() {
+            // Not a TDX environment
+            continue;
+        }
+        triples.push(ReferenceTriple {
+            comid_id: comid_id.clone(),
+            index,
+            environment,
+            values,
+        });
+    }
+    Ok(triples)
+}
+
+fn parse_environment(environment: &Value) -> Result<CorimEnvironment> {
+    let map = as_map(environment, "environment-map")?;
+    let Some(class) = map_get(map, ENVIRONMENT_CLASS) else {
+        return Ok(CorimEnvironment::default());
+    };
+    let class = as_map(class, "class-map")?;
+    Ok(CorimEnvironment {
+        class_id: map_get(class, CLASS_ID).map(identifier),
+        vendor: map_get(class, CLASS_VENDOR)
+            .and_then(Value::as_text)
+            .map(str::to_string),
+        model: map_get(class, CLASS_MODEL)
+            .and_then(Value::as_text)
+            .map(str::to_string),
+    })
+}
+
+/// Collect the TDX reference values of a measurement map. Measurements are named by their
+/// text key (`MRTD`, `MRSEAM`, `RTMR0` to `RTMR3`, `TEE_TCB_SVN`, `SEAM_SVN`, `QE_SVN`),
+/// RTMRs can also be given as integrity registers 0 to 3.
+fn parse_measurement(measurement: &Value, values: &mut TdxReferenceValues) -> Result<()> {
+    let map = as_map(measurement, "measurement-map")?;
+    let mval = map_get(map, MEASUREMENT_VALUES)
+        .ok_or_else(|| TdxError::Corim("Measurement has no values".to_string()))?;
+    let mval = as_map(mval, "measurement-values-map")?;
+
+    if let Some(registers) = map_get(mval, MVAL_INTEGRITY_REGISTERS) {
+        for (index, digests) in as_map(registers, "integrity-registers")? {
+            let index = match index {
+                Value::Integer(i) => i128::from(*i).to_string(),
+                Value::Text(t) => t.clone(),
+                _ => continue,
+            };
+            if let Some(field) = rtmr_field(values, &index) {
+                field.extend(sha384_digests(digests, &format!("RTMR{index}"))?);
+            }
+        }
+    }
+
+    let key = map_get(map, MEASUREMENT_KEY)
+        .and_then(Value::as_text)
+        .map(str::to_ascii_uppercase);
+    let Some(key) = key else {
+        return Ok(());
+    };
+    let field = match key.as_str() {
+        "MRTD" => Some(&mut values.mr_td),
+        "MRSEAM" => Some(&mut values.mr_seam),
+        "TEE_TCB_SVN" => {
+            let raw = map_get(mval, MVAL_RAW_VALUE)
+                .map(|raw| untag_ref(raw, TAG_BYTES))
+                .and_then(Value::as_bytes)
+                .ok_or_else(|| TdxError::Corim("TEE_TCB_SVN has no raw value".to_string()))?;
+            if raw.len() != 16 {
+                return Err(TdxError::Corim(format!(
+                    "TEE_TCB_SVN is {} bytes, expected 16",
+                    raw.len()
+                )));
+            }
+            values.min_tee_tcb_svn = raw.clone();
+            None
+        }
+        "SEAM_SVN" => {
+            values.min_seam_svn = Some(min_svn(mval, &key)?);
+            None
+        }
+        "QE_SVN" => {
+            values.min_qe_svn = Some(min_svn(mval, &key)?);
+            None
+        }
+        other => other
+            .strip_prefix("RTMR")
+            .and_then(|index| rtmr_field(values, index)),
+    };
+    if let (Some(field), Some(digests)) = (field, map_get(mval, MVAL_DIGESTS)) {
+        field.extend(sha384_digests(digests, &key)?);
+    }
+    Ok(())
+}
+
+/// Returns the minimum SVN of a measurement, given as an untagged or `tagged-min-svn` value.
+fn min_svn(mval: &[(Value, Value)], key: &str) -> Result<u64> {
+    let svn =
+        map_get(mval, MVAL_SVN).ok_or_else(|| TdxError::Corim(format!("{key} has no SVN")))?;
+    let svn = match svn {
+        Value::Tag(TAG_MIN_SVN, inner) => inner.as_ref(),
+        Value::Tag(TAG_SVN, _) => {
+            return Err(TdxError::Corim(format!(
+                "{key} is an exact SVN, only minimum SVNs are supported"
+            )))
+        }
+        svn => svn,
+    };
+    match svn {
+        Value::Integer(i) => u64::try_from(i128::from(*i))
+            .map_err(|_| TdxError::Corim(format!("{key} is not a valid SVN"))),
+        _ => Err(TdxError::Corim(format!("{key} is not an integer"))),
+    }
+}
+
+fn rtmr_field<'a>(values: &'a mut TdxReferenceValues, index: &str) -> Option<&'a mut Vec<Vec<u8>>> {
+    match index {
+        "0" => Some(&mut values.rtmr0),
+        "1" => Some(&mut values.rtmr1),
+        "2" => Some(&mut values.rtmr2),
+        "3" => Some(&mut values.rtmr3),
+        _ => None,
+    }
+}
+
+/// Returns the SHA-384 values of the digests array of measurement `name`, ignoring other
+/// algorithms. Fails if there are none: an empty reference list would accept any value.
+fn sha384_digests(digests: &Value, name: &str) -> Result<Vec<Vec<u8>>> {
+    let digests = digests
+        .as_array()
+        .ok_or_else(|| TdxError::Corim("Digests are not an array".to_string()))?;
+    let mut values = Vec::new();
+    for digest in digests {
+        let (alg, value) = match digest.as_array().map(Vec::as_slice) {
+            Some([alg, Value::Bytes(value)]) => (alg, value),
+            _ => return Err(TdxError::Corim("Malformed digest".to_string())),
+        };
+        let is_sha384 = match alg {
+            Value::Integer(i) => i128::from(*i) == ALG_SHA384,
+            Value::Text(name) => name.eq_ignore_ascii_case("sha-384"),
+            _ => false,
+        };
+        if !is_sha384 {
+            continue;
+        }
+        if value.len() != 48 {
+            return Err(TdxError::Corim(format!(
+                "SHA-384 digest is {} bytes",
+                value.len()
+            )));
+        }
+        values.push(value.clone());
+    }
+    if values.is_empty() {
+        return Err(TdxError::Corim(format!("{name} has no SHA-384 digest")));
+    }
+    Ok(values)
+}
+
+fn as_map<'a>(value: &'a Value, name: &str) -> Result<&'a Vec<(Value, Value)>> {
+    value
+        .as_map()
+        .ok_or_else(|| TdxError::Corim(format!("Expected a {name}")))
+}
+
+fn map_get(map: &[(Value, Value)], key: i128) -> Option<&Value> {
+    map.iter()
+        .find(|(k, _)| matches!(k, Value::Integer(i) if i128::from(*i) == key))
+        .map(|(_, v)| v)
+}
+
+fn untag(value: Value, tag: u64) -> Value {
+    match value {
+        Value::Tag(t, inner) if t == tag => *inner,
+        value => value,
+    }
+}
+
+fn untag_ref(value: &Value, tag: u64) -> &Value {
+    match value {
+        Value::Tag(t, inner) if *t == tag => inner,
+        value => value,
+    }
+}
+
+/// Render a CoRIM identifier (text, UUID, OID or bytes) as a string.
+fn identifier(value: &Value) -> String {
+    match value {
+        Value::Text(text) => text.clone(),
+        Value::Tag(TAG_UUID, inner) => match inner.as_bytes() {
+            Some(bytes) if bytes.len() == 16 => format_uuid(bytes),
+            _ => identifier(inner),
+        },
+        Value::Bytes(bytes) if bytes.len() == 16 => format_uuid(bytes),
+        Value::Bytes(bytes) => hex::encode(bytes),
+        Value::Tag(TAG_OID, inner) => match inner.as_bytes() {
+            Some(bytes) => format_oid(bytes),
+            None => identifier(inner),
+        },
+        Value::Tag(_, inner) => identifier(inner),
+        Value::Integer(i) => i128::from(*i).to_string(),
+        other => format!("{other:?}"),
+    }
+}
+
+fn format_uuid(bytes: &[u8]) -> String {
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Format a BER encoded OID (without tag and length) in dotted form.
+fn format_oid(bytes: &[u8]) -> String {
+    let mut arcs = Vec::new();
+    let mut arc: u64 = 0;
+    for byte in bytes {
+        arc = (arc << 7) | u64::from(byte & 0x7f);
+        if byte & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = (arc / 40).min(2);
+                arcs.push(first);
+                arcs.push(arc - first * 40);
+            } else {
+                arcs.push(arc);
+            }
+            arc = 0;
+        }
+    }
+    arcs.iter()
+        .map(u64::to_string)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verified_output::TcbStatus;
+
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+    /// ES256 COSE_Sign1 CoRIM whose CoMID has three reference triples for the TDX v4 test
+    /// quote: an `Acme`/`other-td` triple, an `Example`/`confidential-td` triple with all the
+    /// quote measurements and SVNs, and an `Example`/`strict-td` triple requiring higher SEAM
+    /// and QE SVNs.
+    const REFERENCE_CORIM: &[u8] = include_bytes!("../tests/data/reference.corim");
+    const CORIM_SIGNER: &[u8] = include_bytes!("../tests/data/corim_signer.pem");
+
+    fn verifier() -> CorimVerifier {
+        CorimVerifier::new().with_pem_key(CORIM_SIGNER).unwrap()
+    }
+
+    fn output() -> VerifiedOutput {
+        VerifiedOutput {
+            quote_version: 4,
+            tee_type: 0x81,
+            tcb_status: TcbStatus::UpToDate,
+            fmspc: [0; 6],
+            quote_body: TDX_V4_QUOTE[48..632].to_vec(),
+            advisory_ids: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parses_signed_corim() {
+        let corim = Corim::from_bytes(REFERENCE_CORIM, &verifier()).unwrap();
+        assert_eq!(corim.id, "corim-test");
+        assert!(corim.signed);
+        assert_eq!(corim.reference_triples.len(), 3);
+        let values = &corim.reference_triples[1].values;
+        assert_eq!(values.min_seam_svn, Some(8));
+        assert_eq!(values.min_qe_svn, Some(6));
+        assert_eq!(values.min_tee_tcb_svn.len(), 16);
+        assert!([&values.rtmr0, &values.rtmr1, &values.rtmr2, &values.rtmr3]
+            .iter()
+            .all(|rtmr| rtmr.len() == 1));
+    }
+
+    #[test]
+    fn rejects_bad_signatures() {
+        let other_key = concat!(
+            "-----BEGIN PUBLIC KEY-----\n",
+            "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEWzaJDay9fJqWu3Sh7iiz0tdbcuCa\n",
+            "IO8lz45v2KnwNQ0OFL7Y1GgqNNg1OL3/W5bommZm7A21dF0C+hIQBy33Wg==\n",
+            "-----END PUBLIC KEY-----\n",
+        );
+        let other = CorimVerifier::new()
+            .with_pem_key(other_key.as_bytes())
+            .unwrap();
+        assert!(Corim::from_bytes(REFERENCE_CORIM, &other).is_err());
+
+        // Flip a byte of the CoRIM id in the payload
+        let mut tampered = REFERENCE_CORIM.to_vec();
+        let offset = tampered
+            .windows(10)
+            .position(|w| w == b"corim-test")
+            .unwrap();
+        tampered[offset] ^= 1;
+        assert!(Corim::from_bytes(&tampered, &verifier()).is_err());
+    }
+
+    #[test]
+    fn appraises_against_the_environment_class() {
+        let corim = Corim::from_bytes(REFERENCE_CORIM, &verifier()).unwrap();
+        let output = output();
+
+        let triple = corim
+            .appraise(
+                TDX_V4_QUOTE,
+                &output,
+                &CorimEnvironment::new("Example", "confidential-td"),
+            )
+            .unwrap();
+        assert_eq!(triple.index, 1);
+        let triple = corim
+            .appraise(
+                TDX_V4_QUOTE,
+                &output,
+                &CorimEnvironment::new("Acme", "other-td"),
+            )
+            .unwrap();
+        assert_eq!(triple.index, 0);
+
+        let err = corim
+            .appraise(
+                TDX_V4_QUOTE,
+                &output,
+                &CorimEnvironment::new("Example", "strict-td"),
+            )
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("comid-test#0: environment"), "{err}");
+        assert!(err.contains("comid-test#2: seam_svn, qe_svn"), "{err}");
+
+        assert!(corim
+            .appraise(TDX_V4_QUOTE, &output, &CorimEnvironment::default())
+            .is_err());
+        let matching = corim
+            .matching_triples(
+                TDX_V4_QUOTE,
+                &output,
+                &CorimEnvironment {
+                    vendor: Some("Example".to_string()),
+                    ..CorimEnvironment::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(matching.len(), 1);
+    }
+
+    /// Unsigned CoRIM with one reference triple holding `measurement`.
+    fn unsigned_corim(measurement: Value) -> Vec<u8> {
+        let triple = Value::Array(vec![
+            Value::Map(Vec::new()),
+            Value::Array(vec![measurement]),
+        ]);
+        let comid = Value::Map(vec![
+            (
+                Value::from(COMID_TAG_IDENTITY as i64),
+                Value::Map(vec![(Value::from(0), Value::Text("comid".to_string()))]),
+            ),
+            (
+                Value::from(COMID_TRIPLES as i64),
+                Value::Map(vec![(Value::from(0), Value::Array(vec![triple]))]),
+            ),
+        ]);
+        let mut comid_bytes = Vec::new();
+        coset::cbor::ser::into_writer(&comid, &mut comid_bytes).unwrap();
+        let corim = Value::Tag(
+            TAG_UNSIGNED_CORIM,
+            Box::new(Value::Map(vec![
+                (Value::from(0), Value::Text("corim".to_string())),
+                (
+                    Value::from(1),
+                    Value::Array(vec![Value::Tag(
+                        TAG_COMID,
+                        Box::new(Value::Bytes(comid_bytes)),
+                    )]),
+                ),
+            ])),
+        );
+        let mut bytes = Vec::new();
+        coset::cbor::ser::into_writer(&corim, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn digests_measurement(key: &str, digests: Vec<(i64, usize)>) -> Value {
+        let digests = digests
+            .into_iter()
+            .map(|(alg, len)| Value::Array(vec![Value::from(alg), Value::Bytes(vec![0; len])]))
+            .collect();
+        Value::Map(vec![
+            (Value::from(0), Value::Text(key.to_string())),
+            (
+                Value::from(1),
+                Value::Map(vec![(Value::from(2), Value::Array(digests))]),
+            ),
+        ])
+    }
+
+    #[test]
+    fn requires_sha384_digests() {
+        let verifier = CorimVerifier::new().allow_unsigned(true);
+
+        let corim = unsigned_corim(digests_measurement("MRTD", vec![(1, 32), (7, 48)]));
+        let corim = Corim::from_bytes(&corim, &verifier).unwrap();
+        assert_eq!(corim.reference_triples[0].values.mr_td, vec![vec![0; 48]]);
+
+        // Only a sha-256 digest: an empty list would accept any MRTD
+        for key in ["MRTD", "MRSEAM", "RTMR2"] {
+            let corim = unsigned_corim(digests_measurement(key, vec![(1, 32)]));
+            let err = Corim::from_bytes(&corim, &verifier)
+                .unwrap_err()
+                .to_string();
+            assert!(
+                err.contains(&format!("{key} has no SHA-384 digest")),
+                "{err}"
+            );
+        }
+        let corim = unsigned_corim(digests_measurement("MRTD", Vec::new()));
+        assert!(Corim::from_bytes(&corim, &verifier).is_err());
+
+        let registers = Value::Map(vec![(
+            Value::from(1),
+            Value::Map(vec![(
+                Value::from(MVAL_INTEGRITY_REGISTERS as i64),
+                Value::Map(vec![(
+                    Value::from(3),
+                    Value::Array(vec![Value::Array(vec![
+                        Value::from(1),
+                        Value::Bytes(vec![0; 32]),
+                    ])]),
+                )]),
+            )]),
+        )]);
+        let err = Corim::from_bytes(&unsigned_corim(registers), &verifier)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("RTMR3 has no SHA-384 digest"), "{err}");
+    }
+
+    #[test]
+    fn rejects_quote_of_another_output() {
+        let corim = Corim::from_bytes(REFERENCE_CORIM, &verifier()).unwrap();
+        let mut output = output();
+        output.quote_body[520] ^= 1;
+        assert!(corim
+            .appraise(
+                TDX_V4_QUOTE,
+                &output,
+                &CorimEnvironment::new("Example", "confidential-td"),
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,618 @@
+use crate::cert_data::CERT_DATA_TYPE_PCK_CERT_CHAIN;
+use crate::pck::{get_pck_sgx_extensions_from_chain, SgxExtensions};
+use crate::quote::{EnclaveReport, QuoteBody, QuoteData, TdReport10};
+use serde::{Deserialize, Serialize};
+
+/// Category of a quote field, used to group the differences between two quotes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffCategory {
+    /// Quote version, TEE type, attestation key type and body type.
+    Header,
+    /// MRTD, RTMRs, MRCONFIGID, MROWNER and MROWNERCONFIG.
+    TdMeasurements,
+    /// TD attributes, XFAM and SEAM attributes.
+    TdAttributes,
+    /// TEE TCB SVNs and the TDX module identity (MRSEAM, MRSIGNERSEAM, MRSERVICETD).
+    TeeTcb,
+    /// Enclave identity and attributes of an SGX quote.
+    Enclave,
+    /// Identity of the quoting enclave and the QE / PCE SVNs of the header.
+    QeIdentity,
+    /// FMSPC, TCB and platform fields of the PCK certificate.
+    PckCertificate,
+    /// Fields expected to change between quotes: report data, signatures, keys and
+    /// certification data.
+    Volatile,
+}
+
+impl std::fmt::Display for DiffCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DiffCategory::Header => "Header",
+            DiffCategory::TdMeasurements => "TD measurements",
+            DiffCategory::TdAttributes => "TD attributes",
+            DiffCategory::TeeTcb => "TEE TCB",
+            DiffCategory::Enclave => "Enclave",
+            DiffCategory::QeIdentity => "QE identity",
+            DiffCategory::PckCertificate => "PCK certificate",
+            DiffCategory::Volatile => "Volatile",
+        })
+    }
+}
+
+/// A field that differs between two quotes. Byte fields are hex-encoded, and a side is
+/// `None` when the field is absent from that quote.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldDiff {
+    pub category: DiffCategory,
+    pub field: String,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+/// Options of [`diff_quotes`].
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Also report fields in [`DiffCategory::Volatile`].
+    pub include_volatile: bool,
+}
+
+/// The differences between two quotes, ordered by category.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteDiff {
+    pub fields: Vec<FieldDiff>,
+}
+
+impl QuoteDiff {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns the differences in `category`.
+    pub fn category(&self, category: DiffCategory) -> impl Iterator<Item = &FieldDiff> {
+        self.fields.iter().filter(move |f| f.category == category)
+    }
+}
+
+impl std::fmt::Display for QuoteDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut current = None;
+        for diff in &self.fields {
+            if current != Some(diff.category) {
+                writeln!(f, "[{}]", diff.category)?;
+                current = Some(diff.category);
+            }
+            writeln!(f, "  {}:", diff.field)?;
+            writeln!(f, "    - {}", diff.left.as_deref().unwrap_or("<absent>"))?;
+            writeln!(f, "    + {}", diff.right.as_deref().unwrap_or("<absent>"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare two quotes field by field.
+///
+/// Volatile fields (report data, signatures, attestation key, certification data) are only
+/// compared when [`DiffOptions::include_volatile`] is set. The PCK certificate is compared
+/// when both quotes carry a PCK certificate chain.
+pub fn diff_quotes(left: &QuoteData, right: &QuoteData, options: &DiffOptions) -> QuoteDiff {
+    let mut differ = Differ {
+        fields: Vec::new(),
+        include_volatile: options.include_volatile,
+    };
+
+    let (l, r) = (&left.header, &right.header);
+    differ.value(DiffCategory::Header, "version", l.version, r.version);
+    differ.value(DiffCategory::Header, "tee_type", l.tee_type, r.tee_type);
+    differ.value(
+        DiffCategory::Header,
+        "attestation_key_type",
+        l.attestation_key_type,
+        r.attestation_key_type,
+    );
+    differ.value(
+        DiffCategory::Header,
+        "body_type",
+        body_type(&left.body),
+        body_type(&right.body),
+    );
+    differ.value(DiffCategory::QeIdentity, "qe_svn", l.qe_svn, r.qe_svn);
+    differ.value(DiffCategory::QeIdentity, "pce_svn", l.pce_svn, r.pce_svn);
+    differ.bytes(
+        DiffCategory::QeIdentity,
+        "qe_vendor_id",
+        &l.qe_vendor_id,
+        &r.qe_vendor_id,
+    );
+    differ.bytes(
+        DiffCategory::Volatile,
+        "user_data",
+        &l.user_data,
+        &r.user_data,
+    );
+
+    match (&left.body, &right.body) {
+        (QuoteBody::Sgx(l), QuoteBody::Sgx(r)) => differ.enclave(l, r),
+        (l, r) => {
+            if let (Some(l), Some(r)) = (l.td_report(), r.td_report()) {
+                differ.td_report(l, r);
+            }
+            let (l, r) = (td15_fields(l), td15_fields(r));
+            if l != r {
+                differ.optional(
+                    DiffCategory::TeeTcb,
+                    "tee_tcb_svn2",
+                    l.as_ref().map(|f| f.0.clone()),
+                    r.as_ref().map(|f| f.0.clone()),
+                );
+                differ.optional(
+                    DiffCategory::TeeTcb,
+                    "mr_servicetd",
+                    l.map(|f| f.1),
+                    r.map(|f| f.1),
+                );
+            }
+        }
+    }
+    differ.bytes(
+        DiffCategory::Volatile,
+        "report_data",
+        left.report_data(),
+        right.report_data(),
+    );
+
+    let (l, r) = (&left.signature, &right.signature);
+    let (lq, rq) = (&l.qe_report, &r.qe_report);
+    differ.bytes(
+        DiffCategory::QeIdentity,
+        "qe_mr_enclave",
+        &lq.mr_enclave,
+        &rq.mr_enclave,
+    );
+    differ.bytes(
+        DiffCategory::QeIdentity,
+        "qe_mr_signer",
+        &lq.mr_signer,
+        &rq.mr_signer,
+    );
+    differ.value(
+        DiffCategory::QeIdentity,
+        "qe_isv_prod_id",
+        lq.isv_prod_id,
+        rq.isv_prod_id,
+    );
+    differ.value(
+        DiffCategory::QeIdentity,
+        "qe_isv_svn",
+        lq.isv_svn,
+        rq.isv_svn,
+    );
+    differ.bytes(
+        DiffCategory::QeIdentity,
+        "qe_attributes",
+        &lq.attributes,
+        &rq.attributes,
+    );
+    differ.bytes(
+        DiffCategory::QeIdentity,
+        "qe_misc_select",
+        &lq.misc_select.to_le_bytes(),
+        &rq.misc_select.to_le_bytes(),
+    );
+    differ.bytes(
+        DiffCategory::Volatile,
+        "qe_report_data",
+        &lq.report_data,
+        &rq.report_data,
+    );
+    differ.bytes(
+        DiffCategory::Volatile,
+        "signature",
+        &l.signature,
+        &r.signature,
+    );
+    differ.bytes(
+        DiffCategory::Volatile,
+        "attestation_key",
+        &l.attestation_key,
+        &r.attestation_key,
+    );
+    differ.bytes(
+        DiffCategory::Volatile,
+        "qe_report_signature",
+        &l.qe_report_signature,
+        &r.qe_report_signature,
+    );
+    differ.bytes(
+        DiffCategory::Volatile,
+        "qe_auth_data",
+        &l.qe_auth_data,
+        &r.qe_auth_data,
+    );
+    differ.value(
+        DiffCategory::Volatile,
+        "cert_data_type",
+        l.cert_data_type,
+        r.cert_data_type,
+    );
+    differ.bytes(
+        DiffCategory::Volatile,
+        "cert_data",
+        &l.cert_data,
+        &r.cert_data,
+    );
+
+    if let (Some(l), Some(r)) = (pck_extensions(left), pck_extensions(right)) {
+        differ.pck(&l, &r);
+    }
+
+    differ.fields.sort_by_key(|f| f.category);
+    QuoteDiff {
+        fields: differ.fields,
+    }
+}
+
+struct Differ {
+    fields: Vec<FieldDiff>,
+    include_volatile: bool,
+}
+
+impl Differ {
+    fn optional(
+        &mut self,
+        category: DiffCategory,
+        field: &str,
+        left: Option<String>,
+        right: Option<String>,
+    ) {
+        if left == right || (category == DiffCategory::Volatile && !self.include_volatile) {
+            return;
+        }
+        self.fields.push(FieldDiff {
+            category,
+            field: field.to_string(),
+            left,
+            right,
+        });
+    }
+
+    fn value<T: PartialEq + ToString>(&mut self, category: DiffCategory, field: &str, l: T, r: T) {
+        if l != r {
+            self.optional(category, field, Some(l.to_string()), Some(r.to_string()));
+        }
+    }
+
+    fn bytes(&mut self, category: DiffCategory, field: &str, l: &[u8], r: &[u8]) {
+        if l != r {
+            self.optional(category, field, Some(hex::encode(l)), Some(hex::encode(r)));
+        }
+    }
+
+    fn enclave(&mut self, l: &EnclaveReport, r: &EnclaveReport) {
+        let c = DiffCategory::Enclave;
+        self.bytes(c, "mr_enclave", &l.mr_enclave, &r.mr_enclave);
+        self.bytes(c, "mr_signer", &l.mr_signer, &r.mr_signer);
+        self.value(c, "isv_prod_id", l.isv_prod_id, r.isv_prod_id);
+        self.value(c, "isv_svn", l.isv_svn, r.isv_svn);
+        self.bytes(c, "attributes", &l.attributes, &r.attributes);
+        self.bytes(
+            c,
+            "misc_select",
+            &l.misc_select.to_le_bytes(),
+            &r.misc_select.to_le_bytes(),
+        );
+        self.bytes(c, "cpu_svn", &l.cpu_svn, &r.cpu_svn);
+    }
+
+    fn td_report(&mut self, l: &TdReport10, r: &TdReport10) {
+        let c = DiffCategory::TdMeasurements;
+        self.bytes(c, "mr_td", &l.mr_td, &r.mr_td);
+        self.bytes(c, "rtmr0", &l.rtmr0, &r.rtmr0);
+        self.bytes(c, "rtmr1", &l.rtmr1, &r.rtmr1);
+        self.bytes(c, "rtmr2", &l.rtmr2, &r.rtmr2);
+        self.bytes(c, "rtmr3", &l.rtmr3, &r.rtmr3);
+        self.bytes(c, "mr_config_id", &l.mr_config_id, &r.mr_config_id);
+        self.bytes(c, "mr_owner", &l.mr_owner, &r.mr_owner);
+        self.bytes(c, "mr_owner_config", &l.mr_owner_config, &r.mr_owner_config);
+
+        let c = DiffCategory::TdAttributes;
+        self.bytes(c, "td_attributes", &l.td_attributes, &r.td_attributes);
+        self.bytes(c, "xfam", &l.xfam, &r.xfam);
+        self.bytes(c, "seam_attributes", &l.seam_attributes, &r.seam_attributes);
+
+        let c = DiffCategory::TeeTcb;
+        self.bytes(c, "tee_tcb_svn", &l.tee_tcb_svn, &r.tee_tcb_svn);
+        self.bytes(c, "mr_seam", &l.mr_seam, &r.mr_seam);
+        self.bytes(c, "mr_signer_seam", &l.mr_signer_seam, &r.mr_signer_seam);
+    }
+
+    fn pck(&mut self, l: &SgxExtensions, r: &SgxExtensions) {
+        let c = DiffCategory::PckCertificate;
+        self.bytes(c, "fmspc", &l.fmspc, &r.fmspc);
+        self.bytes(c, "pce_id", &l.pce_id, &r.pce_id);
+        self.value(
+            c,
+            "sgx_type",
+            format!("{:?}", l.sgx_type),
+            format!("{:?}", r.sgx_type),
+        );
+        self.bytes(
+            c,
+            "sgx_tcb_comp_svn",
+            &l.tcb.sgx_tcb_comp_svn,
+            &r.tcb.sgx_tcb_comp_svn,
+        );
+        self.value(c, "pcesvn", l.tcb.pcesvn, r.tcb.pcesvn);
+        self.bytes(c, "cpusvn", &l.tcb.cpusvn, &r.tcb.cpusvn);
+        self.bytes(c, "ppid", &l.ppid, &r.ppid);
+        self.optional(
+            c,
+            "platform_instance_id",
+            l.platform_instance_id.map(hex::encode),
+            r.platform_instance_id.map(hex::encode),
+        );
+        self.optional(
+            c,
+            "configuration",
+            l.configuration.as_ref().map(|config| format!("{config:?}")),
+            r.configuration.as_ref().map(|config| format!("{config:?}")),
+        );
+    }
+}
+
+fn body_type(body: &QuoteBody) -> &'static str {
+    match body {
+        QuoteBody::Sgx(_) => "sgx",
+        QuoteBody::Td10(_) => "td10",
+        QuoteBody::Td15(_) => "td15",
+    }
+}
+
+/// Hex-encoded TEE_TCB_SVN2 and MRSERVICETD of a TDX 1.5 body.
+fn td15_fields(body: &QuoteBody) -> Option<(String, String)> {
+    match body {
+        QuoteBody::Td15(report) => Some((
+            hex::encode(report.tee_tcb_svn2),
+            hex::encode(report.mr_servicetd),
+        )),
+        _ => None,
+    }
+}
+
+fn pck_extensions(quote: &QuoteData) -> Option<SgxExtensions> {
+    if quote.signature.cert_data_type != CERT_DATA_TYPE_PCK_CERT_CHAIN {
+        return None;
+    }
+    get_pck_sgx_extensions_from_chain(&quote.signature.cert_data).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SGX_V3_QUOTE: &[u8] = include_bytes!("../examples/testdata/sgx_v3_quote.bin");
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+    const TDX_V5_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v5_quote.bin");
+
+    fn quote(raw: &[u8]) -> QuoteData {
+        QuoteData::from_bytes(raw).unwrap()
+    }
+
+    fn compare(left: &QuoteData, right: &QuoteData) -> QuoteDiff {
+        diff_quotes(left, right, &DiffOptions::default())
+    }
+
+    fn fields(diff: &QuoteDiff, category: DiffCategory) -> Vec<&str> {
+        diff.category(category).map(|f| f.field.as_str()).collect()
+    }
+
+    #[test]
+    fn finds_no_difference_between_equal_quotes() {
+        for raw in [SGX_V3_QUOTE, TDX_V4_QUOTE, TDX_V5_QUOTE] {
+            let options = DiffOptions {
+                include_volatile: true,
+            };
+            assert!(diff_quotes(&quote(raw), &quote(raw), &options).is_empty());
+        }
+    }
+
+    #[test]
+    fn diffs_td_measurements() {
+        let left = quote(TDX_V4_QUOTE);
+        let mut right = left.clone();
+        let report = right.td_report_mut().unwrap();
+        report.mr_td[0] ^= 1;
+        report.rtmr2 = [0xaa; 48];
+        report.mr_owner[47] ^= 1;
+
+        let diff = compare(&left, &right);
+        assert_eq!(diff.fields.len(), 3, "{diff}");
+        assert_eq!(
+            fields(&diff, DiffCategory::TdMeasurements),
+            ["mr_td", "rtmr2", "mr_owner"]
+        );
+        let rtmr2 = &diff.fields[1];
+        assert_eq!(
+            rtmr2.left.as_deref(),
+            Some(hex::encode(left.td_report().unwrap().rtmr2).as_str())
+        );
+        assert_eq!(rtmr2.right, Some("aa".repeat(48)));
+    }
+
+    #[test]
+    fn diffs_td_attributes_and_tee_tcb() {
+        let left = quote(TDX_V4_QUOTE);
+        let mut right = left.clone();
+        let report = right.td_report_mut().unwrap();
+        report.td_attributes[0] ^= 1;
+        report.tee_tcb_svn[0] = report.tee_tcb_svn[0].wrapping_add(1);
+        report.mr_seam[0] ^= 1;
+        report.mr_signer_seam[0] ^= 1;
+
+        let diff = compare(&left, &right);
+        assert_eq!(fields(&diff, DiffCategory::TdAttributes), ["td_attributes"]);
+        assert_eq!(
+            fields(&diff, DiffCategory::TeeTcb),
+            ["tee_tcb_svn", "mr_seam", "mr_signer_seam"]
+        );
+        assert_eq!(diff.fields.len(), 4, "{diff}");
+        // ordered by category
+        assert_eq!(diff.fields[0].category, DiffCategory::TdAttributes);
+    }
+
+    #[test]
+    fn diffs_qe_identity() {
+        let left = quote(TDX_V4_QUOTE);
+        let mut right = left.clone();
+        right.header.qe_svn += 1;
+        right.signature.qe_report.isv_svn += 1;
+        right.signature.qe_report.mr_signer[0] ^= 1;
+
+        let diff = compare(&left, &right);
+        assert_eq!(
+            fields(&diff, DiffCategory::QeIdentity),
+            ["qe_svn", "qe_mr_signer", "qe_isv_svn"]
+        );
+        assert_eq!(diff.fields.len(), 3, "{diff}");
+        assert_eq!(diff.fields[0].left, Some(left.header.qe_svn.to_string()));
+        assert_eq!(diff.fields[0].right, Some(right.header.qe_svn.to_string()));
+    }
+
+    #[test]
+    fn diffs_pck_certificates() {
+        let left = quote(TDX_V4_QUOTE);
+        let mut right = left.clone();
+        // The PCK certificate chain of another platform
+        right.set_pck_cert_chain(&quote(SGX_V3_QUOTE).signature.cert_data);
+
+        let diff = compare(&left, &right);
+        assert!(diff
+            .fields
+            .iter()
+            .all(|f| f.category == DiffCategory::PckCertificate));
+        let pck = fields(&diff, DiffCategory::PckCertificate);
+        assert!(pck.contains(&"fmspc") && pck.contains(&"ppid"), "{pck:?}");
+        let fmspc = diff.category(DiffCategory::PckCertificate).next().unwrap();
+        assert_eq!(fmspc.left.as_deref(), Some("00806f050000"));
+        assert_eq!(fmspc.right.as_deref(), Some("00606a000000"));
+
+        let options = DiffOptions {
+            include_volatile: true,
+        };
+        let diff = diff_quotes(&left, &right, &options);
+        assert_eq!(fields(&diff, DiffCategory::Volatile), ["cert_data"]);
+
+        // Without a PCK certificate chain on both sides, the certificates are not compared
+        right.signature.cert_data_type = 1;
+        let diff = compare(&left, &right);
+        assert!(diff.is_empty(), "{diff}");
+    }
+
+    #[test]
+    fn hides_volatile_fields_unless_included() {
+        let left = quote(TDX_V4_QUOTE);
+        let mut right = left.clone();
+        right.td_report_mut().unwrap().report_data = [0x42; 64];
+        right.header.user_data[0] ^= 1;
+        right.signature.signature[0] ^= 1;
+        right.signature.qe_report.report_data[0] ^= 1;
+        right.signature.qe_auth_data.push(0);
+
+        assert!(compare(&left, &right).is_empty());
+
+        let options = DiffOptions {
+            include_volatile: true,
+        };
+        let diff = diff_quotes(&left, &right, &options);
+        assert_eq!(
+            fields(&diff, DiffCategory::Volatile),
+            [
+                "user_data",
+                "report_data",
+                "qe_report_data",
+                "signature",
+                "qe_auth_data"
+            ]
+        );
+        assert_eq!(diff.fields.len(), 5, "{diff}");
+        assert_eq!(diff.fields[1].right, Some("42".repeat(64)));
+    }
+
+    #[test]
+    fn diffs_td10_and_td15_bodies() {
+        let td10 = quote(TDX_V4_QUOTE);
+        let td15 = quote(TDX_V5_QUOTE);
+        let diff = compare(&td10, &td15);
+        assert!(fields(&diff, DiffCategory::Header).contains(&"body_type"));
+        let body_type = diff
+            .category(DiffCategory::Header)
+            .find(|f| f.field == "body_type")
+            .unwrap();
+        assert_eq!(body_type.left.as_deref(), Some("td10"));
+        assert_eq!(body_type.right.as_deref(), Some("td15"));
+        // Fields only a TDX 1.5 body has are absent on the TDX 1.0 side
+        let svn2 = diff
+            .category(DiffCategory::TeeTcb)
+            .find(|f| f.field == "tee_tcb_svn2")
+            .unwrap();
+        assert_eq!(svn2.left, None);
+        assert!(svn2.right.is_some());
+        let mr_servicetd = diff
+            .category(DiffCategory::TeeTcb)
+            .find(|f| f.field == "mr_servicetd")
+            .unwrap();
+        assert_eq!(mr_servicetd.left, None);
+        assert!(diff.to_string().contains("    - <absent>"));
+        // The TD report fields shared by both bodies are still compared
+        assert!(fields(&diff, DiffCategory::TdMeasurements).contains(&"mr_td"));
+
+        let mut right = td15.clone();
+        match &mut right.body {
+            QuoteBody::Td15(report) => {
+                report.tee_tcb_svn2[0] ^= 1;
+                report.td10.rtmr0[0] ^= 1;
+            }
+            _ => panic!("expected a TDX 1.5 body"),
+        }
+        let diff = compare(&td15, &right);
+        assert_eq!(fields(&diff, DiffCategory::TdMeasurements), ["rtmr0"]);
+        assert_eq!(fields(&diff, DiffCategory::TeeTcb), ["tee_tcb_svn2"]);
+        assert_eq!(diff.fields.len(), 2, "{diff}");
+    }
+
+    #[test]
+    fn diffs_sgx_and_tdx_quotes() {
+        let sgx = quote(SGX_V3_QUOTE);
+        let tdx = quote(TDX_V4_QUOTE);
+        let diff = compare(&sgx, &tdx);
+        assert_eq!(
+            fields(&diff, DiffCategory::Header),
+            ["version", "tee_type", "body_type"]
+        );
+        // Bodies of different TEEs have no fields in common
+        for category in [
+            DiffCategory::TdMeasurements,
+            DiffCategory::TdAttributes,
+            DiffCategory::TeeTcb,
+            DiffCategory::Enclave,
+        ] {
+            assert_eq!(diff.category(category).count(), 0, "{diff}");
+        }
+
+        let mut right = sgx.clone();
+        match &mut right.body {
+            QuoteBody::Sgx(report) => {
+                report.mr_enclave[0] ^= 1;
+                report.isv_svn += 1;
+            }
+            _ => panic!("expected an SGX body"),
+        }
+        let diff = compare(&sgx, &right);
+        assert_eq!(
+            fields(&diff, DiffCategory::Enclave),
+            ["mr_enclave", "isv_svn"]
+        );
+        assert_eq!(diff.fields.len(), 2, "{diff}");
+    }
+}
@@ -1,13 +1,19 @@
-use coco_provider::error::CocoError;
-
 pub type Result<T> = std::result::Result<T, TdxError>;
 
 #[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
 pub enum TdxError {
     #[error("Anyhow: {0}")]
     Anyhow(String),
+    #[error("Broker: {0}")]
+    Broker(String),
+    #[error("Channel: {0}")]
+    Channel(String),
     #[error("ConfigOptions: {0}")]
     ConfigOptions(String),
+    #[error("Contract: {0}")]
+    Contract(String),
+    #[error("Corim: {0}")]
+    Corim(String),
     #[error("Cpu: {0}")]
     Cpu(String),
     #[error("Dcap: {0}")]
@@ -18,6 +24,10 @@ pub enum TdxError {
     Http(String),
     #[error("IO: {0}")]
     IO(String),
+    #[error("Kbs: {0}")]
+    Kbs(String),
+    #[error("Nonce: {0}")]
+    Nonce(String),
     #[error("SSL: {0}")]
     SSL(String),
     #[error("Tpm: {0}")]
@@ -28,8 +38,9 @@ pub enum TdxError {
     Unknown,
 }
 
-impl From<CocoError> for TdxError {
-    fn from(err: CocoError) -> Self {
+#[cfg(feature = "coco-provider")]
+impl From<coco_provider::error::CocoError> for TdxError {
+    fn from(err: coco_provider::error::CocoError) -> Self {
         TdxError::Firmware(err.to_string())
     }
 }
@@ -46,6 +57,7 @@ impl From<std::io::Error> for TdxError {
     }
 }
 
+#[cfg(feature = "http")]
 impl From<ureq::Error> for TdxError {
     fn from(err: ureq::Error) -> Self {
         TdxError::Http(err.to_string())
@@ -0,0 +1,243 @@
+//! Single-shot HPKE (RFC 9180) in base mode with DHKEM(X25519, HKDF-SHA256), HKDF-SHA256 and
+//! ChaCha20Poly1305.
+
+use crate::error::{Result, TdxError};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const KEM_ID: u16 = 0x0020;
+const KDF_ID: u16 = 0x0001;
+const AEAD_ID: u16 = 0x0003;
+const MODE_BASE: u8 = 0x00;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// Length of the encapsulated key sent along with the ciphertext.
+pub const ENC_LEN: usize = 32;
+
+/// An X25519 key pair to receive HPKE messages.
+#[derive(Clone)]
+pub struct HpkeKeyPair {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl std::fmt::Debug for HpkeKeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HpkeKeyPair")
+            .field("public", &hex::encode(self.public.as_bytes()))
+            .finish_non_exhaustive()
+    }
+}
+
+impl HpkeKeyPair {
+    pub fn generate() -> Self {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        HpkeKeyPair { secret, public }
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.public.to_bytes()
+    }
+
+    /// Decrypt a message sealed to this key pair with [`seal`].
+    pub fn open(&self, enc: &[u8], info: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let enc: [u8; ENC_LEN] = enc.try_into().map_err(|_| {
+            TdxError::Broker(format!(
+                "Invalid HPKE encapsulated key length: {}",
+                enc.len()
+            ))
+        })?;
+        let dh = self.secret.diffie_hellman(&PublicKey::from(enc));
+        if !dh.was_contributory() {
+            return Err(TdxError::Broker(
+                "Invalid HPKE encapsulated key".to_string(),
+            ));
+        }
+        let shared_secret = extract_and_expand(dh.as_bytes(), &enc, self.public.as_bytes())?;
+        let (key, nonce) = key_schedule(&shared_secret, info)?;
+        ChaCha20Poly1305::new(&key.into())
+            .decrypt(
+                &nonce.into(),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .map_err(|_| TdxError::Broker("HPKE decryption failed".to_string()))
+    }
+}
+
+/// Encrypt `plaintext` to `public_key`, returning the encapsulated key and the ciphertext.
+pub fn seal(
+    public_key: &[u8],
+    info: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<([u8; ENC_LEN], Vec<u8>)> {
+    let public_key: [u8; 32] = public_key.try_into().map_err(|_| {
+        TdxError::Broker(format!(
+            "Invalid X25519 public key length: {}",
+            public_key.len()
+        ))
+    })?;
+    let ephemeral = StaticSecret::random_from_rng(OsRng);
+    let enc = PublicKey::from(&ephemeral).to_bytes();
+    let dh = ephemeral.diffie_hellman(&PublicKey::from(public_key));
+    if !dh.was_contributory() {
+        return Err(TdxError::Broker("Invalid X25519 public key".to_string()));
+    }
+    let shared_secret = extract_and_expand(dh.as_bytes(), &enc, &public_key)?;
+    let (key, nonce) = key_schedule(&shared_secret, info)?;
+    let ciphertext = ChaCha20Poly1305::new(&key.into())
+        .encrypt(
+            &nonce.into(),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| TdxError::Broker("HPKE encryption failed".to_string()))?;
+    Ok((enc, ciphertext))
+}
+
+/// DHKEM ExtractAndExpand, with the KEM context `enc || pkR`.
+fn extract_and_expand(dh: &[u8], enc: &[u8], public_key: &[u8]) -> Result<[u8; 32]> {
+    let suite_id = kem_suite_id();
+    let prk = labeled_extract(&suite_id, b"", b"eae_prk", dh);
+    let kem_context = [enc, public_key].concat();
+    let mut shared_secret = [0u8; 32];
+    labeled_expand(
+        &suite_id,
+        &prk,
+        b"shared_secret",
+        &kem_context,
+        &mut shared_secret,
+    )?;
+    Ok(shared_secret)
+}
+
+/// Base mode key schedule, returning the AEAD key and the nonce of the first message.
+fn key_schedule(shared_secret: &[u8], info: &[u8]) -> Result<([u8; KEY_LEN], [u8; NONCE_LEN])> {
+    let suite_id = hpke_suite_id();
+    let psk_id_hash = labeled_extract(&suite_id, b"", b"psk_id_hash", b"");
+    let info_hash = labeled_extract(&suite_id, b"", b"info_hash", info);
+    let context = [&[MODE_BASE][..], &psk_id_hash[..], &info_hash[..]].concat();
+    let secret = labeled_extract(&suite_id, shared_secret, b"secret", b"");
+
+    let mut key = [0u8; KEY_LEN];
+    labeled_expand(&suite_id, &secret, b"key", &context, &mut key)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    labeled_expand(&suite_id, &secret, b"base_nonce", &context, &mut nonce)?;
+    Ok((key, nonce))
+}
+
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> Vec<u8> {
+    let labeled_ikm = [&b"HPKE-v1"[..], suite_id, label, ikm].concat();
+    let (prk, _) = Hkdf::<Sha256>::extract(Some(salt), &labeled_ikm);
+    prk.to_vec()
+}
+
+fn labeled_expand(
+    suite_id: &[u8],
+    prk: &[u8],
+    label: &[u8],
+    info: &[u8],
+    out: &mut [u8],
+) -> Result<()> {
+    let length = (out.len() as u16).to_be_bytes();
+    let labeled_info = [&length[..], &b"HPKE-v1"[..], suite_id, label, info].concat();
+    let error = || TdxError::Broker("HPKE key derivation failed".to_string());
+    let hkdf = Hkdf::<Sha256>::from_prk(prk).map_err(|_| error())?;
+    hkdf.expand(&labeled_info, out).map_err(|_| error())
+}
+
+fn kem_suite_id() -> Vec<u8> {
+    [&b"KEM"[..], &KEM_ID.to_be_bytes()[..]].concat()
+}
+
+fn hpke_suite_id() -> Vec<u8> {
+    [
+        &b"HPKE"[..],
+        &KEM_ID.to_be_bytes()[..],
+        &KDF_ID.to_be_bytes()[..],
+        &AEAD_ID.to_be_bytes()[..],
+    ]
+    .concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 9180, A.2.1: DHKEM(X25519, HKDF-SHA256), HKDF-SHA256, ChaCha20Poly1305, base mode
+    const SK_R: &str = "8057991eef8f1f1af18f4a9491d16a1ce333f695d4db8e38da75975c4478e0fb";
+    const PK_R: &str = "4310ee97d88cc1f088a5576c77ab0cf5c3ac797f3d95139c6c84b5429c59662a";
+    const ENC: &str = "1afa08d3dec047a643885163f1180476fa7ddb54c6a8029ea33f95796bf2ac4a";
+    const SHARED_SECRET: &str = "0bbe78490412b4bbea4812666f7916932b828bba79942424abb65244930d69a7";
+    const KEY: &str = "ad2744de8e17f4ebba575b3f5f5a8fa1f69c2a07f6e7500bc60ca6e3e3ec1c91";
+    const BASE_NONCE: &str = "5c4d98150661b848853b547f";
+    const INFO: &str = "4f6465206f6e2061204772656369616e2055726e";
+    const PT: &str = "4265617574792069732074727574682c20747275746820626561757479";
+    const AAD: &str = "436f756e742d30";
+    const CT: &str = "1c5250d8034ec2b784ba2cfd69dbdb8af406cfe3ff938e131f0def8c8b60b4db21993c62ce81883d2dd1b51a28";
+
+    fn recipient() -> HpkeKeyPair {
+        let secret: [u8; 32] = hex::decode(SK_R).unwrap().try_into().unwrap();
+        let secret = StaticSecret::from(secret);
+        let public = PublicKey::from(&secret);
+        HpkeKeyPair { secret, public }
+    }
+
+    #[test]
+    fn rfc9180_key_schedule() {
+        let recipient = recipient();
+        assert_eq!(hex::encode(recipient.public_key()), PK_R);
+
+        let enc = hex::decode(ENC).unwrap();
+        let pk_r = recipient.public_key();
+        let dh = recipient
+            .secret
+            .diffie_hellman(&PublicKey::from(<[u8; 32]>::try_from(&enc[..]).unwrap()));
+        let shared_secret = extract_and_expand(dh.as_bytes(), &enc, &pk_r).unwrap();
+        assert_eq!(hex::encode(shared_secret), SHARED_SECRET);
+
+        let (key, nonce) = key_schedule(&shared_secret, &hex::decode(INFO).unwrap()).unwrap();
+        assert_eq!(hex::encode(key), KEY);
+        assert_eq!(hex::encode(nonce), BASE_NONCE);
+    }
+
+    #[test]
+    fn rfc9180_open() {
+        let plaintext = recipient()
+            .open(
+                &hex::decode(ENC).unwrap(),
+                &hex::decode(INFO).unwrap(),
+                &hex::decode(AAD).unwrap(),
+                &hex::decode(CT).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(hex::encode(plaintext), PT);
+    }
+
+    #[test]
+    fn seal_then_open() {
+        let recipient = HpkeKeyPair::generate();
+        let (enc, ciphertext) = seal(&recipient.public_key(), b"info", b"aad", b"secret").unwrap();
+        let plaintext = recipient.open(&enc, b"info", b"aad", &ciphertext).unwrap();
+        assert_eq!(plaintext, b"secret");
+
+        assert!(recipient
+            .open(&enc, b"info", b"other", &ciphertext)
+            .is_err());
+        assert!(recipient
+            .open(&enc[1..], b"info", b"aad", &ciphertext)
+            .is_err());
+        assert!(seal(&[0; 32], b"info", b"aad", b"secret").is_err());
+    }
+}
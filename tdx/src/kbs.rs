@@ -0,0 +1,573 @@
+//! Attester client for the Confidential Containers Key Broker Service (KBS), speaking the RCAR
+//! protocol (Request, Challenge, Attestation, Response) of KBS protocol version 0.1.0.
+
+use crate::device::DeviceOptions;
+use crate::error::{Result, TdxError};
+use crate::Tdx;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use aes_kw::KekAes256;
+use base64_url::base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{EncodedPoint, FieldBytes, PublicKey, SecretKey};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256, Sha384};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub const KBS_PROTOCOL_VERSION: &str = "0.1.0";
+/// Where the kernel exposes the ACPI CC event log of the TD.
+pub const CCEL_PATH: &str = "/sys/firmware/acpi/tables/data/CCEL";
+const SESSION_COOKIE: &str = "kbs-session-id";
+const KEY_ALG: &str = "ECDH-ES+A256KW";
+const CONTENT_ALG: &str = "A256GCM";
+
+/// Generates a quote over the given report data, in place of the local TDX device.
+pub type QuoteFn = dyn Fn(&[u8; 64]) -> Result<Vec<u8>> + Send + Sync;
+
+#[derive(Serialize)]
+struct AuthRequest<'a> {
+    version: &'a str,
+    tee: &'a str,
+    #[serde(rename = "extra-params")]
+    extra_params: &'a str,
+}
+
+/// Response of `POST /kbs/v0/auth`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Challenge {
+    pub nonce: String,
+    #[serde(rename = "extra-params", default)]
+    pub extra_params: serde_json::Value,
+}
+
+/// An EC P-256 public key as a JWK, which the KBS encrypts resources to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TeePubKey {
+    pub kty: String,
+    pub crv: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub alg: String,
+    pub x: String,
+    pub y: String,
+}
+
+impl TeePubKey {
+    fn to_public_key(&self) -> Result<PublicKey> {
+        if self.kty != "EC" || self.crv != "P-256" {
+            return Err(TdxError::Kbs(format!(
+                "Unsupported key type {} {}",
+                self.kty, self.crv
+            )));
+        }
+        let x = base64_url::decode(&self.x)?;
+        let y = base64_url::decode(&self.y)?;
+        if x.len() != 32 || y.len() != 32 {
+            return Err(TdxError::Kbs("Invalid P-256 coordinates".to_string()));
+        }
+        let point = EncodedPoint::from_affine_coordinates(
+            FieldBytes::from_slice(&x),
+            FieldBytes::from_slice(&y),
+            false,
+        );
+        PublicKey::from_sec1_bytes(point.as_bytes())
+            .map_err(|_| TdxError::Kbs("Invalid P-256 public key".to_string()))
+    }
+}
+
+/// TDX evidence as expected by the KBS TDX verifier, with base64-encoded fields.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TdxEvidence {
+    /// ACPI CC event log (CCEL)
+    pub cc_eventlog: Option<String>,
+    pub quote: String,
+    /// Attestation agent event log
+    pub aa_eventlog: Option<String>,
+}
+
+/// Body of `POST /kbs/v0/attest`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    #[serde(rename = "tee-pubkey")]
+    pub tee_pubkey: TeePubKey,
+    /// JSON-encoded [`TdxEvidence`]
+    #[serde(rename = "tee-evidence")]
+    pub tee_evidence: String,
+}
+
+#[derive(Deserialize)]
+struct AttestationResponse {
+    token: String,
+}
+
+/// A resource encrypted to the TEE key, in JWE JSON serialization.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Response {
+    pub protected: String,
+    pub encrypted_key: String,
+    pub iv: String,
+    pub ciphertext: String,
+    pub tag: String,
+}
+
+#[derive(Deserialize)]
+struct JweHeader {
+    alg: String,
+    enc: String,
+    epk: TeePubKey,
+    apu: Option<String>,
+    apv: Option<String>,
+}
+
+/// The runtime data the KBS expects to be bound into the report data: the challenge nonce
+/// and the TEE public key, as JSON with sorted keys.
+pub fn runtime_data(nonce: &str, tee_pubkey: &TeePubKey) -> Result<String> {
+    serde_json::to_string(&json!({ "nonce": nonce, "tee-pubkey": tee_pubkey }))
+        .map_err(|e| TdxError::Kbs(format!("Failed to encode the runtime data: {e}")))
+}
+
+/// SHA-384 of the runtime data, zero-padded to 64 bytes, as hashed by the KBS TDX verifier.
+pub fn report_data_for_runtime_data(runtime_data: &[u8]) -> [u8; 64] {
+    let mut report_data = [0u8; 64];
+    report_data[..48].copy_from_slice(&Sha384::digest(runtime_data));
+    report_data
+}
+
+/// An ephemeral P-256 key pair the KBS encrypts resources to.
+#[derive(Clone)]
+pub struct TeeKeyPair {
+    secret: SecretKey,
+}
+
+impl std::fmt::Debug for TeeKeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TeeKeyPair")
+            .field("public", &self.public_jwk())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TeeKeyPair {
+    pub fn generate() -> Self {
+        TeeKeyPair {
+            secret: SecretKey::random(&mut OsRng),
+        }
+    }
+
+    pub fn public_jwk(&self) -> TeePubKey {
+        let point = self.secret.public_key().to_encoded_point(false);
+        TeePubKey {
+            kty: "EC".to_string(),
+            crv: "P-256".to_string(),
+            alg: KEY_ALG.to_string(),
+            x: base64_url::encode(point.x().expect("uncompressed point")),
+            y: base64_url::encode(point.y().expect("uncompressed point")),
+        }
+    }
+
+    /// Decrypt a resource encrypted with ECDH-ES+A256KW and A256GCM.
+    pub fn decrypt(&self, response: &Response) -> Result<Vec<u8>> {
+        let header: JweHeader = serde_json::from_slice(&base64_url::decode(&response.protected)?)
+            .map_err(|e| TdxError::Kbs(format!("Invalid JWE header: {e}")))?;
+        if header.alg != KEY_ALG || header.enc != CONTENT_ALG {
+            return Err(TdxError::Kbs(format!(
+                "Unsupported JWE algorithms {} {}",
+                header.alg, header.enc
+            )));
+        }
+        let epk = header.epk.to_public_key()?;
+        let shared = p256::ecdh::diffie_hellman(self.secret.to_nonzero_scalar(), epk.as_affine());
+
+        // Concat KDF other info (RFC 7518, section 4.6.2)
+        let apu = header.apu.as_deref().map(base64_url::decode).transpose()?;
+        let apv = header.apv.as_deref().map(base64_url::decode).transpose()?;
+        let other_info = [
+            length_prefixed(KEY_ALG.as_bytes()),
+            length_prefixed(apu.as_deref().unwrap_or_default()),
+            length_prefixed(apv.as_deref().unwrap_or_default()),
+            256u32.to_be_bytes().to_vec(),
+        ]
+        .concat();
+        let mut kek = [0u8; 32];
+        concat_kdf::derive_key_into::<Sha256>(shared.raw_secret_bytes(), &other_info, &mut kek)
+            .map_err(|e| TdxError::Kbs(format!("Key derivation failed: {e}")))?;
+        let cek = KekAes256::from(kek)
+            .unwrap_vec(&base64_url::decode(&response.encrypted_key)?)
+            .map_err(|e| TdxError::Kbs(format!("Failed to unwrap the content key: {e}")))?;
+
+        let iv = base64_url::decode(&response.iv)?;
+        if iv.len() != 12 {
+            return Err(TdxError::Kbs(format!("Invalid IV length: {}", iv.len())));
+        }
+        let mut ciphertext = base64_url::decode(&response.ciphertext)?;
+        ciphertext.extend(base64_url::decode(&response.tag)?);
+        Aes256Gcm::new_from_slice(&cek)
+            .map_err(|_| TdxError::Kbs("Invalid content key length".to_string()))?
+            .decrypt(
+                Nonce::from_slice(&iv),
+                Payload {
+                    msg: &ciphertext,
+                    aad: response.protected.as_bytes(),
+                },
+            )
+            .map_err(|_| TdxError::Kbs("Failed to decrypt the resource".to_string()))
+    }
+}
+
+fn length_prefixed(data: &[u8]) -> Vec<u8> {
+    [&(data.len() as u32).to_be_bytes()[..], data].concat()
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+    cookie: String,
+    token: String,
+}
+
+/// Runs the RCAR handshake with a KBS and retrieves resources from it.
+///
+/// ```no_run
+/// let mut client = tdx::kbs::KbsClient::new("http://kbs.internal:8080");
+/// let key = client.get_resource("default/disk/key")?;
+/// # Ok::<(), tdx::error::TdxError>(())
+/// ```
+#[derive(Clone)]
+pub struct KbsClient {
+    base_url: String,
+    tdx: Tdx,
+    quote_fn: Option<Arc<QuoteFn>>,
+    ccel_path: Option<PathBuf>,
+    key: TeeKeyPair,
+    session: Option<Session>,
+}
+
+impl std::fmt::Debug for KbsClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KbsClient")
+            .field("base_url", &self.base_url)
+            .field("ccel_path", &self.ccel_path)
+            .field("key", &self.key)
+            .field("attested", &self.session.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl KbsClient {
+    /// Create a client for the KBS at `base_url`, e.g. `http://127.0.0.1:8080`.
+    pub fn new(base_url: &str) -> Self {
+        KbsClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            tdx: Tdx::new(),
+            quote_fn: None,
+            ccel_path: Some(CCEL_PATH.into()),
+            key: TeeKeyPair::generate(),
+            session: None,
+        }
+    }
+
+    /// Generate quotes with `quote_fn` instead of the local TDX device, e.g. to replay a
+    /// recorded quote against a stub KBS.
+    pub fn with_quote_fn(mut self, quote_fn: Arc<QuoteFn>) -> Self {
+        self.quote_fn = Some(quote_fn);
+        self
+    }
+
+    /// Read the CC event log from `path`, or send none. Defaults to [`CCEL_PATH`].
+    pub fn with_ccel_path(mut self, path: Option<PathBuf>) -> Self {
+        self.ccel_path = path;
+        self
+    }
+
+    /// The attestation token of the current session, if attested.
+    pub fn token(&self) -> Option<&str> {
+        self.session.as_ref().map(|session| session.token.as_str())
+    }
+
+    /// Generate evidence over `report_data`.
+    pub fn evidence(&self, report_data: [u8; 64]) -> Result<TdxEvidence> {
+        let quote = match &self.quote_fn {
+            Some(quote_fn) => quote_fn(&report_data)?,
+            None => {
+                self.tdx
+                    .get_attestation_report_raw_with_options(DeviceOptions {
+                        report_data: Some(report_data),
+                    })?
+                    .0
+            }
+        };
+        let cc_eventlog = match &self.ccel_path {
+            Some(path) if path.exists() => Some(BASE64.encode(std::fs::read(path)?)),
+            _ => None,
+        };
+        Ok(TdxEvidence {
+            cc_eventlog,
+            quote: BASE64.encode(quote),
+            aa_eventlog: None,
+        })
+    }
+
+    /// Run the `/auth` and `/attest` exchange, returning the attestation token.
+    pub fn attest(&mut self) -> Result<String> {
+        let response = ureq::post(&self.url("auth")).send_json(AuthRequest {
+            version: KBS_PROTOCOL_VERSION,
+            tee: "tdx",
+            extra_params: "",
+        })?;
+        let cookie = session_cookie(&response)
+            .ok_or_else(|| TdxError::Kbs("The KBS did not set a session cookie".to_string()))?;
+        let challenge: Challenge = response.into_json()?;
+
+        let tee_pubkey = self.key.public_jwk();
+        let runtime_data = runtime_data(&challenge.nonce, &tee_pubkey)?;
+        let evidence = self.evidence(report_data_for_runtime_data(runtime_data.as_bytes()))?;
+        let attestation = Attestation {
+            tee_pubkey,
+            tee_evidence: serde_json::to_string(&evidence)
+                .map_err(|e| TdxError::Kbs(format!("Failed to encode the evidence: {e}")))?,
+        };
+        let response = ureq::post(&self.url("attest"))
+            .set("Cookie", &cookie)
+            .send_json(&attestation)?;
+        let cookie = session_cookie(&response).unwrap_or(cookie);
+        let AttestationResponse { token } = response.into_json()?;
+        self.session = Some(Session {
+            cookie,
+            token: token.clone(),
+        });
+        Ok(token)
+    }
+
+    /// Retrieve and decrypt the resource at `path` (`<repository>/<type>/<tag>`), attesting
+    /// first if there is no session, or again if the session expired.
+    pub fn get_resource(&mut self, path: &str) -> Result<Vec<u8>> {
+        if self.session.is_none() {
+            self.attest()?;
+        }
+        let response = match self.request_resource(path)? {
+            Some(response) => response,
+            None => {
+                self.attest()?;
+                self.request_resource(path)?
+                    .ok_or_else(|| TdxError::Kbs(format!("Access to resource {path} was denied")))?
+            }
+        };
+        let response: Response = response.into_json()?;
+        self.key.decrypt(&response)
+    }
+
+    /// Request a resource in the current session, returning `None` if it is unauthorized.
+    fn request_resource(&self, path: &str) -> Result<Option<ureq::Response>> {
+        let session = self
+            .session
+            .as_ref()
+            .ok_or_else(|| TdxError::Kbs("Not attested".to_string()))?;
+        let url = self.url(&format!("resource/{}", path.trim_start_matches('/')));
+        match ureq::get(&url)
+            .set("Cookie", &session.cookie)
+            .set("Authorization", &format!("Bearer {}", session.token))
+            .call()
+        {
+            Ok(response) => Ok(Some(response)),
+            Err(ureq::Error::Status(401, _)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn url(&self, endpoint: &str) -> String {
+        format!("{}/kbs/v0/{}", self.base_url, endpoint)
+    }
+}
+
+fn session_cookie(response: &ureq::Response) -> Option<String> {
+    response.all("set-cookie").into_iter().find_map(|header| {
+        let pair = header.split(';').next()?.trim();
+        let (name, _) = pair.split_once('=')?;
+        (name == SESSION_COOKIE).then(|| pair.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    // JWE encrypting "disk encryption key" to the P-256 key with scalar 0x11..11, with the
+    // ephemeral scalar 0x22..22, CEK 0x33..33 and IV 0x44..44, computed with Python
+    // `cryptography`
+    const TEE_X: &str = "AhfmF_C2RDkoJ4-WmZ5pojpPLBUr321s32bluAKC1O0";
+    const TEE_Y: &str = "GUp968uXcS0t2jyoWqh2Wlb0X8dYWZZS8ol8ZTBuV5Q";
+    const PROTECTED: &str = "eyJhbGciOiJFQ0RILUVTK0EyNTZLVyIsImVuYyI6IkEyNTZHQ00iLCJlcGsiOnsia3R5IjoiRUMiLCJjcnYiOiJQLTI1NiIsIngiOiIxbHFUbDN5cVBSc0lHRkxfVjZlZVJsOFdZRmR6QkxycTFRWGRPa2hZblBNIiwieSI6IlVCaGVpVk55MzJJaDZqb1RkVmZrY18zYloxWHdXOVVIdzhVel9PbkpFb1UifX0";
+    const RESOURCE: &[u8] = b"disk encryption key";
+
+    fn key_pair() -> TeeKeyPair {
+        TeeKeyPair {
+            secret: SecretKey::from_slice(&[0x11; 32]).unwrap(),
+        }
+    }
+
+    fn response() -> Response {
+        Response {
+            protected: PROTECTED.to_string(),
+            encrypted_key: "Tv226vhM20rpXNoICDhHf0YT2lYcLlnHvVvuv_Fmpi_nXNRYDlyw1A".to_string(),
+            iv: "RERERERERERERERE".to_string(),
+            ciphertext: "VPi3-epj4QjS-9Tmn_KkUXHpaA".to_string(),
+            tag: "j2jWR1ESGqhnajCKv1WtQA".to_string(),
+        }
+    }
+
+    #[test]
+    fn decrypts_jwe() {
+        let key = key_pair();
+        let jwk = key.public_jwk();
+        assert_eq!((jwk.x.as_str(), jwk.y.as_str()), (TEE_X, TEE_Y));
+        assert_eq!(key.decrypt(&response()).unwrap(), RESOURCE);
+
+        let mut tampered = response();
+        tampered.tag = base64_url::encode(&[0u8; 16]);
+        assert!(key.decrypt(&tampered).is_err());
+        assert!(TeeKeyPair::generate().decrypt(&response()).is_err());
+    }
+
+    struct Request {
+        method: String,
+        path: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl Request {
+        fn read(stream: &TcpStream) -> Request {
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let mut parts = line.split_whitespace();
+            let method = parts.next().unwrap().to_string();
+            let path = parts.next().unwrap().to_string();
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                match line.trim_end().split_once(':') {
+                    Some((name, value)) => {
+                        headers.push((name.to_ascii_lowercase(), value.trim().to_string()))
+                    }
+                    None => break,
+                }
+            }
+            let length = headers
+                .iter()
+                .find(|(name, _)| name == "content-length")
+                .map_or(0, |(_, value)| value.parse().unwrap());
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            Request {
+                method,
+                path,
+                headers,
+                body,
+            }
+        }
+
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(header, _)| header == name)
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    fn reply(mut stream: TcpStream, status: &str, headers: &str, body: &str) {
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+             Connection: close\r\n{headers}\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+    }
+
+    /// A KBS that expects the fixed TEE key, answers the first resource request of each
+    /// session with 401 until it attested twice, and serves the fixed JWE.
+    fn stub_kbs(listener: TcpListener, attestations: Arc<AtomicUsize>) {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let request = Request::read(&stream);
+            let session = attestations.load(Ordering::SeqCst);
+            match (request.method.as_str(), request.path.as_str()) {
+                ("POST", "/kbs/v0/auth") => {
+                    let auth: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                    assert_eq!(auth["version"], KBS_PROTOCOL_VERSION);
+                    assert_eq!(auth["tee"], "tdx");
+                    reply(
+                        stream,
+                        "200 OK",
+                        &format!("Set-Cookie: {SESSION_COOKIE}=session-{session}; Path=/kbs\r\n"),
+                        &format!(r#"{{"nonce":"nonce-{session}","extra-params":""}}"#),
+                    );
+                }
+                ("POST", "/kbs/v0/attest") => {
+                    let cookie = format!("{SESSION_COOKIE}=session-{session}");
+                    assert_eq!(request.header("cookie"), Some(cookie.as_str()));
+                    let attestation: Attestation = serde_json::from_slice(&request.body).unwrap();
+                    assert_eq!(attestation.tee_pubkey, key_pair().public_jwk());
+                    let evidence: TdxEvidence =
+                        serde_json::from_str(&attestation.tee_evidence).unwrap();
+                    let runtime_data =
+                        runtime_data(&format!("nonce-{session}"), &attestation.tee_pubkey).unwrap();
+                    assert_eq!(
+                        BASE64.decode(&evidence.quote).unwrap(),
+                        report_data_for_runtime_data(runtime_data.as_bytes())
+                    );
+                    assert_eq!(evidence.cc_eventlog, None);
+                    let session = attestations.fetch_add(1, Ordering::SeqCst) + 1;
+                    reply(
+                        stream,
+                        "200 OK",
+                        "",
+                        &format!(r#"{{"token":"token-{session}"}}"#),
+                    );
+                }
+                ("GET", "/kbs/v0/resource/default/disk/key") => {
+                    let token = format!("Bearer token-{session}");
+                    assert_eq!(request.header("authorization"), Some(token.as_str()));
+                    if session < 2 {
+                        reply(stream, "401 Unauthorized", "", "{}");
+                    } else {
+                        let body = serde_json::to_string(&response()).unwrap();
+                        reply(stream, "200 OK", "", &body);
+                        return;
+                    }
+                }
+                _ => reply(stream, "404 Not Found", "", "{}"),
+            }
+        }
+    }
+
+    #[test]
+    fn retrieves_resource_from_stub_kbs() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let attestations = Arc::new(AtomicUsize::new(0));
+        let server = {
+            let attestations = attestations.clone();
+            thread::spawn(move || stub_kbs(listener, attestations))
+        };
+
+        // The quote stands in for a TD quote: the stub KBS only checks the report data
+        let mut client = KbsClient::new(&base_url)
+            .with_quote_fn(Arc::new(|report_data: &[u8; 64]| Ok(report_data.to_vec())))
+            .with_ccel_path(None);
+        client.key = key_pair();
+        let resource = client.get_resource("/default/disk/key").unwrap();
+        server.join().unwrap();
+
+        assert_eq!(resource, RESOURCE);
+        assert_eq!(attestations.load(Ordering::SeqCst), 2);
+        assert_eq!(client.token(), Some("token-2"));
+    }
+}
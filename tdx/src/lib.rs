@@ -1,24 +1,94 @@
+#[cfg(feature = "broker")]
+pub mod broker;
+#[cfg(feature = "clib")]
+pub mod c;
+pub mod cert_data;
+#[cfg(feature = "channel")]
+pub mod channel;
+pub mod collateral;
+#[cfg(feature = "verifier")]
+pub mod corim;
+#[cfg(feature = "coco-provider")]
 pub mod device;
+pub mod diff;
 pub mod error;
+#[cfg(feature = "broker")]
+pub mod hpke;
+#[cfg(feature = "kbs")]
+pub mod kbs;
+#[cfg(feature = "verifier")]
+pub mod nonce;
+#[cfg(feature = "onchain")]
+pub mod onchain;
+#[cfg(feature = "onchain")]
+pub mod pccs;
+pub mod pck;
+#[cfg(feature = "http")]
+pub mod pcs;
+#[cfg(feature = "verifier")]
+pub mod policy;
+#[cfg(feature = "python")]
+mod python;
+pub mod quote;
+#[cfg(feature = "ratls")]
+pub mod ratls;
+pub mod rtmr;
+pub mod tdvf;
+#[cfg(test)]
+#[allow(dead_code)] // not every feature set uses every fixture
+mod test_pki;
 pub mod utils;
+#[cfg(feature = "verifier")]
+pub mod validation;
+pub mod verified_output;
+#[cfg(feature = "verifier")]
+pub mod verifier;
+#[cfg(feature = "wasm")]
+mod wasm;
+pub mod zk;
 
-use alloy::providers::ProviderBuilder;
-use automata_dcap_network_registry::Network;
+#[cfg(feature = "onchain")]
+use alloy::signers::local::PrivateKeySigner;
+#[cfg(feature = "onchain")]
 use dcap_rs::types::collateral::Collateral;
 use dcap_rs::types::quote::Quote;
 use dcap_rs::verify_dcap_quote;
 use error::{Result, TdxError};
-use pccs_reader_rs::{find_missing_collaterals_from_quote, CollateralError};
+#[cfg(feature = "onchain")]
+use onchain::{OnchainOptions, OnchainSubmission};
+#[cfg(feature = "onchain")]
+use pccs::{CollateralSource, PccsUploadOptions, UploadReport};
+use std::borrow::Cow;
 use std::time::SystemTime;
 
-use crate::utils::der_to_pem_bytes;
+use crate::cert_data::CertificationData;
+use crate::collateral::RawCollaterals;
+#[cfg(feature = "http")]
+use crate::pcs::PcsClient;
+use crate::utils::PckCA;
+#[cfg(feature = "onchain")]
+use crate::validation::CollateralValidator;
+use crate::verified_output::VerifiedOutput;
+#[cfg(feature = "onchain")]
+use crate::zk::ZkGuestInput;
 
 #[derive(Debug, Clone, Default)]
-pub struct Tdx;
+pub struct Tdx {
+    #[cfg(feature = "http")]
+    pcs_client: Option<PcsClient>,
+}
 
 impl Tdx {
     pub fn new() -> Self {
-        Tdx
+        Tdx::default()
+    }
+
+    /// Use `pcs_client` to retrieve PCK certificates for quotes that carry an encrypted PPID
+    /// (certification data types 2 and 3) instead of a PCK certificate chain.
+    #[cfg(feature = "http")]
+    pub fn with_pcs_client(mut self, pcs_client: PcsClient) -> Self {
+        self.pcs_client = Some(pcs_client);
+        self
     }
 
     /// Retrieve an Attestation Report in raw bytes.
@@ -27,8 +97,10 @@ impl Tdx {
     /// - A tuple containing the attestation report and the optional var data.
     /// - The attestation report is raw bytes that can be used with dcap-rs's QuoteV4::from_bytes().
     /// - The var data is an optional `Vec<u8>` containing the var data.
+    ///
     /// Var data is only available if the device resides on an Azure Confidential VM.
     /// Var data provided by Azure can be used to verify the contents of the attestation report's report_data
+    #[cfg(feature = "coco-provider")]
     pub fn get_attestation_report_raw(&self) -> Result<(Vec<u8>, Option<Vec<u8>>)> {
         let device = device::Device::with_default_options()?;
         device.get_attestation_report_raw()
@@ -37,6 +109,7 @@ impl Tdx {
     /// Retrieve an Attestation Report (as raw bytes) with options.
     /// When available, users can pass in a 64 byte report data when requesting an attestation report.
     /// This cannot be used on Azure Confidential VM.
+    #[cfg(feature = "coco-provider")]
     pub fn get_attestation_report_raw_with_options(
         &self,
         options: device::DeviceOptions,
@@ -45,239 +118,192 @@ impl Tdx {
         device.get_attestation_report_raw()
     }
 
+    #[cfg(feature = "onchain")]
     pub async fn verify_attestation_report_raw(&self, raw_quote: &[u8]) -> Result<()> {
-        let collaterals = self.get_collaterals(raw_quote).await?;
-        let quote = Quote::read(&mut &*raw_quote)?;
-        verify_dcap_quote(SystemTime::now(), collaterals, quote)?;
+        self.verify_attestation_report_raw_with_output(raw_quote)
+            .await?;
         Ok(())
     }
 
-    /// Retrieve the collaterals required to verify the attestation report.
-    pub async fn get_collaterals(&self, raw_quote: &[u8]) -> Result<Collateral> {
-        // Get network configuration (defaults to automata_testnet)
-        let network = Network::default_network(None)
-            .ok_or_else(|| TdxError::Http("Failed to get network config".to_string()))?;
-
-        // Get RPC endpoint from network registry
-        let rpc_url = network
-            .rpc_endpoints
-            .first()
-            .ok_or_else(|| TdxError::Http("No RPC endpoints available".to_string()))?
-            .parse()
-            .map_err(|e| TdxError::Http(format!("Failed to parse RPC URL: {}", e)))?;
-
-        let provider = ProviderBuilder::new().connect_http(rpc_url);
-
-        // Fetch collaterals from on-chain PCCS using the library
-        let collaterals = find_missing_collaterals_from_quote(
-            &provider, None, // deployment_version - uses default
-            raw_quote, false, // don't print to disk
-            None,  // tcb_eval_num
-        )
-        .await
-        .map_err(|e| match e {
-            CollateralError::Missing(report) => {
-                TdxError::Http(format!("Missing collaterals: {report:?}"))
-            }
-            CollateralError::Validation(msg) => {
-                TdxError::Http(format!("Validation error: {}", msg))
-            }
-        })?;
-
-        // Convert library's Collaterals to dcap-rs Collateral
-        // The library returns DER-encoded certs, dcap-rs expects PEM for the cert chain
-        let mut combined_pem =
-            der_to_pem_bytes(&collaterals.tcb_signing_ca);
-        combined_pem.extend_from_slice(&der_to_pem_bytes(&collaterals.root_ca));
+    /// Verify the attestation report off-chain and return the verified output, in the same
+    /// packed format as the on-chain verifier and the zkVM programs produce.
+    #[cfg(feature = "onchain")]
+    pub async fn verify_attestation_report_raw_with_output(
+        &self,
+        raw_quote: &[u8],
+    ) -> Result<VerifiedOutput> {
+        let raw_quote = self.resolve_pck_cert_chain_async(raw_quote).await?;
+        let collaterals = collateral::fetch_raw_collaterals(&raw_quote)
+            .await?
+            .to_collateral()?;
+        let quote = Quote::read(&mut &*raw_quote)?;
+        let output = verify_dcap_quote(SystemTime::now(), collaterals, quote)?;
+        VerifiedOutput::from_bytes_for_quote(&output.to_bytes(), &raw_quote)
+    }
 
-        Ok(Collateral::new(
-            &collaterals.root_ca_crl,
-            &collaterals.pck_crl,
-            &combined_pem,
-            &collaterals.tcb_info,
-            &collaterals.qe_identity,
-        )?)
+    /// Verify the attestation report off-chain against caller-supplied collaterals at `time`,
+    /// without any network access.
+    pub fn verify_attestation_report_with_collaterals(
+        &self,
+        raw_quote: &[u8],
+        collaterals: &RawCollaterals,
+        time: SystemTime,
+    ) -> Result<VerifiedOutput> {
+        let raw_quote = self.resolve_pck_cert_chain(raw_quote)?;
+        let quote = Quote::read(&mut &*raw_quote)?;
+        let output = verify_dcap_quote(time, collaterals.to_collateral()?, quote)?;
+        VerifiedOutput::from_bytes_for_quote(&output.to_bytes(), &raw_quote)
     }
-}
 
-#[cfg(feature = "clib")]
-pub mod c {
-    use std::ptr::copy_nonoverlapping;
-    use std::sync::{LazyLock, Mutex};
+    /// Check a verified output returned on-chain or committed by a ZK proof against the result
+    /// of verifying the same quote locally.
+    ///
+    /// Returns the decoded output when both agree, or a `TdxError::Dcap` naming the fields
+    /// that differ.
+    #[cfg(feature = "onchain")]
+    pub async fn check_verified_output(
+        &self,
+        raw_quote: &[u8],
+        verified_output: &[u8],
+    ) -> Result<VerifiedOutput> {
+        let remote = VerifiedOutput::from_bytes_for_quote(verified_output, raw_quote)?;
+        let local = self
+            .verify_attestation_report_raw_with_output(raw_quote)
+            .await?;
+        let mismatches = remote.mismatches(&local);
+        if !mismatches.is_empty() {
+            let fields: Vec<String> = mismatches.iter().map(|f| f.to_string()).collect();
+            return Err(TdxError::Dcap(format!(
+                "Verified output does not match local verification: {}",
+                fields.join(", ")
+            )));
+        }
+        Ok(remote)
+    }
 
-    use super::device::DeviceOptions;
-    use super::Tdx;
+    /// Verify the attestation report with the on-chain DCAP Attestation contract using an `eth_call`.
+    ///
+    /// Collaterals are read by the contract from the Automata on-chain PCCS.
+    #[cfg(feature = "onchain")]
+    pub async fn verify_attestation_report_onchain(
+        &self,
+        raw_quote: &[u8],
+        options: &OnchainOptions,
+    ) -> Result<VerifiedOutput> {
+        let raw_quote = self.resolve_pck_cert_chain_async(raw_quote).await?;
+        onchain::verify_quote_onchain(&raw_quote, options).await
+    }
 
-    /// Error codes returned by C FFI functions.
-    pub const TDX_OK: i32 = 0;
-    pub const TDX_ERR_NULL_POINTER: i32 = -1;
-    pub const TDX_ERR_BUFFER_TOO_SMALL: i32 = -2;
-    pub const TDX_ERR_NO_REPORT: i32 = -3;
-    pub const TDX_ERR_ATTESTATION_FAILED: i32 = -4;
-    pub const TDX_ERR_LOCK_POISONED: i32 = -5;
+    /// Submit the attestation report to the on-chain DCAP Attestation contract in a transaction
+    /// signed by `signer`, so that the verification is recorded on-chain.
+    #[cfg(feature = "onchain")]
+    pub async fn submit_attestation_report_onchain(
+        &self,
+        raw_quote: &[u8],
+        signer: PrivateKeySigner,
+        options: &OnchainOptions,
+    ) -> Result<OnchainSubmission> {
+        let raw_quote = self.resolve_pck_cert_chain_async(raw_quote).await?;
+        onchain::submit_quote_onchain(&raw_quote, signer, options).await
+    }
 
-    static ATTESTATION_REPORT: LazyLock<Mutex<Vec<u8>>> = LazyLock::new(|| Mutex::new(Vec::new()));
-    static VAR_DATA: LazyLock<Mutex<Vec<u8>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+    /// Retrieve the collaterals required to verify the attestation report.
+    #[cfg(feature = "onchain")]
+    pub async fn get_collaterals(&self, raw_quote: &[u8]) -> Result<Collateral> {
+        self.get_raw_collaterals(raw_quote).await?.to_collateral()
+    }
 
-    /// Helper to store report and var_data into the global statics.
-    /// Returns the report length on success, or a negative error code on failure.
-    fn store_report(report_bytes: Vec<u8>, var_data: Option<Vec<u8>>) -> i32 {
-        let report_len = match i32::try_from(report_bytes.len()) {
-            Ok(len) => len,
-            Err(_) => {
-                eprintln!("tdx: report length exceeds i32::MAX");
-                return TDX_ERR_ATTESTATION_FAILED;
-            }
-        };
-        match ATTESTATION_REPORT.lock() {
-            Ok(mut t) => *t = report_bytes,
-            Err(e) => {
-                eprintln!("tdx: attestation report lock poisoned: {e}");
-                return TDX_ERR_LOCK_POISONED;
-            }
-        }
-        // Always update var_data: clear it when None to avoid stale data from previous calls
-        match VAR_DATA.lock() {
-            Ok(mut t) => *t = var_data.unwrap_or_default(),
-            Err(e) => {
-                eprintln!("tdx: var data lock poisoned: {e}");
-                return TDX_ERR_LOCK_POISONED;
-            }
-        }
-        report_len
+    /// Retrieve the collaterals required to verify the attestation report, as fetched from the
+    /// on-chain PCCS (DER certificates and CRLs, JSON TCB info and QE identity).
+    #[cfg(feature = "onchain")]
+    pub async fn get_raw_collaterals(&self, raw_quote: &[u8]) -> Result<RawCollaterals> {
+        let raw_quote = self.resolve_pck_cert_chain_async(raw_quote).await?;
+        collateral::fetch_raw_collaterals(&raw_quote).await
     }
 
-    /// Generate the attestation report with default settings.
-    ///
-    /// Returns the size of the report on success (>= 0), which you can use to malloc
-    /// a buffer of suitable size before calling `tdx_get_attestation_report_raw()`.
-    /// Returns a negative error code on failure.
-    #[unsafe(no_mangle)]
-    pub extern "C" fn tdx_generate_attestation_report() -> i32 {
-        let tdx = Tdx::new();
-        let (report_bytes, var_data) = match tdx.get_attestation_report_raw() {
-            Ok(r) => r,
-            Err(e) => {
-                eprintln!("tdx: failed to get attestation report: {e}");
-                return TDX_ERR_ATTESTATION_FAILED;
-            }
-        };
-        store_report(report_bytes, var_data)
+    /// Retrieve the collaterals of the attestation report and validate them locally against the
+    /// pinned Intel root before use, instead of trusting the on-chain PCCS.
+    #[cfg(feature = "onchain")]
+    pub async fn get_validated_collaterals(&self, raw_quote: &[u8]) -> Result<RawCollaterals> {
+        let raw_quote = self.resolve_pck_cert_chain_async(raw_quote).await?;
+        let collaterals = collateral::fetch_raw_collaterals(&raw_quote).await?;
+        CollateralValidator::default()
+            .validate_for_quote(&raw_quote, &collaterals, SystemTime::now())?
+            .into_result()?;
+        Ok(collaterals)
     }
 
-    /// Generate the attestation report with custom report_data (64 bytes).
+    /// Upload the collaterals required to verify the attestation report that are missing from
+    /// the on-chain PCCS, fetching them from `source` and signing the transactions with `signer`.
     ///
-    /// `report_data` must point to a buffer of at least 64 bytes.
-    /// Returns the size of the report on success (>= 0), or a negative error code on failure.
-    #[unsafe(no_mangle)]
-    pub extern "C" fn tdx_generate_attestation_report_with_options(report_data: *const u8) -> i32 {
-        if report_data.is_null() {
-            eprintln!("tdx: report_data is null");
-            return TDX_ERR_NULL_POINTER;
-        }
-        let tdx = Tdx::new();
-        let mut rust_report_data: [u8; 64] = [0; 64];
-        unsafe {
-            copy_nonoverlapping(report_data, rust_report_data.as_mut_ptr(), 64);
-        }
-        let device_options = DeviceOptions {
-            report_data: Some(rust_report_data),
-        };
-        let (report_bytes, var_data) =
-            match tdx.get_attestation_report_raw_with_options(device_options) {
-                Ok(r) => r,
-                Err(e) => {
-                    eprintln!("tdx: failed to get attestation report with options: {e}");
-                    return TDX_ERR_ATTESTATION_FAILED;
-                }
-            };
-        store_report(report_bytes, var_data)
+    /// Use this when `get_collaterals` fails with missing collaterals, e.g. for a new FMSPC.
+    #[cfg(feature = "onchain")]
+    pub async fn upload_missing_collaterals(
+        &self,
+        raw_quote: &[u8],
+        source: &CollateralSource,
+        signer: PrivateKeySigner,
+        options: &PccsUploadOptions,
+    ) -> Result<UploadReport> {
+        let raw_quote = self.resolve_pck_cert_chain_async(raw_quote).await?;
+        pccs::upload_missing_collaterals(&raw_quote, source, signer, options).await
     }
 
-    /// Copy the attestation report into the provided buffer.
+    /// Build the input for the Automata DCAP zkVM guest programs (Risc0 / SP1).
     ///
-    /// `buf` must point to a buffer of at least `buf_len` bytes.
-    /// Call `tdx_generate_attestation_report()` first to obtain the required size.
-    ///
-    /// Returns the number of bytes written on success (>= 0), or a negative error code:
-    /// - `TDX_ERR_NULL_POINTER` if `buf` is null
-    /// - `TDX_ERR_BUFFER_TOO_SMALL` if `buf_len` is smaller than the report
-    /// - `TDX_ERR_NO_REPORT` if no report has been generated yet
-    /// - `TDX_ERR_LOCK_POISONED` if the internal mutex is poisoned
-    #[unsafe(no_mangle)]
-    pub extern "C" fn tdx_get_attestation_report_raw(buf: *mut u8, buf_len: usize) -> i32 {
-        if buf.is_null() {
-            eprintln!("tdx: tdx_get_attestation_report_raw: buf is null");
-            return TDX_ERR_NULL_POINTER;
-        }
-        let bytes = match ATTESTATION_REPORT.lock() {
-            Ok(t) => t,
-            Err(e) => {
-                eprintln!("tdx: attestation report lock poisoned: {e}");
-                return TDX_ERR_LOCK_POISONED;
-            }
-        };
-        if bytes.is_empty() {
-            eprintln!(
-                "tdx: no attestation report found, call tdx_generate_attestation_report() first"
-            );
-            return TDX_ERR_NO_REPORT;
-        }
-        if buf_len < bytes.len() {
-            eprintln!("tdx: buffer too small ({buf_len} < {})", bytes.len());
-            return TDX_ERR_BUFFER_TOO_SMALL;
-        }
-        unsafe {
-            copy_nonoverlapping(bytes.as_ptr(), buf, bytes.len());
-        }
-        bytes.len() as i32 // safe: checked via buf_len which is usize from C caller
+    /// Collaterals are fetched from the on-chain PCCS and the current time is used as the
+    /// verification time.
+    #[cfg(feature = "onchain")]
+    pub async fn get_zk_guest_input(&self, raw_quote: &[u8]) -> Result<ZkGuestInput> {
+        let raw_quote = self.resolve_pck_cert_chain_async(raw_quote).await?;
+        let collaterals = collateral::fetch_raw_collaterals(&raw_quote).await?;
+        ZkGuestInput::new(&raw_quote, &collaterals, zk::current_time()?)
     }
 
-    /// Retrieve the length of var_data.
-    ///
-    /// Call this only after `tdx_generate_attestation_report()`.
-    /// Returns 0 if var_data is empty, or a negative error code on failure.
-    #[unsafe(no_mangle)]
-    pub extern "C" fn tdx_get_var_data_len() -> i32 {
-        match VAR_DATA.lock() {
-            Ok(t) => t.len() as i32,
-            Err(e) => {
-                eprintln!("tdx: var data lock poisoned: {e}");
-                TDX_ERR_LOCK_POISONED
-            }
-        }
+    /// Extract the FMSPC and the issuing PCK CA from the PCK certificate of the attestation
+    /// report, retrieving the certificate from the configured PCS client if needed.
+    pub fn get_pck_fmspc_and_issuer(&self, raw_quote: &[u8]) -> Result<(String, PckCA)> {
+        let raw_quote = self.resolve_pck_cert_chain(raw_quote)?;
+        let quote = Quote::read(&mut &*raw_quote)?;
+        utils::get_pck_fmspc_and_issuer(&quote)
     }
 
-    /// Copy var_data into the provided buffer.
+    /// Returns the attestation report with a PCK certificate chain as certification data.
     ///
-    /// `buf` must point to a buffer of at least `buf_len` bytes.
-    /// Call `tdx_get_var_data_len()` first to obtain the required size.
+    /// Reports generated on hosts without a cached PCK certificate carry an encrypted PPID
+    /// instead, in which case the certificate is retrieved from the configured PCS client.
+    /// Reports that already carry the chain are returned as is.
     ///
-    /// Returns the number of bytes written on success (>= 0), or a negative error code.
-    #[unsafe(no_mangle)]
-    pub extern "C" fn tdx_get_var_data(buf: *mut u8, buf_len: usize) -> i32 {
-        if buf.is_null() {
-            eprintln!("tdx: tdx_get_var_data: buf is null");
-            return TDX_ERR_NULL_POINTER;
-        }
-        let bytes = match VAR_DATA.lock() {
-            Ok(t) => t,
-            Err(e) => {
-                eprintln!("tdx: var data lock poisoned: {e}");
-                return TDX_ERR_LOCK_POISONED;
-            }
-        };
-        if bytes.is_empty() {
-            eprintln!("tdx: no var data found, call tdx_generate_attestation_report() first");
-            return TDX_ERR_NO_REPORT;
+    /// The PCS request blocks, async callers should run this on a blocking thread.
+    pub fn resolve_pck_cert_chain<'a>(&self, raw_quote: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        #[cfg(feature = "http")]
+        if let Some(pcs_client) = &self.pcs_client {
+            return cert_data::with_pck_cert_chain(raw_quote, pcs_client);
         }
-        if buf_len < bytes.len() {
-            eprintln!("tdx: buffer too small ({buf_len} < {})", bytes.len());
-            return TDX_ERR_BUFFER_TOO_SMALL;
+        match cert_data::get_certification_data(raw_quote)? {
+            CertificationData::PpidCleartext(_)
+            | CertificationData::PpidRsa2048Encrypted(_)
+            | CertificationData::PpidRsa3072Encrypted(_) => Err(TdxError::ConfigOptions(
+                "Quote carries a PPID instead of a PCK certificate chain, configure a PCS client with Tdx::with_pcs_client".to_string(),
+            )),
+            _ => Ok(Cow::Borrowed(raw_quote)),
         }
-        unsafe {
-            copy_nonoverlapping(bytes.as_ptr(), buf, bytes.len());
+    }
+
+    /// [`Tdx::resolve_pck_cert_chain`] for async callers, running the PCS request (if any) on
+    /// the blocking thread pool.
+    #[cfg(feature = "onchain")]
+    async fn resolve_pck_cert_chain_async<'a>(&self, raw_quote: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        if let CertificationData::PckCertChain(_) = cert_data::get_certification_data(raw_quote)? {
+            return Ok(Cow::Borrowed(raw_quote));
         }
-        bytes.len() as i32
+        let tdx = self.clone();
+        let raw_quote = raw_quote.to_vec();
+        let resolved = tokio::task::spawn_blocking(move || {
+            tdx.resolve_pck_cert_chain(&raw_quote).map(Cow::into_owned)
+        })
+        .await
+        .map_err(|e| TdxError::Anyhow(e.to_string()))??;
+        Ok(Cow::Owned(resolved))
     }
 }
@@ -0,0 +1,393 @@
+use crate::error::{Result, TdxError};
+use crate::utils::serde_hex;
+use crate::verified_output::VerifiedOutput;
+#[cfg(feature = "coco-provider")]
+use crate::verifier::CollateralProvider;
+use crate::verifier::{Evidence, QuoteVerifier};
+#[cfg(feature = "coco-provider")]
+use crate::Tdx;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Length of a nonce: issue time (u64), 16 random bytes and a 32-byte HMAC-SHA256 tag.
+pub const NONCE_LEN: usize = 8 + 16 + 32;
+/// Default lifetime of an issued nonce.
+pub const DEFAULT_NONCE_TTL: Duration = Duration::from_secs(5 * 60);
+/// Default tolerance for issuers whose clocks run ahead of the validating instance.
+pub const DEFAULT_CLOCK_SKEW: Duration = Duration::from_secs(30);
+
+/// A verifier-issued challenge, authenticated with the issuer's HMAC key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Nonce(#[serde(with = "serde_hex")] [u8; NONCE_LEN]);
+
+impl Nonce {
+    pub fn from_bytes(bytes: [u8; NONCE_LEN]) -> Self {
+        Nonce(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; NONCE_LEN] {
+        &self.0
+    }
+
+    /// Issue time, in seconds since the UNIX epoch.
+    pub fn issued_at(&self) -> u64 {
+        u64::from_be_bytes(self.0[..8].try_into().expect("8 bytes"))
+    }
+
+    /// The report data an attester answering this nonce must put in its quote:
+    /// `SHA-512(nonce || runtime_data)`.
+    pub fn report_data(&self, runtime_data: &[u8]) -> [u8; 64] {
+        let mut hasher = Sha512::new();
+        hasher.update(self.0);
+        hasher.update(runtime_data);
+        hasher.finalize().into()
+    }
+}
+
+/// Issues and validates time-limited, single-use nonces.
+///
+/// Nonces are stateless: their issue time and random part are authenticated with an
+/// HMAC-SHA256 tag, so any issuer sharing the key can validate them. Single use is enforced by
+/// the [`ReplayStore`] passed to [`NonceIssuer::redeem`].
+#[derive(Clone)]
+pub struct NonceIssuer {
+    key: [u8; 32],
+    ttl: Duration,
+    clock_skew: Duration,
+}
+
+impl std::fmt::Debug for NonceIssuer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NonceIssuer")
+            .field("ttl", &self.ttl)
+            .field("clock_skew", &self.clock_skew)
+            .finish_non_exhaustive()
+    }
+}
+
+impl NonceIssuer {
+    /// Create an issuer with a fixed HMAC key, shared by every instance of the verifier.
+    pub fn new(key: [u8; 32]) -> Self {
+        NonceIssuer {
+            key,
+            ttl: DEFAULT_NONCE_TTL,
+            clock_skew: DEFAULT_CLOCK_SKEW,
+        }
+    }
+
+    /// Create an issuer with a random key. Its nonces can only be validated by itself.
+    pub fn random() -> Self {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        NonceIssuer::new(key)
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Accept nonces issued up to `clock_skew` in the future, by instances sharing the key
+    /// whose clocks run ahead.
+    pub fn with_clock_skew(mut self, clock_skew: Duration) -> Self {
+        self.clock_skew = clock_skew;
+        self
+    }
+
+    pub fn issue(&self) -> Nonce {
+        self.issue_at(SystemTime::now())
+    }
+
+    pub fn issue_at(&self, now: SystemTime) -> Nonce {
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[..8].copy_from_slice(&unix_secs(now).to_be_bytes());
+        rand::thread_rng().fill_bytes(&mut nonce[8..24]);
+        let tag = self.mac(&nonce[..24]).finalize().into_bytes();
+        nonce[24..].copy_from_slice(&tag);
+        Nonce(nonce)
+    }
+
+    /// Check that `nonce` was issued by this issuer and has not expired at `now`.
+    pub fn validate_at(&self, nonce: &Nonce, now: SystemTime) -> Result<()> {
+        self.mac(&nonce.0[..24])
+            .verify_slice(&nonce.0[24..])
+            .map_err(|_| TdxError::Nonce("Nonce was not issued by this verifier".to_string()))?;
+        let now = unix_secs(now);
+        let issued_at = nonce.issued_at();
+        if issued_at > now.saturating_add(self.clock_skew.as_secs()) {
+            return Err(TdxError::Nonce("Nonce is issued in the future".to_string()));
+        }
+        if now.saturating_sub(issued_at) > self.ttl.as_secs() {
+            return Err(TdxError::Nonce("Nonce has expired".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Validate `nonce` and mark it as used in `store`, failing if it was already used.
+    pub fn redeem(&self, nonce: &Nonce, store: &dyn ReplayStore) -> Result<()> {
+        let now = SystemTime::now();
+        self.validate_at(nonce, now)?;
+        let expires_at = nonce.issued_at() + self.ttl.as_secs();
+        if !store.insert(&Sha256::digest(nonce.0), expires_at)? {
+            return Err(TdxError::Nonce("Nonce has already been used".to_string()));
+        }
+        Ok(())
+    }
+
+    fn mac(&self, data: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("any key length");
+        mac.update(data);
+        mac
+    }
+}
+
+/// Records redeemed nonces until they expire, to reject replays.
+///
+/// Implement it over a shared database to run several verifier instances.
+pub trait ReplayStore: Send + Sync {
+    /// Record `id`, which may be forgotten after `expires_at` (UNIX seconds). Returns `false`
+    /// if `id` was already recorded.
+    fn insert(&self, id: &[u8], expires_at: u64) -> Result<bool>;
+}
+
+/// In-memory [`ReplayStore`] for a single verifier instance.
+#[derive(Debug, Default)]
+pub struct MemoryReplayStore {
+    entries: Mutex<HashMap<Vec<u8>, u64>>,
+}
+
+impl MemoryReplayStore {
+    pub fn new() -> Self {
+        MemoryReplayStore::default()
+    }
+}
+
+impl ReplayStore for MemoryReplayStore {
+    fn insert(&self, id: &[u8], expires_at: u64) -> Result<bool> {
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| TdxError::Nonce("Replay store lock poisoned".to_string()))?;
+        let now = unix_secs(SystemTime::now());
+        entries.retain(|_, expires_at| *expires_at >= now);
+        if entries.contains_key(id) {
+            return Ok(false);
+        }
+        entries.insert(id.to_vec(), expires_at);
+        Ok(true)
+    }
+}
+
+/// Response of `GET /nonce`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NonceResponse {
+    pub nonce: Nonce,
+    /// Expiry time of the nonce, in seconds since the UNIX epoch.
+    pub expires_at: u64,
+}
+
+/// Body of `POST /attest`: evidence whose report data is `SHA-512(nonce || runtime_data)`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestationRequest {
+    pub nonce: Nonce,
+    /// Data the attester binds to the quote along with the nonce, e.g. a public key.
+    #[serde(with = "serde_hex", default)]
+    pub runtime_data: Vec<u8>,
+    pub evidence: Evidence,
+}
+
+impl AttestationRequest {
+    /// Generate evidence on this TD answering `nonce`, binding `runtime_data` as well.
+    #[cfg(feature = "coco-provider")]
+    pub fn generate(
+        tdx: &Tdx,
+        nonce: Nonce,
+        runtime_data: Vec<u8>,
+        collaterals: Option<&CollateralProvider>,
+    ) -> Result<Self> {
+        let evidence = Evidence::generate(tdx, nonce.report_data(&runtime_data), collaterals)?;
+        Ok(AttestationRequest {
+            nonce,
+            runtime_data,
+            evidence,
+        })
+    }
+}
+
+/// Verifier side of the challenge-response protocol.
+pub struct ChallengeVerifier {
+    issuer: NonceIssuer,
+    store: Box<dyn ReplayStore>,
+    verifier: QuoteVerifier,
+}
+
+impl ChallengeVerifier {
+    /// Create a challenge verifier with an in-memory replay store.
+    pub fn new(issuer: NonceIssuer, verifier: QuoteVerifier) -> Self {
+        ChallengeVerifier {
+            issuer,
+            store: Box::new(MemoryReplayStore::new()),
+            verifier,
+        }
+    }
+
+    pub fn with_replay_store(mut self, store: Box<dyn ReplayStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Issue a nonce, answering `GET /nonce`.
+    pub fn challenge(&self) -> NonceResponse {
+        let nonce = self.issuer.issue();
+        NonceResponse {
+            nonce,
+            expires_at: nonce.issued_at() + self.issuer.ttl().as_secs(),
+        }
+    }
+
+    /// Verify an attestation answering one of our nonces. The nonce is consumed once the
+    /// evidence verifies, so replaying the same request fails, while a request that fails
+    /// verification cannot burn the nonce of the attester it was issued to.
+    pub fn verify(&self, request: &AttestationRequest) -> Result<VerifiedOutput> {
+        self.issuer.validate_at(&request.nonce, SystemTime::now())?;
+        let report_data = request.nonce.report_data(&request.runtime_data);
+        let output = self
+            .verifier
+            .verify_evidence(&request.evidence, &report_data)?;
+        self.issuer.redeem(&request.nonce, self.store.as_ref())?;
+        Ok(output)
+    }
+}
+
+/// Attester side of the challenge-response protocol, talking to a verifier over HTTP.
+#[cfg(feature = "coco-provider")]
+#[derive(Debug, Clone)]
+pub struct ChallengeClient {
+    base_url: String,
+    tdx: Tdx,
+    collaterals: Option<CollateralProvider>,
+}
+
+#[cfg(feature = "coco-provider")]
+impl ChallengeClient {
+    /// Create a client for the verifier at `base_url`, serving `GET /nonce` and `POST /attest`.
+    pub fn new(base_url: &str) -> Self {
+        ChallengeClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            tdx: Tdx::new(),
+            collaterals: None,
+        }
+    }
+
+    /// Send the collaterals of the quote along with it.
+    pub fn with_collaterals(mut self, collaterals: CollateralProvider) -> Self {
+        self.collaterals = Some(collaterals);
+        self
+    }
+
+    pub fn fetch_nonce(&self) -> Result<NonceResponse> {
+        let response = ureq::get(&format!("{}/nonce", self.base_url)).call()?;
+        Ok(response.into_json()?)
+    }
+
+    /// Fetch a nonce and generate evidence answering it, without submitting it.
+    pub fn prepare(&self, runtime_data: Vec<u8>) -> Result<AttestationRequest> {
+        let nonce = self.fetch_nonce()?.nonce;
+        AttestationRequest::generate(&self.tdx, nonce, runtime_data, self.collaterals.as_ref())
+    }
+
+    /// Fetch a nonce, answer it and submit the evidence, returning the verifier's response.
+    pub fn attest(&self, runtime_data: Vec<u8>) -> Result<serde_json::Value> {
+        let request = self.prepare(runtime_data)?;
+        let response = ureq::post(&format!("{}/attest", self.base_url)).send_json(&request)?;
+        Ok(response.into_json()?)
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verifier::CollateralProvider;
+    use std::sync::Arc;
+
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn validates_within_ttl_and_clock_skew() {
+        let issuer = NonceIssuer::new([7; 32]);
+        let nonce = issuer.issue_at(at(1_000));
+        assert!(issuer.validate_at(&nonce, at(1_000)).is_ok());
+        assert!(issuer.validate_at(&nonce, at(1_300)).is_ok());
+        assert!(issuer.validate_at(&nonce, at(1_301)).is_err());
+        // Issued by an instance whose clock runs ahead
+        assert!(issuer.validate_at(&nonce, at(970)).is_ok());
+        assert!(issuer.validate_at(&nonce, at(969)).is_err());
+        let strict = issuer.clone().with_clock_skew(Duration::ZERO);
+        assert!(strict.validate_at(&nonce, at(999)).is_err());
+
+        let other = NonceIssuer::new([8; 32]);
+        assert!(other.validate_at(&nonce, at(1_000)).is_err());
+    }
+
+    #[test]
+    fn rejects_replays() {
+        let issuer = NonceIssuer::new([7; 32]);
+        let store = MemoryReplayStore::new();
+        let nonce = issuer.issue();
+        assert!(issuer.redeem(&nonce, &store).is_ok());
+        assert!(issuer.redeem(&nonce, &store).is_err());
+    }
+
+    /// Replay store shared with the test, to observe redeemed nonces.
+    #[derive(Clone, Default)]
+    struct SharedStore(Arc<MemoryReplayStore>);
+
+    impl ReplayStore for SharedStore {
+        fn insert(&self, id: &[u8], expires_at: u64) -> Result<bool> {
+            self.0.insert(id, expires_at)
+        }
+    }
+
+    #[test]
+    fn keeps_nonce_when_verification_fails() {
+        let issuer = NonceIssuer::new([7; 32]);
+        let store = SharedStore::default();
+        let provider = CollateralProvider::Custom(Arc::new(|_: &[u8]| {
+            Err(TdxError::Dcap("no collaterals".to_string()))
+        }));
+        let challenges = ChallengeVerifier::new(issuer.clone(), QuoteVerifier::new(provider))
+            .with_replay_store(Box::new(store.clone()));
+
+        let nonce = challenges.challenge().nonce;
+        let request = AttestationRequest {
+            nonce,
+            runtime_data: Vec::new(),
+            evidence: Evidence {
+                quote: TDX_V4_QUOTE.to_vec(),
+                collaterals: None,
+            },
+        };
+        assert!(challenges.verify(&request).is_err());
+        assert!(issuer.redeem(&nonce, &store).is_ok());
+    }
+}
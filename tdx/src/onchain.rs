@@ -0,0 +1,148 @@
+use crate::error::{Result, TdxError};
+use crate::verified_output::VerifiedOutput;
+use alloy::primitives::{Address, Bytes, TxHash, U256};
+use alloy::providers::{Provider, ProviderBuilder};
+use alloy::signers::local::PrivateKeySigner;
+use alloy::sol;
+use automata_dcap_network_registry::Network;
+
+sol! {
+    #[sol(rpc)]
+    interface IAutomataDcapAttestation {
+        function verifyAndAttestOnChain(bytes calldata rawQuote)
+            external
+            payable
+            returns (bool success, bytes memory output);
+    }
+}
+
+/// Options for on-chain quote verification.
+///
+/// By default the RPC endpoint and the DCAP Attestation contract address are resolved from the
+/// default network of `automata_dcap_network_registry`. Both can be overridden, e.g. to point at
+/// a local anvil node with a freshly deployed verifier.
+#[derive(Debug, Clone, Default)]
+pub struct OnchainOptions {
+    /// RPC endpoint to use instead of the network's first endpoint.
+    pub rpc_url: Option<String>,
+    /// DCAP Attestation contract to use instead of the network's deployment.
+    pub dcap_attestation: Option<Address>,
+    /// Wei to attach to the call, covering the verifier fee if one is configured.
+    pub value: U256,
+}
+
+/// Result of a submitted on-chain verification transaction.
+#[derive(Debug, Clone)]
+pub struct OnchainSubmission {
+    pub tx_hash: TxHash,
+    pub block_number: Option<u64>,
+    pub verified_output: VerifiedOutput,
+}
+
+impl OnchainOptions {
+    /// Resolve the RPC URL and the DCAP Attestation contract address, consulting the network
+    /// registry only for the values that are not overridden.
+    fn resolve(&self) -> Result<(String, Address)> {
+        if let (Some(rpc_url), Some(address)) = (&self.rpc_url, self.dcap_attestation) {
+            return Ok((rpc_url.clone(), address));
+        }
+
+        let network = Network::default_network(None)
+            .ok_or_else(|| TdxError::Http("Failed to get network config".to_string()))?;
+        let rpc_url = match &self.rpc_url {
+            Some(url) => url.clone(),
+            None => network
+                .rpc_endpoints
+                .first()
+                .ok_or_else(|| TdxError::Http("No RPC endpoints available".to_string()))?
+                .clone(),
+        };
+        let address = self
+            .dcap_attestation
+            .unwrap_or(network.contracts.dcap.dcap_attestation);
+        Ok((rpc_url, address))
+    }
+}
+
+/// Run `verifyAndAttestOnChain` as an `eth_call` and decode the verified output.
+///
+/// No transaction is sent, so no signer is required.
+pub async fn verify_quote_onchain(
+    raw_quote: &[u8],
+    options: &OnchainOptions,
+) -> Result<VerifiedOutput> {
+    let (rpc_url, address) = options.resolve()?;
+    let provider = ProviderBuilder::new().connect_http(parse_rpc_url(&rpc_url)?);
+    call_verify(&provider, address, raw_quote, options.value).await
+}
+
+/// Sign and submit a `verifyAndAttestOnChain` transaction with `signer`.
+///
+/// The call is simulated first so that a failing verification is reported without spending
+/// gas, and so that the verified output can be returned alongside the receipt.
+pub async fn submit_quote_onchain(
+    raw_quote: &[u8],
+    signer: PrivateKeySigner,
+    options: &OnchainOptions,
+) -> Result<OnchainSubmission> {
+    let (rpc_url, address) = options.resolve()?;
+    let provider = ProviderBuilder::new()
+        .wallet(signer)
+        .connect_http(parse_rpc_url(&rpc_url)?);
+
+    let verified_output = call_verify(&provider, address, raw_quote, options.value).await?;
+
+    let contract = IAutomataDcapAttestation::new(address, &provider);
+    let receipt = contract
+        .verifyAndAttestOnChain(Bytes::copy_from_slice(raw_quote))
+        .value(options.value)
+        .send()
+        .await
+        .map_err(|e| TdxError::Contract(format!("Failed to send transaction: {e}")))?
+        .get_receipt()
+        .await
+        .map_err(|e| TdxError::Contract(format!("Failed to get transaction receipt: {e}")))?;
+
+    if !receipt.status() {
+        return Err(TdxError::Contract(format!(
+            "Verification transaction {} reverted",
+            receipt.transaction_hash
+        )));
+    }
+
+    Ok(OnchainSubmission {
+        tx_hash: receipt.transaction_hash,
+        block_number: receipt.block_number,
+        verified_output,
+    })
+}
+
+async fn call_verify<P: Provider>(
+    provider: &P,
+    address: Address,
+    raw_quote: &[u8],
+    value: U256,
+) -> Result<VerifiedOutput> {
+    let contract = IAutomataDcapAttestation::new(address, provider);
+    let ret = contract
+        .verifyAndAttestOnChain(Bytes::copy_from_slice(raw_quote))
+        .value(value)
+        .call()
+        .await
+        .map_err(|e| TdxError::Contract(format!("verifyAndAttestOnChain call failed: {e}")))?;
+
+    if !ret.success {
+        // On failure the contract returns the reason as a UTF-8 string in `output`.
+        return Err(TdxError::Dcap(format!(
+            "On-chain verification failed: {}",
+            String::from_utf8_lossy(&ret.output)
+        )));
+    }
+    VerifiedOutput::from_bytes_for_quote(&ret.output, raw_quote)
+}
+
+fn parse_rpc_url(rpc_url: &str) -> Result<alloy::transports::http::reqwest::Url> {
+    rpc_url
+        .parse()
+        .map_err(|e| TdxError::Http(format!("Failed to parse RPC URL: {}", e)))
+}
@@ -0,0 +1,517 @@
+use crate::collateral::{split_signed_json, RawCollaterals};
+use crate::error::{Result, TdxError};
+use crate::pcs::PcsClient;
+use crate::utils::{get_pck_fmspc_and_issuer, PckCA, TeeType};
+use alloy::network::Ethereum;
+use alloy::primitives::{Address, Bytes, TxHash, U256};
+use alloy::providers::{PendingTransactionBuilder, ProviderBuilder};
+use alloy::signers::local::PrivateKeySigner;
+use alloy::sol;
+use alloy::sol_types::{Panic, Revert, SolError};
+use automata_dcap_network_registry::Network;
+use dcap_rs::types::quote::Quote;
+use std::path::PathBuf;
+
+sol! {
+    #[sol(rpc)]
+    interface IFmspcTcbDao {
+        struct TcbInfoJsonObj {
+            string tcbInfoStr;
+            bytes signature;
+        }
+
+        function getTcbInfo(uint256 tcbType, string calldata fmspc, uint256 version)
+            external
+            view
+            returns (TcbInfoJsonObj memory tcbObj);
+
+        function upsertFmspcTcb(TcbInfoJsonObj calldata tcbInfoObj)
+            external
+            returns (bytes32 attestationId);
+    }
+
+    #[sol(rpc)]
+    interface IEnclaveIdentityDao {
+        struct EnclaveIdentityJsonObj {
+            string identityStr;
+            bytes signature;
+        }
+
+        function getEnclaveIdentity(uint256 id, uint256 version)
+            external
+            view
+            returns (EnclaveIdentityJsonObj memory enclaveIdObj);
+
+        function upsertEnclaveIdentity(
+            uint256 id,
+            uint256 version,
+            EnclaveIdentityJsonObj calldata enclaveIdentityObj
+        ) external returns (bytes32 attestationId);
+    }
+
+    #[sol(rpc)]
+    interface IPcsDao {
+        enum CA {
+            ROOT,
+            PROCESSOR,
+            PLATFORM,
+            SIGNING
+        }
+
+        function getCertificateById(CA ca) external view returns (bytes memory cert, bytes memory crl);
+
+        function upsertPcsCertificates(CA ca, bytes calldata cert) external returns (bytes32 attestationId);
+
+        function upsertPckCrl(CA ca, bytes calldata crl) external returns (bytes32 attestationId);
+
+        function upsertRootCACrl(bytes calldata rootcacrl) external returns (bytes32 attestationId);
+    }
+}
+
+// Versions of the collaterals stored by the DAOs
+const TCB_INFO_VERSION: u64 = 3;
+const ENCLAVE_IDENTITY_VERSION: u64 = 4;
+// Enclave identity IDs in the EnclaveIdentityDao
+const ENCLAVE_ID_QE: u64 = 0;
+const ENCLAVE_ID_TD_QE: u64 = 2;
+
+/// Where to obtain the collaterals that are missing on-chain.
+#[derive(Debug, Clone)]
+pub enum CollateralSource {
+    /// Fetch from the Intel PCS, or a PCCS exposing the same API.
+    Pcs(PcsClient),
+    /// Read from a JSON file saved with [`RawCollaterals::save`].
+    File(PathBuf),
+}
+
+/// Options for uploading collaterals to the on-chain PCCS.
+///
+/// The RPC endpoint and the DAO addresses default to the deployment of the default network in
+/// `automata_dcap_network_registry`.
+#[derive(Debug, Clone, Default)]
+pub struct PccsUploadOptions {
+    pub rpc_url: Option<String>,
+    pub fmspc_tcb_dao: Option<Address>,
+    pub enclave_id_dao: Option<Address>,
+    pub pcs_dao: Option<Address>,
+}
+
+/// A single collateral item stored by the on-chain PCCS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollateralItem {
+    TcbInfo,
+    QeIdentity,
+    RootCa,
+    TcbSigningCa,
+    RootCaCrl,
+    PckCrl(PckCA),
+}
+
+/// A collateral item written to the on-chain PCCS.
+#[derive(Debug, Clone)]
+pub struct UploadedCollateral {
+    pub item: CollateralItem,
+    pub tx_hash: TxHash,
+}
+
+/// Outcome of [`upload_missing_collaterals`].
+#[derive(Debug, Clone, Default)]
+pub struct UploadReport {
+    /// Items that were missing and have been written.
+    pub written: Vec<UploadedCollateral>,
+    /// Items that were already present on-chain.
+    pub present: Vec<CollateralItem>,
+}
+
+struct DaoAddresses {
+    fmspc_tcb_dao: Address,
+    enclave_id_dao: Address,
+    pcs_dao: Address,
+}
+
+impl PccsUploadOptions {
+    fn resolve(&self) -> Result<(String, DaoAddresses)> {
+        if let (Some(rpc_url), Some(fmspc_tcb_dao), Some(enclave_id_dao), Some(pcs_dao)) = (
+            &self.rpc_url,
+            self.fmspc_tcb_dao,
+            self.enclave_id_dao,
+            self.pcs_dao,
+        ) {
+            return Ok((
+                rpc_url.clone(),
+                DaoAddresses {
+                    fmspc_tcb_dao,
+                    enclave_id_dao,
+                    pcs_dao,
+                },
+            ));
+        }
+
+        let network = Network::default_network(None)
+            .ok_or_else(|| TdxError::Http("Failed to get network config".to_string()))?;
+        let rpc_url = match &self.rpc_url {
+            Some(url) => url.clone(),
+            None => network
+                .rpc_endpoints
+                .first()
+                .ok_or_else(|| TdxError::Http("No RPC endpoints available".to_string()))?
+                .clone(),
+        };
+        let pccs = &network.contracts.pccs;
+        Ok((
+            rpc_url,
+            DaoAddresses {
+                fmspc_tcb_dao: self.fmspc_tcb_dao.unwrap_or(pccs.fmspc_tcb_dao),
+                enclave_id_dao: self.enclave_id_dao.unwrap_or(pccs.enclave_id_dao),
+                pcs_dao: self.pcs_dao.unwrap_or(pccs.pcs_dao),
+            },
+        ))
+    }
+}
+
+/// Upload the collaterals required to verify `raw_quote` that are missing from the on-chain
+/// PCCS, in transactions signed by `signer`.
+///
+/// Each item is checked individually, so only the missing ones are fetched from `source` and
+/// written.
+pub async fn upload_missing_collaterals(
+    raw_quote: &[u8],
+    source: &CollateralSource,
+    signer: PrivateKeySigner,
+    options: &PccsUploadOptions,
+) -> Result<UploadReport> {
+    let quote = Quote::read(&mut &*raw_quote)?;
+    let tee_type = TeeType::from_header(quote.header.tee_type)?;
+    let (fmspc, pck_ca) = get_pck_fmspc_and_issuer(&quote)?;
+
+    let (rpc_url, daos) = options.resolve()?;
+    let rpc_url = rpc_url
+        .parse()
+        .map_err(|e| TdxError::Http(format!("Failed to parse RPC URL: {}", e)))?;
+    let provider = ProviderBuilder::new().wallet(signer).connect_http(rpc_url);
+
+    let fmspc_tcb_dao = IFmspcTcbDao::new(daos.fmspc_tcb_dao, &provider);
+    let enclave_id_dao = IEnclaveIdentityDao::new(daos.enclave_id_dao, &provider);
+    let pcs_dao = IPcsDao::new(daos.pcs_dao, &provider);
+
+    let tcb_type = U256::from(match tee_type {
+        TeeType::Sgx => 0u64,
+        TeeType::Tdx => 1u64,
+    });
+    let enclave_id = U256::from(match tee_type {
+        TeeType::Sgx => ENCLAVE_ID_QE,
+        TeeType::Tdx => ENCLAVE_ID_TD_QE,
+    });
+    let pck_ca_id = match pck_ca {
+        PckCA::Platform => IPcsDao::CA::PLATFORM,
+        PckCA::Processor => IPcsDao::CA::PROCESSOR,
+    };
+
+    // A DAO either returns empty data or reverts on unknown keys
+    let tcb_info = read_dao(
+        fmspc_tcb_dao
+            .getTcbInfo(tcb_type, fmspc.clone(), U256::from(TCB_INFO_VERSION))
+            .call()
+            .await,
+    )?;
+    let qe_identity = read_dao(
+        enclave_id_dao
+            .getEnclaveIdentity(enclave_id, U256::from(ENCLAVE_IDENTITY_VERSION))
+            .call()
+            .await,
+    )?;
+    let root = read_dao(pcs_dao.getCertificateById(IPcsDao::CA::ROOT).call().await)?;
+    let signing = read_dao(
+        pcs_dao
+            .getCertificateById(IPcsDao::CA::SIGNING)
+            .call()
+            .await,
+    )?;
+    let pck = read_dao(pcs_dao.getCertificateById(pck_ca_id).call().await)?;
+    let plan = plan_upload(&[
+        (
+            CollateralItem::TcbInfo,
+            tcb_info.is_some_and(|obj| !obj.tcbInfoStr.is_empty()),
+        ),
+        (
+            CollateralItem::QeIdentity,
+            qe_identity.is_some_and(|obj| !obj.identityStr.is_empty()),
+        ),
+        (
+            CollateralItem::RootCa,
+            root.as_ref().is_some_and(|ret| !ret.cert.is_empty()),
+        ),
+        (
+            CollateralItem::TcbSigningCa,
+            signing.is_some_and(|ret| !ret.cert.is_empty()),
+        ),
+        (
+            CollateralItem::RootCaCrl,
+            root.is_some_and(|ret| !ret.crl.is_empty()),
+        ),
+        (
+            CollateralItem::PckCrl(pck_ca),
+            pck.is_some_and(|ret| !ret.crl.is_empty()),
+        ),
+    ]);
+    let mut report = UploadReport {
+        written: Vec::new(),
+        present: plan.present,
+    };
+    if plan.missing.is_empty() {
+        return Ok(report);
+    }
+
+    let collaterals = match source {
+        CollateralSource::Pcs(client) => client.get_collaterals(tee_type, &fmspc, pck_ca)?,
+        CollateralSource::File(path) => RawCollaterals::load(path)?,
+    };
+
+    for item in plan.missing {
+        let call_result = match item {
+            CollateralItem::TcbInfo => {
+                let (tcb_info_str, signature) =
+                    split_signed_json(&collaterals.tcb_info, "tcbInfo")?;
+                let obj = IFmspcTcbDao::TcbInfoJsonObj {
+                    tcbInfoStr: tcb_info_str,
+                    signature: signature.into(),
+                };
+                send(fmspc_tcb_dao.upsertFmspcTcb(obj).send().await).await
+            }
+            CollateralItem::QeIdentity => {
+                let (identity_str, signature) =
+                    split_signed_json(&collaterals.qe_identity, "enclaveIdentity")?;
+                let obj = IEnclaveIdentityDao::EnclaveIdentityJsonObj {
+                    identityStr: identity_str,
+                    signature: signature.into(),
+                };
+                send(
+                    enclave_id_dao
+                        .upsertEnclaveIdentity(
+                            enclave_id,
+                            U256::from(ENCLAVE_IDENTITY_VERSION),
+                            obj,
+                        )
+                        .send()
+                        .await,
+                )
+                .await
+            }
+            CollateralItem::RootCa => {
+                let cert = Bytes::copy_from_slice(&collaterals.root_ca);
+                send(
+                    pcs_dao
+                        .upsertPcsCertificates(IPcsDao::CA::ROOT, cert)
+                        .send()
+                        .await,
+                )
+                .await
+            }
+            CollateralItem::TcbSigningCa => {
+                let cert = Bytes::copy_from_slice(&collaterals.tcb_signing_ca);
+                send(
+                    pcs_dao
+                        .upsertPcsCertificates(IPcsDao::CA::SIGNING, cert)
+                        .send()
+                        .await,
+                )
+                .await
+            }
+            CollateralItem::RootCaCrl => {
+                let crl = Bytes::copy_from_slice(&collaterals.root_ca_crl);
+                send(pcs_dao.upsertRootCACrl(crl).send().await).await
+            }
+            CollateralItem::PckCrl(_) => {
+                let crl = Bytes::copy_from_slice(&collaterals.pck_crl);
+                send(pcs_dao.upsertPckCrl(pck_ca_id, crl).send().await).await
+            }
+        };
+        let tx_hash = call_result
+            .map_err(|e| TdxError::Contract(format!("Failed to upload {item:?}: {e}")))?;
+        report.written.push(UploadedCollateral { item, tx_hash });
+    }
+
+    Ok(report)
+}
+
+/// Wait for a sent transaction to be mined, returning its hash if it succeeded.
+async fn send(
+    pending: std::result::Result<PendingTransactionBuilder<Ethereum>, alloy::contract::Error>,
+) -> std::result::Result<TxHash, String> {
+    let receipt = pending
+        .map_err(|e| e.to_string())?
+        .get_receipt()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !receipt.status() {
+        return Err(format!("transaction {} reverted", receipt.transaction_hash));
+    }
+    Ok(receipt.transaction_hash)
+}
+
+/// Collateral items split by whether the on-chain PCCS already has them.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct UploadPlan {
+    /// Items to write, in upload order.
+    missing: Vec<CollateralItem>,
+    present: Vec<CollateralItem>,
+}
+
+/// Plan the upload of the items checked on-chain, given as `(item, present)`. The DAOs verify
+/// each item against certificates and CRLs already on-chain, so the root CA goes first, then
+/// the TCB signing CA it issued, then the CRLs, and last the TCB info and QE identity signed
+/// by the TCB signing CA.
+fn plan_upload(checked: &[(CollateralItem, bool)]) -> UploadPlan {
+    let mut plan = UploadPlan::default();
+    for &(item, present) in checked {
+        if present {
+            plan.present.push(item);
+        } else {
+            plan.missing.push(item);
+        }
+    }
+    plan.missing.sort_by_key(|item| match item {
+        CollateralItem::RootCa => 0,
+        CollateralItem::TcbSigningCa => 1,
+        CollateralItem::RootCaCrl | CollateralItem::PckCrl(_) => 2,
+        CollateralItem::TcbInfo | CollateralItem::QeIdentity => 3,
+    });
+    plan
+}
+
+/// The value returned by a DAO read, or `None` if the read reverted because the key is unknown.
+/// Failures to reach the node or to decode its answer, reverts without revert data and panics
+/// are returned.
+fn read_dao<T>(read: std::result::Result<T, alloy::contract::Error>) -> Result<Option<T>> {
+    use alloy::contract::Error;
+
+    let err = match read {
+        Ok(value) => return Ok(Some(value)),
+        Err(err) => err,
+    };
+    if let Some(data) = err.as_revert_data() {
+        check_revert(&data)?;
+        return Ok(None);
+    }
+    Err(match &err {
+        Error::TransportError(e) if e.as_error_resp().is_none() => {
+            TdxError::Http(format!("DAO read failed: {err}"))
+        }
+        _ => TdxError::Contract(format!("DAO read failed: {err}")),
+    })
+}
+
+/// Decode the revert data of a DAO read. A DAO reverts on unknown keys with no data, a reason
+/// string or a custom error, while a panic (`Panic(uint256)`) is a failure of the DAO.
+fn check_revert(data: &[u8]) -> Result<()> {
+    if data.starts_with(&Panic::SELECTOR) {
+        let panic = Panic::abi_decode(data)
+            .map_err(|e| TdxError::Contract(format!("Invalid DAO panic data: {e}")))?;
+        return Err(TdxError::Contract(format!("DAO read failed: {panic}")));
+    }
+    if data.starts_with(&Revert::SELECTOR) {
+        Revert::abi_decode(data)
+            .map_err(|e| TdxError::Contract(format!("Invalid DAO revert reason: {e}")))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::transports::RpcError;
+
+    /// A DAO read failing with the JSON-RPC error `payload`.
+    fn rpc_error(payload: &str) -> alloy::contract::Error {
+        alloy::contract::Error::TransportError(RpcError::ErrorResp(
+            serde_json::from_str(payload).unwrap(),
+        ))
+    }
+
+    fn reverted(data: &[u8]) -> alloy::contract::Error {
+        rpc_error(&format!(
+            r#"{{"code": 3, "message": "execution reverted", "data": "0x{}"}}"#,
+            hex::encode(data)
+        ))
+    }
+
+    #[test]
+    fn plans_uploads_in_dependency_order() {
+        use CollateralItem::*;
+
+        let all = [
+            TcbInfo,
+            QeIdentity,
+            RootCa,
+            TcbSigningCa,
+            RootCaCrl,
+            PckCrl(PckCA::Platform),
+        ];
+        let checked = |present: &[CollateralItem]| all.map(|item| (item, present.contains(&item)));
+
+        let plan = plan_upload(&checked(&[]));
+        assert_eq!(
+            plan.missing,
+            [
+                RootCa,
+                TcbSigningCa,
+                RootCaCrl,
+                PckCrl(PckCA::Platform),
+                TcbInfo,
+                QeIdentity
+            ]
+        );
+        assert!(plan.present.is_empty());
+
+        assert_eq!(
+            plan_upload(&checked(&all)),
+            UploadPlan {
+                missing: Vec::new(),
+                present: all.to_vec(),
+            }
+        );
+
+        let plan = plan_upload(&checked(&[RootCa, RootCaCrl, QeIdentity]));
+        assert_eq!(
+            plan.missing,
+            [TcbSigningCa, PckCrl(PckCA::Platform), TcbInfo]
+        );
+        assert_eq!(plan.present, [QeIdentity, RootCa, RootCaCrl]);
+    }
+
+    #[test]
+    fn decodes_revert_data() {
+        // Unknown keys: a bare revert, a reason string or a custom error
+        assert!(check_revert(&[]).is_ok());
+        assert!(check_revert(&Revert::from("not found").abi_encode()).is_ok());
+        assert!(check_revert(&[0xde, 0xad, 0xbe, 0xef]).is_ok());
+
+        let err = check_revert(&Panic::from(0x32).abi_encode()).unwrap_err();
+        assert!(matches!(err, TdxError::Contract(_)), "{err}");
+        assert!(err.to_string().contains("out-of-bounds"), "{err}");
+        // A reason string that does not decode
+        assert!(check_revert(&Revert::SELECTOR).is_err());
+    }
+
+    #[test]
+    fn reads_dao_values() {
+        assert_eq!(read_dao(Ok(1)).unwrap(), Some(1));
+        assert_eq!(read_dao::<()>(Err(reverted(&[]))).unwrap(), None);
+        let reason = Revert::from("unknown FMSPC").abi_encode();
+        assert_eq!(read_dao::<()>(Err(reverted(&reason))).unwrap(), None);
+
+        let panic = Panic::from(0x11).abi_encode();
+        assert!(matches!(
+            read_dao::<()>(Err(reverted(&panic))),
+            Err(TdxError::Contract(_))
+        ));
+        // Errors that are not reverts are returned, not taken as missing collaterals
+        let err = rpc_error(r#"{"code": -32000, "message": "header not found"}"#);
+        assert!(matches!(
+            read_dao::<()>(Err(err)),
+            Err(TdxError::Contract(_))
+        ));
+        let err = alloy::contract::Error::TransportError(RpcError::NullResp);
+        assert!(matches!(read_dao::<()>(Err(err)), Err(TdxError::Http(_))));
+    }
+}
@@ -0,0 +1,397 @@
+use crate::error::{Result, TdxError};
+use dcap_rs::types::quote::Quote;
+use dcap_rs::utils::cert_chain_processor;
+use x509_parser::oid_registry::asn1_rs::{oid, Any, FromDer, Oid, Sequence, Tag};
+use x509_parser::prelude::{parse_x509_pem, X509Certificate};
+
+const SGX_EXTENSIONS_OID: &str = "1.2.840.113741.1.13.1";
+const TCB_OID: &str = "1.2.840.113741.1.13.1.2";
+const CONFIGURATION_OID: &str = "1.2.840.113741.1.13.1.7";
+
+/// SGX type of the platform, from the PCK certificate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SgxType {
+    Standard,
+    Scalable,
+    ScalableWithIntegrity,
+}
+
+/// TCB level the PCK certificate was issued for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PckTcb {
+    /// SGX TCB component SVNs 1 to 16.
+    pub sgx_tcb_comp_svn: [u8; 16],
+    pub pcesvn: u16,
+    pub cpusvn: [u8; 16],
+}
+
+/// Platform configuration flags, only present in certificates issued by the PCK Platform CA.
+/// A flag is `None` when the platform does not report it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlatformConfiguration {
+    pub dynamic_platform: Option<bool>,
+    pub cached_keys: Option<bool>,
+    pub smt_enabled: Option<bool>,
+}
+
+/// The SGX extensions (OID 1.2.840.113741.1.13.1) of a PCK certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SgxExtensions {
+    pub ppid: [u8; 16],
+    pub tcb: PckTcb,
+    pub pce_id: [u8; 2],
+    pub fmspc: [u8; 6],
+    pub sgx_type: SgxType,
+    /// Only present in certificates issued by the PCK Platform CA.
+    pub platform_instance_id: Option<[u8; 16]>,
+    /// Only present in certificates issued by the PCK Platform CA.
+    pub configuration: Option<PlatformConfiguration>,
+}
+
+impl SgxExtensions {
+    /// Parse the SGX extensions of a PCK certificate.
+    pub fn from_certificate<'a>(cert: &'a X509Certificate<'a>) -> Result<Self> {
+        let sgx_extensions_bytes = cert
+            .get_extension_unique(&oid!(1.2.840 .113741 .1 .13 .1))
+            .map_err(|e| TdxError::X509(format!("Duplicate SGX extensions in certificate: {e}")))?
+            .ok_or_else(|| {
+                TdxError::X509(format!(
+                    "Certificate missing SGX extensions (OID {SGX_EXTENSIONS_OID})"
+                ))
+            })?
+            .value;
+        Self::from_der(sgx_extensions_bytes)
+    }
+
+    /// Parse the DER-encoded value of the SGX extensions.
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        let mut ppid = None;
+        let mut tcb = None;
+        let mut pce_id = None;
+        let mut fmspc = None;
+        let mut sgx_type = None;
+        let mut platform_instance_id = None;
+        let mut configuration = None;
+
+        for (id, value) in parse_entries(der, SGX_EXTENSIONS_OID)? {
+            match id.as_str() {
+                "1" => ppid = Some(octets::<16>(&value, "PPID")?),
+                "2" => tcb = Some(parse_tcb(&value)?),
+                "3" => pce_id = Some(octets::<2>(&value, "PCE-ID")?),
+                "4" => fmspc = Some(octets::<6>(&value, "FMSPC")?),
+                "5" => {
+                    sgx_type = Some(match integer(&value, Tag::Enumerated, "SGX Type")? {
+                        0 => SgxType::Standard,
+                        1 => SgxType::Scalable,
+                        2 => SgxType::ScalableWithIntegrity,
+                        other => return Err(TdxError::X509(format!("Unknown SGX Type: {other}"))),
+                    })
+                }
+                "6" => platform_instance_id = Some(octets::<16>(&value, "PlatformInstanceID")?),
+                "7" => configuration = Some(parse_configuration(&value)?),
+                // Unknown entries are skipped for forward compatibility
+                _ => {}
+            }
+        }
+
+        let missing = |name: &str| TdxError::X509(format!("{name} not found in SGX extensions"));
+        Ok(SgxExtensions {
+            ppid: ppid.ok_or_else(|| missing("PPID"))?,
+            tcb: tcb.ok_or_else(|| missing("TCB"))?,
+            pce_id: pce_id.ok_or_else(|| missing("PCE-ID"))?,
+            fmspc: fmspc.ok_or_else(|| missing("FMSPC"))?,
+            sgx_type: sgx_type.ok_or_else(|| missing("SGX Type"))?,
+            platform_instance_id,
+            configuration,
+        })
+    }
+}
+
+/// Parse the SGX extensions of the PCK certificate embedded in a quote.
+pub fn get_pck_sgx_extensions(quote: &Quote) -> Result<SgxExtensions> {
+    get_pck_sgx_extensions_from_chain(&quote.signature.cert_data.cert_data)
+}
+
+/// Parse the SGX extensions of the first certificate of a PEM-encoded PCK certificate chain.
+pub fn get_pck_sgx_extensions_from_chain(raw_cert_data: &[u8]) -> Result<SgxExtensions> {
+    let ranges = cert_chain_processor::find_certificate_ranges(raw_cert_data);
+    let (pck_start, pck_end) = *ranges
+        .first()
+        .ok_or_else(|| TdxError::Dcap("No certificates found".to_string()))?;
+    let (_, pem_struct) = parse_x509_pem(&raw_cert_data[pck_start..pck_end])
+        .map_err(|e| TdxError::X509(format!("x509_parser error: {e}")))?;
+    let pck = pem_struct
+        .parse_x509()
+        .map_err(|e| TdxError::X509(format!("x509 error: {e}")))?;
+    SgxExtensions::from_certificate(&pck)
+}
+
+/// An SGX extension entry: the last arc of its OID, and its DER-encoded value.
+type Entry = (String, Vec<u8>);
+
+/// Parse a `SEQUENCE OF SEQUENCE { OID, value }` whose OIDs are direct children of `parent`,
+/// identifying each entry by the last arc of its OID (e.g. `"4"` for FMSPC, `"17"` for PCESVN
+/// within the TCB sequence).
+fn parse_entries(der: &[u8], parent: &str) -> Result<Vec<Entry>> {
+    let (_, outer) = Sequence::from_der(der)
+        .map_err(|e| TdxError::X509(format!("Failed to parse SGX extensions sequence: {e}")))?;
+
+    let mut entries = Vec::new();
+    let mut i = outer.content.as_ref();
+    while !i.is_empty() {
+        let (j, entry) = Sequence::from_der(i)
+            .map_err(|e| TdxError::X509(format!("Failed to parse SGX extension entry: {e}")))?;
+        i = j;
+        let (value, entry_oid) = Oid::from_der(entry.content.as_ref())
+            .map_err(|e| TdxError::X509(format!("Failed to parse SGX extension OID: {e}")))?;
+        let id = entry_oid.to_id_string();
+        let arc = id
+            .strip_prefix(parent)
+            .and_then(|rest| rest.strip_prefix('.'))
+            .filter(|arc| !arc.is_empty() && !arc.contains('.'))
+            .ok_or_else(|| {
+                TdxError::X509(format!("Unexpected SGX extension OID {id} under {parent}"))
+            })?;
+        entries.push((arc.to_string(), value.to_vec()));
+    }
+    Ok(entries)
+}
+
+/// Parse the TCB sequence, which must contain all 16 SGX TCB component SVNs, the PCESVN and
+/// the CPUSVN.
+fn parse_tcb(der: &[u8]) -> Result<PckTcb> {
+    let mut tcb = PckTcb::default();
+    let mut found = [false; 18];
+    for (id, value) in parse_entries(der, TCB_OID)? {
+        let component: usize = id
+            .parse()
+            .map_err(|_| TdxError::X509(format!("Unexpected TCB entry: {id}")))?;
+        match component {
+            1..=16 => {
+                let svn = integer(&value, Tag::Integer, "SGX TCB component SVN")?;
+                tcb.sgx_tcb_comp_svn[component - 1] = u8::try_from(svn).map_err(|_| {
+                    TdxError::X509(format!("SGX TCB component {component} SVN out of range"))
+                })?;
+            }
+            17 => {
+                let svn = integer(&value, Tag::Integer, "PCESVN")?;
+                tcb.pcesvn = u16::try_from(svn)
+                    .map_err(|_| TdxError::X509("PCESVN out of range".to_string()))?;
+            }
+            18 => tcb.cpusvn = octets::<16>(&value, "CPUSVN")?,
+            _ => continue,
+        }
+        found[component - 1] = true;
+    }
+    if let Some(missing) = found.iter().position(|found| !found) {
+        return Err(TdxError::X509(format!(
+            "TCB component {} not found in SGX extensions",
+            missing + 1
+        )));
+    }
+    Ok(tcb)
+}
+
+fn parse_configuration(der: &[u8]) -> Result<PlatformConfiguration> {
+    let mut configuration = PlatformConfiguration::default();
+    for (id, value) in parse_entries(der, CONFIGURATION_OID)? {
+        match id.as_str() {
+            "1" => configuration.dynamic_platform = Some(boolean(&value, "DynamicPlatform")?),
+            "2" => configuration.cached_keys = Some(boolean(&value, "CachedKeys")?),
+            "3" => configuration.smt_enabled = Some(boolean(&value, "SMTEnabled")?),
+            _ => {}
+        }
+    }
+    Ok(configuration)
+}
+
+fn any_with_tag<'a>(der: &'a [u8], tag: Tag, name: &str) -> Result<Any<'a>> {
+    let (_, any) =
+        Any::from_der(der).map_err(|e| TdxError::X509(format!("Failed to parse {name}: {e}")))?;
+    if any.tag() != tag {
+        return Err(TdxError::X509(format!(
+            "{name} has unexpected tag {:?} (expected {:?})",
+            any.tag(),
+            tag
+        )));
+    }
+    Ok(any)
+}
+
+fn octets<const N: usize>(der: &[u8], name: &str) -> Result<[u8; N]> {
+    let any = any_with_tag(der, Tag::OctetString, name)?;
+    any.data.try_into().map_err(|_| {
+        TdxError::X509(format!(
+            "{name} has unexpected length: {} (expected {N})",
+            any.data.len()
+        ))
+    })
+}
+
+fn integer(der: &[u8], tag: Tag, name: &str) -> Result<u64> {
+    let any = any_with_tag(der, tag, name)?;
+    if any.data.is_empty() || any.data[0] & 0x80 != 0 {
+        return Err(TdxError::X509(format!(
+            "{name} is not a valid unsigned integer"
+        )));
+    }
+    // A leading zero byte only carries the sign, at most 8 bytes of magnitude fit in a u64
+    let magnitude = match any.data {
+        [0, rest @ ..] if !rest.is_empty() => rest,
+        data => data,
+    };
+    if magnitude.len() > 8 {
+        return Err(TdxError::X509(format!("{name} does not fit in 64 bits")));
+    }
+    Ok(magnitude.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+}
+
+fn boolean(der: &[u8], name: &str) -> Result<bool> {
+    let any = any_with_tag(der, Tag::Boolean, name)?;
+    match any.data {
+        [0x00] => Ok(false),
+        [_] => Ok(true),
+        _ => Err(TdxError::X509(format!("{name} is not a valid boolean"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut der = vec![tag];
+        if content.len() < 0x80 {
+            der.push(content.len() as u8);
+        } else {
+            der.extend_from_slice(&[0x82, (content.len() >> 8) as u8, content.len() as u8]);
+        }
+        der.extend_from_slice(content);
+        der
+    }
+
+    fn oid(id: &str) -> Vec<u8> {
+        let arcs: Vec<u64> = id.split('.').map(|arc| arc.parse().unwrap()).collect();
+        let mut content = vec![(arcs[0] * 40 + arcs[1]) as u8];
+        for &arc in &arcs[2..] {
+            let mut bytes = vec![(arc & 0x7f) as u8];
+            let mut rest = arc >> 7;
+            while rest > 0 {
+                bytes.push((rest & 0x7f) as u8 | 0x80);
+                rest >>= 7;
+            }
+            content.extend(bytes.iter().rev());
+        }
+        tlv(0x06, &content)
+    }
+
+    fn sequence(entries: &[(String, Vec<u8>)]) -> Vec<u8> {
+        let content: Vec<u8> = entries
+            .iter()
+            .flat_map(|(id, value)| tlv(0x30, &[oid(id), value.clone()].concat()))
+            .collect();
+        tlv(0x30, &content)
+    }
+
+    fn tcb(components: impl Iterator<Item = u64>) -> Vec<u8> {
+        let entries: Vec<_> = components
+            .map(|component| {
+                let value = match component {
+                    18 => tlv(0x04, &[0; 16]),
+                    _ => tlv(0x02, &[1]),
+                };
+                (format!("{TCB_OID}.{component}"), value)
+            })
+            .collect();
+        sequence(&entries)
+    }
+
+    fn extensions(tcb: Vec<u8>) -> Vec<u8> {
+        let entry = |arc: u32, value: Vec<u8>| (format!("{SGX_EXTENSIONS_OID}.{arc}"), value);
+        sequence(&[
+            entry(1, tlv(0x04, &[0x11; 16])),
+            entry(2, tcb),
+            entry(3, tlv(0x04, &[0, 0])),
+            entry(4, tlv(0x04, &[0x00, 0x90, 0x6e, 0xd5, 0x00, 0x00])),
+            entry(5, tlv(0x0a, &[0])),
+        ])
+    }
+
+    #[test]
+    fn parses_pck_certificate_of_test_quote() {
+        let quote = Quote::read(&mut &*TDX_V4_QUOTE).unwrap();
+        let extensions = get_pck_sgx_extensions(&quote).unwrap();
+
+        let ppid = hex::decode("52e85f3a8e334936300e5f059bae8227").unwrap();
+        assert_eq!(extensions.ppid[..], ppid);
+        assert_eq!(
+            extensions.tcb.sgx_tcb_comp_svn,
+            [8, 8, 2, 2, 4, 1, 0, 6, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(extensions.tcb.pcesvn, 11);
+        assert_eq!(
+            extensions.tcb.cpusvn,
+            [8, 8, 2, 2, 4, 1, 0, 6, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(extensions.pce_id, [0, 0]);
+        assert_eq!(extensions.fmspc, [0x00, 0x80, 0x6f, 0x05, 0x00, 0x00]);
+        assert_eq!(extensions.sgx_type, SgxType::Scalable);
+        assert!(extensions.platform_instance_id.is_some());
+        assert_eq!(
+            extensions.configuration,
+            Some(PlatformConfiguration {
+                dynamic_platform: Some(true),
+                cached_keys: Some(false),
+                smt_enabled: Some(true),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_complete_tcb() {
+        let extensions = SgxExtensions::from_der(&extensions(tcb(1..=18))).unwrap();
+        assert_eq!(extensions.tcb.sgx_tcb_comp_svn, [1; 16]);
+        assert_eq!(extensions.tcb.pcesvn, 1);
+        assert_eq!(extensions.sgx_type, SgxType::Standard);
+        assert_eq!(extensions.platform_instance_id, None);
+    }
+
+    #[test]
+    fn rejects_missing_tcb_component() {
+        let der = extensions(tcb((1..=18).filter(|&component| component != 17)));
+        assert!(SgxExtensions::from_der(&der).is_err());
+    }
+
+    #[test]
+    fn rejects_foreign_oids() {
+        // Entry under another arc that happens to end in ".4"
+        let mut der = extensions(tcb(1..=18));
+        let fmspc = oid(&format!("{SGX_EXTENSIONS_OID}.4"));
+        let foreign = oid("1.2.840.113741.1.13.2.4");
+        assert_eq!(fmspc.len(), foreign.len());
+        let at = der
+            .windows(fmspc.len())
+            .position(|window| window == fmspc)
+            .unwrap();
+        der[at..at + fmspc.len()].copy_from_slice(&foreign);
+        assert!(SgxExtensions::from_der(&der).is_err());
+
+        // Entry nested below the expected arc
+        let nested = (format!("{SGX_EXTENSIONS_OID}.4.1"), tlv(0x04, &[0; 6]));
+        assert!(parse_entries(&sequence(&[nested]), SGX_EXTENSIONS_OID).is_err());
+    }
+
+    #[test]
+    fn bounds_integers_to_64_bits() {
+        let max = [&[0x00][..], &[0xff; 8]].concat();
+        assert_eq!(
+            integer(&tlv(0x02, &max), Tag::Integer, "test").unwrap(),
+            u64::MAX
+        );
+        assert!(integer(&tlv(0x02, &[0x01; 9]), Tag::Integer, "test").is_err());
+        assert!(integer(&tlv(0x02, &[0x80]), Tag::Integer, "test").is_err());
+        assert!(integer(&tlv(0x02, &[]), Tag::Integer, "test").is_err());
+    }
+}
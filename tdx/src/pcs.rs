@@ -0,0 +1,422 @@
+use crate::cert_data::PpidInfo;
+use crate::collateral::RawCollaterals;
+use crate::error::{Result, TdxError};
+use crate::utils::{PckCA, TeeType};
+use std::io::Read;
+
+/// Base URL of the Intel Provisioning Certification Service.
+pub const INTEL_PCS_URL: &str = "https://api.trustedservices.intel.com";
+
+/// A collateral returned by the PCS together with the certificate chain of its issuer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcsResponse {
+    pub body: Vec<u8>,
+    /// PEM-encoded issuer certificate chain, as returned in the response headers.
+    pub issuer_chain: Vec<u8>,
+}
+
+/// Minimal client for the Intel PCS v4 API, or a PCCS exposing the same API.
+#[derive(Debug, Clone)]
+pub struct PcsClient {
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl Default for PcsClient {
+    fn default() -> Self {
+        PcsClient::new(INTEL_PCS_URL)
+    }
+}
+
+impl PcsClient {
+    /// Create a client for the PCS (or PCCS) at `base_url`, e.g. `https://localhost:8081`.
+    pub fn new(base_url: &str) -> Self {
+        PcsClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: None,
+        }
+    }
+
+    /// Set the subscription key sent as `Ocp-Apim-Subscription-Key`.
+    /// Only required by the Intel PCS to retrieve PCK certificates.
+    pub fn with_api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    /// Retrieve the signed TCB info JSON for `fmspc` (hex-encoded).
+    pub fn get_tcb_info(&self, tee_type: TeeType, fmspc: &str) -> Result<PcsResponse> {
+        let url = format!(
+            "{}/{}/certification/v4/tcb",
+            self.base_url,
+            tee_type.pcs_path()
+        );
+        self.get_with_issuer_chain(
+            self.request(&url).query("fmspc", fmspc),
+            "TCB-Info-Issuer-Chain",
+        )
+    }
+
+    /// Retrieve the signed QE identity JSON (the TD QE identity for TDX).
+    pub fn get_qe_identity(&self, tee_type: TeeType) -> Result<PcsResponse> {
+        let url = format!(
+            "{}/{}/certification/v4/qe/identity",
+            self.base_url,
+            tee_type.pcs_path()
+        );
+        self.get_with_issuer_chain(self.request(&url), "SGX-Enclave-Identity-Issuer-Chain")
+    }
+
+    /// Retrieve the DER-encoded CRL of the given PCK CA.
+    pub fn get_pck_crl(&self, ca: PckCA) -> Result<PcsResponse> {
+        let url = format!("{}/sgx/certification/v4/pckcrl", self.base_url);
+        let ca = match ca {
+            PckCA::Platform => "platform",
+            PckCA::Processor => "processor",
+        };
+        self.get_with_issuer_chain(
+            self.request(&url).query("ca", ca).query("encoding", "der"),
+            "SGX-PCK-CRL-Issuer-Chain",
+        )
+    }
+
+    /// Retrieve the PCK certificate for a platform identified by its encrypted PPID, returning
+    /// the PEM chain `PCK certificate || PCK CA || root CA`, as carried by type 5 certification
+    /// data.
+    pub fn get_pck_cert_chain(&self, ppid: &PpidInfo) -> Result<Vec<u8>> {
+        let url = format!("{}/sgx/certification/v4/pckcert", self.base_url);
+        let request = self
+            .request(&url)
+            .query("encrypted_ppid", &hex::encode(&ppid.ppid))
+            .query("cpusvn", &hex::encode(ppid.cpusvn))
+            .query("pcesvn", &hex::encode(ppid.pcesvn.to_le_bytes()))
+            .query("pceid", &hex::encode(ppid.pce_id.to_le_bytes()));
+        let response = self.get_with_issuer_chain(request, "SGX-PCK-Certificate-Issuer-Chain")?;
+        let mut chain = response.body;
+        chain.extend_from_slice(&response.issuer_chain);
+        Ok(chain)
+    }
+
+    /// Retrieve the DER-encoded Intel SGX Root CA CRL. The Intel PCS returns it as DER and a
+    /// PCCS hex-encoded, both are accepted.
+    pub fn get_root_ca_crl(&self) -> Result<Vec<u8>> {
+        let url = format!("{}/sgx/certification/v4/rootcacrl", self.base_url);
+        let body = read_body(self.request(&url).call()?)?;
+        // A DER CRL starts with a SEQUENCE tag, which is not a hex digit
+        if body.first() == Some(&0x30) {
+            return Ok(body);
+        }
+        hex::decode(std::str::from_utf8(&body)?.trim())
+            .map_err(|e| TdxError::Http(format!("Invalid root CA CRL: {e}")))
+    }
+
+    /// Retrieve every collateral needed to verify a quote of `tee_type` whose PCK certificate
+    /// was issued by `pck_ca` for `fmspc`.
+    pub fn get_collaterals(
+        &self,
+        tee_type: TeeType,
+        fmspc: &str,
+        pck_ca: PckCA,
+    ) -> Result<RawCollaterals> {
+        let tcb_info = self.get_tcb_info(tee_type, fmspc)?;
+        let qe_identity = self.get_qe_identity(tee_type)?;
+        let pck_crl = self.get_pck_crl(pck_ca)?;
+        let root_ca_crl = self.get_root_ca_crl()?;
+
+        // TCB info issuer chain: [0] TCB signing CA, [1] root CA
+        let chain = pem::parse_many(&tcb_info.issuer_chain)
+            .map_err(|e| TdxError::X509(format!("Failed to parse TCB info issuer chain: {e}")))?;
+        if chain.len() < 2 {
+            return Err(TdxError::X509(format!(
+                "TCB info issuer chain has {} certificates (expected 2)",
+                chain.len()
+            )));
+        }
+
+        Ok(RawCollaterals {
+            tcb_info: tcb_info.body,
+            qe_identity: qe_identity.body,
+            root_ca: chain[chain.len() - 1].contents().to_vec(),
+            tcb_signing_ca: chain[0].contents().to_vec(),
+            root_ca_crl,
+            pck_crl: pck_crl.body,
+        })
+    }
+
+    fn request(&self, url: &str) -> ureq::Request {
+        let request = ureq::get(url);
+        match &self.api_key {
+            Some(key) => request.set("Ocp-Apim-Subscription-Key", key),
+            None => request,
+        }
+    }
+
+    fn get_with_issuer_chain(&self, request: ureq::Request, header: &str) -> Result<PcsResponse> {
+        let response = request.call()?;
+        let issuer_chain = response
+            .header(header)
+            .map(|chain| percent_decode(chain.as_bytes()))
+            .transpose()?
+            .ok_or_else(|| {
+                TdxError::Http(format!("PCS response is missing the {header} header"))
+            })?;
+        Ok(PcsResponse {
+            body: read_body(response)?,
+            issuer_chain,
+        })
+    }
+}
+
+fn read_body(response: ureq::Response) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    response.into_reader().read_to_end(&mut body)?;
+    Ok(body)
+}
+
+/// Decode the URL-encoded certificate chains returned in PCS headers.
+fn percent_decode(input: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%' {
+            let hex = input
+                .get(i + 1..i + 3)
+                .ok_or_else(|| TdxError::Http("Truncated percent-encoding".to_string()))?;
+            let byte = u8::from_str_radix(std::str::from_utf8(hex)?, 16)
+                .map_err(|e| TdxError::Http(format!("Invalid percent-encoding: {e}")))?;
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(input[i]);
+            i += 1;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// Test PCK certificate chain: PCK certificate, PCK CA and root CA.
+    const CERT_CHAIN: &[u8] = include_bytes!("../tests/data/pck_cert_chain.pem");
+
+    type Response = (u16, Vec<(&'static str, String)>, Vec<u8>);
+
+    /// Serve the responses of `respond`, by request path, on a local port. Returns the base URL
+    /// and the heads of the requests received.
+    fn serve(
+        respond: impl Fn(&str) -> Response + Send + 'static,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut head = Vec::new();
+                let mut byte = [0u8];
+                while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                    head.push(byte[0]);
+                }
+                let head = String::from_utf8(head).unwrap();
+                let path = head.split(' ').nth(1).unwrap_or_default().to_string();
+                let (status, headers, body) = respond(&path);
+                received.lock().unwrap().push(head);
+                let mut response = format!(
+                    "HTTP/1.1 {status} Test\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    body.len()
+                );
+                for (name, value) in headers {
+                    response.push_str(&format!("{name}: {value}\r\n"));
+                }
+                response.push_str("\r\n");
+                stream.write_all(response.as_bytes()).unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    /// Percent-encode every byte that is not alphanumeric, as the PCS does in its headers.
+    fn percent_encode(input: &[u8]) -> String {
+        input
+            .iter()
+            .map(|&b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => (b as char).to_string(),
+                _ => format!("%{b:02X}"),
+            })
+            .collect()
+    }
+
+    /// The last two certificates of [`CERT_CHAIN`], standing in for a TCB signing CA and root.
+    fn issuer_chain() -> (Vec<u8>, Vec<pem::Pem>) {
+        let certificates = pem::parse_many(CERT_CHAIN).unwrap();
+        let issuers = certificates[1..].to_vec();
+        (pem::encode_many(&issuers).into_bytes(), issuers)
+    }
+
+    fn paths(requests: &Mutex<Vec<String>>) -> Vec<String> {
+        requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|head| head.split(' ').nth(1).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_percent_encoding() {
+        assert_eq!(percent_decode(b"a%20b%0Ac").unwrap(), b"a b\nc");
+        assert_eq!(
+            percent_decode(&percent_encode(CERT_CHAIN).into_bytes()).unwrap(),
+            CERT_CHAIN
+        );
+        assert!(percent_decode(b"abc%2").is_err());
+        assert!(percent_decode(b"%zz").is_err());
+    }
+
+    #[test]
+    fn fetches_collaterals() {
+        let (chain, issuers) = issuer_chain();
+        let header = percent_encode(&chain);
+        let (url, requests) = serve(move |path| {
+            let with_chain = |name, body: &[u8]| (200, vec![(name, header.clone())], body.to_vec());
+            match path {
+                "/tdx/certification/v4/tcb?fmspc=00806F050000" => {
+                    with_chain("TCB-Info-Issuer-Chain", b"tcb info")
+                }
+                "/tdx/certification/v4/qe/identity" => {
+                    with_chain("SGX-Enclave-Identity-Issuer-Chain", b"qe identity")
+                }
+                "/sgx/certification/v4/pckcrl?ca=platform&encoding=der" => {
+                    with_chain("SGX-PCK-CRL-Issuer-Chain", &[0x30, 0x01, 0x02])
+                }
+                // as a PCCS returns it
+                "/sgx/certification/v4/rootcacrl" => (200, Vec::new(), b"300103\n".to_vec()),
+                _ => (404, Vec::new(), Vec::new()),
+            }
+        });
+
+        let client = PcsClient::new(&format!("{url}/")).with_api_key("secret");
+        let collaterals = client
+            .get_collaterals(TeeType::Tdx, "00806F050000", PckCA::Platform)
+            .unwrap();
+        assert_eq!(collaterals.tcb_info, b"tcb info");
+        assert_eq!(collaterals.qe_identity, b"qe identity");
+        assert_eq!(collaterals.pck_crl, [0x30, 0x01, 0x02]);
+        assert_eq!(collaterals.root_ca_crl, [0x30, 0x01, 0x03]);
+        assert_eq!(collaterals.tcb_signing_ca, issuers[0].contents());
+        assert_eq!(collaterals.root_ca, issuers[1].contents());
+
+        assert_eq!(
+            paths(&requests),
+            [
+                "/tdx/certification/v4/tcb?fmspc=00806F050000",
+                "/tdx/certification/v4/qe/identity",
+                "/sgx/certification/v4/pckcrl?ca=platform&encoding=der",
+                "/sgx/certification/v4/rootcacrl",
+            ]
+        );
+        for head in requests.lock().unwrap().iter() {
+            assert!(
+                head.to_lowercase()
+                    .contains("ocp-apim-subscription-key: secret\r\n"),
+                "{head}"
+            );
+        }
+    }
+
+    #[test]
+    fn fetches_root_ca_crl_as_der_or_hex() {
+        let (url, _) = serve(|path| match path {
+            "/pcs/sgx/certification/v4/rootcacrl" => (200, Vec::new(), vec![0x30, 0x00]),
+            "/pccs/sgx/certification/v4/rootcacrl" => (200, Vec::new(), b"3000".to_vec()),
+            _ => (200, Vec::new(), b"not a CRL".to_vec()),
+        });
+        let crl = |base: &str| PcsClient::new(&format!("{url}{base}")).get_root_ca_crl();
+        assert_eq!(crl("/pcs").unwrap(), [0x30, 0x00]);
+        assert_eq!(crl("/pccs").unwrap(), [0x30, 0x00]);
+        assert!(matches!(crl("/other"), Err(TdxError::Http(_))));
+    }
+
+    #[test]
+    fn fetches_pck_cert_chain() {
+        let (chain, _) = issuer_chain();
+        let header = percent_encode(&chain);
+        let (url, requests) = serve(move |_| {
+            let headers = vec![("SGX-PCK-Certificate-Issuer-Chain", header.clone())];
+            (200, headers, b"pck certificate\n".to_vec())
+        });
+        let ppid = PpidInfo {
+            ppid: vec![0xab; 4],
+            cpusvn: [0x01; 16],
+            pcesvn: 0x0b,
+            pce_id: 0,
+        };
+        let mut expected = b"pck certificate\n".to_vec();
+        expected.extend_from_slice(&chain);
+        assert_eq!(
+            PcsClient::new(&url).get_pck_cert_chain(&ppid).unwrap(),
+            expected
+        );
+        assert_eq!(
+            paths(&requests),
+            [format!(
+                "/sgx/certification/v4/pckcert?encrypted_ppid=abababab&cpusvn={}&pcesvn=0b00&pceid=0000",
+                "01".repeat(16)
+            )]
+        );
+        // No API key unless one is set
+        assert!(!requests.lock().unwrap()[0]
+            .to_lowercase()
+            .contains("ocp-apim-subscription-key"));
+    }
+
+    #[test]
+    fn rejects_incomplete_responses() {
+        let (chain, issuers) = issuer_chain();
+        let root_only = percent_encode(pem::encode(&issuers[1]).as_bytes());
+        let header = percent_encode(&chain);
+        let (url, _) = serve(move |path| match path {
+            // The issuer chain header is missing
+            "/tdx/certification/v4/tcb?fmspc=00806F050000" => (200, Vec::new(), Vec::new()),
+            // The issuer chain lacks the TCB signing CA
+            "/sgx/certification/v4/tcb?fmspc=00606A000000" => (
+                200,
+                vec![("TCB-Info-Issuer-Chain", root_only.clone())],
+                Vec::new(),
+            ),
+            "/sgx/certification/v4/qe/identity" => (
+                200,
+                vec![("SGX-Enclave-Identity-Issuer-Chain", header.clone())],
+                Vec::new(),
+            ),
+            "/sgx/certification/v4/pckcrl?ca=processor&encoding=der" => (
+                200,
+                vec![("SGX-PCK-CRL-Issuer-Chain", header.clone())],
+                Vec::new(),
+            ),
+            "/sgx/certification/v4/rootcacrl" => (200, Vec::new(), vec![0x30, 0x00]),
+            _ => (404, Vec::new(), Vec::new()),
+        });
+        let client = PcsClient::new(&url);
+
+        let err = client
+            .get_tcb_info(TeeType::Tdx, "00806F050000")
+            .unwrap_err();
+        assert!(err.to_string().contains("TCB-Info-Issuer-Chain"), "{err}");
+        let err = client
+            .get_collaterals(TeeType::Sgx, "00606A000000", PckCA::Processor)
+            .unwrap_err();
+        assert!(err.to_string().contains("1 certificates"), "{err}");
+        assert!(matches!(
+            client.get_qe_identity(TeeType::Tdx),
+            Err(TdxError::Http(_))
+        ));
+    }
+}
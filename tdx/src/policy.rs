@@ -0,0 +1,489 @@
+use crate::error::{Result, TdxError};
+use crate::quote::QuoteBody;
+use crate::verified_output::{TcbStatus, VerifiedOutput};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Appraisal policy applied to the output of a successful verification.
+///
+/// Measurement fields are allowlists of hex-encoded values, an empty list accepts any value.
+/// TDX fields are ignored for SGX quotes and SGX fields for TDX quotes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppraisalPolicy {
+    /// Accepted TCB statuses. Defaults to `UpToDate` only.
+    pub accepted_tcb_statuses: Vec<TcbStatus>,
+    /// Advisory IDs that may be reported. `None` accepts any advisory.
+    pub accepted_advisory_ids: Option<Vec<String>>,
+    /// Accept TDs and enclaves with the DEBUG attribute set.
+    pub allow_debug: bool,
+    #[serde(with = "hex_list")]
+    pub fmspc: Vec<Vec<u8>>,
+
+    #[serde(with = "hex_list")]
+    pub mr_seam: Vec<Vec<u8>>,
+    #[serde(with = "hex_list")]
+    pub mr_td: Vec<Vec<u8>>,
+    #[serde(with = "hex_list")]
+    pub mr_config_id: Vec<Vec<u8>>,
+    #[serde(with = "hex_list")]
+    pub mr_owner: Vec<Vec<u8>>,
+    #[serde(with = "hex_list")]
+    pub mr_owner_config: Vec<Vec<u8>>,
+    #[serde(with = "hex_list")]
+    pub rtmr0: Vec<Vec<u8>>,
+    #[serde(with = "hex_list")]
+    pub rtmr1: Vec<Vec<u8>>,
+    #[serde(with = "hex_list")]
+    pub rtmr2: Vec<Vec<u8>>,
+    #[serde(with = "hex_list")]
+    pub rtmr3: Vec<Vec<u8>>,
+    #[serde(with = "hex_list")]
+    pub td_attributes: Vec<Vec<u8>>,
+    #[serde(with = "hex_list")]
+    pub xfam: Vec<Vec<u8>>,
+
+    #[serde(with = "hex_list")]
+    pub mr_enclave: Vec<Vec<u8>>,
+    #[serde(with = "hex_list")]
+    pub mr_signer: Vec<Vec<u8>>,
+    pub isv_prod_id: Option<u16>,
+    pub min_isv_svn: Option<u16>,
+}
+
+impl Default for AppraisalPolicy {
+    fn default() -> Self {
+        AppraisalPolicy {
+            accepted_tcb_statuses: vec![TcbStatus::UpToDate],
+            accepted_advisory_ids: None,
+            allow_debug: false,
+            fmspc: Vec::new(),
+            mr_seam: Vec::new(),
+            mr_td: Vec::new(),
+            mr_config_id: Vec::new(),
+            mr_owner: Vec::new(),
+            mr_owner_config: Vec::new(),
+            rtmr0: Vec::new(),
+            rtmr1: Vec::new(),
+            rtmr2: Vec::new(),
+            rtmr3: Vec::new(),
+            td_attributes: Vec::new(),
+            xfam: Vec::new(),
+            mr_enclave: Vec::new(),
+            mr_signer: Vec::new(),
+            isv_prod_id: None,
+            min_isv_svn: None,
+        }
+    }
+}
+
+/// A policy rule that the verified quote does not satisfy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyViolation {
+    /// Name of the policy field, e.g. `mr_td`.
+    pub field: String,
+    pub reason: String,
+}
+
+impl AppraisalPolicy {
+    /// Load a policy from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let data = std::fs::read(path)?;
+        serde_json::from_slice(&data)
+            .map_err(|e| TdxError::ConfigOptions(format!("Failed to parse policy: {e}")))
+    }
+
+    /// Returns every rule of the policy that `output` violates.
+    pub fn appraise(&self, output: &VerifiedOutput) -> Result<Vec<PolicyViolation>> {
+        let mut violations = Vec::new();
+        let mut violation = |field: &str, reason: String| {
+            violations.push(PolicyViolation {
+                field: field.to_string(),
+                reason,
+            })
+        };
+
+        if !self.accepted_tcb_statuses.contains(&output.tcb_status) {
+            violation(
+                "accepted_tcb_statuses",
+                format!("TCB status {} is not accepted", output.tcb_status),
+            );
+        }
+        if let Some(accepted) = &self.accepted_advisory_ids {
+            for id in &output.advisory_ids {
+                if !accepted.contains(id) {
+                    violation(
+                        "accepted_advisory_ids",
+                        format!("Advisory {id} is not accepted"),
+                    );
+                }
+            }
+        }
+        check_allowed(&mut violation, "fmspc", &self.fmspc, &output.fmspc);
+
+        match output.body()? {
+            QuoteBody::Sgx(report) => {
+                // SGX ATTRIBUTES.DEBUG is bit 1
+                if !self.allow_debug && report.attributes[0] & 0x02 != 0 {
+                    violation("allow_debug", "Enclave is in debug mode".to_string());
+                }
+                check_allowed(
+                    &mut violation,
+                    "mr_enclave",
+                    &self.mr_enclave,
+                    &report.mr_enclave,
+                );
+                check_allowed(
+                    &mut violation,
+                    "mr_signer",
+                    &self.mr_signer,
+                    &report.mr_signer,
+                );
+                if let Some(isv_prod_id) = self.isv_prod_id {
+                    if report.isv_prod_id != isv_prod_id {
+                        violation(
+                            "isv_prod_id",
+                            format!("ISV product ID {} is not {isv_prod_id}", report.isv_prod_id),
+                        );
+                    }
+                }
+                if let Some(min_isv_svn) = self.min_isv_svn {
+                    if report.isv_svn < min_isv_svn {
+                        violation(
+                            "min_isv_svn",
+                            format!("ISV SVN {} is below {min_isv_svn}", report.isv_svn),
+                        );
+                    }
+                }
+            }
+            body => {
+                let report = body.td_report().expect("TDX body");
+                // TDX TD_ATTRIBUTES.DEBUG is bit 0
+                if !self.allow_debug && report.td_attributes[0] & 0x01 != 0 {
+                    violation("allow_debug", "TD is in debug mode".to_string());
+                }
+                let fields: [MeasurementField; 11] = [
+                    ("mr_seam", &self.mr_seam, &report.mr_seam),
+                    ("mr_td", &self.mr_td, &report.mr_td),
+                    ("mr_config_id", &self.mr_config_id, &report.mr_config_id),
+                    ("mr_owner", &self.mr_owner, &report.mr_owner),
+                    (
+                        "mr_owner_config",
+                        &self.mr_owner_config,
+                        &report.mr_owner_config,
+                    ),
+                    ("rtmr0", &self.rtmr0, &report.rtmr0),
+                    ("rtmr1", &self.rtmr1, &report.rtmr1),
+                    ("rtmr2", &self.rtmr2, &report.rtmr2),
+                    ("rtmr3", &self.rtmr3, &report.rtmr3),
+                    ("td_attributes", &self.td_attributes, &report.td_attributes),
+                    ("xfam", &self.xfam, &report.xfam),
+                ];
+                for (field, allowed, value) in fields {
+                    check_allowed(&mut violation, field, allowed, value);
+                }
+            }
+        }
+        Ok(violations)
+    }
+
+    /// Returns an error listing every violated rule, if any.
+    pub fn check(&self, output: &VerifiedOutput) -> Result<()> {
+        let violations = self.appraise(output)?;
+        if violations.is_empty() {
+            return Ok(());
+        }
+        let violations: Vec<String> = violations
+            .iter()
+            .map(|v| format!("{}: {}", v.field, v.reason))
+            .collect();
+        Err(TdxError::Dcap(format!(
+            "Policy appraisal failed: {}",
+            violations.join("; ")
+        )))
+    }
+}
+
+/// A measurement: its name, the accepted values and the value reported by the quote.
+pub(crate) type MeasurementField<'a> = (&'static str, &'a [Vec<u8>], &'a [u8]);
+
+fn check_allowed(
+    violation: &mut impl FnMut(&str, String),
+    field: &str,
+    allowed: &[Vec<u8>],
+    value: &[u8],
+) {
+    if !allowed.is_empty() && !allowed.iter().any(|a| a == value) {
+        violation(field, format!("{} is not allowed", hex::encode(value)));
+    }
+}
+
+/// Serde helpers for lists of hex strings.
+pub(crate) mod hex_list {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(values: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(values.iter().map(hex::encode))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<u8>>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|s| hex::decode(s.trim_start_matches("0x")).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quote::TdReport10;
+    use crate::verified_output::{TEE_TYPE_SGX, TEE_TYPE_TDX};
+
+    const SGX_V3_QUOTE: &[u8] = include_bytes!("../examples/testdata/sgx_v3_quote.bin");
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+    const TDX_V5_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v5_quote.bin");
+    const FMSPC: [u8; 6] = [0x00, 0x80, 0x6f, 0x05, 0x00, 0x00];
+
+    fn output(quote_version: u16, tee_type: u32, quote_body: &[u8]) -> VerifiedOutput {
+        VerifiedOutput {
+            quote_version,
+            tee_type,
+            tcb_status: TcbStatus::UpToDate,
+            fmspc: FMSPC,
+            quote_body: quote_body.to_vec(),
+            advisory_ids: Vec::new(),
+        }
+    }
+
+    fn sgx() -> VerifiedOutput {
+        output(3, TEE_TYPE_SGX, &SGX_V3_QUOTE[48..432])
+    }
+
+    fn td10() -> VerifiedOutput {
+        output(4, TEE_TYPE_TDX, &TDX_V4_QUOTE[48..632])
+    }
+
+    /// The TD 1.5 body of the v5 quote, after its body type and size.
+    fn td15() -> VerifiedOutput {
+        output(5, TEE_TYPE_TDX, &TDX_V5_QUOTE[54..702])
+    }
+
+    fn with_status(mut output: VerifiedOutput, status: TcbStatus, ids: &[&str]) -> VerifiedOutput {
+        output.tcb_status = status;
+        output.advisory_ids = ids.iter().map(|id| id.to_string()).collect();
+        output
+    }
+
+    /// `output` with `mask` ORed into its body at `offset`.
+    fn with_bits(mut output: VerifiedOutput, offset: usize, mask: u8) -> VerifiedOutput {
+        output.quote_body[offset] |= mask;
+        output
+    }
+
+    fn td_report(output: &VerifiedOutput) -> TdReport10 {
+        output.body().unwrap().td_report().unwrap().clone()
+    }
+
+    fn policy(json: serde_json::Value) -> AppraisalPolicy {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn appraises_verified_outputs() {
+        let td_attributes_offset = 120;
+        let sgx_attributes_offset = 48;
+        let mr_td = hex::encode(td_report(&td10()).mr_td);
+        let rtmr3 = hex::encode(td_report(&td15()).rtmr3);
+        let QuoteBody::Sgx(enclave) = sgx().body().unwrap() else {
+            panic!("SGX body");
+        };
+        let mr_enclave = hex::encode(enclave.mr_enclave);
+
+        let cases: Vec<(&str, AppraisalPolicy, VerifiedOutput, &[&str])> = vec![
+            ("default", AppraisalPolicy::default(), td10(), &[]),
+            ("default td15", AppraisalPolicy::default(), td15(), &[]),
+            ("default sgx", AppraisalPolicy::default(), sgx(), &[]),
+            // Only UpToDate is accepted by default
+            (
+                "out of date",
+                AppraisalPolicy::default(),
+                with_status(td10(), TcbStatus::OutOfDate, &[]),
+                &["accepted_tcb_statuses"],
+            ),
+            (
+                "sw hardening",
+                AppraisalPolicy::default(),
+                with_status(td10(), TcbStatus::SwHardeningNeeded, &["INTEL-SA-00615"]),
+                &["accepted_tcb_statuses"],
+            ),
+            (
+                "accepted status, any advisory",
+                policy(serde_json::json!({
+                    "accepted_tcb_statuses": ["UpToDate", "SWHardeningNeeded"]
+                })),
+                with_status(td10(), TcbStatus::SwHardeningNeeded, &["INTEL-SA-00615"]),
+                &[],
+            ),
+            (
+                "advisory filtering",
+                policy(serde_json::json!({
+                    "accepted_tcb_statuses": ["SWHardeningNeeded"],
+                    "accepted_advisory_ids": ["INTEL-SA-00615"]
+                })),
+                with_status(
+                    td10(),
+                    TcbStatus::SwHardeningNeeded,
+                    &["INTEL-SA-00615", "INTEL-SA-00837"],
+                ),
+                &["accepted_advisory_ids"],
+            ),
+            (
+                "no accepted advisories",
+                policy(serde_json::json!({ "accepted_advisory_ids": [] })),
+                with_status(td10(), TcbStatus::UpToDate, &["INTEL-SA-00837"]),
+                &["accepted_advisory_ids"],
+            ),
+            (
+                "td debug",
+                AppraisalPolicy::default(),
+                with_bits(td10(), td_attributes_offset, 0x01),
+                &["allow_debug"],
+            ),
+            (
+                "td15 debug",
+                AppraisalPolicy::default(),
+                with_bits(td15(), td_attributes_offset, 0x01),
+                &["allow_debug"],
+            ),
+            (
+                "td debug allowed",
+                policy(serde_json::json!({ "allow_debug": true })),
+                with_bits(td10(), td_attributes_offset, 0x01),
+                &[],
+            ),
+            // The SGX debug bit of a TD and the TDX debug bit of an enclave are other fields
+            (
+                "td attribute bit 1",
+                AppraisalPolicy::default(),
+                with_bits(td10(), td_attributes_offset, 0x02),
+                &[],
+            ),
+            (
+                "sgx debug",
+                AppraisalPolicy::default(),
+                with_bits(sgx(), sgx_attributes_offset, 0x02),
+                &["allow_debug"],
+            ),
+            (
+                "sgx attribute bit 0",
+                AppraisalPolicy::default(),
+                with_bits(sgx(), sgx_attributes_offset, 0x01),
+                &[],
+            ),
+            (
+                "mr_td allowed",
+                policy(
+                    serde_json::json!({ "mr_td": [hex::encode([0; 48]), format!("0x{mr_td}")] }),
+                ),
+                td10(),
+                &[],
+            ),
+            (
+                "mr_td not allowed",
+                policy(serde_json::json!({ "mr_td": [hex::encode([0; 48])] })),
+                td10(),
+                &["mr_td"],
+            ),
+            (
+                "td15 rtmr3",
+                policy(serde_json::json!({ "rtmr3": [rtmr3], "rtmr2": [hex::encode([0; 48])] })),
+                td15(),
+                &["rtmr2"],
+            ),
+            (
+                "fmspc",
+                policy(serde_json::json!({ "fmspc": ["00906ed50000"] })),
+                td10(),
+                &["fmspc"],
+            ),
+            // TDX fields are ignored for SGX quotes and SGX fields for TDX quotes
+            (
+                "sgx fields on td",
+                policy(serde_json::json!({
+                    "mr_enclave": [hex::encode([0; 32])],
+                    "isv_prod_id": 1,
+                    "min_isv_svn": 1
+                })),
+                td10(),
+                &[],
+            ),
+            (
+                "td fields on sgx",
+                policy(serde_json::json!({ "mr_td": [hex::encode([0; 48])] })),
+                sgx(),
+                &[],
+            ),
+            (
+                "sgx identity",
+                policy(serde_json::json!({
+                    "mr_enclave": [mr_enclave],
+                    "mr_signer": [hex::encode([0; 32])],
+                    "isv_prod_id": 1,
+                    "min_isv_svn": 1
+                })),
+                sgx(),
+                &["mr_signer", "isv_prod_id", "min_isv_svn"],
+            ),
+            (
+                "every violation",
+                policy(serde_json::json!({
+                    "accepted_advisory_ids": [],
+                    "fmspc": ["00906ed50000"],
+                    "mr_seam": [hex::encode([0; 48])],
+                    "xfam": ["0000000000000000"]
+                })),
+                with_bits(
+                    with_status(td10(), TcbStatus::Revoked, &["INTEL-SA-00837"]),
+                    td_attributes_offset,
+                    0x01,
+                ),
+                &[
+                    "accepted_tcb_statuses",
+                    "accepted_advisory_ids",
+                    "fmspc",
+                    "allow_debug",
+                    "mr_seam",
+                    "xfam",
+                ],
+            ),
+        ];
+
+        for (name, policy, output, expected) in cases {
+            let violations = policy.appraise(&output).unwrap();
+            let fields: Vec<&str> = violations.iter().map(|v| v.field.as_str()).collect();
+            assert_eq!(fields, expected, "{name}");
+            assert_eq!(policy.check(&output).is_ok(), expected.is_empty(), "{name}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_policies() {
+        assert!(serde_json::from_str::<AppraisalPolicy>(r#"{"mr_td": ["zz"]}"#).is_err());
+        assert!(serde_json::from_str::<AppraisalPolicy>(r#"{"mrtd": []}"#).is_err());
+        assert!(
+            serde_json::from_str::<AppraisalPolicy>(r#"{"accepted_tcb_statuses": ["Fine"]}"#)
+                .is_err()
+        );
+        assert_eq!(
+            serde_json::from_str::<AppraisalPolicy>("{}").unwrap(),
+            AppraisalPolicy::default()
+        );
+
+        let mut output = td10();
+        output.quote_body.pop();
+        assert!(AppraisalPolicy::default().appraise(&output).is_err());
+    }
+}
@@ -0,0 +1,454 @@
+//! Python bindings, built with the `python` feature, e.g. with `maturin build --features python`.
+
+use crate::collateral::RawCollaterals;
+use crate::device::DeviceOptions;
+use crate::error::{Result, TdxError};
+use crate::pcs::PcsClient;
+use crate::quote::{QuoteBody, QuoteData};
+use crate::utils::{runtime, PckCA};
+use crate::verified_output::VerifiedOutput;
+use crate::verifier::CollateralProvider;
+use crate::Tdx;
+use pyo3::prelude::*;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+mod exceptions {
+    use pyo3::exceptions::PyException;
+
+    pyo3::create_exception!(
+        tdx,
+        TdxError,
+        PyException,
+        "Base class of the errors raised by this module."
+    );
+    pyo3::create_exception!(
+        tdx,
+        DeviceError,
+        TdxError,
+        "The TDX device or its firmware failed to produce a quote."
+    );
+    pyo3::create_exception!(
+        tdx,
+        VerificationError,
+        TdxError,
+        "A quote, certificate or collateral is malformed or does not verify."
+    );
+    pyo3::create_exception!(
+        tdx,
+        NetworkError,
+        TdxError,
+        "A PCS, PCCS or RPC request failed."
+    );
+    pyo3::create_exception!(tdx, ConfigError, TdxError, "Invalid options.");
+}
+
+use exceptions::{ConfigError, DeviceError, NetworkError, VerificationError};
+
+impl From<TdxError> for PyErr {
+    fn from(err: TdxError) -> Self {
+        let message = err.to_string();
+        match err {
+            TdxError::Cpu(_) | TdxError::Firmware(_) | TdxError::Tpm(_) => {
+                DeviceError::new_err(message)
+            }
+            TdxError::Dcap(_) | TdxError::X509(_) | TdxError::SSL(_) | TdxError::Corim(_) => {
+                VerificationError::new_err(message)
+            }
+            TdxError::Http(_) | TdxError::Contract(_) => NetworkError::new_err(message),
+            TdxError::ConfigOptions(_) => ConfigError::new_err(message),
+            _ => exceptions::TdxError::new_err(message),
+        }
+    }
+}
+
+/// A parsed quote. Fields that do not apply to the report body type are `None`.
+#[pyclass(name = "Quote", module = "tdx", frozen)]
+struct PyQuote(QuoteData);
+
+#[pymethods]
+impl PyQuote {
+    #[getter]
+    fn version(&self) -> u16 {
+        self.0.header.version
+    }
+
+    #[getter]
+    fn tee_type(&self) -> u32 {
+        self.0.header.tee_type
+    }
+
+    #[getter]
+    fn qe_svn(&self) -> u16 {
+        self.0.header.qe_svn
+    }
+
+    #[getter]
+    fn pce_svn(&self) -> u16 {
+        self.0.header.pce_svn
+    }
+
+    #[getter]
+    fn qe_vendor_id(&self) -> [u8; 16] {
+        self.0.header.qe_vendor_id
+    }
+
+    /// `sgx`, `td10` or `td15`
+    #[getter]
+    fn body_type(&self) -> &'static str {
+        match self.0.body {
+            QuoteBody::Sgx(_) => "sgx",
+            QuoteBody::Td10(_) => "td10",
+            QuoteBody::Td15(_) => "td15",
+        }
+    }
+
+    #[getter]
+    fn report_data(&self) -> [u8; 64] {
+        *self.0.report_data()
+    }
+
+    #[getter]
+    fn tee_tcb_svn(&self) -> Option<[u8; 16]> {
+        self.0.td_report().map(|report| report.tee_tcb_svn)
+    }
+
+    #[getter]
+    fn mr_seam(&self) -> Option<[u8; 48]> {
+        self.0.td_report().map(|report| report.mr_seam)
+    }
+
+    #[getter]
+    fn mr_signer_seam(&self) -> Option<[u8; 48]> {
+        self.0.td_report().map(|report| report.mr_signer_seam)
+    }
+
+    #[getter]
+    fn td_attributes(&self) -> Option<[u8; 8]> {
+        self.0.td_report().map(|report| report.td_attributes)
+    }
+
+    #[getter]
+    fn xfam(&self) -> Option<[u8; 8]> {
+        self.0.td_report().map(|report| report.xfam)
+    }
+
+    #[getter]
+    fn mr_td(&self) -> Option<[u8; 48]> {
+        self.0.td_report().map(|report| report.mr_td)
+    }
+
+    #[getter]
+    fn mr_config_id(&self) -> Option<[u8; 48]> {
+        self.0.td_report().map(|report| report.mr_config_id)
+    }
+
+    #[getter]
+    fn mr_owner(&self) -> Option<[u8; 48]> {
+        self.0.td_report().map(|report| report.mr_owner)
+    }
+
+    #[getter]
+    fn mr_owner_config(&self) -> Option<[u8; 48]> {
+        self.0.td_report().map(|report| report.mr_owner_config)
+    }
+
+    /// RTMR0 to RTMR3
+    #[getter]
+    fn rtmrs(&self) -> Option<Vec<[u8; 48]>> {
+        self.0
+            .td_report()
+            .map(|report| report.rtmrs().into_iter().copied().collect())
+    }
+
+    #[getter]
+    fn mr_enclave(&self) -> Option<[u8; 32]> {
+        match &self.0.body {
+            QuoteBody::Sgx(report) => Some(report.mr_enclave),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    fn mr_signer(&self) -> Option<[u8; 32]> {
+        match &self.0.body {
+            QuoteBody::Sgx(report) => Some(report.mr_signer),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    fn cert_data_type(&self) -> u16 {
+        self.0.signature.cert_data_type
+    }
+
+    /// The quote as JSON, with byte fields hex-encoded.
+    fn to_json(&self) -> PyResult<String> {
+        Ok(self.0.to_json()?)
+    }
+
+    fn to_bytes(&self) -> PyResult<Vec<u8>> {
+        Ok(self.0.to_bytes()?)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Quote(version={}, tee_type={:#x}, body_type={})",
+            self.version(),
+            self.tee_type(),
+            self.body_type()
+        )
+    }
+}
+
+/// The result of a successful quote verification.
+#[pyclass(name = "VerifiedOutput", module = "tdx", frozen)]
+struct PyVerifiedOutput(VerifiedOutput);
+
+#[pymethods]
+impl PyVerifiedOutput {
+    #[getter]
+    fn quote_version(&self) -> u16 {
+        self.0.quote_version
+    }
+
+    #[getter]
+    fn tee_type(&self) -> u32 {
+        self.0.tee_type
+    }
+
+    /// TCB status as named by Intel, e.g. `UpToDate`
+    #[getter]
+    fn tcb_status(&self) -> &'static str {
+        self.0.tcb_status.as_str()
+    }
+
+    /// FMSPC as uppercase hex
+    #[getter]
+    fn fmspc(&self) -> String {
+        self.0.fmspc_hex()
+    }
+
+    #[getter]
+    fn advisory_ids(&self) -> Vec<String> {
+        self.0.advisory_ids.clone()
+    }
+
+    /// The report body of the verified quote
+    #[getter]
+    fn quote_body(&self) -> Vec<u8> {
+        self.0.quote_body.clone()
+    }
+
+    /// The verified output in its packed on-chain format
+    fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_bytes()
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string(&self.0).map_err(|e| {
+            TdxError::Anyhow(format!("Failed to serialize the verified output: {e}")).into()
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "VerifiedOutput(tcb_status={}, fmspc={})",
+            self.tcb_status(),
+            self.fmspc()
+        )
+    }
+}
+
+fn pcs_client(pcs_url: Option<&str>, pcs_api_key: Option<&str>) -> Option<PcsClient> {
+    let client = pcs_url.map(PcsClient::new);
+    match pcs_api_key {
+        Some(key) => Some(client.unwrap_or_default().with_api_key(key)),
+        None => client,
+    }
+}
+
+fn tdx_with(pcs_client: &Option<PcsClient>) -> Tdx {
+    match pcs_client {
+        Some(client) => Tdx::new().with_pcs_client(client.clone()),
+        None => Tdx::new(),
+    }
+}
+
+/// Collaterals from the PCS if configured, from the Automata on-chain PCCS otherwise.
+fn fetch_collaterals(
+    tdx: &Tdx,
+    pcs_client: &Option<PcsClient>,
+    quote: &[u8],
+) -> Result<RawCollaterals> {
+    match pcs_client {
+        Some(client) => CollateralProvider::Pcs(client.clone()).collaterals(tdx, quote),
+        None => runtime()?.block_on(tdx.get_raw_collaterals(quote)),
+    }
+}
+
+/// Generate a quote on this TD over `report_data` (up to 64 bytes, zero-padded), or random
+/// report data if omitted.
+///
+/// Returns the quote and, on Azure confidential VMs, the var data bound into its report data.
+#[pyfunction]
+#[pyo3(signature = (report_data=None))]
+fn get_quote(py: Python<'_>, report_data: Option<Vec<u8>>) -> PyResult<(Vec<u8>, Option<Vec<u8>>)> {
+    let report_data = report_data
+        .map(|data| {
+            if data.len() > 64 {
+                return Err(ConfigError::new_err("Report data exceeds 64 bytes"));
+            }
+            let mut padded = [0u8; 64];
+            padded[..data.len()].copy_from_slice(&data);
+            Ok(padded)
+        })
+        .transpose()?;
+    let result = py.detach(|| match report_data {
+        Some(report_data) => Tdx::new().get_attestation_report_raw_with_options(DeviceOptions {
+            report_data: Some(report_data),
+        }),
+        None => Tdx::new().get_attestation_report_raw(),
+    });
+    Ok(result?)
+}
+
+/// Parse a quote (v3, v4 or v5).
+#[pyfunction]
+fn parse_quote(quote: &[u8]) -> PyResult<PyQuote> {
+    Ok(PyQuote(QuoteData::from_bytes(quote)?))
+}
+
+/// Extract the FMSPC (uppercase hex) and the PCK CA (`platform` or `processor`) of a quote.
+///
+/// `pcs_url` resolves the PCK certificate of quotes carrying an encrypted PPID.
+#[pyfunction]
+#[pyo3(signature = (quote, pcs_url=None, pcs_api_key=None))]
+fn get_fmspc(
+    py: Python<'_>,
+    quote: &[u8],
+    pcs_url: Option<&str>,
+    pcs_api_key: Option<&str>,
+) -> PyResult<(String, &'static str)> {
+    let tdx = tdx_with(&pcs_client(pcs_url, pcs_api_key));
+    let (fmspc, pck_ca) = py.detach(|| tdx.get_pck_fmspc_and_issuer(quote))?;
+    let pck_ca = match pck_ca {
+        PckCA::Platform => "platform",
+        PckCA::Processor => "processor",
+    };
+    Ok((fmspc.to_uppercase(), pck_ca))
+}
+
+/// Retrieve the collaterals of a quote as JSON, in the format of `tdx collateral save`, from
+/// the Intel PCS or the PCCS at `pcs_url` if either PCS argument is set, from the Automata
+/// on-chain PCCS otherwise.
+#[pyfunction]
+#[pyo3(signature = (quote, pcs_url=None, pcs_api_key=None))]
+fn get_collaterals(
+    py: Python<'_>,
+    quote: &[u8],
+    pcs_url: Option<&str>,
+    pcs_api_key: Option<&str>,
+) -> PyResult<String> {
+    let pcs_client = pcs_client(pcs_url, pcs_api_key);
+    let tdx = tdx_with(&pcs_client);
+    let collaterals = py.detach(|| fetch_collaterals(&tdx, &pcs_client, quote))?;
+    serde_json::to_string(&collaterals)
+        .map_err(|e| TdxError::Anyhow(format!("Failed to serialize the collaterals: {e}")).into())
+}
+
+/// Verify a quote at `time` (seconds since the UNIX epoch, defaults to now).
+///
+/// `collaterals` is JSON as returned by `get_collaterals`. Without it, collaterals are
+/// retrieved as by `get_collaterals`. Raises `VerificationError` if the quote does not verify.
+#[pyfunction]
+#[pyo3(signature = (quote, collaterals=None, time=None, pcs_url=None, pcs_api_key=None))]
+fn verify(
+    py: Python<'_>,
+    quote: &[u8],
+    collaterals: Option<&str>,
+    time: Option<u64>,
+    pcs_url: Option<&str>,
+    pcs_api_key: Option<&str>,
+) -> PyResult<PyVerifiedOutput> {
+    let collaterals = collaterals
+        .map(serde_json::from_str::<RawCollaterals>)
+        .transpose()
+        .map_err(|e| ConfigError::new_err(format!("Invalid collaterals: {e}")))?;
+    let time = match time {
+        Some(secs) => UNIX_EPOCH
+            .checked_add(Duration::from_secs(secs))
+            .ok_or_else(|| ConfigError::new_err(format!("Invalid verification time {secs}")))?,
+        None => SystemTime::now(),
+    };
+    let pcs_client = pcs_client(pcs_url, pcs_api_key);
+    let tdx = tdx_with(&pcs_client);
+    let output = py.detach(|| {
+        let collaterals = match collaterals {
+            Some(collaterals) => collaterals,
+            None => fetch_collaterals(&tdx, &pcs_client, quote)?,
+        };
+        tdx.verify_attestation_report_with_collaterals(quote, &collaterals, time)
+    })?;
+    Ok(PyVerifiedOutput(output))
+}
+
+#[pymodule]
+fn tdx(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_function(wrap_pyfunction!(get_quote, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_quote, m)?)?;
+    m.add_function(wrap_pyfunction!(get_fmspc, m)?)?;
+    m.add_function(wrap_pyfunction!(get_collaterals, m)?)?;
+    m.add_function(wrap_pyfunction!(verify, m)?)?;
+    m.add_class::<PyQuote>()?;
+    m.add_class::<PyVerifiedOutput>()?;
+    m.add("TdxError", py.get_type::<exceptions::TdxError>())?;
+    m.add("DeviceError", "DeviceError")?;
+    m.add("VerificationError", "VerificationError")?;
+    m.add("NetworkError", "NetworkError")?;
+    m.add("ConfigError", "ConfigError")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_errors_to_exceptions() {
+        Python::initialize();
+        Python::attach(|py| {
+            let cases = [
+                (TdxError::Firmware("tdx guest".to_string()), "DeviceError"),
+                (TdxError::Tpm("nv read".to_string()), "DeviceError"),
+                (TdxError::Dcap("signature".to_string()), "VerificationError"),
+                (TdxError::X509("chain".to_string()), "VerificationError"),
+                (TdxError::Corim("triple".to_string()), "VerificationError"),
+                (TdxError::Http("timeout".to_string()), "NetworkError"),
+                (TdxError::Contract("revert".to_string()), "NetworkError"),
+                (TdxError::ConfigOptions("time".to_string()), "ConfigError"),
+                (TdxError::IO("read".to_string()), "TdxError"),
+            ];
+            for (err, expected) in cases {
+                let message = err.to_string();
+                let err = PyErr::from(err);
+                assert_eq!(err.get_type(py).name().unwrap().to_string(), expected);
+                assert!(err.is_instance_of::<exceptions::TdxError>(py));
+                assert_eq!(err.value(py).to_string(), message);
+            }
+        });
+    }
+
+    #[test]
+    fn rejects_out_of_range_verification_time() {
+        Python::initialize();
+        Python::attach(|py| {
+            let quote = crate::test_pki::QUOTE;
+            let collaterals = std::str::from_utf8(crate::test_pki::COLLATERALS).unwrap();
+            let err = verify(py, quote, Some(collaterals), Some(u64::MAX), None, None)
+                .err()
+                .unwrap();
+            assert!(err.is_instance_of::<ConfigError>(py));
+        });
+    }
+}
@@ -0,0 +1,915 @@
+use crate::cert_data::{CERT_DATA_TYPE_PCK_CERT_CHAIN, CERT_DATA_TYPE_QE_REPORT};
+use crate::error::{Result, TdxError};
+use crate::utils::serde_hex;
+use dcap_rs::utils::cert_chain_processor;
+use p256::ecdsa::signature::{Signer, Verifier};
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use x509_parser::prelude::{parse_x509_certificate, parse_x509_pem, X509Certificate};
+
+pub const QUOTE_HEADER_LEN: usize = 48;
+pub const ENCLAVE_REPORT_LEN: usize = 384;
+pub const TD10_REPORT_LEN: usize = 584;
+pub const TD15_REPORT_LEN: usize = 648;
+/// Version of the JSON produced by [`QuoteData::to_json`], bumped on incompatible changes.
+pub const JSON_SCHEMA_VERSION: u64 = 1;
+
+const TEE_TYPE_SGX: u32 = 0x00;
+const TEE_TYPE_TDX: u32 = 0x81;
+// Body types of quote v5
+const BODY_TYPE_SGX: u16 = 1;
+const BODY_TYPE_TD10: u16 = 2;
+const BODY_TYPE_TD15: u16 = 3;
+
+/// Quote header, common to every quote version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteHeader {
+    pub version: u16,
+    pub attestation_key_type: u16,
+    pub tee_type: u32,
+    pub qe_svn: u16,
+    pub pce_svn: u16,
+    #[serde(with = "serde_hex")]
+    pub qe_vendor_id: [u8; 16],
+    #[serde(with = "serde_hex")]
+    pub user_data: [u8; 20],
+}
+
+/// SGX enclave report body, used for SGX quotes and for the QE report.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnclaveReport {
+    #[serde(with = "serde_hex")]
+    pub cpu_svn: [u8; 16],
+    pub misc_select: u32,
+    #[serde(with = "serde_hex")]
+    pub reserved1: [u8; 28],
+    #[serde(with = "serde_hex")]
+    pub attributes: [u8; 16],
+    #[serde(with = "serde_hex")]
+    pub mr_enclave: [u8; 32],
+    #[serde(with = "serde_hex")]
+    pub reserved2: [u8; 32],
+    #[serde(with = "serde_hex")]
+    pub mr_signer: [u8; 32],
+    #[serde(with = "serde_hex")]
+    pub reserved3: [u8; 96],
+    pub isv_prod_id: u16,
+    pub isv_svn: u16,
+    #[serde(with = "serde_hex")]
+    pub reserved4: [u8; 60],
+    #[serde(with = "serde_hex")]
+    pub report_data: [u8; 64],
+}
+
+/// TDX 1.0 TD report body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TdReport10 {
+    #[serde(with = "serde_hex")]
+    pub tee_tcb_svn: [u8; 16],
+    #[serde(with = "serde_hex")]
+    pub mr_seam: [u8; 48],
+    #[serde(with = "serde_hex")]
+    pub mr_signer_seam: [u8; 48],
+    #[serde(with = "serde_hex")]
+    pub seam_attributes: [u8; 8],
+    #[serde(with = "serde_hex")]
+    pub td_attributes: [u8; 8],
+    #[serde(with = "serde_hex")]
+    pub xfam: [u8; 8],
+    #[serde(with = "serde_hex")]
+    pub mr_td: [u8; 48],
+    #[serde(with = "serde_hex")]
+    pub mr_config_id: [u8; 48],
+    #[serde(with = "serde_hex")]
+    pub mr_owner: [u8; 48],
+    #[serde(with = "serde_hex")]
+    pub mr_owner_config: [u8; 48],
+    #[serde(with = "serde_hex")]
+    pub rtmr0: [u8; 48],
+    #[serde(with = "serde_hex")]
+    pub rtmr1: [u8; 48],
+    #[serde(with = "serde_hex")]
+    pub rtmr2: [u8; 48],
+    #[serde(with = "serde_hex")]
+    pub rtmr3: [u8; 48],
+    #[serde(with = "serde_hex")]
+    pub report_data: [u8; 64],
+}
+
+/// TDX 1.5 TD report body, a TDX 1.0 body followed by two more fields.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TdReport15 {
+    #[serde(flatten)]
+    pub td10: TdReport10,
+    #[serde(with = "serde_hex")]
+    pub tee_tcb_svn2: [u8; 16],
+    #[serde(with = "serde_hex")]
+    pub mr_servicetd: [u8; 48],
+}
+
+/// The report body of a quote.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QuoteBody {
+    Sgx(EnclaveReport),
+    Td10(TdReport10),
+    Td15(TdReport15),
+}
+
+impl QuoteBody {
+    /// Parse a report body, identifying its type from its size.
+    pub fn from_bytes(body: &[u8]) -> Result<Self> {
+        let mut r = Reader::new(body);
+        let parsed = match body.len() {
+            ENCLAVE_REPORT_LEN => QuoteBody::Sgx(EnclaveReport::read(&mut r)?),
+            TD10_REPORT_LEN => QuoteBody::Td10(TdReport10::read(&mut r)?),
+            TD15_REPORT_LEN => QuoteBody::Td15(TdReport15::read(&mut r)?),
+            len => {
+                return Err(TdxError::Dcap(format!(
+                    "Report body size {len} does not match any known report body"
+                )))
+            }
+        };
+        Ok(parsed)
+    }
+
+    /// Returns the TD report fields shared by TDX 1.0 and 1.5 bodies, if this is a TD report.
+    pub fn td_report(&self) -> Option<&TdReport10> {
+        match self {
+            QuoteBody::Td10(report) => Some(report),
+            QuoteBody::Td15(report) => Some(&report.td10),
+            QuoteBody::Sgx(_) => None,
+        }
+    }
+
+    /// Returns the report data of the body.
+    pub fn report_data(&self) -> &[u8; 64] {
+        match self {
+            QuoteBody::Sgx(report) => &report.report_data,
+            QuoteBody::Td10(report) => &report.report_data,
+            QuoteBody::Td15(report) => &report.td10.report_data,
+        }
+    }
+}
+
+/// ECDSA signature data of a quote.
+///
+/// Quote v3 carries the QE report and the certification data directly; quote v4 and v5 wrap
+/// them in certification data of type 6. Both are represented the same way here and laid out
+/// according to the header version when encoding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteSignatureData {
+    /// ECDSA P-256 signature (`r || s`) over the header and body.
+    #[serde(with = "serde_hex")]
+    pub signature: [u8; 64],
+    /// Attestation public key (uncompressed `X || Y`).
+    #[serde(with = "serde_hex")]
+    pub attestation_key: [u8; 64],
+    pub qe_report: EnclaveReport,
+    /// ECDSA P-256 signature (`r || s`) of the QE report by the PCK key.
+    #[serde(with = "serde_hex")]
+    pub qe_report_signature: [u8; 64],
+    #[serde(with = "serde_hex")]
+    pub qe_auth_data: Vec<u8>,
+    pub cert_data_type: u16,
+    #[serde(with = "serde_hex")]
+    pub cert_data: Vec<u8>,
+}
+
+/// Summary of a certificate of the PCK certificate chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateSummary {
+    pub subject: String,
+    pub issuer: String,
+    pub serial_number: String,
+    pub not_before: String,
+    pub not_after: String,
+}
+
+/// A typed SGX / TDX quote (v3, v4 or v5) that can be parsed, modified and serialized back.
+///
+/// Useful to build test vectors: parse a known-good quote, mutate a field and, if the
+/// signatures should stay valid, re-sign it with test keys.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteData {
+    pub header: QuoteHeader,
+    pub body: QuoteBody,
+    pub signature: QuoteSignatureData,
+    /// Bytes following the signature data, such as the zero padding of a quote read from a
+    /// fixed-size buffer. Kept so that [`QuoteData::to_bytes`] reproduces the parsed quote.
+    #[serde(with = "serde_hex", default, skip_serializing_if = "Vec::is_empty")]
+    pub trailing_data: Vec<u8>,
+}
+
+impl QuoteData {
+    /// Parse a raw quote.
+    pub fn from_bytes(raw_quote: &[u8]) -> Result<Self> {
+        let mut r = Reader::new(raw_quote);
+        let header = QuoteHeader::read(&mut r)?;
+        let body = match (header.version, header.tee_type) {
+            (3, TEE_TYPE_SGX) | (4, TEE_TYPE_SGX) => QuoteBody::Sgx(EnclaveReport::read(&mut r)?),
+            (4, TEE_TYPE_TDX) => QuoteBody::Td10(TdReport10::read(&mut r)?),
+            (5, _) => {
+                let body_type = r.u16()?;
+                let body_size = r.u32()? as usize;
+                let expected = match body_type {
+                    BODY_TYPE_SGX => ENCLAVE_REPORT_LEN,
+                    BODY_TYPE_TD10 => TD10_REPORT_LEN,
+                    BODY_TYPE_TD15 => TD15_REPORT_LEN,
+                    _ => {
+                        return Err(TdxError::Dcap(format!(
+                            "Unknown quote v5 body type: {body_type}"
+                        )))
+                    }
+                };
+                if body_size != expected {
+                    return Err(TdxError::Dcap(format!(
+                        "Quote v5 body type {body_type} has size {body_size} (expected {expected})"
+                    )));
+                }
+                match body_type {
+                    BODY_TYPE_SGX => QuoteBody::Sgx(EnclaveReport::read(&mut r)?),
+                    BODY_TYPE_TD10 => QuoteBody::Td10(TdReport10::read(&mut r)?),
+                    _ => QuoteBody::Td15(TdReport15::read(&mut r)?),
+                }
+            }
+            (version, tee_type) => {
+                return Err(TdxError::Dcap(format!(
+                    "Unsupported quote: version {version}, tee type {tee_type:#x}"
+                )))
+            }
+        };
+
+        let signature_len = r.u32()? as usize;
+        let mut s = Reader::new(r.bytes(signature_len)?);
+        let signature = s.array()?;
+        let attestation_key = s.array()?;
+        if header.version >= 4 {
+            let outer_type = s.u16()?;
+            if outer_type != CERT_DATA_TYPE_QE_REPORT {
+                return Err(TdxError::Dcap(format!(
+                    "Unexpected certification data type {outer_type} (expected {CERT_DATA_TYPE_QE_REPORT})"
+                )));
+            }
+            let outer_len = s.u32()? as usize;
+            s = Reader::new(s.bytes(outer_len)?);
+        }
+        let qe_report = EnclaveReport::read(&mut s)?;
+        let qe_report_signature = s.array()?;
+        let qe_auth_data_len = s.u16()? as usize;
+        let qe_auth_data = s.bytes(qe_auth_data_len)?.to_vec();
+        let cert_data_type = s.u16()?;
+        let cert_data_len = s.u32()? as usize;
+        let cert_data = s.bytes(cert_data_len)?.to_vec();
+
+        Ok(QuoteData {
+            header,
+            body,
+            signature: QuoteSignatureData {
+                signature,
+                attestation_key,
+                qe_report,
+                qe_report_signature,
+                qe_auth_data,
+                cert_data_type,
+                cert_data,
+            },
+            trailing_data: r.data.to_vec(),
+        })
+    }
+
+    /// Serialize the quote, laying out the body and signature data for `header.version`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = self.signed_bytes()?;
+        let sig = &self.signature;
+
+        let qe_auth_data_len = u16::try_from(sig.qe_auth_data.len())
+            .map_err(|_| TdxError::Dcap("QE authentication data too large".to_string()))?;
+        let mut qe_report_cert_data = Vec::new();
+        sig.qe_report.write(&mut qe_report_cert_data);
+        qe_report_cert_data.extend_from_slice(&sig.qe_report_signature);
+        qe_report_cert_data.extend_from_slice(&qe_auth_data_len.to_le_bytes());
+        qe_report_cert_data.extend_from_slice(&sig.qe_auth_data);
+        qe_report_cert_data.extend_from_slice(&sig.cert_data_type.to_le_bytes());
+        qe_report_cert_data.extend_from_slice(&len_u32(&sig.cert_data)?.to_le_bytes());
+        qe_report_cert_data.extend_from_slice(&sig.cert_data);
+
+        let mut signature_data = Vec::new();
+        signature_data.extend_from_slice(&sig.signature);
+        signature_data.extend_from_slice(&sig.attestation_key);
+        if self.header.version >= 4 {
+            signature_data.extend_from_slice(&CERT_DATA_TYPE_QE_REPORT.to_le_bytes());
+            signature_data.extend_from_slice(&len_u32(&qe_report_cert_data)?.to_le_bytes());
+        }
+        signature_data.extend_from_slice(&qe_report_cert_data);
+
+        out.extend_from_slice(&len_u32(&signature_data)?.to_le_bytes());
+        out.extend_from_slice(&signature_data);
+        out.extend_from_slice(&self.trailing_data);
+        Ok(out)
+    }
+
+    /// The bytes covered by the attestation key signature: the header and the body (with the
+    /// body type and size for quote v5).
+    pub fn signed_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.header.write(&mut out);
+        let mut body = Vec::new();
+        let body_type = match &self.body {
+            QuoteBody::Sgx(report) => {
+                report.write(&mut body);
+                BODY_TYPE_SGX
+            }
+            QuoteBody::Td10(report) => {
+                report.write(&mut body);
+                BODY_TYPE_TD10
+            }
+            QuoteBody::Td15(report) => {
+                report.write(&mut body);
+                BODY_TYPE_TD15
+            }
+        };
+        match (self.header.version, body_type) {
+            (3, BODY_TYPE_SGX) | (4, BODY_TYPE_SGX) | (4, BODY_TYPE_TD10) => {}
+            (5, _) => {
+                out.extend_from_slice(&body_type.to_le_bytes());
+                out.extend_from_slice(&len_u32(&body)?.to_le_bytes());
+            }
+            (version, _) => {
+                return Err(TdxError::Dcap(format!(
+                    "Body type {body_type} cannot be encoded in a v{version} quote"
+                )))
+            }
+        }
+        out.extend_from_slice(&body);
+        Ok(out)
+    }
+
+    /// Returns the TD report fields shared by TDX 1.0 and 1.5 bodies, if this is a TDX quote.
+    pub fn td_report(&self) -> Option<&TdReport10> {
+        self.body.td_report()
+    }
+
+    /// Mutable access to the TD report fields shared by TDX 1.0 and 1.5 bodies.
+    pub fn td_report_mut(&mut self) -> Option<&mut TdReport10> {
+        match &mut self.body {
+            QuoteBody::Td10(report) => Some(report),
+            QuoteBody::Td15(report) => Some(&mut report.td10),
+            QuoteBody::Sgx(_) => None,
+        }
+    }
+
+    /// Returns the report data of the quote body.
+    pub fn report_data(&self) -> &[u8; 64] {
+        self.body.report_data()
+    }
+
+    /// Serialize the quote as pretty-printed JSON, with byte fields hex-encoded and a top-level
+    /// `schema_version` ([`JSON_SCHEMA_VERSION`]).
+    ///
+    /// When the certification data is a PCK certificate chain, a summary of each certificate is
+    /// added under `signature.certificates`. If the chain cannot be parsed, e.g. in a mutated
+    /// quote, `certificates` is `null` and `certificates_error` holds the reason. Both are
+    /// informational and ignored by [`QuoteData::from_json`].
+    pub fn to_json(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self)
+            .map_err(|e| TdxError::IO(format!("Failed to serialize quote: {e}")))?;
+        value["schema_version"] = JSON_SCHEMA_VERSION.into();
+        if self.signature.cert_data_type == CERT_DATA_TYPE_PCK_CERT_CHAIN {
+            match self.certificates() {
+                Ok(certificates) => {
+                    value["signature"]["certificates"] = serde_json::to_value(certificates)
+                        .map_err(|e| {
+                            TdxError::IO(format!("Failed to serialize certificates: {e}"))
+                        })?;
+                }
+                Err(e) => {
+                    value["signature"]["certificates"] = serde_json::Value::Null;
+                    value["signature"]["certificates_error"] = e.to_string().into();
+                }
+            }
+        }
+        serde_json::to_string_pretty(&value)
+            .map_err(|e| TdxError::IO(format!("Failed to serialize quote: {e}")))
+    }
+
+    /// Parse a quote from the JSON produced by [`QuoteData::to_json`].
+    ///
+    /// JSON without a `schema_version` is accepted as version 1, newer versions are rejected.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)
+            .map_err(|e| TdxError::IO(format!("Failed to parse quote: {e}")))?;
+        let version = match value.get("schema_version") {
+            None => 1,
+            Some(version) => version
+                .as_u64()
+                .ok_or_else(|| TdxError::IO(format!("Invalid quote schema_version: {version}")))?,
+        };
+        if version > JSON_SCHEMA_VERSION {
+            return Err(TdxError::IO(format!(
+                "Unsupported quote schema_version {version} (supported up to {JSON_SCHEMA_VERSION})"
+            )));
+        }
+        serde_json::from_value(value)
+            .map_err(|e| TdxError::IO(format!("Failed to parse quote: {e}")))
+    }
+
+    /// Summaries of the certificates of the PCK certificate chain (certification data type 5),
+    /// from the PCK certificate to the root CA.
+    pub fn certificates(&self) -> Result<Vec<CertificateSummary>> {
+        if self.signature.cert_data_type != CERT_DATA_TYPE_PCK_CERT_CHAIN {
+            return Err(TdxError::Dcap(format!(
+                "Certification data type {} is not a PCK certificate chain",
+                self.signature.cert_data_type
+            )));
+        }
+        let cert_data = &self.signature.cert_data;
+        cert_chain_processor::find_certificate_ranges(cert_data)
+            .into_iter()
+            .map(|(start, end)| {
+                let (_, pem) = parse_x509_pem(&cert_data[start..end])
+                    .map_err(|e| TdxError::X509(format!("x509_parser error: {e}")))?;
+                let cert = pem
+                    .parse_x509()
+                    .map_err(|e| TdxError::X509(format!("x509 error: {e}")))?;
+                Ok(CertificateSummary {
+                    subject: cert.subject().to_string(),
+                    issuer: cert.issuer().to_string(),
+                    serial_number: cert.raw_serial_as_string(),
+                    not_before: cert.validity().not_before.to_string(),
+                    not_after: cert.validity().not_after.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// Replace the certification data with a PEM-encoded PCK certificate chain.
+    pub fn set_pck_cert_chain(&mut self, pem_chain: &[u8]) {
+        self.signature.cert_data_type = CERT_DATA_TYPE_PCK_CERT_CHAIN;
+        self.signature.cert_data = pem_chain.to_vec();
+    }
+
+    /// Truncate the certification data to `len` bytes.
+    pub fn truncate_cert_data(&mut self, len: usize) {
+        self.signature.cert_data.truncate(len);
+    }
+
+    /// Replace the attestation key with `attestation_key` and re-sign the header and body.
+    ///
+    /// The QE report binds the attestation key, so a new key also requires
+    /// [`QuoteData::sign_qe_report`] for the quote to verify.
+    pub fn sign(&mut self, attestation_key: &SigningKey) -> Result<()> {
+        self.signature.attestation_key = public_key_bytes(attestation_key);
+        let signature: Signature = attestation_key.sign(&self.signed_bytes()?);
+        self.signature.signature = signature.to_bytes().into();
+        Ok(())
+    }
+
+    /// Bind the attestation key and QE authentication data into the QE report data, then
+    /// re-sign the QE report with `pck_key`.
+    ///
+    /// The certification data should then carry a PCK certificate for `pck_key`, see
+    /// [`QuoteData::set_pck_cert_chain`].
+    pub fn sign_qe_report(&mut self, pck_key: &SigningKey) {
+        let sig = &mut self.signature;
+        sig.qe_report.report_data = qe_report_data(&sig.attestation_key, &sig.qe_auth_data);
+
+        let mut qe_report = Vec::with_capacity(ENCLAVE_REPORT_LEN);
+        sig.qe_report.write(&mut qe_report);
+        let signature: Signature = pck_key.sign(&qe_report);
+        sig.qe_report_signature = signature.to_bytes().into();
+    }
+
+    /// Check the signatures binding the quote together, without collaterals: the PCK
+    /// certificate chain is signed link by link, the QE report is signed by the PCK key and
+    /// binds the attestation key and QE authentication data, and the header and body are signed
+    /// by the attestation key.
+    ///
+    /// The root of the chain, revocation, the TCB status and the QE identity are not checked,
+    /// see [`crate::Tdx::verify_attestation_report_with_collaterals`] for a full verification.
+    pub fn verify_signatures(&self) -> Result<()> {
+        let sig = &self.signature;
+        if sig.cert_data_type != CERT_DATA_TYPE_PCK_CERT_CHAIN {
+            return Err(TdxError::Dcap(format!(
+                "Certification data type {} is not a PCK certificate chain",
+                sig.cert_data_type
+            )));
+        }
+        let chain = pem::parse_many(&sig.cert_data)
+            .map_err(|e| TdxError::X509(format!("Invalid PCK certificate chain: {e}")))?;
+        if chain.len() != 3 {
+            return Err(TdxError::Dcap(format!(
+                "PCK certificate chain has {} certificates (expected 3)",
+                chain.len()
+            )));
+        }
+        let chain = chain
+            .iter()
+            .map(|pem| {
+                parse_x509_certificate(pem.contents())
+                    .map(|(_, cert)| cert)
+                    .map_err(|e| TdxError::X509(format!("x509 error: {e}")))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for (cert, issuer) in chain.iter().zip(chain.iter().skip(1).chain(chain.last())) {
+            verify_certificate(cert, issuer)?;
+        }
+
+        let mut qe_report = Vec::with_capacity(ENCLAVE_REPORT_LEN);
+        sig.qe_report.write(&mut qe_report);
+        verifying_key(&chain[0].public_key().subject_public_key.data)?
+            .verify(&qe_report, &signature(&sig.qe_report_signature)?)
+            .map_err(|_| TdxError::Dcap("QE report signature verification failed".to_string()))?;
+        if sig.qe_report.report_data != qe_report_data(&sig.attestation_key, &sig.qe_auth_data) {
+            return Err(TdxError::Dcap(
+                "QE report data does not bind the attestation key".to_string(),
+            ));
+        }
+
+        let attestation_key = [&[0x04][..], &sig.attestation_key].concat();
+        verifying_key(&attestation_key)?
+            .verify(&self.signed_bytes()?, &signature(&sig.signature)?)
+            .map_err(|_| TdxError::Dcap("Quote signature verification failed".to_string()))
+    }
+}
+
+impl QuoteHeader {
+    fn read(r: &mut Reader) -> Result<Self> {
+        Ok(QuoteHeader {
+            version: r.u16()?,
+            attestation_key_type: r.u16()?,
+            tee_type: r.u32()?,
+            qe_svn: r.u16()?,
+            pce_svn: r.u16()?,
+            qe_vendor_id: r.array()?,
+            user_data: r.array()?,
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.version.to_le_bytes());
+        out.extend_from_slice(&self.attestation_key_type.to_le_bytes());
+        out.extend_from_slice(&self.tee_type.to_le_bytes());
+        out.extend_from_slice(&self.qe_svn.to_le_bytes());
+        out.extend_from_slice(&self.pce_svn.to_le_bytes());
+        out.extend_from_slice(&self.qe_vendor_id);
+        out.extend_from_slice(&self.user_data);
+    }
+}
+
+impl EnclaveReport {
+    fn read(r: &mut Reader) -> Result<Self> {
+        Ok(EnclaveReport {
+            cpu_svn: r.array()?,
+            misc_select: r.u32()?,
+            reserved1: r.array()?,
+            attributes: r.array()?,
+            mr_enclave: r.array()?,
+            reserved2: r.array()?,
+            mr_signer: r.array()?,
+            reserved3: r.array()?,
+            isv_prod_id: r.u16()?,
+            isv_svn: r.u16()?,
+            reserved4: r.array()?,
+            report_data: r.array()?,
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.cpu_svn);
+        out.extend_from_slice(&self.misc_select.to_le_bytes());
+        out.extend_from_slice(&self.reserved1);
+        out.extend_from_slice(&self.attributes);
+        out.extend_from_slice(&self.mr_enclave);
+        out.extend_from_slice(&self.reserved2);
+        out.extend_from_slice(&self.mr_signer);
+        out.extend_from_slice(&self.reserved3);
+        out.extend_from_slice(&self.isv_prod_id.to_le_bytes());
+        out.extend_from_slice(&self.isv_svn.to_le_bytes());
+        out.extend_from_slice(&self.reserved4);
+        out.extend_from_slice(&self.report_data);
+    }
+}
+
+impl TdReport10 {
+    fn read(r: &mut Reader) -> Result<Self> {
+        Ok(TdReport10 {
+            tee_tcb_svn: r.array()?,
+            mr_seam: r.array()?,
+            mr_signer_seam: r.array()?,
+            seam_attributes: r.array()?,
+            td_attributes: r.array()?,
+            xfam: r.array()?,
+            mr_td: r.array()?,
+            mr_config_id: r.array()?,
+            mr_owner: r.array()?,
+            mr_owner_config: r.array()?,
+            rtmr0: r.array()?,
+            rtmr1: r.array()?,
+            rtmr2: r.array()?,
+            rtmr3: r.array()?,
+            report_data: r.array()?,
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.tee_tcb_svn);
+        out.extend_from_slice(&self.mr_seam);
+        out.extend_from_slice(&self.mr_signer_seam);
+        out.extend_from_slice(&self.seam_attributes);
+        out.extend_from_slice(&self.td_attributes);
+        out.extend_from_slice(&self.xfam);
+        out.extend_from_slice(&self.mr_td);
+        out.extend_from_slice(&self.mr_config_id);
+        out.extend_from_slice(&self.mr_owner);
+        out.extend_from_slice(&self.mr_owner_config);
+        out.extend_from_slice(&self.rtmr0);
+        out.extend_from_slice(&self.rtmr1);
+        out.extend_from_slice(&self.rtmr2);
+        out.extend_from_slice(&self.rtmr3);
+        out.extend_from_slice(&self.report_data);
+    }
+
+    /// Returns RTMR0 to RTMR3.
+    pub fn rtmrs(&self) -> [&[u8; 48]; 4] {
+        [&self.rtmr0, &self.rtmr1, &self.rtmr2, &self.rtmr3]
+    }
+}
+
+impl TdReport15 {
+    fn read(r: &mut Reader) -> Result<Self> {
+        Ok(TdReport15 {
+            td10: TdReport10::read(r)?,
+            tee_tcb_svn2: r.array()?,
+            mr_servicetd: r.array()?,
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        self.td10.write(out);
+        out.extend_from_slice(&self.tee_tcb_svn2);
+        out.extend_from_slice(&self.mr_servicetd);
+    }
+}
+
+/// Report data of a QE report binding `attestation_key` and `qe_auth_data`: their SHA-256
+/// followed by 32 zero bytes.
+fn qe_report_data(attestation_key: &[u8; 64], qe_auth_data: &[u8]) -> [u8; 64] {
+    let mut hasher = Sha256::new();
+    hasher.update(attestation_key);
+    hasher.update(qe_auth_data);
+    let mut report_data = [0u8; 64];
+    report_data[..32].copy_from_slice(&hasher.finalize());
+    report_data
+}
+
+fn verify_certificate(cert: &X509Certificate, issuer: &X509Certificate) -> Result<()> {
+    let signature = Signature::from_der(&cert.signature_value.data)
+        .map_err(|e| TdxError::X509(format!("Invalid certificate signature: {e}")))?;
+    verifying_key(&issuer.public_key().subject_public_key.data)?
+        .verify(cert.tbs_certificate.as_ref(), &signature)
+        .map_err(|_| {
+            TdxError::Dcap(format!(
+                "Signature of certificate {} by {} verification failed",
+                cert.subject(),
+                issuer.subject()
+            ))
+        })
+}
+
+fn verifying_key(sec1: &[u8]) -> Result<VerifyingKey> {
+    VerifyingKey::from_sec1_bytes(sec1)
+        .map_err(|e| TdxError::Dcap(format!("Invalid P-256 public key: {e}")))
+}
+
+fn signature(bytes: &[u8; 64]) -> Result<Signature> {
+    Signature::from_slice(bytes).map_err(|e| TdxError::Dcap(format!("Invalid signature: {e}")))
+}
+
+fn public_key_bytes(key: &SigningKey) -> [u8; 64] {
+    let point = key.verifying_key().to_encoded_point(false);
+    let mut out = [0u8; 64];
+    // Skip the 0x04 uncompressed point prefix
+    out.copy_from_slice(&point.as_bytes()[1..]);
+    out
+}
+
+fn len_u32(data: &[u8]) -> Result<u32> {
+    u32::try_from(data.len()).map_err(|_| TdxError::Dcap("Quote field too large".to_string()))
+}
+
+/// Little-endian cursor over a byte slice.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(TdxError::Dcap(format!(
+                "Quote is truncated: expected {len} more bytes, {} left",
+                self.data.len()
+            )));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().expect("slice has length N"))
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SGX_V3_QUOTE: &[u8] = include_bytes!("../examples/testdata/sgx_v3_quote.bin");
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+    const TDX_V5_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v5_quote.bin");
+    const QUOTES: [&[u8]; 3] = [SGX_V3_QUOTE, TDX_V4_QUOTE, TDX_V5_QUOTE];
+    /// Test PCK certificate chain whose leaf is the public key of [`pck_key`].
+    const TEST_PCK_CERT_CHAIN: &[u8] = include_bytes!("../tests/data/pck_cert_chain.pem");
+
+    fn attestation_key() -> SigningKey {
+        SigningKey::from_slice(&[0x11; 32]).unwrap()
+    }
+
+    fn pck_key() -> SigningKey {
+        SigningKey::from_slice(&[0x22; 32]).unwrap()
+    }
+
+    fn dcap_error(quote: &QuoteData) -> String {
+        match quote.verify_signatures() {
+            Err(TdxError::Dcap(message)) => message,
+            other => panic!("expected a Dcap error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn round_trips_test_quotes() {
+        for raw_quote in QUOTES {
+            let quote = QuoteData::from_bytes(raw_quote).unwrap();
+            assert_eq!(quote.to_bytes().unwrap(), raw_quote);
+        }
+    }
+
+    #[test]
+    fn round_trips_json() {
+        for raw_quote in QUOTES {
+            let quote = QuoteData::from_bytes(raw_quote).unwrap();
+            let json = quote.to_json().unwrap();
+            assert_eq!(QuoteData::from_json(&json).unwrap(), quote);
+        }
+    }
+
+    #[test]
+    fn json_has_schema_version_and_certificates() {
+        let quote = QuoteData::from_bytes(TDX_V4_QUOTE).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&quote.to_json().unwrap()).unwrap();
+        assert_eq!(json["schema_version"], JSON_SCHEMA_VERSION);
+        assert_eq!(
+            json["signature"]["certificates"].as_array().unwrap().len(),
+            3
+        );
+        assert!(json["signature"].get("certificates_error").is_none());
+    }
+
+    #[test]
+    fn json_reports_unparsable_certificates() {
+        let mut quote = QuoteData::from_bytes(TDX_V4_QUOTE).unwrap();
+        // Corrupt the DER header of the PCK certificate
+        let chain = String::from_utf8(quote.signature.cert_data.clone()).unwrap();
+        let chain = chain.replacen("-----\nMII", "-----\nAAA", 1);
+        assert_ne!(chain.as_bytes(), quote.signature.cert_data);
+        quote.set_pck_cert_chain(chain.as_bytes());
+        let json = quote.to_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(value["signature"]["certificates"].is_null());
+        assert!(value["signature"]["certificates_error"].is_string());
+        assert_eq!(QuoteData::from_json(&json).unwrap(), quote);
+    }
+
+    #[test]
+    fn json_rejects_newer_schema() {
+        let quote = QuoteData::from_bytes(TDX_V4_QUOTE).unwrap();
+        let mut value: serde_json::Value = serde_json::from_str(&quote.to_json().unwrap()).unwrap();
+        value["schema_version"] = (JSON_SCHEMA_VERSION + 1).into();
+        assert!(QuoteData::from_json(&value.to_string()).is_err());
+
+        value.as_object_mut().unwrap().remove("schema_version");
+        assert_eq!(QuoteData::from_json(&value.to_string()).unwrap(), quote);
+    }
+
+    #[test]
+    fn keeps_trailing_data() {
+        // The v4 test quote is zero-padded
+        let quote = QuoteData::from_bytes(TDX_V4_QUOTE).unwrap();
+        assert!(!quote.trailing_data.is_empty());
+        assert!(quote.trailing_data.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn verifies_test_quotes() {
+        for raw_quote in QUOTES {
+            QuoteData::from_bytes(raw_quote)
+                .unwrap()
+                .verify_signatures()
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_flipped_measurement() {
+        let mut quote = QuoteData::from_bytes(TDX_V4_QUOTE).unwrap();
+        quote.td_report_mut().unwrap().mr_td[0] ^= 0xff;
+        assert_eq!(dcap_error(&quote), "Quote signature verification failed");
+    }
+
+    #[test]
+    fn rejects_truncated_cert_data() {
+        let mut quote = QuoteData::from_bytes(TDX_V4_QUOTE).unwrap();
+        let len = quote.signature.cert_data.len();
+        quote.truncate_cert_data(len / 2);
+        assert_eq!(
+            dcap_error(&quote),
+            "PCK certificate chain has 1 certificates (expected 3)"
+        );
+    }
+
+    #[test]
+    fn rejects_swapped_qe_report() {
+        let other = QuoteData::from_bytes(SGX_V3_QUOTE).unwrap();
+        let mut quote = QuoteData::from_bytes(TDX_V4_QUOTE).unwrap();
+        quote.signature.qe_report = other.signature.qe_report;
+        quote.signature.qe_report_signature = other.signature.qe_report_signature;
+        assert_eq!(
+            dcap_error(&quote),
+            "QE report signature verification failed"
+        );
+    }
+
+    #[test]
+    fn rejects_resigned_body() {
+        let mut quote = QuoteData::from_bytes(TDX_V4_QUOTE).unwrap();
+        quote.td_report_mut().unwrap().mr_td[0] ^= 0xff;
+        quote.sign(&attestation_key()).unwrap();
+        assert_eq!(
+            dcap_error(&quote),
+            "QE report data does not bind the attestation key"
+        );
+    }
+
+    #[test]
+    fn rejects_resigned_qe_report() {
+        let mut quote = QuoteData::from_bytes(TDX_V4_QUOTE).unwrap();
+        quote.sign(&attestation_key()).unwrap();
+        quote.sign_qe_report(&pck_key());
+        assert_eq!(
+            dcap_error(&quote),
+            "QE report signature verification failed"
+        );
+    }
+
+    #[test]
+    fn rejects_misordered_chain() {
+        let mut quote = QuoteData::from_bytes(TDX_V4_QUOTE).unwrap();
+        let mut chain = pem::parse_many(&quote.signature.cert_data).unwrap();
+        chain.swap(0, 1);
+        quote.set_pck_cert_chain(pem::encode_many(&chain).as_bytes());
+        assert!(dcap_error(&quote).starts_with("Signature of certificate"));
+    }
+
+    #[test]
+    fn accepts_fully_resigned_quote() {
+        for raw_quote in QUOTES {
+            let mut quote = QuoteData::from_bytes(raw_quote).unwrap();
+            match &mut quote.body {
+                QuoteBody::Sgx(report) => report.mr_enclave[0] ^= 0xff,
+                QuoteBody::Td10(report) => report.mr_td[0] ^= 0xff,
+                QuoteBody::Td15(report) => report.td10.mr_td[0] ^= 0xff,
+            }
+            quote.sign(&attestation_key()).unwrap();
+            quote.sign_qe_report(&pck_key());
+            quote.set_pck_cert_chain(TEST_PCK_CERT_CHAIN);
+            quote.verify_signatures().unwrap();
+
+            let reparsed = QuoteData::from_bytes(&quote.to_bytes().unwrap()).unwrap();
+            assert_eq!(reparsed, quote);
+        }
+    }
+}
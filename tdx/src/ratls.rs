@@ -0,0 +1,508 @@
+use crate::collateral::RawCollaterals;
+#[cfg(feature = "coco-provider")]
+use crate::device::DeviceOptions;
+use crate::error::{Result, TdxError};
+use crate::verified_output::VerifiedOutput;
+#[cfg(feature = "coco-provider")]
+use crate::verifier::CollateralProvider;
+use crate::verifier::QuoteVerifier;
+#[cfg(feature = "coco-provider")]
+use crate::Tdx;
+#[cfg(feature = "coco-provider")]
+use rcgen::PublicKeyData;
+use rcgen::{CertificateParams, CustomExtension, DistinguishedName, DnType, KeyPair};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::{CertificateError, DigitallySignedStruct, DistinguishedName as TlsName, OtherError};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use x509_parser::oid_registry::Oid;
+use x509_parser::prelude::parse_x509_certificate;
+
+/// OID of the certificate extension carrying the raw TDX quote, as used by Intel's RA-TLS.
+pub const TDX_QUOTE_OID: &[u64] = &[1, 2, 840, 113741, 1, 5, 5, 1, 6];
+/// OID of the certificate extension carrying the collaterals of the quote as JSON. This
+/// extension is specific to this SDK.
+pub const COLLATERALS_OID: &[u64] = &[1, 3, 6, 1, 4, 1, 60522, 1, 1];
+
+/// Report data binding a TLS key: the SHA-256 of its DER SubjectPublicKeyInfo, zero-padded.
+pub fn report_data_for_key(spki_der: &[u8]) -> [u8; 64] {
+    let mut report_data = [0u8; 64];
+    report_data[..32].copy_from_slice(&Sha256::digest(spki_der));
+    report_data
+}
+
+/// A self-signed certificate carrying a quote that binds its key.
+#[derive(Debug, Clone)]
+pub struct RaTlsCertificate {
+    cert_der: Vec<u8>,
+    key_der: Vec<u8>,
+    quote: Vec<u8>,
+}
+
+impl RaTlsCertificate {
+    /// Generate a P-256 key pair in the TD, bind its public key into the report data of a
+    /// fresh quote and issue a self-signed certificate for `subject_alt_names` carrying it.
+    ///
+    /// When `collaterals` is set, the collaterals of the quote are embedded as well, so peers
+    /// verifying with [`CollateralProvider::Embedded`] need no network access.
+    #[cfg(feature = "coco-provider")]
+    pub fn generate(
+        tdx: &Tdx,
+        subject_alt_names: Vec<String>,
+        collaterals: Option<&CollateralProvider>,
+    ) -> Result<Self> {
+        let key_pair = KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256)
+            .map_err(|e| TdxError::SSL(format!("Failed to generate key pair: {e}")))?;
+        let report_data = report_data_for_key(&key_pair.subject_public_key_info());
+        let (quote, _) = tdx.get_attestation_report_raw_with_options(DeviceOptions {
+            report_data: Some(report_data),
+        })?;
+        let collaterals = collaterals
+            .map(|provider| provider.collaterals(tdx, &quote))
+            .transpose()?;
+        Self::from_quote(key_pair, subject_alt_names, quote, collaterals.as_ref())
+    }
+
+    /// Issue a self-signed certificate for `key_pair` carrying a quote obtained elsewhere,
+    /// whose report data must start with [`report_data_for_key`] of the key pair.
+    pub fn from_quote(
+        key_pair: KeyPair,
+        subject_alt_names: Vec<String>,
+        quote: Vec<u8>,
+        collaterals: Option<&RawCollaterals>,
+    ) -> Result<Self> {
+        let mut params = CertificateParams::new(subject_alt_names)
+            .map_err(|e| TdxError::SSL(format!("Invalid subject alt names: {e}")))?;
+        let mut name = DistinguishedName::new();
+        name.push(DnType::CommonName, "TDX RA-TLS");
+        params.distinguished_name = name;
+        params
+            .custom_extensions
+            .push(CustomExtension::from_oid_content(
+                TDX_QUOTE_OID,
+                quote.clone(),
+            ));
+        if let Some(collaterals) = collaterals {
+            let collaterals = serde_json::to_vec(collaterals)
+                .map_err(|e| TdxError::SSL(format!("Failed to encode collaterals: {e}")))?;
+            params
+                .custom_extensions
+                .push(CustomExtension::from_oid_content(
+                    COLLATERALS_OID,
+                    collaterals,
+                ));
+        }
+        let cert = params
+            .self_signed(&key_pair)
+            .map_err(|e| TdxError::SSL(format!("Failed to sign certificate: {e}")))?;
+
+        Ok(RaTlsCertificate {
+            cert_der: cert.der().to_vec(),
+            key_der: key_pair.serialize_der(),
+            quote,
+        })
+    }
+
+    pub fn cert_der(&self) -> &[u8] {
+        &self.cert_der
+    }
+
+    /// PKCS#8 DER encoded private key.
+    pub fn key_der(&self) -> &[u8] {
+        &self.key_der
+    }
+
+    pub fn quote(&self) -> &[u8] {
+        &self.quote
+    }
+
+    /// The certificate chain and private key, as expected by
+    /// `rustls::ConfigBuilder::with_single_cert` and `with_client_auth_cert`.
+    pub fn rustls_cert(&self) -> (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>) {
+        (
+            vec![CertificateDer::from(self.cert_der.clone())],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(self.key_der.clone())),
+        )
+    }
+}
+
+/// The quote and collaterals carried by an RA-TLS certificate.
+#[derive(Debug, Clone)]
+pub struct RaTlsEvidence {
+    pub quote: Vec<u8>,
+    pub collaterals: Option<RawCollaterals>,
+    /// DER SubjectPublicKeyInfo of the certificate key.
+    pub spki_der: Vec<u8>,
+}
+
+impl RaTlsEvidence {
+    /// Extract the evidence from a DER certificate.
+    pub fn from_cert(cert_der: &[u8]) -> Result<Self> {
+        let (_, cert) = parse_x509_certificate(cert_der)
+            .map_err(|e| TdxError::X509(format!("Invalid certificate: {e}")))?;
+        let extension = |oid: &[u64]| {
+            let oid = Oid::from(oid).expect("valid OID");
+            cert.extensions()
+                .iter()
+                .find(|ext| ext.oid == oid)
+                .map(|ext| ext.value.to_vec())
+        };
+        let quote = extension(TDX_QUOTE_OID)
+            .ok_or_else(|| TdxError::X509("Certificate does not carry a TDX quote".to_string()))?;
+        let collaterals = extension(COLLATERALS_OID)
+            .map(|json| serde_json::from_slice(&json))
+            .transpose()
+            .map_err(|e| TdxError::X509(format!("Invalid collaterals extension: {e}")))?;
+        Ok(RaTlsEvidence {
+            quote,
+            collaterals,
+            spki_der: cert.tbs_certificate.subject_pki.raw.to_vec(),
+        })
+    }
+}
+
+/// Verifies the quote of RA-TLS peer certificates, for use as a rustls server or client
+/// certificate verifier.
+///
+/// The certificate is accepted when its quote verifies, binds the certificate key and
+/// satisfies the policy of the [`QuoteVerifier`]. No certificate authority is involved.
+#[derive(Debug, Clone)]
+pub struct RaTlsVerifier {
+    verifier: QuoteVerifier,
+    provider: Arc<CryptoProvider>,
+}
+
+impl RaTlsVerifier {
+    pub fn new(verifier: QuoteVerifier) -> Self {
+        RaTlsVerifier {
+            verifier,
+            provider: Arc::new(rustls::crypto::ring::default_provider()),
+        }
+    }
+
+    /// Verify the TLS handshake signatures with `provider` instead of ring.
+    pub fn with_crypto_provider(mut self, provider: Arc<CryptoProvider>) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    /// Verify the quote of a DER certificate and its binding to the certificate key.
+    pub fn verify_certificate(&self, cert_der: &[u8]) -> Result<VerifiedOutput> {
+        self.verify_certificate_at(cert_der, UnixTime::now())
+    }
+
+    fn verify_certificate_at(&self, cert_der: &[u8], now: UnixTime) -> Result<VerifiedOutput> {
+        let evidence = RaTlsEvidence::from_cert(cert_der)?;
+        let report_data = report_data_for_key(&evidence.spki_der);
+        self.verifier.verify_at(
+            &evidence.quote,
+            evidence.collaterals.as_ref(),
+            &report_data,
+            UNIX_EPOCH + Duration::from_secs(now.as_secs()),
+        )
+    }
+
+    fn verify_peer(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        now: UnixTime,
+    ) -> std::result::Result<(), rustls::Error> {
+        self.verify_certificate_at(end_entity, now)
+            .map(|_| ())
+            .map_err(|e| {
+                rustls::Error::InvalidCertificate(CertificateError::Other(OtherError(Arc::new(e))))
+            })
+    }
+}
+
+impl ServerCertVerifier for RaTlsVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        self.verify_peer(end_entity, now)?;
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+impl ClientCertVerifier for RaTlsVerifier {
+    fn root_hint_subjects(&self) -> &[TlsName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> std::result::Result<ClientCertVerified, rustls::Error> {
+        self.verify_peer(end_entity, now)?;
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quote::QuoteData;
+    use crate::test_pki;
+    use crate::verifier::{CollateralProvider, EmbeddedCollaterals};
+    use rcgen::PublicKeyData;
+    use rustls::time_provider::TimeProvider;
+    use rustls::{
+        ClientConfig, ClientConnection, ConnectionCommon, ServerConfig, ServerConnection,
+    };
+
+    /// A certificate whose test quote binds `report_data`, or the certificate key if `None`.
+    fn certificate(report_data: Option<[u8; 64]>, collaterals: bool) -> RaTlsCertificate {
+        let key_pair = KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        let report_data =
+            report_data.unwrap_or(report_data_for_key(&key_pair.subject_public_key_info()));
+        let quote = test_pki::quote_with_report_data(report_data);
+        let collaterals = collaterals.then(test_pki::collaterals);
+        RaTlsCertificate::from_quote(
+            key_pair,
+            vec!["localhost".to_string()],
+            quote,
+            collaterals.as_ref(),
+        )
+        .unwrap()
+    }
+
+    fn verifier() -> RaTlsVerifier {
+        let embedded = EmbeddedCollaterals::new().with_validator(test_pki::validator());
+        RaTlsVerifier::new(QuoteVerifier::new(CollateralProvider::Embedded(embedded)))
+    }
+
+    fn now() -> UnixTime {
+        UnixTime::since_unix_epoch(test_pki::now().duration_since(UNIX_EPOCH).unwrap())
+    }
+
+    #[derive(Debug)]
+    struct TestTime;
+
+    impl TimeProvider for TestTime {
+        fn current_time(&self) -> Option<UnixTime> {
+            Some(now())
+        }
+    }
+
+    #[test]
+    fn extracts_evidence() {
+        let cert = certificate(None, true);
+        let evidence = RaTlsEvidence::from_cert(cert.cert_der()).unwrap();
+        assert_eq!(evidence.quote, cert.quote());
+        assert_eq!(evidence.collaterals, Some(test_pki::collaterals()));
+        let (_, parsed) = parse_x509_certificate(cert.cert_der()).unwrap();
+        assert_eq!(evidence.spki_der, parsed.tbs_certificate.subject_pki.raw);
+        assert_eq!(
+            &QuoteData::from_bytes(&evidence.quote)
+                .unwrap()
+                .report_data()[..32],
+            &Sha256::digest(&evidence.spki_der)[..]
+        );
+
+        let evidence = RaTlsEvidence::from_cert(certificate(None, false).cert_der()).unwrap();
+        assert_eq!(evidence.collaterals, None);
+    }
+
+    #[test]
+    fn rejects_certificates_without_quote() {
+        let key_pair = KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap();
+        let cert = CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .self_signed(&key_pair)
+            .unwrap();
+        let err = RaTlsEvidence::from_cert(cert.der()).unwrap_err();
+        assert!(
+            err.to_string().contains("does not carry a TDX quote"),
+            "{err}"
+        );
+        assert!(verifier().verify_certificate_at(cert.der(), now()).is_err());
+    }
+
+    #[test]
+    fn verifies_key_binding() {
+        let verifier = verifier();
+        let cert = certificate(None, true);
+        let output = verifier
+            .verify_certificate_at(cert.cert_der(), now())
+            .unwrap();
+        assert_eq!(output.fmspc_hex(), "00806F050000");
+
+        let err = verifier
+            .verify_certificate_at(certificate(Some([0; 64]), true).cert_der(), now())
+            .unwrap_err();
+        assert!(err.to_string().contains("Report data"), "{err}");
+
+        // The verifier only accepts embedded collaterals
+        let err = verifier
+            .verify_certificate_at(certificate(None, false).cert_der(), now())
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("does not carry collaterals"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn verifies_server_and_client_certificates() {
+        let verifier = verifier();
+        let cert = CertificateDer::from(certificate(None, true).cert_der().to_vec());
+        let unbound = CertificateDer::from(certificate(Some([0; 64]), true).cert_der().to_vec());
+        let name = ServerName::try_from("localhost").unwrap();
+
+        verifier
+            .verify_server_cert(&cert, &[], &name, &[], now())
+            .unwrap();
+        verifier.verify_client_cert(&cert, &[], now()).unwrap();
+        assert!(matches!(
+            verifier.verify_server_cert(&unbound, &[], &name, &[], now()),
+            Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+                _
+            )))
+        ));
+        assert!(verifier.verify_client_cert(&unbound, &[], now()).is_err());
+
+        // Collaterals expire in 2034
+        let later = UnixTime::since_unix_epoch(Duration::from_secs(2_051_222_400));
+        assert!(verifier.verify_client_cert(&cert, &[], later).is_err());
+    }
+
+    /// Run a TLS handshake between `client` and `server` over in-memory buffers.
+    fn handshake(
+        client: &mut ClientConnection,
+        server: &mut ServerConnection,
+    ) -> std::result::Result<(), rustls::Error> {
+        while client.is_handshaking() || server.is_handshaking() {
+            transfer(client, server)?;
+            transfer(server, client)?;
+        }
+        Ok(())
+    }
+
+    /// Deliver everything `from` has to send. The certificates carry a quote and its
+    /// collaterals, so a flight spans several `read_tls` calls.
+    fn transfer<A, B>(
+        from: &mut ConnectionCommon<A>,
+        to: &mut ConnectionCommon<B>,
+    ) -> std::result::Result<(), rustls::Error> {
+        let mut buf = Vec::new();
+        from.write_tls(&mut buf).unwrap();
+        let mut pending = buf.as_slice();
+        while !pending.is_empty() {
+            to.read_tls(&mut pending).unwrap();
+            to.process_new_packets()?;
+        }
+        Ok(())
+    }
+
+    fn connect(
+        client_cert: &RaTlsCertificate,
+        server_cert: &RaTlsCertificate,
+    ) -> std::result::Result<(), rustls::Error> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let (certs, key) = server_cert.rustls_cert();
+        let server_config =
+            ServerConfig::builder_with_details(provider.clone(), Arc::new(TestTime))
+                .with_safe_default_protocol_versions()?
+                .with_client_cert_verifier(Arc::new(verifier()))
+                .with_single_cert(certs, key)?;
+        let (certs, key) = client_cert.rustls_cert();
+        let client_config = ClientConfig::builder_with_details(provider, Arc::new(TestTime))
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier()))
+            .with_client_auth_cert(certs, key)?;
+
+        let name = ServerName::try_from("localhost").unwrap();
+        let mut client = ClientConnection::new(Arc::new(client_config), name)?;
+        let mut server = ServerConnection::new(Arc::new(server_config))?;
+        handshake(&mut client, &mut server)
+    }
+
+    #[test]
+    fn establishes_mutual_tls() {
+        let cert = certificate(None, true);
+        connect(&cert, &certificate(None, true)).unwrap();
+        let unbound = certificate(Some([0; 64]), true);
+        assert!(connect(&cert, &unbound).is_err());
+        assert!(connect(&unbound, &cert).is_err());
+    }
+}
@@ -0,0 +1,808 @@
+use crate::error::{Result, TdxError};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha384};
+
+// TCG PC Client event types
+pub const EV_SEPARATOR: u32 = 0x0000_0004;
+pub const EV_EVENT_TAG: u32 = 0x0000_0006;
+pub const EV_PLATFORM_CONFIG_FLAGS: u32 = 0x0000_000a;
+pub const EV_IPL: u32 = 0x0000_000d;
+pub const EV_EFI_VARIABLE_DRIVER_CONFIG: u32 = 0x8000_0001;
+pub const EV_EFI_VARIABLE_BOOT: u32 = 0x8000_0002;
+pub const EV_EFI_BOOT_SERVICES_APPLICATION: u32 = 0x8000_0003;
+pub const EV_EFI_GPT_EVENT: u32 = 0x8000_0006;
+pub const EV_EFI_ACTION: u32 = 0x8000_0007;
+pub const EV_EFI_PLATFORM_FIRMWARE_BLOB2: u32 = 0x8000_000a;
+pub const EV_EFI_HANDOFF_TABLES2: u32 = 0x8000_000b;
+pub const EV_EFI_VARIABLE_AUTHORITY: u32 = 0x8000_00e0;
+
+/// GUID of the UEFI global variables (`SecureBoot`, `PK`, `KEK`, `BootOrder`, `Boot####`).
+pub const EFI_GLOBAL_VARIABLE_GUID: &str = "8be4df61-93ca-11d2-aa0d-00e098032b8c";
+/// GUID of the image security database variables (`db`, `dbx`).
+pub const EFI_IMAGE_SECURITY_DATABASE_GUID: &str = "d719b2cb-3d3a-4596-a3bc-dad00e67656f";
+/// GUID of the shim variables (`SbatLevel`, `MokList`).
+pub const SHIM_LOCK_GUID: &str = "605dab50-e046-4300-abb6-3dd810dd8b23";
+
+const CALLING_EFI_APPLICATION: &str = "Calling EFI Application from Boot Option";
+const EXIT_BOOT_SERVICES_INVOCATION: &str = "Exit Boot Services Invocation";
+const EXIT_BOOT_SERVICES_RETURNED: &str = "Exit Boot Services Returned with Success";
+
+/// An event extended into an RTMR.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeasuredEvent {
+    /// RTMR index (0 to 3).
+    pub rtmr: usize,
+    pub event_type: u32,
+    pub description: String,
+    #[serde(with = "crate::utils::serde_hex")]
+    pub digest: [u8; 48],
+}
+
+/// Replays events into RTMR0 to RTMR3, starting from zero like a freshly created TD.
+#[derive(Debug, Clone)]
+pub struct RtmrCalculator {
+    rtmrs: [[u8; 48]; 4],
+    events: Vec<MeasuredEvent>,
+}
+
+impl Default for RtmrCalculator {
+    fn default() -> Self {
+        Self {
+            rtmrs: [[0; 48]; 4],
+            events: Vec::new(),
+        }
+    }
+}
+
+impl RtmrCalculator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Extend `digest` into `rtmr`: `RTMR = SHA384(RTMR || digest)`.
+    pub fn extend(&mut self, rtmr: usize, event_type: u32, description: &str, digest: [u8; 48]) {
+        let mut hasher = Sha384::new();
+        hasher.update(self.rtmrs[rtmr]);
+        hasher.update(digest);
+        self.rtmrs[rtmr] = hasher.finalize().into();
+        self.events.push(MeasuredEvent {
+            rtmr,
+            event_type,
+            description: description.to_string(),
+            digest,
+        });
+    }
+
+    /// Extend the SHA-384 digest of `data` into `rtmr`.
+    pub fn measure(&mut self, rtmr: usize, event_type: u32, description: &str, data: &[u8]) {
+        self.extend(rtmr, event_type, description, sha384(data));
+    }
+
+    pub fn rtmr(&self, index: usize) -> [u8; 48] {
+        self.rtmrs[index]
+    }
+
+    pub fn rtmrs(&self) -> [[u8; 48]; 4] {
+        self.rtmrs
+    }
+
+    /// The events replayed so far, in order.
+    pub fn events(&self) -> &[MeasuredEvent] {
+        &self.events
+    }
+}
+
+/// A boot variable (`BootOrder`, `Boot####`) measured during boot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootVariable {
+    pub name: String,
+    #[serde(with = "crate::utils::serde_hex")]
+    pub data: Vec<u8>,
+}
+
+/// Secure Boot configuration of the firmware. Empty keys are measured as empty variables, as
+/// OVMF does when Secure Boot is not provisioned.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecureBootConfig {
+    pub enabled: bool,
+    #[serde(with = "crate::utils::serde_hex")]
+    pub pk: Vec<u8>,
+    #[serde(with = "crate::utils::serde_hex")]
+    pub kek: Vec<u8>,
+    #[serde(with = "crate::utils::serde_hex")]
+    pub db: Vec<u8>,
+    #[serde(with = "crate::utils::serde_hex")]
+    pub dbx: Vec<u8>,
+}
+
+/// Variables measured by shim before it starts grub. shim measures the runtime mirrors
+/// (`MokListRT`, `MokListXRT`, `MokListTrustedRT`), which include the certificates and hashes
+/// built into shim, so take their contents from a reference boot.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShimVariables {
+    /// `SbatLevel`, measured as a variable authority into RTMR0.
+    pub sbat_level: Vec<u8>,
+    /// `MokList`, measured into RTMR2 even when empty.
+    pub mok_list: Vec<u8>,
+    /// `MokListX`, measured into RTMR2 even when empty.
+    pub mok_list_x: Vec<u8>,
+    /// `MokListTrusted`, measured into RTMR2 by shim 15.5 and later.
+    pub mok_list_trusted: Option<Vec<u8>>,
+}
+
+/// An event measured by grub, in the order grub measures them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrubEvent {
+    /// A file read by grub (`grub.cfg`, modules, fonts), measured under its path.
+    File { path: String, data: Vec<u8> },
+    /// A command run by grub, measured as `grub_cmd: <command>`.
+    Command(String),
+    /// The kernel loaded by the `linux` command, measured under its path and followed by
+    /// `kernel_cmdline: <cmdline>`.
+    Kernel { path: String },
+    /// The initrd loaded by the `initrd` command, measured under its path.
+    Initrd { path: String },
+}
+
+/// How the kernel is started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BootFlow {
+    /// The VMM passes the kernel, initrd and command line to the firmware (QEMU `-kernel`),
+    /// and the kernel EFI stub measures the initrd and its load options. OVMF's QEMU kernel
+    /// loader appends ` initrd=initrd` to the command line in the load options when the VMM
+    /// passes an initrd.
+    Direct,
+    /// The firmware starts shim, which measures its variables and starts grub, which reads
+    /// its configuration, runs its commands and loads the kernel and initrd.
+    ShimGrub {
+        shim: Vec<u8>,
+        shim_variables: ShimVariables,
+        grub: Vec<u8>,
+        /// Must load the kernel once, and the initrd once if the chain has one.
+        grub_events: Vec<GrubEvent>,
+    },
+}
+
+/// Inputs of the boot chain of a TD.
+#[derive(Debug, Clone)]
+pub struct BootChain {
+    /// TD HOB passed by the VMM to the firmware.
+    pub td_hob: Vec<u8>,
+    /// Configuration firmware volume of the TDVF image.
+    pub cfv: Vec<u8>,
+    pub secure_boot: SecureBootConfig,
+    /// ACPI blobs passed by the VMM (`etc/table-loader`, `etc/acpi/rsdp`, `etc/acpi/tables`),
+    /// in the order they are measured.
+    pub acpi_tables: Vec<(String, Vec<u8>)>,
+    /// `BootOrder` followed by the `Boot####` variables, in the order they are measured.
+    pub boot_variables: Vec<BootVariable>,
+    /// The kernel PE/COFF image.
+    pub kernel: Vec<u8>,
+    pub initrd: Option<Vec<u8>>,
+    pub cmdline: String,
+    /// `UEFI_GPT_DATA` of the boot disk (see [`uefi_gpt_data`]), measured before the first
+    /// boot application when booting from a disk.
+    pub gpt: Option<Vec<u8>>,
+    pub flow: BootFlow,
+}
+
+impl BootChain {
+    /// Replay the events of booting this chain and return the calculator holding the expected
+    /// RTMR values and the event sequence.
+    ///
+    /// The sequence models OVMF built for TDX (PCR 0, 1 and 7 map to RTMR0, PCR 2 to 6 to
+    /// RTMR1 and PCR 8 to 15 to RTMR2) followed by the kernel EFI stub or shim and grub.
+    /// Firmware or bootloader versions that measure differently will produce other values, so
+    /// compare the event sequence against the CCEL of a reference boot when onboarding a new
+    /// platform.
+    pub fn compute(&self) -> Result<RtmrCalculator> {
+        let mut calc = RtmrCalculator::new();
+
+        // RTMR0: firmware configuration
+        calc.measure(0, EV_EFI_HANDOFF_TABLES2, "TD HOB", &self.td_hob);
+        calc.measure(0, EV_EFI_PLATFORM_FIRMWARE_BLOB2, "CFV", &self.cfv);
+        let secure_boot = &self.secure_boot;
+        let variables = [
+            (
+                EFI_GLOBAL_VARIABLE_GUID,
+                "SecureBoot",
+                vec![secure_boot.enabled as u8],
+            ),
+            (EFI_GLOBAL_VARIABLE_GUID, "PK", secure_boot.pk.clone()),
+            (EFI_GLOBAL_VARIABLE_GUID, "KEK", secure_boot.kek.clone()),
+            (
+                EFI_IMAGE_SECURITY_DATABASE_GUID,
+                "db",
+                secure_boot.db.clone(),
+            ),
+            (
+                EFI_IMAGE_SECURITY_DATABASE_GUID,
+                "dbx",
+                secure_boot.dbx.clone(),
+            ),
+        ];
+        for (guid, name, data) in variables {
+            let digest = uefi_variable_digest(guid, name, &data)?;
+            calc.extend(0, EV_EFI_VARIABLE_DRIVER_CONFIG, name, digest);
+        }
+        calc.extend(0, EV_SEPARATOR, "Separator (PCR 7)", separator_digest());
+        for (name, data) in &self.acpi_tables {
+            calc.measure(0, EV_PLATFORM_CONFIG_FLAGS, name, data);
+        }
+        // Unlike the Secure Boot configuration, edk2 measures only the data of boot variables
+        for variable in &self.boot_variables {
+            calc.measure(0, EV_EFI_VARIABLE_BOOT, &variable.name, &variable.data);
+        }
+
+        // ReadyToBoot: boot attempt and separators of PCR 0 to 6
+        calc.measure(
+            1,
+            EV_EFI_ACTION,
+            CALLING_EFI_APPLICATION,
+            CALLING_EFI_APPLICATION.as_bytes(),
+        );
+        for pcr in 0..7 {
+            let rtmr = if pcr < 2 { 0 } else { 1 };
+            calc.extend(
+                rtmr,
+                EV_SEPARATOR,
+                &format!("Separator (PCR {pcr})"),
+                separator_digest(),
+            );
+        }
+
+        // RTMR1: boot disk and applications, RTMR2: bootloader and kernel measurements
+        if let Some(gpt) = &self.gpt {
+            calc.measure(1, EV_EFI_GPT_EVENT, "GPT", gpt);
+        }
+        match &self.flow {
+            BootFlow::Direct => {
+                calc.extend(
+                    1,
+                    EV_EFI_BOOT_SERVICES_APPLICATION,
+                    "Kernel",
+                    authenticode_sha384(&self.kernel)?,
+                );
+                // The kernel EFI stub measures its load options as UTF-16 with a NUL terminator
+                calc.measure(
+                    2,
+                    EV_EVENT_TAG,
+                    "Kernel command line",
+                    &utf16_nul(&self.direct_load_options()),
+                );
+                if let Some(initrd) = &self.initrd {
+                    calc.measure(2, EV_EVENT_TAG, "Initrd", initrd);
+                }
+            }
+            BootFlow::ShimGrub {
+                shim,
+                shim_variables,
+                grub,
+                grub_events,
+            } => {
+                calc.extend(
+                    1,
+                    EV_EFI_BOOT_SERVICES_APPLICATION,
+                    "Shim",
+                    authenticode_sha384(shim)?,
+                );
+                let digest =
+                    uefi_variable_digest(SHIM_LOCK_GUID, "SbatLevel", &shim_variables.sbat_level)?;
+                calc.extend(0, EV_EFI_VARIABLE_AUTHORITY, "SbatLevel", digest);
+                calc.measure(2, EV_IPL, "MokList", &shim_variables.mok_list);
+                calc.measure(2, EV_IPL, "MokListX", &shim_variables.mok_list_x);
+                if let Some(trusted) = &shim_variables.mok_list_trusted {
+                    calc.measure(2, EV_IPL, "MokListTrusted", trusted);
+                }
+                calc.extend(
+                    1,
+                    EV_EFI_BOOT_SERVICES_APPLICATION,
+                    "Grub",
+                    authenticode_sha384(grub)?,
+                );
+                self.replay_grub(&mut calc, grub_events)?;
+                calc.extend(
+                    1,
+                    EV_EFI_BOOT_SERVICES_APPLICATION,
+                    "Kernel",
+                    authenticode_sha384(&self.kernel)?,
+                );
+            }
+        }
+
+        calc.measure(
+            1,
+            EV_EFI_ACTION,
+            EXIT_BOOT_SERVICES_INVOCATION,
+            EXIT_BOOT_SERVICES_INVOCATION.as_bytes(),
+        );
+        calc.measure(
+            1,
+            EV_EFI_ACTION,
+            EXIT_BOOT_SERVICES_RETURNED,
+            EXIT_BOOT_SERVICES_RETURNED.as_bytes(),
+        );
+        Ok(calc)
+    }
+
+    /// Load options OVMF's QEMU kernel loader starts a directly booted kernel with: the command
+    /// line, followed by ` initrd=initrd` when the VMM passes an initrd.
+    fn direct_load_options(&self) -> String {
+        match self.initrd {
+            Some(_) => format!("{} initrd=initrd", self.cmdline),
+            None => self.cmdline.clone(),
+        }
+    }
+
+    /// Replay the grub events into RTMR2. grub measures commands into PCR 8 and files into
+    /// PCR 9, which both map to RTMR2, so their interleaving matters.
+    fn replay_grub(&self, calc: &mut RtmrCalculator, events: &[GrubEvent]) -> Result<()> {
+        let invalid =
+            |reason: &str| TdxError::ConfigOptions(format!("Invalid grub events: {reason}"));
+        let (mut kernels, mut initrds) = (0, 0);
+        for event in events {
+            match event {
+                GrubEvent::File { path, data } => calc.measure(2, EV_IPL, path, data),
+                GrubEvent::Command(command) => {
+                    let event = format!("grub_cmd: {command}");
+                    calc.measure(2, EV_IPL, &event, event.as_bytes());
+                }
+                GrubEvent::Kernel { path } => {
+                    kernels += 1;
+                    calc.measure(2, EV_IPL, path, &self.kernel);
+                    let event = format!("kernel_cmdline: {}", self.cmdline);
+                    calc.measure(2, EV_IPL, &event, event.as_bytes());
+                }
+                GrubEvent::Initrd { path } => {
+                    let initrd = self
+                        .initrd
+                        .as_ref()
+                        .ok_or_else(|| invalid("initrd loaded but the chain has none"))?;
+                    initrds += 1;
+                    calc.measure(2, EV_IPL, path, initrd);
+                }
+            }
+        }
+        if kernels != 1 {
+            return Err(invalid("the kernel must be loaded exactly once"));
+        }
+        if self.initrd.is_some() && initrds != 1 {
+            return Err(invalid("the initrd must be loaded exactly once"));
+        }
+        Ok(())
+    }
+}
+
+/// Digest of a `UEFI_VARIABLE_DATA` structure: vendor GUID, name length, data length,
+/// UTF-16 name and data.
+pub fn uefi_variable_digest(guid: &str, name: &str, data: &[u8]) -> Result<[u8; 48]> {
+    let name: Vec<u16> = name.encode_utf16().collect();
+    let mut hasher = Sha384::new();
+    hasher.update(parse_guid(guid)?);
+    hasher.update((name.len() as u64).to_le_bytes());
+    hasher.update((data.len() as u64).to_le_bytes());
+    for c in name {
+        hasher.update(c.to_le_bytes());
+    }
+    hasher.update(data);
+    Ok(hasher.finalize().into())
+}
+
+/// Build the `UEFI_GPT_DATA` measured by the firmware from the start of a disk image with
+/// 512-byte sectors: the partition table header, the number of used partitions and the used
+/// partition entries.
+pub fn uefi_gpt_data(disk: &[u8]) -> Result<Vec<u8>> {
+    const SECTOR: usize = 512;
+    const HEADER_LEN: usize = 92;
+    let invalid = |reason: &str| TdxError::Firmware(format!("Invalid GPT: {reason}"));
+    let header = disk
+        .get(SECTOR..SECTOR + HEADER_LEN)
+        .ok_or_else(|| invalid("truncated header"))?;
+    if &header[..8] != b"EFI PART" {
+        return Err(invalid("missing signature"));
+    }
+    let u32_at =
+        |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap()) as usize;
+    let entries_lba = u64::from_le_bytes(header[72..80].try_into().unwrap());
+    let count = u32_at(80);
+    let entry_len = u32_at(84);
+    if entry_len < 128 {
+        return Err(invalid("partition entries too small"));
+    }
+    let start = usize::try_from(entries_lba)
+        .ok()
+        .and_then(|lba| lba.checked_mul(SECTOR))
+        .ok_or_else(|| invalid("partition entries out of bounds"))?;
+    let entries = count
+        .checked_mul(entry_len)
+        .and_then(|len| disk.get(start..start.checked_add(len)?))
+        .ok_or_else(|| invalid("partition entries out of bounds"))?;
+
+    // Entries with a zero partition type GUID are unused
+    let used: Vec<&[u8]> = entries
+        .chunks_exact(entry_len)
+        .filter(|entry| entry[..16] != [0u8; 16])
+        .collect();
+    let mut data = header.to_vec();
+    data.extend_from_slice(&(used.len() as u64).to_le_bytes());
+    for entry in used {
+        data.extend_from_slice(entry);
+    }
+    Ok(data)
+}
+
+/// Digest of an `EV_SEPARATOR` event.
+pub fn separator_digest() -> [u8; 48] {
+    sha384(&[0u8; 4])
+}
+
+/// Authenticode SHA-384 digest of a PE/COFF image, as measured by the firmware when it loads
+/// an EFI application.
+pub fn authenticode_sha384(image: &[u8]) -> Result<[u8; 48]> {
+    let invalid = |reason: &str| TdxError::Firmware(format!("Invalid PE image: {reason}"));
+    let u16_at = |offset: usize| {
+        image
+            .get(offset..offset + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(|| invalid("truncated headers"))
+    };
+    let u32_at = |offset: usize| {
+        image
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| invalid("truncated headers"))
+    };
+
+    if image.get(..2) != Some(&b"MZ"[..]) {
+        return Err(invalid("missing MZ signature"));
+    }
+    let pe = u32_at(0x3c)?;
+    if image.get(pe..pe + 4) != Some(&b"PE\0\0"[..]) {
+        return Err(invalid("missing PE signature"));
+    }
+    let coff = pe + 4;
+    let section_count = u16_at(coff + 2)?;
+    let optional_header = coff + 20;
+    let optional_header_len = u16_at(coff + 16)?;
+    let data_directories = match u16_at(optional_header)? {
+        0x10b => optional_header + 96,
+        0x20b => optional_header + 112,
+        _ => return Err(invalid("unknown optional header magic")),
+    };
+    let checksum = optional_header + 64;
+    let headers_len = u32_at(optional_header + 60)?;
+    // Certificate table: data directory 4
+    let cert_directory = data_directories + 4 * 8;
+    let cert_len = u32_at(cert_directory + 4)?;
+    if headers_len > image.len() || cert_directory + 8 > headers_len {
+        return Err(invalid("headers out of bounds"));
+    }
+
+    let mut hasher = Sha384::new();
+    hasher.update(&image[..checksum]);
+    hasher.update(&image[checksum + 4..cert_directory]);
+    hasher.update(&image[cert_directory + 8..headers_len]);
+
+    let section_table = optional_header + optional_header_len;
+    let mut sections = Vec::with_capacity(section_count);
+    for i in 0..section_count {
+        let header = section_table + i * 40;
+        let raw_len = u32_at(header + 16)?;
+        let raw_offset = u32_at(header + 20)?;
+        if raw_len > 0 {
+            sections.push((raw_offset, raw_len));
+        }
+    }
+    sections.sort();
+    let mut hashed = headers_len;
+    for (offset, len) in sections {
+        let data = image
+            .get(offset..offset + len)
+            .ok_or_else(|| invalid("section out of bounds"))?;
+        hasher.update(data);
+        hashed += len;
+    }
+    // Trailing data, excluding the attribute certificates
+    let end = image.len().saturating_sub(cert_len);
+    if end > hashed {
+        hasher.update(&image[hashed..end]);
+    }
+    Ok(hasher.finalize().into())
+}
+
+fn sha384(data: &[u8]) -> [u8; 48] {
+    Sha384::digest(data).into()
+}
+
+fn utf16_nul(s: &str) -> Vec<u8> {
+    s.encode_utf16()
+        .chain(std::iter::once(0))
+        .flat_map(|c| c.to_le_bytes())
+        .collect()
+}
+
+/// Encode a GUID string in its mixed-endian binary form.
+fn parse_guid(guid: &str) -> Result<[u8; 16]> {
+    let invalid = || TdxError::ConfigOptions(format!("Invalid GUID: {guid}"));
+    let parts: Vec<&str> = guid.split('-').collect();
+    if parts.len() != 5 || parts.iter().map(|p| p.len()).ne([8, 4, 4, 4, 12]) {
+        return Err(invalid());
+    }
+    let bytes = hex::decode(parts.concat()).map_err(|_| invalid())?;
+    let mut out = [0u8; 16];
+    out[..4].copy_from_slice(&[bytes[3], bytes[2], bytes[1], bytes[0]]);
+    out[4..6].copy_from_slice(&[bytes[5], bytes[4]]);
+    out[6..8].copy_from_slice(&[bytes[7], bytes[6]]);
+    out[8..].copy_from_slice(&bytes[8..]);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PE32+ image with a non-zero checksum and one section filled with `fill`.
+    fn pe_image(fill: u8) -> Vec<u8> {
+        let mut image = vec![0u8; 0x400];
+        image[..2].copy_from_slice(b"MZ");
+        image[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        image[0x40..0x44].copy_from_slice(b"PE\0\0");
+        image[0x44..0x46].copy_from_slice(&0x8664u16.to_le_bytes());
+        image[0x46..0x48].copy_from_slice(&1u16.to_le_bytes());
+        image[0x54..0x56].copy_from_slice(&240u16.to_le_bytes());
+        // Optional header: magic, SizeOfHeaders, CheckSum, NumberOfRvaAndSizes
+        image[0x58..0x5a].copy_from_slice(&0x20bu16.to_le_bytes());
+        image[0x94..0x98].copy_from_slice(&0x200u32.to_le_bytes());
+        image[0x98..0x9c].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+        image[0xc4..0xc8].copy_from_slice(&16u32.to_le_bytes());
+        // Section table
+        image[0x148..0x14d].copy_from_slice(b".text");
+        image[0x158..0x15c].copy_from_slice(&0x200u32.to_le_bytes());
+        image[0x15c..0x160].copy_from_slice(&0x200u32.to_le_bytes());
+        image[0x200..].fill(fill);
+        image
+    }
+
+    /// Protective MBR, a GPT header at LBA 1 and four partition entries at LBA 2, of which
+    /// entries 0 and 2 are used.
+    fn disk() -> Vec<u8> {
+        let mut disk = vec![0u8; 3 * 512];
+        let header = &mut disk[512..604];
+        header[..8].copy_from_slice(b"EFI PART");
+        header[8..12].copy_from_slice(&0x10000u32.to_le_bytes());
+        header[12..16].copy_from_slice(&92u32.to_le_bytes());
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&4u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        for (entry, type_byte, start) in [(0, 0xaa, 0), (2, 0xbb, 112)] {
+            let entry = &mut disk[1024 + entry * 128..1024 + (entry + 1) * 128];
+            entry[..16].fill(type_byte);
+            for (i, byte) in entry[16..].iter_mut().enumerate() {
+                *byte = (start + i) as u8;
+            }
+        }
+        disk
+    }
+
+    fn shim_grub_chain() -> BootChain {
+        BootChain {
+            td_hob: b"td hob".to_vec(),
+            cfv: b"cfv".to_vec(),
+            secure_boot: SecureBootConfig {
+                enabled: true,
+                pk: b"pk".to_vec(),
+                kek: b"kek".to_vec(),
+                db: b"db".to_vec(),
+                dbx: b"dbx".to_vec(),
+            },
+            acpi_tables: vec![("etc/acpi/tables".to_string(), b"acpi".to_vec())],
+            boot_variables: vec![BootVariable {
+                name: "BootOrder".to_string(),
+                data: vec![0, 0],
+            }],
+            kernel: pe_image(0x11),
+            initrd: Some(b"initrd".to_vec()),
+            cmdline: "BOOT_IMAGE=/vmlinuz root=/dev/vda1".to_string(),
+            gpt: Some(uefi_gpt_data(&disk()).unwrap()),
+            flow: BootFlow::ShimGrub {
+                shim: pe_image(0x22),
+                shim_variables: ShimVariables {
+                    sbat_level: b"sbat,1,2022111500\nshim,2\ngrub,3\n".to_vec(),
+                    mok_list: b"moklist".to_vec(),
+                    mok_list_x: b"moklistx".to_vec(),
+                    mok_list_trusted: Some(vec![1]),
+                },
+                grub: pe_image(0x33),
+                grub_events: vec![
+                    GrubEvent::File {
+                        path: "(hd0,gpt1)/EFI/ubuntu/grub.cfg".to_string(),
+                        data: b"configfile $prefix/grub.cfg\n".to_vec(),
+                    },
+                    GrubEvent::Command("configfile (hd0,gpt1)/boot/grub/grub.cfg".to_string()),
+                    GrubEvent::File {
+                        path: "(hd0,gpt1)/boot/grub/grub.cfg".to_string(),
+                        data: b"linux /vmlinuz root=/dev/vda1\ninitrd /initrd.img\n".to_vec(),
+                    },
+                    GrubEvent::Command("linux /vmlinuz root=/dev/vda1".to_string()),
+                    GrubEvent::Kernel {
+                        path: "/vmlinuz".to_string(),
+                    },
+                    GrubEvent::Command("initrd /initrd.img".to_string()),
+                    GrubEvent::Initrd {
+                        path: "/initrd.img".to_string(),
+                    },
+                ],
+            },
+        }
+    }
+
+    /// The kernel, initrd and command line of [`shim_grub_chain`] passed by the VMM.
+    fn direct_chain() -> BootChain {
+        BootChain {
+            cmdline: "console=ttyS0 root=/dev/vda1".to_string(),
+            gpt: None,
+            flow: BootFlow::Direct,
+            ..shim_grub_chain()
+        }
+    }
+
+    #[test]
+    fn builds_gpt_event_data() {
+        let data = uefi_gpt_data(&disk()).unwrap();
+        assert_eq!(data.len(), 92 + 8 + 2 * 128);
+        assert_eq!(data[..92], disk()[512..604]);
+        assert_eq!(data[92..100], 2u64.to_le_bytes());
+        assert_eq!(data[100..116], [0xaa; 16]);
+        assert_eq!(data[228..244], [0xbb; 16]);
+
+        let mut corrupted = disk();
+        corrupted[512] = b'X';
+        assert!(uefi_gpt_data(&corrupted).is_err());
+        assert!(uefi_gpt_data(&disk()[..1100]).is_err());
+    }
+
+    #[test]
+    fn computes_known_shim_grub_rtmrs() {
+        // Replayed over the same inputs by an independent implementation of the OVMF, shim and
+        // grub measurements. This checks the event sequence modelled here, not a captured CCEL.
+        let expected = [
+            concat!(
+                "7e11d98f6b2c08a385f9aedb5efe8b9c577b3271d7ecc568b4320f656eee3d74",
+                "6b1eb0fb35cd04e50d92aa26c3e13ca0",
+            ),
+            concat!(
+                "9f8c1145b257765c6d6ec9e2556d3f6cd56004979fb1bfd07f6d7fc93fb8c4c1",
+                "fffec0d2a7200f259cee9254f4e9d82d",
+            ),
+            concat!(
+                "acb6cbb37066758116f034b200545b7bcc6deb0866e59b2a640325dcf5be9c3c",
+                "ace31ee36320e2b2c72a189d84cb2876",
+            ),
+        ];
+        let calc = shim_grub_chain().compute().unwrap();
+        for (index, expected) in expected.iter().enumerate() {
+            assert_eq!(hex::encode(calc.rtmr(index)), *expected, "RTMR{index}");
+        }
+        assert_eq!(calc.rtmr(3), [0u8; 48]);
+
+        let descriptions: Vec<&str> = calc
+            .events()
+            .iter()
+            .filter(|event| event.rtmr == 2)
+            .map(|event| event.description.as_str())
+            .collect();
+        assert_eq!(
+            descriptions[..4],
+            [
+                "MokList",
+                "MokListX",
+                "MokListTrusted",
+                "(hd0,gpt1)/EFI/ubuntu/grub.cfg"
+            ]
+        );
+        assert!(calc
+            .events()
+            .iter()
+            .any(|event| event.rtmr == 0 && event.event_type == EV_EFI_VARIABLE_AUTHORITY));
+    }
+
+    #[test]
+    fn computes_direct_boot_rtmrs() {
+        // Checks the events modelled for OVMF's QEMU kernel loader and the kernel EFI stub. No
+        // CCEL of a direct boot was available to compare these against.
+        let chain = direct_chain();
+        let calc = chain.compute().unwrap();
+        let load_options = utf16_nul("console=ttyS0 root=/dev/vda1 initrd=initrd");
+        let rtmr2: Vec<&MeasuredEvent> = calc.events().iter().filter(|e| e.rtmr == 2).collect();
+        assert_eq!(rtmr2.len(), 2);
+        assert_eq!(rtmr2[0].description, "Kernel command line");
+        assert_eq!(rtmr2[0].digest, sha384(&load_options));
+        assert_eq!(rtmr2[1].description, "Initrd");
+        assert_eq!(rtmr2[1].digest, sha384(b"initrd"));
+        let extend = |rtmr: [u8; 48], digest: [u8; 48]| -> [u8; 48] {
+            Sha384::new()
+                .chain_update(rtmr)
+                .chain_update(digest)
+                .finalize()
+                .into()
+        };
+        assert_eq!(
+            calc.rtmr(2),
+            extend(extend([0; 48], sha384(&load_options)), sha384(b"initrd"))
+        );
+        assert_eq!(calc.rtmr(3), [0u8; 48]);
+
+        // The kernel is the only boot application, and nothing measures shim variables
+        let applications: Vec<&MeasuredEvent> = calc
+            .events()
+            .iter()
+            .filter(|e| e.event_type == EV_EFI_BOOT_SERVICES_APPLICATION)
+            .collect();
+        assert_eq!(applications.len(), 1);
+        assert_eq!(
+            applications[0].digest,
+            authenticode_sha384(&chain.kernel).unwrap()
+        );
+        assert!(!calc
+            .events()
+            .iter()
+            .any(|e| e.event_type == EV_EFI_VARIABLE_AUTHORITY || e.event_type == EV_IPL));
+
+        let load_options_digest = |chain: BootChain| {
+            let calc = chain.compute().unwrap();
+            let rtmr2: Vec<[u8; 48]> = calc
+                .events()
+                .iter()
+                .filter(|e| e.rtmr == 2)
+                .map(|e| e.digest)
+                .collect();
+            rtmr2[0]
+        };
+        // Without an initrd the load options are the command line alone
+        let chain = BootChain {
+            initrd: None,
+            ..direct_chain()
+        };
+        assert_eq!(
+            chain
+                .compute()
+                .unwrap()
+                .events()
+                .iter()
+                .filter(|e| e.rtmr == 2)
+                .count(),
+            1
+        );
+        assert_eq!(
+            load_options_digest(chain),
+            sha384(&utf16_nul("console=ttyS0 root=/dev/vda1"))
+        );
+        // With an initrd and no command line, OVMF passes the suffix alone
+        let chain = BootChain {
+            cmdline: String::new(),
+            ..direct_chain()
+        };
+        assert_eq!(
+            load_options_digest(chain),
+            sha384(&utf16_nul(" initrd=initrd"))
+        );
+    }
+
+    #[test]
+    fn requires_grub_to_load_the_kernel_and_initrd_once() {
+        let mut chain = shim_grub_chain();
+        let BootFlow::ShimGrub { grub_events, .. } = &mut chain.flow else {
+            unreachable!()
+        };
+        grub_events.pop();
+        assert!(chain.compute().is_err());
+
+        let mut chain = shim_grub_chain();
+        let BootFlow::ShimGrub { grub_events, .. } = &mut chain.flow else {
+            unreachable!()
+        };
+        grub_events.retain(|event| !matches!(event, GrubEvent::Kernel { .. }));
+        assert!(chain.compute().is_err());
+    }
+}
@@ -0,0 +1,436 @@
+use crate::error::{Result, TdxError};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha384};
+
+const PAGE_SIZE: u64 = 0x1000;
+const MR_EXTEND_CHUNK_SIZE: usize = 256;
+/// Upper bound on the memory measured into MRTD, well above any TDVF image, so that a
+/// malformed image cannot make [`TdvfImage::compute_mrtd`] hash for hours.
+pub const MAX_MEASURED_SIZE: u64 = 4 << 30;
+
+// GUID of the OVMF table footer, located 0x30 bytes before the end of the image
+const OVMF_TABLE_FOOTER_GUID: [u8; 16] = guid_bytes("96b582de-1fb2-45f7-baea-a366c55a082d");
+// GUID of the OVMF table entry holding the TDX metadata offset from the end of the image
+const TDX_METADATA_OFFSET_GUID: [u8; 16] = guid_bytes("e47a6535-984a-4798-865e-4685a7bf8ec2");
+const OVMF_TABLE_FOOTER_OFFSET: usize = 0x30;
+
+const TDVF_SIGNATURE: &[u8; 4] = b"TDVF";
+const TDVF_DESCRIPTOR_LEN: usize = 16;
+const TDVF_SECTION_LEN: usize = 32;
+
+/// Section attribute: the section content is measured into MRTD with TDH.MR.EXTEND.
+pub const TDVF_ATTRIBUTE_MR_EXTEND: u32 = 0x1;
+/// Section attribute: the section pages are added with TDH.MEM.PAGE.AUG and not measured.
+pub const TDVF_ATTRIBUTE_PAGE_AUG: u32 = 0x2;
+
+/// Type of a TDVF metadata section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TdvfSectionType {
+    /// Boot firmware volume
+    Bfv,
+    /// Configuration firmware volume
+    Cfv,
+    TdHob,
+    TempMem,
+    PermMem,
+    Payload,
+    PayloadParam,
+    Other(u32),
+}
+
+impl From<u32> for TdvfSectionType {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => TdvfSectionType::Bfv,
+            1 => TdvfSectionType::Cfv,
+            2 => TdvfSectionType::TdHob,
+            3 => TdvfSectionType::TempMem,
+            4 => TdvfSectionType::PermMem,
+            5 => TdvfSectionType::Payload,
+            6 => TdvfSectionType::PayloadParam,
+            other => TdvfSectionType::Other(other),
+        }
+    }
+}
+
+/// A section of the TDVF metadata, describing how part of the image is loaded into the TD.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TdvfSection {
+    /// Offset of the section data in the image.
+    pub data_offset: u32,
+    pub raw_data_size: u32,
+    /// Guest physical address the section is loaded at.
+    pub memory_address: u64,
+    pub memory_data_size: u64,
+    pub section_type: TdvfSectionType,
+    pub attributes: u32,
+}
+
+impl TdvfSection {
+    /// Whether the section content is extended into MRTD.
+    pub fn is_extended(&self) -> bool {
+        self.attributes & TDVF_ATTRIBUTE_MR_EXTEND != 0
+    }
+
+    /// Whether the section pages are added with TDH.MEM.PAGE.ADD, and thus measured.
+    pub fn is_page_added(&self) -> bool {
+        self.attributes & TDVF_ATTRIBUTE_PAGE_AUG == 0
+    }
+}
+
+/// A TDVF (OVMF built for TDX) firmware image and its TDX metadata.
+#[derive(Debug, Clone)]
+pub struct TdvfImage<'a> {
+    image: &'a [u8],
+    sections: Vec<TdvfSection>,
+}
+
+impl<'a> TdvfImage<'a> {
+    /// Parse the TDX metadata of a TDVF image, located through the OVMF GUIDed table at the end
+    /// of the image.
+    pub fn parse(image: &'a [u8]) -> Result<Self> {
+        let offset = metadata_offset(image)?;
+        let descriptor = image
+            .get(offset..offset + TDVF_DESCRIPTOR_LEN)
+            .ok_or_else(|| TdxError::Firmware("TDVF descriptor out of bounds".to_string()))?;
+        if &descriptor[..4] != TDVF_SIGNATURE {
+            return Err(TdxError::Firmware(
+                "TDVF descriptor signature not found".to_string(),
+            ));
+        }
+        let version = read_u32(descriptor, 8);
+        if version != 1 {
+            return Err(TdxError::Firmware(format!(
+                "Unsupported TDVF descriptor version: {version}"
+            )));
+        }
+        let count = read_u32(descriptor, 12) as usize;
+        let start = offset + TDVF_DESCRIPTOR_LEN;
+        let table = count
+            .checked_mul(TDVF_SECTION_LEN)
+            .and_then(|len| image.get(start..start.checked_add(len)?))
+            .ok_or_else(|| TdxError::Firmware("TDVF sections out of bounds".to_string()))?;
+
+        let sections = table
+            .chunks_exact(TDVF_SECTION_LEN)
+            .map(|entry| {
+                let section = TdvfSection {
+                    data_offset: read_u32(entry, 0),
+                    raw_data_size: read_u32(entry, 4),
+                    memory_address: read_u64(entry, 8),
+                    memory_data_size: read_u64(entry, 16),
+                    section_type: read_u32(entry, 24).into(),
+                    attributes: read_u32(entry, 28),
+                };
+                check_section(image, &section)?;
+                Ok(section)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let measured_size = sections
+            .iter()
+            .filter(|section| section.is_page_added())
+            .try_fold(0u64, |total, section| {
+                total.checked_add(section.memory_data_size)
+            });
+        if !matches!(measured_size, Some(size) if size <= MAX_MEASURED_SIZE) {
+            return Err(TdxError::Firmware(format!(
+                "TDVF sections measure more than {MAX_MEASURED_SIZE:#x} bytes"
+            )));
+        }
+        Ok(TdvfImage { image, sections })
+    }
+
+    pub fn sections(&self) -> &[TdvfSection] {
+        &self.sections
+    }
+
+    /// Returns the raw data of `section` in the image.
+    pub fn section_data(&self, section: &TdvfSection) -> &'a [u8] {
+        &self.image[section.data_offset as usize..][..section.raw_data_size as usize]
+    }
+
+    /// Returns the configuration firmware volume (CFV), measured into RTMR0 at boot.
+    pub fn cfv(&self) -> Option<&'a [u8]> {
+        self.sections
+            .iter()
+            .find(|s| s.section_type == TdvfSectionType::Cfv)
+            .map(|s| self.section_data(s))
+    }
+
+    /// Compute the MRTD of a TD booted from this image, by replaying the TDH.MEM.PAGE.ADD and
+    /// TDH.MR.EXTEND operations the VMM performs over the sections of the metadata.
+    pub fn compute_mrtd(&self) -> [u8; 48] {
+        let mut mrtd = Sha384::new();
+        for section in &self.sections {
+            if !section.is_page_added() {
+                continue;
+            }
+            let data = self.section_data(section);
+            let mut gpa = section.memory_address;
+            let end = section.memory_address + section.memory_data_size;
+            while gpa < end {
+                mrtd.update(measurement_block(b"MEM.PAGE.ADD", gpa));
+                if section.is_extended() {
+                    let page_offset = (gpa - section.memory_address) as usize;
+                    for chunk in 0..(PAGE_SIZE as usize / MR_EXTEND_CHUNK_SIZE) {
+                        let chunk_offset = page_offset + chunk * MR_EXTEND_CHUNK_SIZE;
+                        let chunk_gpa = gpa + (chunk * MR_EXTEND_CHUNK_SIZE) as u64;
+                        mrtd.update(measurement_block(b"MR.EXTEND", chunk_gpa));
+                        // Memory beyond the raw data is zero-filled
+                        let mut content = [0u8; MR_EXTEND_CHUNK_SIZE];
+                        if chunk_offset < data.len() {
+                            let len = (data.len() - chunk_offset).min(MR_EXTEND_CHUNK_SIZE);
+                            content[..len].copy_from_slice(&data[chunk_offset..chunk_offset + len]);
+                        }
+                        mrtd.update(content);
+                    }
+                }
+                gpa += PAGE_SIZE;
+            }
+        }
+        mrtd.finalize().into()
+    }
+}
+
+/// Compute the MRTD of a TD booted from the TDVF image `image`.
+pub fn compute_mrtd(image: &[u8]) -> Result<[u8; 48]> {
+    Ok(TdvfImage::parse(image)?.compute_mrtd())
+}
+
+/// The 128-byte buffer hashed into MRTD for TDH.MEM.PAGE.ADD and TDH.MR.EXTEND: the operation
+/// name padded to 16 bytes followed by the GPA.
+fn measurement_block(operation: &[u8], gpa: u64) -> [u8; 128] {
+    let mut block = [0u8; 128];
+    block[..operation.len()].copy_from_slice(operation);
+    block[16..24].copy_from_slice(&gpa.to_le_bytes());
+    block
+}
+
+/// Find the TDX metadata offset from the OVMF GUIDed table. Entries are laid out backwards
+/// from the footer, each ending with its length (u16) and GUID.
+fn metadata_offset(image: &[u8]) -> Result<usize> {
+    let not_found = || TdxError::Firmware("TDX metadata not found in firmware image".to_string());
+    let footer = image
+        .len()
+        .checked_sub(OVMF_TABLE_FOOTER_OFFSET)
+        .ok_or_else(not_found)?;
+    if image[footer..footer + 16] != OVMF_TABLE_FOOTER_GUID || footer < 2 {
+        return Err(not_found());
+    }
+    // The table length covers the entries, the length itself and the footer GUID
+    let table_len = read_u16(image, footer - 2) as usize;
+    let table_start = (footer + 16).checked_sub(table_len).ok_or_else(not_found)?;
+
+    // Walk the entries, excluding the footer GUID and length
+    let mut end = footer - 2;
+    while end >= table_start + 18 {
+        let guid = &image[end - 16..end];
+        let len = read_u16(image, end - 18) as usize;
+        if len < 18 || end < table_start + len {
+            return Err(TdxError::Firmware(
+                "Malformed OVMF GUIDed table".to_string(),
+            ));
+        }
+        if guid == TDX_METADATA_OFFSET_GUID {
+            let data = &image[end - len..end - 18];
+            if data.len() < 4 {
+                return Err(not_found());
+            }
+            let offset = read_u32(data, 0) as usize;
+            return image.len().checked_sub(offset).ok_or_else(not_found);
+        }
+        end -= len;
+    }
+    Err(not_found())
+}
+
+fn check_section(image: &[u8], section: &TdvfSection) -> Result<()> {
+    let data_end = section.data_offset as u64 + section.raw_data_size as u64;
+    if data_end > image.len() as u64 {
+        return Err(TdxError::Firmware(format!(
+            "TDVF section at {:#x} exceeds the image size",
+            section.data_offset
+        )));
+    }
+    if section
+        .memory_address
+        .checked_add(section.memory_data_size)
+        .is_none()
+    {
+        return Err(TdxError::Firmware(format!(
+            "TDVF section at GPA {:#x} exceeds the address space",
+            section.memory_address
+        )));
+    }
+    if !section.memory_address.is_multiple_of(PAGE_SIZE)
+        || !section.memory_data_size.is_multiple_of(PAGE_SIZE)
+    {
+        return Err(TdxError::Firmware(format!(
+            "TDVF section at GPA {:#x} is not page aligned",
+            section.memory_address
+        )));
+    }
+    if section.raw_data_size as u64 > section.memory_data_size {
+        return Err(TdxError::Firmware(format!(
+            "TDVF section at GPA {:#x} has more data than memory",
+            section.memory_address
+        )));
+    }
+    Ok(())
+}
+
+/// Encode a GUID string in its mixed-endian binary form.
+const fn guid_bytes(guid: &str) -> [u8; 16] {
+    let s = guid.as_bytes();
+    // Hex digit positions of each byte in the canonical string, in binary order
+    const POSITIONS: [usize; 16] = [6, 4, 2, 0, 11, 9, 16, 14, 19, 21, 24, 26, 28, 30, 32, 34];
+    let mut out = [0u8; 16];
+    let mut i = 0;
+    while i < 16 {
+        out[i] = (hex_digit(s[POSITIONS[i]]) << 4) | hex_digit(s[POSITIONS[i] + 1]);
+        i += 1;
+    }
+    out
+}
+
+const fn hex_digit(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        b'A'..=b'F' => c - b'A' + 10,
+        _ => panic!("invalid GUID"),
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().expect("4 bytes"))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().expect("8 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IMAGE_SIZE: usize = 0x3000;
+    const METADATA_OFFSET: usize = 0x1800;
+
+    fn section(
+        data_offset: u32,
+        raw_data_size: u32,
+        memory_address: u64,
+        memory_data_size: u64,
+        section_type: u32,
+        attributes: u32,
+    ) -> TdvfSection {
+        TdvfSection {
+            data_offset,
+            raw_data_size,
+            memory_address,
+            memory_data_size,
+            section_type: section_type.into(),
+            attributes,
+        }
+    }
+
+    /// A BFV (extended), a CFV (added only), temporary memory and PAGE.AUG permanent memory.
+    fn sections() -> Vec<TdvfSection> {
+        vec![
+            section(0, 0x1000, 0xffff_f000, 0x1000, 0, TDVF_ATTRIBUTE_MR_EXTEND),
+            section(0x1000, 0x800, 0xffff_d000, 0x2000, 1, 0),
+            section(0, 0, 0x80_0000, 0x2000, 3, 0),
+            section(0, 0, 0x100_0000, 0x4000, 4, TDVF_ATTRIBUTE_PAGE_AUG),
+        ]
+    }
+
+    /// Lay out a minimal TDVF image: section data, the TDX metadata and the OVMF GUIDed table.
+    fn image(sections: &[TdvfSection]) -> Vec<u8> {
+        let mut image = vec![0u8; IMAGE_SIZE];
+        for (i, byte) in image[..0x1000].iter_mut().enumerate() {
+            *byte = (i % 251) as u8;
+        }
+        for (i, byte) in image[0x1000..0x1800].iter_mut().enumerate() {
+            *byte = (i * 7) as u8;
+        }
+
+        let mut metadata = TDVF_SIGNATURE.to_vec();
+        let len = TDVF_DESCRIPTOR_LEN + TDVF_SECTION_LEN * sections.len();
+        metadata.extend_from_slice(&(len as u32).to_le_bytes());
+        metadata.extend_from_slice(&1u32.to_le_bytes());
+        metadata.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+        for section in sections {
+            let section_type = match section.section_type {
+                TdvfSectionType::Bfv => 0,
+                TdvfSectionType::Cfv => 1,
+                TdvfSectionType::TempMem => 3,
+                TdvfSectionType::PermMem => 4,
+                other => panic!("unexpected section type {other:?}"),
+            };
+            metadata.extend_from_slice(&section.data_offset.to_le_bytes());
+            metadata.extend_from_slice(&section.raw_data_size.to_le_bytes());
+            metadata.extend_from_slice(&section.memory_address.to_le_bytes());
+            metadata.extend_from_slice(&section.memory_data_size.to_le_bytes());
+            metadata.extend_from_slice(&(section_type as u32).to_le_bytes());
+            metadata.extend_from_slice(&section.attributes.to_le_bytes());
+        }
+        image[METADATA_OFFSET..METADATA_OFFSET + metadata.len()].copy_from_slice(&metadata);
+
+        // Table: metadata offset entry (4 + 2 + 16 bytes), table length, footer GUID
+        let footer = IMAGE_SIZE - OVMF_TABLE_FOOTER_OFFSET;
+        image[footer..footer + 16].copy_from_slice(&OVMF_TABLE_FOOTER_GUID);
+        image[footer - 2..footer].copy_from_slice(&40u16.to_le_bytes());
+        let entry = footer - 2 - 22;
+        let offset_from_end = (IMAGE_SIZE - METADATA_OFFSET) as u32;
+        image[entry..entry + 4].copy_from_slice(&offset_from_end.to_le_bytes());
+        image[entry + 4..entry + 6].copy_from_slice(&22u16.to_le_bytes());
+        image[entry + 6..entry + 22].copy_from_slice(&TDX_METADATA_OFFSET_GUID);
+        image
+    }
+
+    #[test]
+    fn parses_metadata() {
+        let image = image(&sections());
+        let tdvf = TdvfImage::parse(&image).unwrap();
+        assert_eq!(tdvf.sections(), sections());
+        assert_eq!(tdvf.cfv(), Some(&image[0x1000..0x1800]));
+    }
+
+    #[test]
+    fn computes_known_mrtd() {
+        // Computed over the same image by an independent implementation of the TDH.MEM.PAGE.ADD
+        // and TDH.MR.EXTEND measurements of the TDX module specification.
+        let expected = hex::decode(concat!(
+            "82302e667103ada0141a420e54f978e648aea0d5cbf011e810602d7d",
+            "4a6cf39e547a71d2629217cd10a52b30b100a56f",
+        ))
+        .unwrap();
+        assert_eq!(compute_mrtd(&image(&sections())).unwrap()[..], expected);
+    }
+
+    #[test]
+    fn rejects_section_beyond_address_space() {
+        let mut sections = sections();
+        sections[2].memory_address = u64::MAX - 0xfff;
+        assert!(TdvfImage::parse(&image(&sections)).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_measurement() {
+        let mut sections = sections();
+        sections[2].memory_data_size = MAX_MEASURED_SIZE;
+        assert!(TdvfImage::parse(&image(&sections)).is_err());
+
+        // Memory added with PAGE.AUG is not measured and not counted
+        let mut sections = self::sections();
+        sections[3].memory_data_size = MAX_MEASURED_SIZE * 2;
+        assert!(TdvfImage::parse(&image(&sections)).is_ok());
+    }
+}
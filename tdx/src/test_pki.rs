@@ -0,0 +1,43 @@
+//! The test PKI of `tests/data`, generated by `tests/data/generate.py`: a TDX v4 quote signed
+//! under a test root, the collaterals rating it UpToDate, and the keys to re-sign it.
+
+use crate::collateral::RawCollaterals;
+use crate::quote::QuoteData;
+#[cfg(feature = "verifier")]
+use crate::validation::CollateralValidator;
+use p256::ecdsa::SigningKey;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// TDX v4 quote whose PCK certificate chains to the test root.
+pub(crate) const QUOTE: &[u8] = include_bytes!("../tests/data/test_pki_quote.bin");
+pub(crate) const COLLATERALS: &[u8] = include_bytes!("../tests/data/collaterals.json");
+const ROOT_PUBLIC_KEY: &str = include_str!("../tests/data/root_public_key.hex");
+
+pub(crate) fn collaterals() -> RawCollaterals {
+    serde_json::from_slice(COLLATERALS).unwrap()
+}
+
+/// A validator pinning the test root instead of the Intel root.
+#[cfg(feature = "verifier")]
+pub(crate) fn validator() -> CollateralValidator {
+    let key = hex::decode(ROOT_PUBLIC_KEY.trim()).unwrap();
+    CollateralValidator::with_root_public_key(key.try_into().unwrap())
+}
+
+/// 2025-01-01T00:00:00Z, within the validity of every certificate and collateral.
+pub(crate) fn now() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(1_735_689_600)
+}
+
+/// The attestation key signing [`QUOTE`].
+pub(crate) fn attestation_key() -> SigningKey {
+    SigningKey::from_slice(&[0x11; 32]).unwrap()
+}
+
+/// [`QUOTE`] with `report_data` in its TD report, re-signed with the attestation key.
+pub(crate) fn quote_with_report_data(report_data: [u8; 64]) -> Vec<u8> {
+    let mut quote = QuoteData::from_bytes(QUOTE).unwrap();
+    quote.td_report_mut().unwrap().report_data = report_data;
+    quote.sign(&attestation_key()).unwrap();
+    quote.to_bytes().unwrap()
+}
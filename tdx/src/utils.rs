@@ -1,7 +1,7 @@
 use crate::error::{Result, TdxError};
+use crate::pck::SgxExtensions;
 use dcap_rs::{types::quote::Quote, utils::cert_chain_processor};
 use rand::RngCore;
-use x509_parser::oid_registry::asn1_rs::{oid, FromDer, OctetString, Oid, Sequence};
 use x509_parser::prelude::{parse_x509_pem, X509Certificate};
 
 /// PCK Certificate Authority type
@@ -10,6 +10,33 @@ pub enum PckCA {
     Platform,
     Processor,
 }
+
+/// TEE type of a quote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TeeType {
+    Sgx,
+    Tdx,
+}
+
+impl TeeType {
+    /// Returns the TEE type from the `tee_type` field of a quote header.
+    pub fn from_header(tee_type: u32) -> Result<Self> {
+        match tee_type {
+            0x00 => Ok(TeeType::Sgx),
+            0x81 => Ok(TeeType::Tdx),
+            _ => Err(TdxError::Dcap(format!("Unknown TEE type: {tee_type:#x}"))),
+        }
+    }
+
+    /// Path segment used by the PCS API for this TEE type.
+    pub fn pcs_path(&self) -> &'static str {
+        match self {
+            TeeType::Sgx => "sgx",
+            TeeType::Tdx => "tdx",
+        }
+    }
+}
+
 /// Generates 64 bytes of random data.
 pub fn generate_random_data() -> [u8; 64] {
     let mut data = [0u8; 64];
@@ -17,6 +44,21 @@ pub fn generate_random_data() -> [u8; 64] {
     data
 }
 
+/// Runtime driving async calls made through the C and Python bindings, whose callers do not
+/// run one.
+#[cfg(any(feature = "clib", feature = "python"))]
+pub(crate) fn runtime() -> Result<&'static tokio::runtime::Runtime> {
+    static RUNTIME: std::sync::LazyLock<std::io::Result<tokio::runtime::Runtime>> =
+        std::sync::LazyLock::new(|| {
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+        });
+    RUNTIME
+        .as_ref()
+        .map_err(|e| TdxError::Anyhow(format!("Failed to start the async runtime: {e}")))
+}
+
 pub fn der_to_pem_bytes(der_bytes: &[u8]) -> Vec<u8> {
     let pem_struct = pem::Pem::new("CERTIFICATE".to_string(), der_bytes.to_vec());
     pem::encode(&pem_struct).into_bytes()
@@ -48,8 +90,7 @@ pub fn get_pck_fmspc_and_issuer(quote: &Quote) -> Result<(String, PckCA)> {
         _ => return Err(TdxError::Dcap(format!("Unknown PCK Issuer: {pck_issuer}"))),
     };
 
-    let fmspc_slice = extract_fmspc_from_extension(&pck)?;
-    let fmspc = hex::encode(fmspc_slice);
+    let fmspc = hex::encode(SgxExtensions::from_certificate(&pck)?.fmspc);
 
     Ok((fmspc, pck_ca))
 }
@@ -66,39 +107,24 @@ fn get_x509_issuer_cn<'a>(cert: &'a X509Certificate<'a>) -> Result<String> {
     Ok(cn_str.to_string())
 }
 
-fn extract_fmspc_from_extension<'a>(cert: &'a X509Certificate<'a>) -> Result<[u8; 6]> {
-    let sgx_extensions_bytes = cert
-        .get_extension_unique(&oid!(1.2.840 .113741 .1 .13 .1))
-        .map_err(|e| TdxError::X509(format!("Duplicate SGX extensions in certificate: {e}")))?
-        .ok_or_else(|| TdxError::X509("Certificate missing SGX extensions (OID 1.2.840.113741.1.13.1)".to_string()))?
-        .value;
-
-    let (_, sgx_extensions) = Sequence::from_der(sgx_extensions_bytes)
-        .map_err(|e| TdxError::X509(format!("Failed to parse SGX extensions sequence: {e}")))?;
-
-    let mut i = sgx_extensions.content.as_ref();
-
-    while !i.is_empty() {
-        let (j, current_sequence) = Sequence::from_der(i)
-            .map_err(|e| TdxError::X509(format!("Failed to parse SGX extension entry: {e}")))?;
-        i = j;
-        let (j, current_oid) = Oid::from_der(current_sequence.content.as_ref())
-            .map_err(|e| TdxError::X509(format!("Failed to parse SGX extension OID: {e}")))?;
-        if current_oid.to_id_string() == "1.2.840.113741.1.13.1.4" {
-            let (_, fmspc_bytes) = OctetString::from_der(j)
-                .map_err(|e| TdxError::X509(format!("Failed to parse FMSPC octet string: {e}")))?;
-            let mut fmspc = [0u8; 6];
-            let fmspc_ref = fmspc_bytes.as_ref();
-            if fmspc_ref.len() != 6 {
-                return Err(TdxError::X509(format!(
-                    "FMSPC has unexpected length: {} (expected 6)",
-                    fmspc_ref.len()
-                )));
-            }
-            fmspc.copy_from_slice(fmspc_ref);
-            return Ok(fmspc);
-        }
+/// Serde helpers to encode byte vectors and arrays as hex strings.
+pub mod serde_hex {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer, T: AsRef<[u8]>>(
+        bytes: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
     }
 
-    Err(TdxError::X509("FMSPC extension (OID 1.2.840.113741.1.13.1.4) not found in certificate".to_string()))
+    pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<Vec<u8>>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let bytes = hex::decode(s.trim_start_matches("0x")).map_err(serde::de::Error::custom)?;
+        let len = bytes.len();
+        T::try_from(bytes)
+            .map_err(|_| serde::de::Error::custom(format!("unexpected length: {len} bytes")))
+    }
 }
@@ -0,0 +1,477 @@
+use crate::collateral::{split_signed_json, RawCollaterals};
+use crate::error::{Result, TdxError};
+use dcap_rs::types::quote::Quote;
+use dcap_rs::utils::cert_chain_processor;
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use std::time::{SystemTime, UNIX_EPOCH};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use x509_parser::prelude::{parse_x509_certificate, parse_x509_crl, parse_x509_pem};
+
+/// Public key (uncompressed X || Y) of the Intel SGX Root CA.
+pub const INTEL_ROOT_CA_PUBLIC_KEY: [u8; 64] = [
+    0x0b, 0xa9, 0xc4, 0xc0, 0xc0, 0xc8, 0x61, 0x93, 0xa3, 0xfe, 0x23, 0xd6, 0xb0, 0x2c, 0xda, 0x10,
+    0xa8, 0xbb, 0xd4, 0xe8, 0x8e, 0x48, 0xb4, 0x45, 0x85, 0x61, 0xa3, 0x6e, 0x70, 0x55, 0x25, 0xf5,
+    0x67, 0x91, 0x8e, 0x2e, 0xdc, 0x88, 0xe4, 0x0d, 0x86, 0x0b, 0xd0, 0xcc, 0x4e, 0xe2, 0x6a, 0xac,
+    0xc9, 0x88, 0xe5, 0x05, 0xa9, 0x53, 0x55, 0x8c, 0x45, 0x3f, 0x6b, 0x09, 0x04, 0xae, 0x73, 0x94,
+];
+
+/// A single check performed by [`CollateralValidator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollateralCheck {
+    /// The root CA public key matches the pinned Intel root.
+    RootCaPinned,
+    RootCaSignature,
+    RootCaValidity,
+    TcbSigningCaSignature,
+    TcbSigningCaValidity,
+    /// The TCB signing CA is not listed in the root CA CRL.
+    TcbSigningCaNotRevoked,
+    RootCaCrlSignature,
+    RootCaCrlValidity,
+    /// The PCK CA is not listed in the root CA CRL.
+    PckCaNotRevoked,
+    /// Requires the PCK CA certificate, see [`CollateralValidator::validate`].
+    PckCrlSignature,
+    PckCrlValidity,
+    TcbInfoSignature,
+    TcbInfoValidity,
+    QeIdentitySignature,
+    QeIdentityValidity,
+}
+
+/// A failed check and the reason it failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationFailure {
+    pub check: CollateralCheck,
+    pub reason: String,
+}
+
+/// Outcome of validating a set of collaterals. Every check is run, so all failures are reported.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub passed: Vec<CollateralCheck>,
+    pub failures: Vec<ValidationFailure>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Returns an error listing every failed check, if any.
+    pub fn into_result(self) -> Result<()> {
+        if self.is_valid() {
+            return Ok(());
+        }
+        let failures: Vec<String> = self
+            .failures
+            .iter()
+            .map(|f| format!("{:?}: {}", f.check, f.reason))
+            .collect();
+        Err(TdxError::Dcap(format!(
+            "Collateral validation failed: {}",
+            failures.join("; ")
+        )))
+    }
+
+    fn record(&mut self, check: CollateralCheck, result: std::result::Result<(), String>) {
+        match result {
+            Ok(()) => self.passed.push(check),
+            Err(reason) => self.failures.push(ValidationFailure { check, reason }),
+        }
+    }
+}
+
+/// Validates collaterals independently of their source: certificate chain and CRL signatures up
+/// to a pinned Intel root, TCB info and QE identity signatures, and issue / expiry dates.
+#[derive(Debug, Clone)]
+pub struct CollateralValidator {
+    root_public_key: [u8; 64],
+}
+
+impl Default for CollateralValidator {
+    fn default() -> Self {
+        CollateralValidator {
+            root_public_key: INTEL_ROOT_CA_PUBLIC_KEY,
+        }
+    }
+}
+
+impl CollateralValidator {
+    /// Pin a different root public key (uncompressed X || Y), e.g. for a test PKI.
+    pub fn with_root_public_key(root_public_key: [u8; 64]) -> Self {
+        CollateralValidator { root_public_key }
+    }
+
+    /// Validate `collaterals` at time `now`.
+    ///
+    /// `pck_ca` is the DER-encoded PCK Platform or Processor CA certificate that signed the
+    /// PCK CRL. It is not part of the collaterals; without it the PCK CRL signature and the PCK
+    /// CA revocation checks are reported as failed.
+    pub fn validate(
+        &self,
+        collaterals: &RawCollaterals,
+        pck_ca: Option<&[u8]>,
+        now: SystemTime,
+    ) -> Result<ValidationReport> {
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| TdxError::Dcap(format!("Validation time before UNIX epoch: {e}")))?;
+        let now = i64::try_from(now.as_secs())
+            .map_err(|_| TdxError::Dcap("Validation time out of range".to_string()))?;
+        let mut report = ValidationReport::default();
+
+        let root_key = public_key_of(&collaterals.root_ca);
+        report.record(
+            CollateralCheck::RootCaPinned,
+            root_key.as_ref().map_err(Clone::clone).and_then(|key| {
+                if key.len() == 65 && key[0] == 0x04 && key[1..] == self.root_public_key[..] {
+                    Ok(())
+                } else {
+                    Err("root CA public key does not match the pinned Intel root".to_string())
+                }
+            }),
+        );
+        report.record(
+            CollateralCheck::RootCaSignature,
+            verify_signed_der(&collaterals.root_ca, &root_key),
+        );
+        report.record(
+            CollateralCheck::RootCaValidity,
+            check_cert_validity(&collaterals.root_ca, now),
+        );
+        report.record(
+            CollateralCheck::TcbSigningCaSignature,
+            verify_signed_der(&collaterals.tcb_signing_ca, &root_key),
+        );
+        report.record(
+            CollateralCheck::TcbSigningCaValidity,
+            check_cert_validity(&collaterals.tcb_signing_ca, now),
+        );
+        report.record(
+            CollateralCheck::TcbSigningCaNotRevoked,
+            check_not_revoked(&collaterals.tcb_signing_ca, &collaterals.root_ca_crl),
+        );
+        report.record(
+            CollateralCheck::RootCaCrlSignature,
+            verify_signed_der(&collaterals.root_ca_crl, &root_key),
+        );
+        report.record(
+            CollateralCheck::RootCaCrlValidity,
+            check_crl_validity(&collaterals.root_ca_crl, now),
+        );
+
+        let pck_ca_key = match pck_ca {
+            Some(pck_ca) => {
+                verify_signed_der(pck_ca, &root_key).and_then(|_| public_key_of(pck_ca))
+            }
+            None => Err("PCK CA certificate not provided".to_string()),
+        };
+        report.record(
+            CollateralCheck::PckCaNotRevoked,
+            pck_ca
+                .ok_or_else(|| "PCK CA certificate not provided".to_string())
+                .and_then(|pck_ca| check_not_revoked(pck_ca, &collaterals.root_ca_crl)),
+        );
+        report.record(
+            CollateralCheck::PckCrlSignature,
+            verify_signed_der(&collaterals.pck_crl, &pck_ca_key),
+        );
+        report.record(
+            CollateralCheck::PckCrlValidity,
+            check_crl_validity(&collaterals.pck_crl, now),
+        );
+
+        let signing_key = public_key_of(&collaterals.tcb_signing_ca);
+        report.record(
+            CollateralCheck::TcbInfoSignature,
+            verify_signed_json(&collaterals.tcb_info, "tcbInfo", &signing_key),
+        );
+        report.record(
+            CollateralCheck::TcbInfoValidity,
+            check_json_validity(&collaterals.tcb_info, "tcbInfo", now),
+        );
+        report.record(
+            CollateralCheck::QeIdentitySignature,
+            verify_signed_json(&collaterals.qe_identity, "enclaveIdentity", &signing_key),
+        );
+        report.record(
+            CollateralCheck::QeIdentityValidity,
+            check_json_validity(&collaterals.qe_identity, "enclaveIdentity", now),
+        );
+
+        Ok(report)
+    }
+
+    /// Validate the collaterals of `raw_quote`, taking the PCK CA certificate from the quote's
+    /// certification data.
+    pub fn validate_for_quote(
+        &self,
+        raw_quote: &[u8],
+        collaterals: &RawCollaterals,
+        now: SystemTime,
+    ) -> Result<ValidationReport> {
+        let quote = Quote::read(&mut &*raw_quote)?;
+        let pck_ca = pck_ca_from_quote(&quote)?;
+        self.validate(collaterals, Some(&pck_ca), now)
+    }
+}
+
+/// Returns the DER-encoded PCK CA certificate (the second certificate of the PCK chain).
+fn pck_ca_from_quote(quote: &Quote) -> Result<Vec<u8>> {
+    let raw_cert_data = &quote.signature.cert_data.cert_data;
+    let ranges = cert_chain_processor::find_certificate_ranges(raw_cert_data);
+    let (start, end) = *ranges
+        .get(1)
+        .ok_or_else(|| TdxError::Dcap("PCK CA certificate not found in quote".to_string()))?;
+    let (_, pem) = parse_x509_pem(&raw_cert_data[start..end])
+        .map_err(|e| TdxError::X509(format!("x509_parser error: {e}")))?;
+    Ok(pem.contents)
+}
+
+fn public_key_of(cert_der: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let (_, cert) =
+        parse_x509_certificate(cert_der).map_err(|e| format!("invalid certificate: {e}"))?;
+    Ok(cert.public_key().subject_public_key.data.to_vec())
+}
+
+/// Verify the signature of a DER-encoded certificate or CRL with the issuer key.
+fn verify_signed_der(
+    der: &[u8],
+    issuer_key: &std::result::Result<Vec<u8>, String>,
+) -> std::result::Result<(), String> {
+    let issuer_key = issuer_key
+        .as_ref()
+        .map_err(|e| format!("issuer key unavailable: {e}"))?;
+    let verify = |tbs: &[u8], signature: &[u8]| {
+        let signature = Signature::from_der(signature)
+            .map_err(|e| format!("invalid signature encoding: {e}"))?;
+        verifying_key(issuer_key)?
+            .verify(tbs, &signature)
+            .map_err(|_| "signature verification failed".to_string())
+    };
+    match parse_x509_certificate(der) {
+        Ok((_, cert)) => verify(cert.tbs_certificate.as_ref(), &cert.signature_value.data),
+        Err(_) => {
+            let (_, crl) =
+                parse_x509_crl(der).map_err(|e| format!("neither a certificate nor a CRL: {e}"))?;
+            verify(crl.tbs_cert_list.as_ref(), &crl.signature_value.data)
+        }
+    }
+}
+
+/// Verify the raw `r || s` signature of a signed Intel JSON collateral.
+fn verify_signed_json(
+    document: &[u8],
+    key: &str,
+    signing_key: &std::result::Result<Vec<u8>, String>,
+) -> std::result::Result<(), String> {
+    let signing_key = signing_key
+        .as_ref()
+        .map_err(|e| format!("TCB signing key unavailable: {e}"))?;
+    let (body, signature) = split_signed_json(document, key).map_err(|e| e.to_string())?;
+    let signature =
+        Signature::from_slice(&signature).map_err(|e| format!("invalid signature: {e}"))?;
+    verifying_key(signing_key)?
+        .verify(body.as_bytes(), &signature)
+        .map_err(|_| "signature verification failed".to_string())
+}
+
+fn verifying_key(sec1: &[u8]) -> std::result::Result<VerifyingKey, String> {
+    VerifyingKey::from_sec1_bytes(sec1).map_err(|e| format!("invalid P-256 public key: {e}"))
+}
+
+fn check_cert_validity(cert_der: &[u8], now: i64) -> std::result::Result<(), String> {
+    let (_, cert) =
+        parse_x509_certificate(cert_der).map_err(|e| format!("invalid certificate: {e}"))?;
+    let validity = cert.validity();
+    check_window(
+        validity.not_before.timestamp(),
+        Some(validity.not_after.timestamp()),
+        now,
+    )
+}
+
+fn check_crl_validity(crl_der: &[u8], now: i64) -> std::result::Result<(), String> {
+    let (_, crl) = parse_x509_crl(crl_der).map_err(|e| format!("invalid CRL: {e}"))?;
+    check_window(
+        crl.last_update().timestamp(),
+        crl.next_update().map(|t| t.timestamp()),
+        now,
+    )
+}
+
+fn check_json_validity(document: &[u8], key: &str, now: i64) -> std::result::Result<(), String> {
+    let (body, _) = split_signed_json(document, key).map_err(|e| e.to_string())?;
+    let body: serde_json::Value =
+        serde_json::from_str(&body).map_err(|e| format!("invalid {key}: {e}"))?;
+    let date = |field: &str| -> std::result::Result<i64, String> {
+        body.get(field)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| format!("{key} is missing {field}"))
+            .and_then(|date| {
+                OffsetDateTime::parse(date, &Rfc3339)
+                    .map(|date| date.unix_timestamp())
+                    .map_err(|e| format!("invalid {field} {date}: {e}"))
+            })
+    };
+    check_window(date("issueDate")?, Some(date("nextUpdate")?), now)
+}
+
+fn check_window(
+    not_before: i64,
+    not_after: Option<i64>,
+    now: i64,
+) -> std::result::Result<(), String> {
+    if now < not_before {
+        return Err(format!("not valid before {not_before} (now {now})"));
+    }
+    match not_after {
+        Some(not_after) if now > not_after => Err(format!("expired at {not_after} (now {now})")),
+        _ => Ok(()),
+    }
+}
+
+/// Check that the certificate `cert_der` is not listed in the CRL `crl_der`.
+fn check_not_revoked(cert_der: &[u8], crl_der: &[u8]) -> std::result::Result<(), String> {
+    let (_, cert) =
+        parse_x509_certificate(cert_der).map_err(|e| format!("invalid certificate: {e}"))?;
+    let (_, crl) = parse_x509_crl(crl_der).map_err(|e| format!("invalid CRL: {e}"))?;
+    if crl
+        .iter_revoked_certificates()
+        .any(|revoked| revoked.user_certificate == cert.serial)
+    {
+        return Err(format!(
+            "certificate {} is revoked",
+            cert.raw_serial_as_string()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pki::{self, collaterals, now, validator};
+    use std::time::Duration;
+
+    // Test PKI mirroring Intel's: a root CA, a TCB signing certificate and a PCK Platform CA,
+    // with collaterals issued on 2024-01-01 and next updates on 2034-01-01.
+    const PCK_CA: &[u8] = include_bytes!("../tests/data/pck_ca.der");
+    /// Root CA CRL revoking both the TCB signing certificate and the PCK CA.
+    const REVOKING_ROOT_CA_CRL: &[u8] = include_bytes!("../tests/data/revoking_root_ca_crl.der");
+
+    fn failed(report: &ValidationReport) -> Vec<CollateralCheck> {
+        report.failures.iter().map(|f| f.check).collect()
+    }
+
+    #[test]
+    fn accepts_valid_collaterals() {
+        let report = validator()
+            .validate(&collaterals(), Some(PCK_CA), now())
+            .unwrap();
+        assert!(report.is_valid(), "{:?}", report.failures);
+        assert_eq!(report.passed.len(), 15);
+    }
+
+    #[test]
+    fn accepts_collaterals_of_test_quote() {
+        let report = validator()
+            .validate_for_quote(test_pki::QUOTE, &collaterals(), now())
+            .unwrap();
+        assert!(report.is_valid(), "{:?}", report.failures);
+        assert_eq!(report.passed.len(), 15);
+    }
+
+    #[test]
+    fn rejects_unpinned_root() {
+        let report = CollateralValidator::default()
+            .validate(&collaterals(), Some(PCK_CA), now())
+            .unwrap();
+        assert_eq!(failed(&report), [CollateralCheck::RootCaPinned]);
+        assert!(report.into_result().is_err());
+    }
+
+    #[test]
+    fn rejects_revoked_intermediates() {
+        let mut collaterals = collaterals();
+        collaterals.root_ca_crl = REVOKING_ROOT_CA_CRL.to_vec();
+        let report = validator()
+            .validate(&collaterals, Some(PCK_CA), now())
+            .unwrap();
+        assert_eq!(
+            failed(&report),
+            [
+                CollateralCheck::TcbSigningCaNotRevoked,
+                CollateralCheck::PckCaNotRevoked
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_tcb_info() {
+        let mut collaterals = collaterals();
+        let tcb_info = String::from_utf8(collaterals.tcb_info).unwrap();
+        collaterals.tcb_info = tcb_info
+            .replace(
+                "\"tcbEvaluationDataNumber\":17",
+                "\"tcbEvaluationDataNumber\":18",
+            )
+            .into_bytes();
+        let report = validator()
+            .validate(&collaterals, Some(PCK_CA), now())
+            .unwrap();
+        assert_eq!(failed(&report), [CollateralCheck::TcbInfoSignature]);
+    }
+
+    #[test]
+    fn rejects_tampered_crl() {
+        let mut collaterals = collaterals();
+        let last = collaterals.pck_crl.len() - 1;
+        collaterals.pck_crl[last] ^= 1;
+        let report = validator()
+            .validate(&collaterals, Some(PCK_CA), now())
+            .unwrap();
+        assert_eq!(failed(&report), [CollateralCheck::PckCrlSignature]);
+    }
+
+    #[test]
+    fn rejects_expired_collaterals() {
+        // 2035-01-01T00:00:00Z, after the next updates
+        let later = UNIX_EPOCH + Duration::from_secs(2_051_222_400);
+        let report = validator()
+            .validate(&collaterals(), Some(PCK_CA), later)
+            .unwrap();
+        assert_eq!(
+            failed(&report),
+            [
+                CollateralCheck::RootCaCrlValidity,
+                CollateralCheck::PckCrlValidity,
+                CollateralCheck::TcbInfoValidity,
+                CollateralCheck::QeIdentityValidity
+            ]
+        );
+    }
+
+    #[test]
+    fn requires_pck_ca() {
+        let report = validator().validate(&collaterals(), None, now()).unwrap();
+        assert_eq!(
+            failed(&report),
+            [
+                CollateralCheck::PckCaNotRevoked,
+                CollateralCheck::PckCrlSignature
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_time_before_epoch() {
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+        assert!(validator()
+            .validate(&collaterals(), Some(PCK_CA), before_epoch)
+            .is_err());
+    }
+}
@@ -0,0 +1,412 @@
+use crate::error::{Result, TdxError};
+use crate::quote::{QuoteBody, QuoteData};
+use crate::utils::serde_hex;
+use alloy::sol_types::{sol_data, SolType};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// TEE type of an SGX quote, as it appears in the quote header and the verified output.
+pub const TEE_TYPE_SGX: u32 = 0x0000_0000;
+/// TEE type of a TDX quote, as it appears in the quote header and the verified output.
+pub const TEE_TYPE_TDX: u32 = 0x0000_0081;
+
+/// Size of an SGX enclave report body.
+pub const ENCLAVE_REPORT_BODY_LEN: usize = 384;
+/// Size of a TDX 1.0 TD report body (quote v4 and v5 body type 2).
+pub const TD10_REPORT_BODY_LEN: usize = 584;
+/// Size of a TDX 1.5 TD report body (quote v5 body type 3).
+pub const TD15_REPORT_BODY_LEN: usize = 648;
+
+// quote_version (2) + tee_type (4) + tcb_status (1) + fmspc (6)
+const HEADER_LEN: usize = 13;
+
+type AdvisoryIds = sol_data::Array<sol_data::String>;
+
+/// Returns the length of the report body in the verified output of a quote of `quote_version`
+/// and `tee_type`: an enclave report for SGX, a TDX 1.0 TD report for quote v4 and a TDX 1.5
+/// TD report for quote v5.
+pub fn report_body_len(tee_type: u32, quote_version: u16) -> Result<usize> {
+    match (tee_type, quote_version) {
+        (TEE_TYPE_SGX, 3..=5) => Ok(ENCLAVE_REPORT_BODY_LEN),
+        (TEE_TYPE_TDX, 4) => Ok(TD10_REPORT_BODY_LEN),
+        (TEE_TYPE_TDX, 5) => Ok(TD15_REPORT_BODY_LEN),
+        _ => Err(TdxError::Dcap(format!(
+            "Unsupported verified output: version {quote_version}, tee type {tee_type:#x}"
+        ))),
+    }
+}
+
+fn read_version_and_tee_type(bytes: &[u8]) -> Result<(u16, u32)> {
+    if bytes.len() < HEADER_LEN {
+        return Err(TdxError::Dcap(format!(
+            "Verified output too short: {} bytes",
+            bytes.len()
+        )));
+    }
+    let quote_version = u16::from_be_bytes([bytes[0], bytes[1]]);
+    let tee_type = u32::from_be_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]);
+    Ok((quote_version, tee_type))
+}
+
+/// TCB status as encoded in the verified output.
+///
+/// The discriminants follow the `TCBStatus` enum of the Automata DCAP Attestation contracts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcbStatus {
+    UpToDate = 0,
+    SwHardeningNeeded = 1,
+    ConfigurationAndSwHardeningNeeded = 2,
+    ConfigurationNeeded = 3,
+    OutOfDate = 4,
+    OutOfDateConfigurationNeeded = 5,
+    Revoked = 6,
+    Unrecognized = 7,
+}
+
+impl TcbStatus {
+    /// Returns the status name as used by Intel in TCB info and QE identity collaterals.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TcbStatus::UpToDate => "UpToDate",
+            TcbStatus::SwHardeningNeeded => "SWHardeningNeeded",
+            TcbStatus::ConfigurationAndSwHardeningNeeded => "ConfigurationAndSWHardeningNeeded",
+            TcbStatus::ConfigurationNeeded => "ConfigurationNeeded",
+            TcbStatus::OutOfDate => "OutOfDate",
+            TcbStatus::OutOfDateConfigurationNeeded => "OutOfDateConfigurationNeeded",
+            TcbStatus::Revoked => "Revoked",
+            TcbStatus::Unrecognized => "Unrecognized",
+        }
+    }
+}
+
+impl std::fmt::Display for TcbStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for TcbStatus {
+    type Err = TdxError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "UpToDate" => TcbStatus::UpToDate,
+            "SWHardeningNeeded" => TcbStatus::SwHardeningNeeded,
+            "ConfigurationAndSWHardeningNeeded" => TcbStatus::ConfigurationAndSwHardeningNeeded,
+            "ConfigurationNeeded" => TcbStatus::ConfigurationNeeded,
+            "OutOfDate" => TcbStatus::OutOfDate,
+            "OutOfDateConfigurationNeeded" => TcbStatus::OutOfDateConfigurationNeeded,
+            "Revoked" => TcbStatus::Revoked,
+            "Unrecognized" => TcbStatus::Unrecognized,
+            _ => return Err(TdxError::Dcap(format!("Unknown TCB status: {s}"))),
+        })
+    }
+}
+
+impl From<TcbStatus> for u8 {
+    fn from(status: TcbStatus) -> Self {
+        status as u8
+    }
+}
+
+impl Serialize for TcbStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for TcbStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl TryFrom<u8> for TcbStatus {
+    type Error = TdxError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        Ok(match value {
+            0 => TcbStatus::UpToDate,
+            1 => TcbStatus::SwHardeningNeeded,
+            2 => TcbStatus::ConfigurationAndSwHardeningNeeded,
+            3 => TcbStatus::ConfigurationNeeded,
+            4 => TcbStatus::OutOfDate,
+            5 => TcbStatus::OutOfDateConfigurationNeeded,
+            6 => TcbStatus::Revoked,
+            7 => TcbStatus::Unrecognized,
+            _ => return Err(TdxError::Dcap(format!("Unknown TCB status: {value}"))),
+        })
+    }
+}
+
+/// The packed output of a successful DCAP verification, as returned by
+/// `verifyAndAttestOnChain` and committed by the Automata zkVM guest programs.
+///
+/// Layout: `quote_version (u16 BE) || tee_type (u32 BE) || tcb_status (u8) || fmspc (6 bytes)
+/// || quote_body || abi.encode(string[] advisory_ids)`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifiedOutput {
+    pub quote_version: u16,
+    pub tee_type: u32,
+    pub tcb_status: TcbStatus,
+    #[serde(with = "serde_hex")]
+    pub fmspc: [u8; 6],
+    /// The raw report body of the verified quote (enclave report or TD report).
+    #[serde(with = "serde_hex")]
+    pub quote_body: Vec<u8>,
+    pub advisory_ids: Vec<String>,
+}
+
+impl VerifiedOutput {
+    /// Decode a verified output from its packed byte representation.
+    ///
+    /// The length of the report body follows from the TEE type and quote version, see
+    /// [`report_body_len`]. Quote v5 TDX outputs are decoded with a TDX 1.5 body; use
+    /// [`VerifiedOutput::from_bytes_for_quote`] when the quote is known.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (quote_version, tee_type) = read_version_and_tee_type(bytes)?;
+        Self::decode(bytes, report_body_len(tee_type, quote_version)?)
+    }
+
+    /// Decode the verified output of `raw_quote`, taking the length of the report body from
+    /// the quote itself. Unlike [`VerifiedOutput::from_bytes`], this also decodes quote v5
+    /// outputs carrying a TDX 1.0 body.
+    pub fn from_bytes_for_quote(bytes: &[u8], raw_quote: &[u8]) -> Result<Self> {
+        let body_len = match QuoteData::from_bytes(raw_quote)?.body {
+            QuoteBody::Sgx(_) => ENCLAVE_REPORT_BODY_LEN,
+            QuoteBody::Td10(_) => TD10_REPORT_BODY_LEN,
+            QuoteBody::Td15(_) => TD15_REPORT_BODY_LEN,
+        };
+        Self::decode(bytes, body_len)
+    }
+
+    fn decode(bytes: &[u8], body_len: usize) -> Result<Self> {
+        let (quote_version, tee_type) = read_version_and_tee_type(bytes)?;
+        let tcb_status = TcbStatus::try_from(bytes[6])?;
+        let mut fmspc = [0u8; 6];
+        fmspc.copy_from_slice(&bytes[7..HEADER_LEN]);
+        let rest = &bytes[HEADER_LEN..];
+        if rest.len() < body_len {
+            return Err(TdxError::Dcap(format!(
+                "Verified output too short for a {body_len}-byte report body: {} bytes after header",
+                rest.len()
+            )));
+        }
+        let (body, tail) = rest.split_at(body_len);
+        let advisory_ids = AdvisoryIds::abi_decode(tail)
+            .map_err(|e| TdxError::Dcap(format!("Invalid advisory IDs in verified output: {e}")))?;
+        Ok(VerifiedOutput {
+            quote_version,
+            tee_type,
+            tcb_status,
+            fmspc,
+            quote_body: body.to_vec(),
+            advisory_ids,
+        })
+    }
+
+    /// Encode the verified output into its packed byte representation.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let advisory_ids = AdvisoryIds::abi_encode(&self.advisory_ids);
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.quote_body.len() + advisory_ids.len());
+        bytes.extend_from_slice(&self.quote_version.to_be_bytes());
+        bytes.extend_from_slice(&self.tee_type.to_be_bytes());
+        bytes.push(self.tcb_status.into());
+        bytes.extend_from_slice(&self.fmspc);
+        bytes.extend_from_slice(&self.quote_body);
+        bytes.extend_from_slice(&advisory_ids);
+        bytes
+    }
+
+    /// Compare this output against another one, typically the result of verifying the same
+    /// quote locally, and return the fields that differ.
+    ///
+    /// Advisory IDs are compared as sets, since their order is not significant.
+    pub fn mismatches(&self, other: &VerifiedOutput) -> Vec<VerifiedOutputField> {
+        let mut fields = Vec::new();
+        if self.quote_version != other.quote_version {
+            fields.push(VerifiedOutputField::QuoteVersion);
+        }
+        if self.tee_type != other.tee_type {
+            fields.push(VerifiedOutputField::TeeType);
+        }
+        if self.tcb_status != other.tcb_status {
+            fields.push(VerifiedOutputField::TcbStatus);
+        }
+        if self.fmspc != other.fmspc {
+            fields.push(VerifiedOutputField::Fmspc);
+        }
+        if self.quote_body != other.quote_body {
+            fields.push(VerifiedOutputField::QuoteBody);
+        }
+        let mut ours = self.advisory_ids.clone();
+        let mut theirs = other.advisory_ids.clone();
+        ours.sort();
+        theirs.sort();
+        if ours != theirs {
+            fields.push(VerifiedOutputField::AdvisoryIds);
+        }
+        fields
+    }
+
+    /// Parse the report body of the verified quote.
+    pub fn body(&self) -> Result<QuoteBody> {
+        QuoteBody::from_bytes(&self.quote_body)
+    }
+
+    /// Returns the FMSPC as an uppercase hex string.
+    pub fn fmspc_hex(&self) -> String {
+        hex::encode_upper(self.fmspc)
+    }
+}
+
+/// A field of [`VerifiedOutput`], used to report mismatches between two outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifiedOutputField {
+    QuoteVersion,
+    TeeType,
+    TcbStatus,
+    Fmspc,
+    QuoteBody,
+    AdvisoryIds,
+}
+
+impl std::fmt::Display for VerifiedOutputField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            VerifiedOutputField::QuoteVersion => "quote_version",
+            VerifiedOutputField::TeeType => "tee_type",
+            VerifiedOutputField::TcbStatus => "tcb_status",
+            VerifiedOutputField::Fmspc => "fmspc",
+            VerifiedOutputField::QuoteBody => "quote_body",
+            VerifiedOutputField::AdvisoryIds => "advisory_ids",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_pki;
+
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+
+    fn output(quote_version: u16, tee_type: u32, body_len: usize) -> VerifiedOutput {
+        VerifiedOutput {
+            quote_version,
+            tee_type,
+            tcb_status: TcbStatus::SwHardeningNeeded,
+            fmspc: [0x00, 0x80, 0x6f, 0x05, 0x00, 0x00],
+            quote_body: (0..body_len).map(|i| i as u8).collect(),
+            advisory_ids: vec!["INTEL-SA-00615".to_string(), "INTEL-SA-00837".to_string()],
+        }
+    }
+
+    #[test]
+    fn round_trip_sgx() {
+        let output = output(3, TEE_TYPE_SGX, ENCLAVE_REPORT_BODY_LEN);
+        assert_eq!(
+            VerifiedOutput::from_bytes(&output.to_bytes()).unwrap(),
+            output
+        );
+    }
+
+    #[test]
+    fn round_trip_td10() {
+        let output = output(4, TEE_TYPE_TDX, TD10_REPORT_BODY_LEN);
+        assert_eq!(
+            VerifiedOutput::from_bytes(&output.to_bytes()).unwrap(),
+            output
+        );
+    }
+
+    #[test]
+    fn round_trip_td15() {
+        let output = output(5, TEE_TYPE_TDX, TD15_REPORT_BODY_LEN);
+        assert_eq!(
+            VerifiedOutput::from_bytes(&output.to_bytes()).unwrap(),
+            output
+        );
+    }
+
+    #[test]
+    fn round_trip_without_advisory_ids() {
+        let mut output = output(4, TEE_TYPE_TDX, TD10_REPORT_BODY_LEN);
+        output.advisory_ids.clear();
+        assert_eq!(
+            VerifiedOutput::from_bytes(&output.to_bytes()).unwrap(),
+            output
+        );
+    }
+
+    #[test]
+    fn v5_td10_body_is_decoded_with_the_quote() {
+        let mut quote = QuoteData::from_bytes(TDX_V4_QUOTE).unwrap();
+        quote.header.version = 5;
+        let raw_quote = quote.to_bytes().unwrap();
+
+        let output = output(5, TEE_TYPE_TDX, TD10_REPORT_BODY_LEN);
+        let bytes = output.to_bytes();
+        assert!(VerifiedOutput::from_bytes(&bytes).is_err());
+        assert_eq!(
+            VerifiedOutput::from_bytes_for_quote(&bytes, &raw_quote).unwrap(),
+            output
+        );
+    }
+
+    #[test]
+    fn decodes_dcap_rs_output() {
+        let collaterals = test_pki::collaterals().to_collateral().unwrap();
+        let quote = dcap_rs::types::quote::Quote::read(&mut &*test_pki::QUOTE).unwrap();
+        let bytes = dcap_rs::verify_dcap_quote(test_pki::now(), collaterals, quote)
+            .unwrap()
+            .to_bytes();
+
+        // abi.encodePacked(uint16 version, bytes4 tee, uint8 status, bytes6 fmspc, bytes body,
+        // abi.encode(string[] advisoryIDs)), as returned by the DCAP Attestation contract
+        let body = &test_pki::QUOTE[48..48 + TD10_REPORT_BODY_LEN];
+        let mut expected = hex::decode("0004000000810000806f050000").unwrap();
+        expected.extend_from_slice(body);
+        // Offset of the empty advisory ID array, then its length
+        expected.extend_from_slice(&[[0; 31].as_slice(), &[0x20], &[0; 32]].concat());
+        assert_eq!(hex::encode(&bytes), hex::encode(&expected));
+
+        let output = VerifiedOutput::from_bytes(&bytes).unwrap();
+        assert_eq!(output.tcb_status, TcbStatus::UpToDate);
+        assert_eq!(output.quote_body, body);
+        assert!(output.advisory_ids.is_empty());
+        assert_eq!(output.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn encodes_advisory_ids_as_the_contract() {
+        // abi.encode(string[]) of the two advisory IDs, computed independently of alloy
+        const ADVISORY_IDS: &str = concat!(
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000040",
+            "0000000000000000000000000000000000000000000000000000000000000080",
+            "000000000000000000000000000000000000000000000000000000000000000e",
+            "494e54454c2d53412d3030363135000000000000000000000000000000000000",
+            "000000000000000000000000000000000000000000000000000000000000000e",
+            "494e54454c2d53412d3030383337000000000000000000000000000000000000",
+        );
+        let output = output(3, TEE_TYPE_SGX, ENCLAVE_REPORT_BODY_LEN);
+        let bytes = output.to_bytes();
+        let (header, rest) = bytes.split_at(HEADER_LEN);
+        assert_eq!(hex::encode(header), "0003000000000100806f050000");
+        assert_eq!(hex::encode(&rest[ENCLAVE_REPORT_BODY_LEN..]), ADVISORY_IDS);
+    }
+
+    #[test]
+    fn rejects_unknown_tee_type() {
+        let output = output(4, 0x42, TD10_REPORT_BODY_LEN);
+        assert!(VerifiedOutput::from_bytes(&output.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_body() {
+        let bytes = output(4, TEE_TYPE_TDX, TD10_REPORT_BODY_LEN).to_bytes();
+        assert!(VerifiedOutput::from_bytes(&bytes[..HEADER_LEN + 100]).is_err());
+    }
+}
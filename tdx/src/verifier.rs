@@ -0,0 +1,426 @@
+use crate::collateral::{split_signed_json, RawCollaterals};
+#[cfg(feature = "coco-provider")]
+use crate::device::DeviceOptions;
+use crate::error::{Result, TdxError};
+#[cfg(feature = "http")]
+use crate::pcs::PcsClient;
+use crate::policy::AppraisalPolicy;
+#[cfg(feature = "http")]
+use crate::quote::QuoteData;
+use crate::utils::serde_hex;
+#[cfg(feature = "http")]
+use crate::utils::TeeType;
+use crate::validation::CollateralValidator;
+use crate::verified_output::VerifiedOutput;
+use crate::Tdx;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::SystemTime;
+use x509_parser::prelude::parse_x509_crl;
+
+/// Callback returning the collaterals of a raw quote.
+pub type CollateralFn = dyn Fn(&[u8]) -> Result<RawCollaterals> + Send + Sync;
+
+/// Where a [`QuoteVerifier`] takes the collaterals of a quote from.
+#[derive(Clone)]
+pub enum CollateralProvider {
+    /// Only accept evidence that carries its own collaterals, checked as configured by
+    /// [`EmbeddedCollaterals`]. Other providers ignore the collaterals of the evidence.
+    Embedded(EmbeddedCollaterals),
+    /// Fixed collaterals, e.g. loaded with [`RawCollaterals::load`].
+    Static(RawCollaterals),
+    /// Fetch the collaterals from the Intel PCS or a PCCS.
+    #[cfg(feature = "http")]
+    Pcs(PcsClient),
+    /// Retrieve the collaterals with a caller-supplied function.
+    Custom(Arc<CollateralFn>),
+}
+
+impl std::fmt::Debug for CollateralProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CollateralProvider::Embedded(embedded) => {
+                f.debug_tuple("Embedded").field(embedded).finish()
+            }
+            CollateralProvider::Static(_) => f.write_str("Static"),
+            #[cfg(feature = "http")]
+            CollateralProvider::Pcs(client) => f.debug_tuple("Pcs").field(client).finish(),
+            CollateralProvider::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+impl CollateralProvider {
+    /// Accept the collaterals embedded in the evidence, validated against the Intel root.
+    pub fn embedded() -> Self {
+        CollateralProvider::Embedded(EmbeddedCollaterals::default())
+    }
+
+    /// Returns the collaterals needed to verify `raw_quote`.
+    #[cfg_attr(not(feature = "http"), allow(unused_variables))] // `tdx` resolves the PCK for PCS
+    pub fn collaterals(&self, tdx: &Tdx, raw_quote: &[u8]) -> Result<RawCollaterals> {
+        match self {
+            CollateralProvider::Embedded(_) => Err(TdxError::Dcap(
+                "Evidence does not carry collaterals".to_string(),
+            )),
+            CollateralProvider::Static(collaterals) => Ok(collaterals.clone()),
+            #[cfg(feature = "http")]
+            CollateralProvider::Pcs(client) => {
+                let quote = QuoteData::from_bytes(raw_quote)?;
+                let tee_type = TeeType::from_header(quote.header.tee_type)?;
+                let (fmspc, pck_ca) = tdx.get_pck_fmspc_and_issuer(raw_quote)?;
+                client.get_collaterals(tee_type, &fmspc, pck_ca)
+            }
+            CollateralProvider::Custom(f) => f(raw_quote),
+        }
+    }
+}
+
+/// Checks applied to collaterals embedded in evidence. They come from the attester, so they
+/// must chain to a pinned root and, with a reference provider, must not be older than the
+/// collaterals it returns.
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddedCollaterals {
+    validator: CollateralValidator,
+    reference: Option<Box<CollateralProvider>>,
+}
+
+impl EmbeddedCollaterals {
+    pub fn new() -> Self {
+        EmbeddedCollaterals::default()
+    }
+
+    /// Validate the collaterals with `validator` instead of against the Intel root.
+    pub fn with_validator(mut self, validator: CollateralValidator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    /// Reject embedded collaterals older than those `reference` returns: a lower TCB
+    /// evaluation data number, or CRLs missing a revocation. Use a cheap source such as
+    /// [`CollateralProvider::Static`] collaterals refreshed in the background.
+    pub fn with_reference(mut self, reference: CollateralProvider) -> Self {
+        self.reference = Some(Box::new(reference));
+        self
+    }
+
+    /// Check `collaterals`, embedded in the evidence of `raw_quote`, at `time`.
+    fn check(
+        &self,
+        tdx: &Tdx,
+        raw_quote: &[u8],
+        collaterals: &RawCollaterals,
+        time: SystemTime,
+    ) -> Result<()> {
+        self.validator
+            .validate_for_quote(raw_quote, collaterals, time)?
+            .into_result()?;
+        if let Some(reference) = &self.reference {
+            check_not_older(collaterals, &reference.collaterals(tdx, raw_quote)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that `embedded` is at least as recent as `reference`.
+fn check_not_older(embedded: &RawCollaterals, reference: &RawCollaterals) -> Result<()> {
+    let documents = [
+        ("tcbInfo", &embedded.tcb_info, &reference.tcb_info),
+        (
+            "enclaveIdentity",
+            &embedded.qe_identity,
+            &reference.qe_identity,
+        ),
+    ];
+    for (key, embedded, reference) in documents {
+        let (embedded, reference) = (
+            tcb_evaluation_data_number(embedded, key)?,
+            tcb_evaluation_data_number(reference, key)?,
+        );
+        if embedded < reference {
+            return Err(TdxError::Dcap(format!(
+                "Embedded {key} has TCB evaluation data number {embedded}, older than {reference}"
+            )));
+        }
+    }
+    let crls = [
+        ("root CA CRL", &embedded.root_ca_crl, &reference.root_ca_crl),
+        ("PCK CRL", &embedded.pck_crl, &reference.pck_crl),
+    ];
+    for (name, embedded, reference) in crls {
+        let embedded = revoked_serials(embedded, name)?;
+        if let Some(serial) = revoked_serials(reference, name)?
+            .into_iter()
+            .find(|serial| !embedded.contains(serial))
+        {
+            return Err(TdxError::Dcap(format!(
+                "Embedded {name} does not revoke certificate {}",
+                hex::encode(serial)
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn tcb_evaluation_data_number(document: &[u8], key: &str) -> Result<u64> {
+    let (body, _) = split_signed_json(document, key)?;
+    let body: serde_json::Value =
+        serde_json::from_str(&body).map_err(|e| TdxError::Dcap(format!("Invalid {key}: {e}")))?;
+    body.get("tcbEvaluationDataNumber")
+        .and_then(serde_json::Value::as_u64)
+        .ok_or_else(|| TdxError::Dcap(format!("{key} is missing tcbEvaluationDataNumber")))
+}
+
+fn revoked_serials(crl_der: &[u8], name: &str) -> Result<Vec<Vec<u8>>> {
+    let (_, crl) =
+        parse_x509_crl(crl_der).map_err(|e| TdxError::X509(format!("Invalid {name}: {e}")))?;
+    Ok(crl
+        .iter_revoked_certificates()
+        .map(|revoked| revoked.raw_serial().to_vec())
+        .collect())
+}
+
+/// A quote and optionally its collaterals, as sent by an attester to a verifier.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Evidence {
+    #[serde(with = "serde_hex")]
+    pub quote: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collaterals: Option<RawCollaterals>,
+}
+
+impl Evidence {
+    /// Generate a quote over `report_data` on this TD, with its collaterals if `collaterals`
+    /// is set.
+    #[cfg(feature = "coco-provider")]
+    pub fn generate(
+        tdx: &Tdx,
+        report_data: [u8; 64],
+        collaterals: Option<&CollateralProvider>,
+    ) -> Result<Self> {
+        let (quote, _) = tdx.get_attestation_report_raw_with_options(DeviceOptions {
+            report_data: Some(report_data),
+        })?;
+        let collaterals = collaterals
+            .map(|provider| provider.collaterals(tdx, &quote))
+            .transpose()?;
+        Ok(Evidence { quote, collaterals })
+    }
+}
+
+/// Synchronous quote verification for protocols that receive quotes from peers: verifies the
+/// quote against its collaterals, checks the key or nonce bound into its report data and
+/// appraises the result against a policy.
+#[derive(Debug, Clone)]
+pub struct QuoteVerifier {
+    tdx: Tdx,
+    collaterals: CollateralProvider,
+    policy: AppraisalPolicy,
+    time: Option<SystemTime>,
+}
+
+impl QuoteVerifier {
+    /// Create a verifier using `collaterals` and the default appraisal policy.
+    pub fn new(collaterals: CollateralProvider) -> Self {
+        QuoteVerifier {
+            tdx: Tdx::new(),
+            collaterals,
+            policy: AppraisalPolicy::default(),
+            time: None,
+        }
+    }
+
+    pub fn with_policy(mut self, policy: AppraisalPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Use `tdx` to verify quotes, e.g. to resolve PCK certificates through a PCS client.
+    pub fn with_tdx(mut self, tdx: Tdx) -> Self {
+        self.tdx = tdx;
+        self
+    }
+
+    /// Verify quotes at `time` instead of the current time, e.g. to check evidence against
+    /// saved collaterals that have since expired.
+    pub fn with_time(mut self, time: SystemTime) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    pub fn policy(&self) -> &AppraisalPolicy {
+        &self.policy
+    }
+
+    /// Verify `evidence` at the verification time, using its collaterals if the verifier accepts
+    /// [`CollateralProvider::Embedded`] ones.
+    pub fn verify_evidence(
+        &self,
+        evidence: &Evidence,
+        report_data: &[u8],
+    ) -> Result<VerifiedOutput> {
+        self.verify(&evidence.quote, evidence.collaterals.as_ref(), report_data)
+    }
+
+    /// Verify `raw_quote` at the time set with [`QuoteVerifier::with_time`], or now. See
+    /// [`QuoteVerifier::verify_at`].
+    pub fn verify(
+        &self,
+        raw_quote: &[u8],
+        embedded: Option<&RawCollaterals>,
+        report_data: &[u8],
+    ) -> Result<VerifiedOutput> {
+        let time = self.time.unwrap_or_else(SystemTime::now);
+        self.verify_at(raw_quote, embedded, report_data, time)
+    }
+
+    /// Verify `raw_quote` at `time`. The `embedded` collaterals that came with the evidence
+    /// are only used, after being checked, with [`CollateralProvider::Embedded`]; other
+    /// providers ignore them. The report data of the quote must start with `report_data`, and
+    /// the verified output must satisfy the policy.
+    pub fn verify_at(
+        &self,
+        raw_quote: &[u8],
+        embedded: Option<&RawCollaterals>,
+        report_data: &[u8],
+        time: SystemTime,
+    ) -> Result<VerifiedOutput> {
+        let fetched;
+        let collaterals = match &self.collaterals {
+            CollateralProvider::Embedded(checks) => {
+                let collaterals = embedded.ok_or_else(|| {
+                    TdxError::Dcap("Evidence does not carry collaterals".to_string())
+                })?;
+                checks.check(&self.tdx, raw_quote, collaterals, time)?;
+                collaterals
+            }
+            provider => {
+                fetched = provider.collaterals(&self.tdx, raw_quote)?;
+                &fetched
+            }
+        };
+        let output =
+            self.tdx
+                .verify_attestation_report_with_collaterals(raw_quote, collaterals, time)?;
+        let body = output.body()?;
+        if !body.report_data().starts_with(report_data) {
+            return Err(TdxError::Dcap(
+                "Report data does not match the expected binding".to_string(),
+            ));
+        }
+        self.policy.check(&output)?;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quote::QuoteData;
+    use crate::test_pki::{self, collaterals, now};
+    use crate::verified_output::TcbStatus;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+    const REVOKING_ROOT_CA_CRL: &[u8] = include_bytes!("../tests/data/revoking_root_ca_crl.der");
+
+    fn verify(provider: CollateralProvider, embedded: Option<&RawCollaterals>) -> String {
+        QuoteVerifier::new(provider)
+            .verify_at(TDX_V4_QUOTE, embedded, &[], now())
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn ignores_embedded_collaterals_unless_configured() {
+        let provider = CollateralProvider::Custom(Arc::new(|_: &[u8]| {
+            Err(TdxError::Dcap("provider called".to_string()))
+        }));
+        let err = verify(provider, Some(&collaterals()));
+        assert!(err.contains("provider called"), "{err}");
+    }
+
+    #[test]
+    fn validates_embedded_collaterals() {
+        let err = verify(CollateralProvider::embedded(), None);
+        assert!(err.contains("does not carry collaterals"), "{err}");
+
+        // The test PKI does not chain to the pinned Intel root
+        let err = verify(CollateralProvider::embedded(), Some(&collaterals()));
+        assert!(err.contains("RootCaPinned"), "{err}");
+    }
+
+    #[test]
+    fn verifies_test_quote() {
+        let output = QuoteVerifier::new(CollateralProvider::Static(collaterals()))
+            .verify_at(test_pki::QUOTE, None, &[], now())
+            .unwrap();
+        assert_eq!(output.tcb_status, TcbStatus::UpToDate);
+        assert_eq!(output.fmspc_hex(), "00806F050000");
+        assert!(output.advisory_ids.is_empty());
+        let quote = QuoteData::from_bytes(test_pki::QUOTE).unwrap();
+        assert_eq!(output.body().unwrap(), quote.body);
+    }
+
+    #[test]
+    fn verifies_validated_embedded_collaterals() {
+        let embedded = EmbeddedCollaterals::new().with_validator(test_pki::validator());
+        QuoteVerifier::new(CollateralProvider::Embedded(embedded))
+            .verify_at(test_pki::QUOTE, Some(&collaterals()), &[], now())
+            .unwrap();
+    }
+
+    #[test]
+    fn checks_report_data_binding() {
+        let quote = test_pki::quote_with_report_data([7; 64]);
+        let verifier = QuoteVerifier::new(CollateralProvider::Static(collaterals()));
+        verifier.verify_at(&quote, None, &[7; 32], now()).unwrap();
+        let err = verifier
+            .verify_at(&quote, None, &[8; 32], now())
+            .unwrap_err();
+        assert!(err.to_string().contains("Report data"), "{err}");
+    }
+
+    #[test]
+    fn rejects_expired_collaterals() {
+        // 2035-01-01T00:00:00Z, after the next update of the collaterals
+        let later = UNIX_EPOCH + Duration::from_secs(2_051_222_400);
+        assert!(
+            QuoteVerifier::new(CollateralProvider::Static(collaterals()))
+                .verify_at(test_pki::QUOTE, None, &[], later)
+                .is_err()
+        );
+        let verifier = QuoteVerifier::new(CollateralProvider::Static(collaterals()));
+        assert!(verifier
+            .clone()
+            .with_time(later)
+            .verify(test_pki::QUOTE, None, &[])
+            .is_err());
+        verifier
+            .with_time(now())
+            .verify(test_pki::QUOTE, None, &[])
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_embedded_collaterals_older_than_reference() {
+        assert!(check_not_older(&collaterals(), &collaterals()).is_ok());
+
+        let mut reference = collaterals();
+        let tcb_info = String::from_utf8(reference.tcb_info).unwrap();
+        reference.tcb_info = tcb_info
+            .replace(
+                "\"tcbEvaluationDataNumber\":17",
+                "\"tcbEvaluationDataNumber\":18",
+            )
+            .into_bytes();
+        let err = check_not_older(&collaterals(), &reference).unwrap_err();
+        assert!(err.to_string().contains("tcbInfo"), "{err}");
+        assert!(check_not_older(&reference, &collaterals()).is_ok());
+
+        let mut reference = collaterals();
+        reference.root_ca_crl = REVOKING_ROOT_CA_CRL.to_vec();
+        let err = check_not_older(&collaterals(), &reference).unwrap_err();
+        assert!(err.to_string().contains("root CA CRL"), "{err}");
+    }
+}
@@ -0,0 +1,173 @@
+//! WebAssembly bindings, built with the `wasm` feature for `wasm32-unknown-unknown`, e.g. with
+//! `wasm-pack build --no-default-features --features wasm`.
+//!
+//! Nothing here touches the network or the clock on its own: collaterals are passed in, or
+//! retrieved through a JavaScript callback, and the verification time defaults to `Date.now()`.
+
+use crate::collateral::RawCollaterals;
+use crate::error::{Result, TdxError};
+use crate::quote::QuoteData;
+use crate::utils::{PckCA, TeeType};
+use crate::Tdx;
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+/// What is needed to retrieve the collaterals of a quote from the PCS or a PCCS.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CollateralQuery {
+    fmspc: String,
+    tee_type: &'static str,
+    pck_ca: &'static str,
+}
+
+impl CollateralQuery {
+    fn from_quote(raw_quote: &[u8]) -> Result<Self> {
+        let quote = QuoteData::from_bytes(raw_quote)?;
+        let tee_type = TeeType::from_header(quote.header.tee_type)?;
+        let (fmspc, pck_ca) = Tdx::new().get_pck_fmspc_and_issuer(raw_quote)?;
+        let pck_ca = match pck_ca {
+            PckCA::Platform => "platform",
+            PckCA::Processor => "processor",
+        };
+        Ok(CollateralQuery {
+            fmspc: fmspc.to_uppercase(),
+            tee_type: tee_type.pcs_path(),
+            pck_ca,
+        })
+    }
+}
+
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue> {
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| TdxError::Anyhow(format!("Failed to convert to a JavaScript value: {e}")))
+}
+
+fn collaterals_from_js(value: JsValue) -> Result<RawCollaterals> {
+    serde_wasm_bindgen::from_value(value)
+        .map_err(|e| TdxError::ConfigOptions(format!("Invalid collaterals: {e}")))
+}
+
+/// `time` in seconds since the UNIX epoch, or now.
+fn verification_time(time: Option<f64>) -> Result<SystemTime> {
+    let millis = match time {
+        Some(secs) => secs * 1000.0,
+        None => js_sys::Date::now(),
+    };
+    let invalid = || TdxError::ConfigOptions(format!("Invalid verification time: {millis} ms"));
+    if !millis.is_finite() || millis < 0.0 || millis >= u64::MAX as f64 {
+        return Err(invalid());
+    }
+    UNIX_EPOCH
+        .checked_add(Duration::from_millis(millis as u64))
+        .ok_or_else(invalid)
+}
+
+fn verify_at(raw_quote: &[u8], collaterals: JsValue, time: Option<f64>) -> Result<JsValue> {
+    let collaterals = collaterals_from_js(collaterals)?;
+    let output = Tdx::new().verify_attestation_report_with_collaterals(
+        raw_quote,
+        &collaterals,
+        verification_time(time)?,
+    )?;
+    to_js(&output)
+}
+
+/// Parse a raw quote into an object with its header, body and signature data.
+#[wasm_bindgen(js_name = parseQuote)]
+pub fn parse_quote(quote: &[u8]) -> std::result::Result<JsValue, JsError> {
+    Ok(to_js(&QuoteData::from_bytes(quote)?)?)
+}
+
+/// Returns `{fmspc, teeType, pckCa}`, the FMSPC of the PCK certificate of a quote along with
+/// the TEE type and issuing PCK CA, as used to query the PCS for its collaterals.
+#[wasm_bindgen(js_name = getFmspc)]
+pub fn get_fmspc(quote: &[u8]) -> std::result::Result<JsValue, JsError> {
+    Ok(to_js(&CollateralQuery::from_quote(quote)?)?)
+}
+
+/// Verify a quote against `collaterals`, an object in the format of `tdx collateral save`, at
+/// `time` (seconds since the UNIX epoch, defaults to now). Returns the verified output.
+#[wasm_bindgen]
+pub fn verify(
+    quote: &[u8],
+    collaterals: JsValue,
+    time: Option<f64>,
+) -> std::result::Result<JsValue, JsError> {
+    Ok(verify_at(quote, collaterals, time)?)
+}
+
+/// Verify a quote with collaterals returned by `fetchCollaterals(fmspc, teeType, pckCa)`,
+/// which may return the collaterals object or a promise of it. Errors thrown or rejected by
+/// the callback are passed through.
+#[wasm_bindgen(js_name = verifyWithFetcher)]
+pub async fn verify_with_fetcher(
+    quote: Vec<u8>,
+    fetch_collaterals: js_sys::Function,
+    time: Option<f64>,
+) -> std::result::Result<JsValue, JsValue> {
+    let query = CollateralQuery::from_quote(&quote).map_err(JsError::from)?;
+    let collaterals = fetch_collaterals.call3(
+        &JsValue::NULL,
+        &JsValue::from_str(&query.fmspc),
+        &JsValue::from_str(query.tee_type),
+        &JsValue::from_str(query.pck_ca),
+    )?;
+    let collaterals = JsFuture::from(js_sys::Promise::resolve(&collaterals)).await?;
+    Ok(verify_at(&quote, collaterals, time).map_err(JsError::from)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SGX_V3_QUOTE: &[u8] = include_bytes!("../examples/testdata/sgx_v3_quote.bin");
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+    const TDX_V5_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v5_quote.bin");
+
+    #[test]
+    fn builds_collateral_queries() {
+        for (quote, fmspc, tee_type) in [
+            (SGX_V3_QUOTE, "00606A000000", "sgx"),
+            (TDX_V4_QUOTE, "00806F050000", "tdx"),
+            (TDX_V5_QUOTE, "90C06F000000", "tdx"),
+        ] {
+            let query = CollateralQuery::from_quote(quote).unwrap();
+            assert_eq!(query.fmspc, fmspc);
+            assert_eq!(query.tee_type, tee_type);
+            assert_eq!(query.pck_ca, "platform");
+        }
+
+        let query = CollateralQuery::from_quote(TDX_V4_QUOTE).unwrap();
+        assert_eq!(
+            serde_json::to_value(&query).unwrap(),
+            serde_json::json!({"fmspc": "00806F050000", "teeType": "tdx", "pckCa": "platform"})
+        );
+        assert!(CollateralQuery::from_quote(b"not a quote").is_err());
+    }
+
+    #[test]
+    fn converts_verification_times() {
+        assert_eq!(
+            verification_time(Some(1_735_689_600.0)).unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_735_689_600)
+        );
+        assert_eq!(
+            verification_time(Some(0.25)).unwrap(),
+            UNIX_EPOCH + Duration::from_millis(250)
+        );
+        assert_eq!(verification_time(Some(0.0)).unwrap(), UNIX_EPOCH);
+        for time in [-1.0, f64::NAN, f64::INFINITY, 1e300] {
+            assert!(
+                matches!(
+                    verification_time(Some(time)),
+                    Err(TdxError::ConfigOptions(_))
+                ),
+                "{time}"
+            );
+        }
+    }
+}
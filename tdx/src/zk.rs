@@ -0,0 +1,194 @@
+use crate::collateral::RawCollaterals;
+use crate::error::{Result, TdxError};
+use crate::utils::{get_pck_fmspc_and_issuer, PckCA};
+use dcap_rs::types::quote::Quote;
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Input for the Automata DCAP zkVM guest programs (Risc0 / SP1).
+///
+/// Serialized layout, all integers little-endian:
+/// `current_time (u64) || quote_len (u32) || collaterals_len (u32) || quote || collaterals`,
+/// where `collaterals` is the output of [`serialize_collaterals`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZkGuestInput {
+    /// Verification time in seconds since the UNIX epoch.
+    pub current_time: u64,
+    pub raw_quote: Vec<u8>,
+    /// Collaterals serialized in the guest's format.
+    pub collaterals: Vec<u8>,
+}
+
+impl ZkGuestInput {
+    /// Build the guest input for `raw_quote`, verified at `current_time`.
+    pub fn new(raw_quote: &[u8], collaterals: &RawCollaterals, current_time: u64) -> Result<Self> {
+        let quote = Quote::read(&mut &*raw_quote)?;
+        let (_, pck_ca) = get_pck_fmspc_and_issuer(&quote)?;
+        Ok(ZkGuestInput {
+            current_time,
+            raw_quote: raw_quote.to_vec(),
+            collaterals: serialize_collaterals(collaterals, pck_ca)?,
+        })
+    }
+
+    /// Serialize the input into the bytes passed to the prover.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let quote_len = len_u32(&self.raw_quote, "quote")?;
+        let collaterals_len = len_u32(&self.collaterals, "collaterals")?;
+
+        let mut input = Vec::with_capacity(16 + self.raw_quote.len() + self.collaterals.len());
+        input.extend_from_slice(&self.current_time.to_le_bytes());
+        input.extend_from_slice(&quote_len.to_le_bytes());
+        input.extend_from_slice(&collaterals_len.to_le_bytes());
+        input.extend_from_slice(&self.raw_quote);
+        input.extend_from_slice(&self.collaterals);
+        Ok(input)
+    }
+
+    /// SHA-256 of the serialized input.
+    pub fn hash(&self) -> Result<[u8; 32]> {
+        Ok(Sha256::digest(self.to_bytes()?).into())
+    }
+}
+
+/// Serialize collaterals in the format expected by the guest programs.
+///
+/// Seven little-endian `u32` lengths followed by the data, in this order: TCB info, QE identity,
+/// root CA, TCB signing CA, root CA CRL, PCK Processor CA CRL, PCK Platform CA CRL.
+/// Only the CRL of the CA that issued the quote's PCK certificate is included, the other slot
+/// is left empty.
+pub fn serialize_collaterals(collaterals: &RawCollaterals, pck_ca: PckCA) -> Result<Vec<u8>> {
+    let empty: &[u8] = &[];
+    let (processor_crl, platform_crl) = match pck_ca {
+        PckCA::Processor => (collaterals.pck_crl.as_slice(), empty),
+        PckCA::Platform => (empty, collaterals.pck_crl.as_slice()),
+    };
+    let fields: [(&[u8], &str); 7] = [
+        (&collaterals.tcb_info, "tcb_info"),
+        (&collaterals.qe_identity, "qe_identity"),
+        (&collaterals.root_ca, "root_ca"),
+        (&collaterals.tcb_signing_ca, "tcb_signing_ca"),
+        (&collaterals.root_ca_crl, "root_ca_crl"),
+        (processor_crl, "pck_processor_crl"),
+        (platform_crl, "pck_platform_crl"),
+    ];
+
+    let mut data = Vec::new();
+    for (field, name) in fields.iter() {
+        data.extend_from_slice(&len_u32(field, name)?.to_le_bytes());
+    }
+    for (field, _) in fields.iter() {
+        data.extend_from_slice(field);
+    }
+    Ok(data)
+}
+
+/// Current time in seconds since the UNIX epoch.
+pub fn current_time() -> Result<u64> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .map_err(|e| TdxError::IO(format!("System time before UNIX epoch: {e}")))
+}
+
+fn len_u32(data: &[u8], name: &str) -> Result<u32> {
+    u32::try_from(data.len())
+        .map_err(|_| TdxError::IO(format!("{name} length {} exceeds u32::MAX", data.len())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+
+    fn collaterals() -> RawCollaterals {
+        RawCollaterals {
+            tcb_info: b"tcb-info".to_vec(),
+            qe_identity: b"qe-identity".to_vec(),
+            root_ca: b"root-ca".to_vec(),
+            tcb_signing_ca: b"tcb-signing-ca".to_vec(),
+            root_ca_crl: b"root-ca-crl".to_vec(),
+            pck_crl: b"pck-platform-crl".to_vec(),
+        }
+    }
+
+    /// Guest input laid out by hand from the host-side `generate_input` and
+    /// `IntelCollateral::to_bytes` of the Automata DCAP zkVM CLI. It pins the layout only; it
+    /// is not output of the CLI, so it cannot catch a misreading of that layout.
+    // TODO: replace with the input the zkVM CLI writes for `tdx_v4_quote.bin`.
+    const GUEST_INPUT: &str = concat!(
+        // current_time = 1_700_000_000, quote_len = 3, collaterals_len = 95
+        "00f1536500000000",
+        "03000000",
+        "5f000000",
+        // quote
+        "aabbcc",
+        // collateral lengths, the processor CRL slot is empty
+        "08000000",
+        "0b000000",
+        "07000000",
+        "0e000000",
+        "0b000000",
+        "00000000",
+        "10000000",
+        // collateral data
+        "7463622d696e666f",
+        "71652d6964656e74697479",
+        "726f6f742d6361",
+        "7463622d7369676e696e672d6361",
+        "726f6f742d63612d63726c",
+        "70636b2d706c6174666f726d2d63726c",
+    );
+
+    #[test]
+    fn serializes_guest_input_layout() {
+        let input = ZkGuestInput {
+            current_time: 1_700_000_000,
+            raw_quote: vec![0xaa, 0xbb, 0xcc],
+            collaterals: serialize_collaterals(&collaterals(), PckCA::Platform).unwrap(),
+        };
+        let expected = hex::decode(GUEST_INPUT).unwrap();
+
+        assert_eq!(input.to_bytes().unwrap(), expected);
+        assert_eq!(
+            input.hash().unwrap(),
+            <[u8; 32]>::from(Sha256::digest(&expected))
+        );
+    }
+
+    #[test]
+    fn processor_crl_goes_in_the_processor_slot() {
+        let collaterals = collaterals();
+        let data = serialize_collaterals(&collaterals, PckCA::Processor).unwrap();
+
+        let lengths: Vec<u32> = data[..28]
+            .chunks(4)
+            .map(|len| u32::from_le_bytes(len.try_into().unwrap()))
+            .collect();
+        assert_eq!(lengths, [8, 11, 7, 14, 11, 16, 0]);
+        assert!(data.ends_with(&collaterals.pck_crl));
+    }
+
+    #[test]
+    fn guest_input_uses_the_issuer_of_the_quote() {
+        // The PCK certificate of the test quote is issued by the Platform CA.
+        let collaterals = collaterals();
+        let input = ZkGuestInput::new(TDX_V4_QUOTE, &collaterals, 1_700_000_000).unwrap();
+
+        assert_eq!(input.raw_quote, TDX_V4_QUOTE);
+        assert_eq!(
+            input.collaterals,
+            serialize_collaterals(&collaterals, PckCA::Platform).unwrap()
+        );
+
+        let bytes = input.to_bytes().unwrap();
+        assert_eq!(bytes[..8], 1_700_000_000u64.to_le_bytes());
+        assert_eq!(bytes[8..12], (TDX_V4_QUOTE.len() as u32).to_le_bytes());
+        assert_eq!(
+            bytes[12..16],
+            (input.collaterals.len() as u32).to_le_bytes()
+        );
+        assert_eq!(&bytes[16..16 + TDX_V4_QUOTE.len()], TDX_V4_QUOTE);
+    }
+}
@@ -0,0 +1,93 @@
+/* Smoke test of the C API against the header generated by `make cbindings`, run with
+ * `make c-test`. Reads the test PKI quote and collaterals from tests/data. */
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "tdx.h"
+
+/* 2025-01-01, within the validity of the test PKI */
+#define TEST_PKI_TIME 1735689600
+
+static uint8_t *read_file(const char *path, size_t *len) {
+    FILE *f = fopen(path, "rb");
+    if (f == NULL) {
+        perror(path);
+        exit(1);
+    }
+    fseek(f, 0, SEEK_END);
+    long size = ftell(f);
+    fseek(f, 0, SEEK_SET);
+    uint8_t *data = malloc(size + 1);
+    if (data == NULL || fread(data, 1, size, f) != (size_t)size) {
+        perror(path);
+        exit(1);
+    }
+    fclose(f);
+    data[size] = 0;
+    *len = size;
+    return data;
+}
+
+#define CHECK(cond)                                                                        \
+    do {                                                                                   \
+        if (!(cond)) {                                                                     \
+            const char *error = tdx_last_error();                                          \
+            fprintf(stderr, "%s:%d: %s failed: %s\n", __FILE__, __LINE__, #cond,           \
+                    error ? error : "no error");                                           \
+            return 1;                                                                      \
+        }                                                                                  \
+    } while (0)
+
+int main(void) {
+    size_t quote_len, collaterals_len;
+    uint8_t *quote = read_file("tests/data/test_pki_quote.bin", &quote_len);
+    char *collaterals = (char *)read_file("tests/data/collaterals.json", &collaterals_len);
+
+    TdxCtx *ctx = tdx_ctx_new();
+    CHECK(ctx != NULL);
+
+    uint8_t report_data[65] = {0};
+    CHECK(tdx_ctx_set_report_data(ctx, report_data, sizeof(report_data)) ==
+          TDX_ERR_INVALID_ARGUMENT);
+    CHECK(tdx_last_error() != NULL);
+    CHECK(tdx_ctx_set_report_data(ctx, report_data, 64) == TDX_OK);
+    CHECK(tdx_last_error() == NULL);
+
+    char fmspc[13];
+    int32_t pck_ca;
+    CHECK(tdx_get_fmspc(ctx, quote, quote_len, fmspc, sizeof(fmspc), &pck_ca) == TDX_OK);
+    CHECK(strcmp(fmspc, "00806F050000") == 0);
+    CHECK(pck_ca == TDX_PCK_CA_PLATFORM);
+    CHECK(tdx_get_fmspc(ctx, quote, quote_len, fmspc, 12, NULL) == TDX_ERR_BUFFER_TOO_SMALL);
+
+    TdxVerifiedOutput *output =
+        tdx_verify_quote_with_collaterals(ctx, quote, quote_len, collaterals, TEST_PKI_TIME);
+    CHECK(output != NULL);
+    TdxVerifiedInfo info;
+    CHECK(tdx_verified_output_info(output, &info) == TDX_OK);
+    CHECK(info.tee_type == 0x81);
+    CHECK(info.tcb_status == 0);
+    uint8_t *body = malloc(info.quote_body_len);
+    CHECK(tdx_verified_output_copy_quote_body(output, body, info.quote_body_len) ==
+          (int32_t)info.quote_body_len);
+    CHECK(memcmp(body, quote + 48, info.quote_body_len) == 0);
+    char *json = tdx_verified_output_to_json(output);
+    CHECK(json != NULL && strstr(json, "UpToDate") != NULL);
+    tdx_string_free(json);
+    tdx_verified_output_free(output);
+
+    CHECK(tdx_verify_quote_with_collaterals(ctx, quote, quote_len, "{}", TEST_PKI_TIME) ==
+          NULL);
+    CHECK(tdx_verify_quote_with_collaterals(NULL, quote, quote_len, collaterals,
+                                            TEST_PKI_TIME) == NULL);
+
+    tdx_ctx_free(ctx);
+    free(body);
+    free(collaterals);
+    free(quote);
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,347 @@
+#!/usr/bin/env python3
+"""Generate the test PKI, collaterals and quote in this directory.
+
+The test PKI mirrors Intel's (root CA, TCB signing certificate, PCK Platform CA and a PCK
+certificate carrying SGX extensions, with Intel's subject names) but uses the fixed private keys
+below, so that tests can sign their own quotes and collaterals. Signatures are deterministic
+(RFC 6979): running this script again reproduces the files byte for byte.
+
+`test_pki_quote.bin` is `examples/testdata/tdx_v4_quote.bin` with its TD report, QE report,
+QE authentication data and PCK SGX extensions kept, but with the QE report re-signed by the test
+PCK key and the quote re-signed by the test attestation key. The TCB info and QE identity are
+shaped like Intel's (TCB info v3 for TDX, QE identity v2 for TD_QE) and rate it UpToDate.
+
+Requires the Python `cryptography` package (44 or later). Run from any directory:
+
+    python3 tdx/tests/data/generate.py
+"""
+
+import datetime
+import hashlib
+import json
+import os
+import struct
+
+from cryptography import x509
+from cryptography.hazmat.primitives import hashes, serialization
+from cryptography.hazmat.primitives.asymmetric import ec
+from cryptography.hazmat.primitives.asymmetric.utils import decode_dss_signature
+from cryptography.x509.oid import NameOID
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+SOURCE_QUOTE = os.path.join(HERE, "..", "..", "examples", "testdata", "tdx_v4_quote.bin")
+
+# Private scalars of the test keys. The attestation and PCK keys are also used by the tests to
+# re-sign quotes, see `QuoteData::sign` and `QuoteData::sign_qe_report`.
+ATTESTATION_KEY = 0x11
+PCK_KEY = 0x22
+ROOT_CA_KEY = 0x31
+TCB_SIGNING_KEY = 0x32
+PCK_CA_KEY = 0x33
+
+SGX_EXTENSIONS_OID = x509.ObjectIdentifier("1.2.840.113741.1.13.1")
+NOT_BEFORE = datetime.datetime(2024, 1, 1, tzinfo=datetime.timezone.utc)
+NOT_AFTER = datetime.datetime(2049, 1, 1, tzinfo=datetime.timezone.utc)
+NEXT_UPDATE = datetime.datetime(2034, 1, 1, tzinfo=datetime.timezone.utc)
+DATES = {"issueDate": "2024-01-01T00:00:00Z", "nextUpdate": "2034-01-01T00:00:00Z"}
+ECDSA = ec.ECDSA(hashes.SHA256(), deterministic_signing=True)
+
+
+def key(byte):
+    return ec.derive_private_key(int.from_bytes(bytes([byte]) * 32, "big"), ec.SECP256R1())
+
+
+def name(cn):
+    return x509.Name(
+        [
+            x509.NameAttribute(NameOID.COMMON_NAME, cn),
+            x509.NameAttribute(NameOID.ORGANIZATION_NAME, "Intel Corporation"),
+            x509.NameAttribute(NameOID.LOCALITY_NAME, "Santa Clara"),
+            x509.NameAttribute(NameOID.STATE_OR_PROVINCE_NAME, "CA"),
+            x509.NameAttribute(NameOID.COUNTRY_NAME, "US"),
+        ]
+    )
+
+
+def der(obj):
+    return obj.public_bytes(serialization.Encoding.DER)
+
+
+def raw_public_key(private_key):
+    point = private_key.public_key().public_bytes(
+        serialization.Encoding.X962, serialization.PublicFormat.UncompressedPoint
+    )
+    return point[1:]
+
+
+def raw_signature(private_key, data):
+    r, s = decode_dss_signature(private_key.sign(data, ECDSA))
+    return r.to_bytes(32, "big") + s.to_bytes(32, "big")
+
+
+def certificate(subject, subject_key, issuer, issuer_key, serial, path_length, extensions=()):
+    ca = path_length is not None
+    builder = (
+        x509.CertificateBuilder()
+        .subject_name(name(subject))
+        .issuer_name(name(issuer))
+        .public_key(subject_key.public_key())
+        .serial_number(serial)
+        .not_valid_before(NOT_BEFORE)
+        .not_valid_after(NOT_AFTER)
+        .add_extension(
+            x509.AuthorityKeyIdentifier.from_issuer_public_key(issuer_key.public_key()),
+            critical=False,
+        )
+        .add_extension(
+            x509.SubjectKeyIdentifier.from_public_key(subject_key.public_key()), critical=False
+        )
+        .add_extension(
+            x509.KeyUsage(
+                digital_signature=not ca,
+                content_commitment=not ca,
+                key_encipherment=False,
+                data_encipherment=False,
+                key_agreement=False,
+                key_cert_sign=ca,
+                crl_sign=ca,
+                encipher_only=False,
+                decipher_only=False,
+            ),
+            critical=True,
+        )
+        .add_extension(x509.BasicConstraints(ca=ca, path_length=path_length), critical=True)
+    )
+    for extension in extensions:
+        builder = builder.add_extension(extension, critical=False)
+    return builder.sign(issuer_key, hashes.SHA256(), ecdsa_deterministic=True)
+
+
+def crl(issuer, issuer_key, revoked):
+    builder = (
+        x509.CertificateRevocationListBuilder()
+        .issuer_name(name(issuer))
+        .last_update(NOT_BEFORE)
+        .next_update(NEXT_UPDATE)
+        .add_extension(x509.CRLNumber(1), critical=False)
+        .add_extension(
+            x509.AuthorityKeyIdentifier.from_issuer_public_key(issuer_key.public_key()),
+            critical=False,
+        )
+    )
+    for serial in revoked:
+        builder = builder.add_revoked_certificate(
+            x509.RevokedCertificateBuilder()
+            .serial_number(serial)
+            .revocation_date(NOT_BEFORE)
+            .build()
+        )
+    return builder.sign(issuer_key, hashes.SHA256(), ecdsa_deterministic=True)
+
+
+def signed_json(signing_key, field, body):
+    body = json.dumps(body, separators=(",", ":"))
+    signature = raw_signature(signing_key, body.encode()).hex()
+    return ('{"%s":%s,"signature":"%s"}' % (field, body, signature)).encode()
+
+
+def split_quote(quote):
+    """Returns the header and body, and the fields of the v4 ECDSA signature data."""
+    (signature_len,) = struct.unpack_from("<I", quote, 632)
+    signature = quote[636 : 636 + signature_len]
+    cert_data_type, cert_data_len = struct.unpack_from("<HI", signature, 128)
+    assert cert_data_type == 6, "expected QE report certification data"
+    qe = signature[134 : 134 + cert_data_len]
+    (auth_len,) = struct.unpack_from("<H", qe, 448)
+    auth_data = qe[450 : 450 + auth_len]
+    inner_type, inner_len = struct.unpack_from("<HI", qe, 450 + auth_len)
+    assert inner_type == 5, "expected a PCK certificate chain"
+    chain = qe[456 + auth_len : 456 + auth_len + inner_len]
+    return quote[:632], qe[:384], auth_data, chain
+
+
+def build_quote(signed, qe_report, auth_data, chain, attestation_key, pck_key):
+    attestation_public = raw_public_key(attestation_key)
+    report_data = hashlib.sha256(attestation_public + auth_data).digest() + bytes(32)
+    qe_report = qe_report[:320] + report_data
+    qe = (
+        qe_report
+        + raw_signature(pck_key, qe_report)
+        + struct.pack("<H", len(auth_data))
+        + auth_data
+        + struct.pack("<HI", 5, len(chain))
+        + chain
+    )
+    signature = (
+        raw_signature(attestation_key, signed)
+        + attestation_public
+        + struct.pack("<HI", 6, len(qe))
+        + qe
+    )
+    return signed + struct.pack("<I", len(signature)) + signature
+
+
+def main():
+    root_key, signing_key, pck_ca_key = key(ROOT_CA_KEY), key(TCB_SIGNING_KEY), key(PCK_CA_KEY)
+    attestation_key, pck_key = key(ATTESTATION_KEY), key(PCK_KEY)
+
+    signed, qe_report, auth_data, source_chain = split_quote(open(SOURCE_QUOTE, "rb").read())
+    source_pck = x509.load_pem_x509_certificates(source_chain)[0]
+    sgx_extensions = source_pck.extensions.get_extension_for_oid(SGX_EXTENSIONS_OID).value
+
+    root = certificate("Intel SGX Root CA", root_key, "Intel SGX Root CA", root_key, 1, 1)
+    signing = certificate("Intel SGX TCB Signing", signing_key, "Intel SGX Root CA", root_key, 2, None)
+    pck_ca = certificate("Intel SGX PCK Platform CA", pck_ca_key, "Intel SGX Root CA", root_key, 3, 0)
+    pck = certificate(
+        "Intel SGX PCK Certificate",
+        pck_key,
+        "Intel SGX PCK Platform CA",
+        pck_ca_key,
+        4,
+        None,
+        [sgx_extensions],
+    )
+    chain = b"".join(c.public_bytes(serialization.Encoding.PEM) for c in (pck, pck_ca, root))
+
+    # TCB levels matching the quote: the PCK certificate's SGX TCB and the TD's TEE TCB SVN
+    tee_tcb_svn = signed[48:64]
+    mr_signer_seam = signed[112:160]
+    seam_attributes = signed[160:168]
+    sgx_tcb = [b for b in _sgx_tcb_components(sgx_extensions.value)]
+    pcesvn = _pcesvn(sgx_extensions.value)
+    tcb_date = "2024-01-01T00:00:00Z"
+    tcb_info = {
+        "id": "TDX",
+        "version": 3,
+        **DATES,
+        "fmspc": "00806f050000",
+        "pceId": "0000",
+        "tcbType": 0,
+        "tcbEvaluationDataNumber": 17,
+        "tdxModule": {
+            "mrsigner": mr_signer_seam.hex().upper(),
+            "attributes": seam_attributes.hex().upper(),
+            "attributesMask": "FFFFFFFFFFFFFFFF",
+        },
+        "tdxModuleIdentities": [
+            {
+                "id": "TDX_%02X" % tee_tcb_svn[1],
+                "mrsigner": mr_signer_seam.hex().upper(),
+                "attributes": seam_attributes.hex().upper(),
+                "attributesMask": "FFFFFFFFFFFFFFFF",
+                "tcbLevels": [
+                    {
+                        "tcb": {"isvsvn": tee_tcb_svn[0]},
+                        "tcbDate": tcb_date,
+                        "tcbStatus": "UpToDate",
+                    }
+                ],
+            }
+        ],
+        "tcbLevels": [
+            {
+                "tcb": {
+                    "sgxtcbcomponents": [{"svn": svn} for svn in sgx_tcb],
+                    "pcesvn": pcesvn,
+                    "tdxtcbcomponents": [{"svn": svn} for svn in tee_tcb_svn],
+                },
+                "tcbDate": tcb_date,
+                "tcbStatus": "UpToDate",
+            },
+            {
+                "tcb": {
+                    "sgxtcbcomponents": [{"svn": 0} for _ in range(16)],
+                    "pcesvn": 0,
+                    "tdxtcbcomponents": [{"svn": 0} for _ in range(16)],
+                },
+                "tcbDate": "2023-01-01T00:00:00Z",
+                "tcbStatus": "OutOfDate",
+                "advisoryIDs": ["INTEL-SA-00837"],
+            },
+        ],
+    }
+    (isv_prod_id, isv_svn) = struct.unpack_from("<HH", qe_report, 256)
+    qe_identity = {
+        "id": "TD_QE",
+        "version": 2,
+        **DATES,
+        "tcbEvaluationDataNumber": 17,
+        "miscselect": qe_report[16:20].hex().upper(),
+        "miscselectMask": "FFFFFFFF",
+        "attributes": "11000000000000000000000000000000",
+        "attributesMask": "FBFFFFFFFFFFFFFF0000000000000000",
+        "mrsigner": qe_report[128:160].hex().upper(),
+        "isvprodid": isv_prod_id,
+        "tcbLevels": [
+            {"tcb": {"isvsvn": isv_svn}, "tcbDate": tcb_date, "tcbStatus": "UpToDate"}
+        ],
+    }
+
+    collaterals = {
+        "tcb_info": signed_json(signing_key, "tcbInfo", tcb_info).hex(),
+        "qe_identity": signed_json(signing_key, "enclaveIdentity", qe_identity).hex(),
+        "root_ca": der(root).hex(),
+        "tcb_signing_ca": der(signing).hex(),
+        "root_ca_crl": der(crl("Intel SGX Root CA", root_key, [])).hex(),
+        "pck_crl": der(crl("Intel SGX PCK Platform CA", pck_ca_key, [])).hex(),
+    }
+    write("collaterals.json", (json.dumps(collaterals, indent=2) + "\n").encode())
+    write("pck_ca.der", der(pck_ca))
+    # Revokes the TCB signing certificate and the PCK CA
+    write("revoking_root_ca_crl.der", der(crl("Intel SGX Root CA", root_key, [2, 3])))
+    write("root_public_key.hex", (raw_public_key(root_key).hex() + "\n").encode())
+    write("pck_cert_chain.pem", chain)
+    quote = build_quote(signed, qe_report, auth_data, chain, attestation_key, pck_key)
+    write("test_pki_quote.bin", quote)
+
+
+def _entries(der_bytes):
+    """Yields the (last OID arc, DER value) entries of a SEQUENCE OF SEQUENCE { OID, value }."""
+    _, body = _tlv(der_bytes)
+    while body:
+        entry, body = _split(body)
+        _, entry = _tlv(entry)
+        oid, value = _split(entry)
+        yield oid[-1], value
+
+
+def _sgx_tcb_components(sgx_extensions):
+    tcb = dict(_entries(sgx_extensions))[2]
+    components = dict(_entries(tcb))
+    return [_tlv(components[i])[1][-1] for i in range(1, 17)]
+
+
+def _pcesvn(sgx_extensions):
+    tcb = dict(_entries(sgx_extensions))[2]
+    return int.from_bytes(_tlv(dict(_entries(tcb))[17])[1], "big")
+
+
+def _tlv(data):
+    """Returns the tag and the content of a DER element."""
+    length = data[1]
+    offset = 2
+    if length & 0x80:
+        count = length & 0x7F
+        length = int.from_bytes(data[2 : 2 + count], "big")
+        offset += count
+    return data[0], data[offset : offset + length]
+
+
+def _split(data):
+    """Splits the first DER element off `data`."""
+    length = data[1]
+    offset = 2
+    if length & 0x80:
+        count = length & 0x7F
+        length = int.from_bytes(data[2 : 2 + count], "big")
+        offset += count
+    return data[: offset + length], data[offset + length :]
+
+
+def write(file_name, data):
+    with open(os.path.join(HERE, file_name), "wb") as f:
+        f.write(data)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,75 @@
+//! On-chain verification against a local anvil node.
+//!
+//! Run anvil on a fork of a network where the DCAP Attestation contract and the on-chain PCCS
+//! hold the collaterals of the test quote, e.g. `anvil --fork-url <automata testnet RPC>`, then:
+//!
+//! ```bash
+//! TDX_TEST_ANVIL_RPC_URL=http://127.0.0.1:8545 cargo test --test onchain -- --ignored
+//! ```
+//!
+//! `TDX_TEST_DCAP_ATTESTATION` overrides the contract address resolved from the network
+//! registry, e.g. for a verifier deployed on a fresh anvil node.
+
+use alloy::primitives::Address;
+use alloy::signers::local::PrivateKeySigner;
+use tdx::onchain::OnchainOptions;
+use tdx::quote::QuoteData;
+use tdx::verified_output::TEE_TYPE_TDX;
+use tdx::Tdx;
+
+/// First default account of anvil.
+const ANVIL_PRIVATE_KEY: &str =
+    "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+const TDX_V4_QUOTE: &[u8] = include_bytes!("../examples/testdata/tdx_v4_quote.bin");
+
+fn anvil_options() -> OnchainOptions {
+    let rpc_url = std::env::var("TDX_TEST_ANVIL_RPC_URL")
+        .expect("TDX_TEST_ANVIL_RPC_URL must point at an anvil node");
+    let dcap_attestation = std::env::var("TDX_TEST_DCAP_ATTESTATION")
+        .ok()
+        .map(|address| {
+            address
+                .parse::<Address>()
+                .expect("TDX_TEST_DCAP_ATTESTATION is not an address")
+        });
+    OnchainOptions {
+        rpc_url: Some(rpc_url),
+        dcap_attestation,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs an anvil node, see TDX_TEST_ANVIL_RPC_URL"]
+async fn verify_quote_with_eth_call() {
+    let output = Tdx::new()
+        .verify_attestation_report_onchain(TDX_V4_QUOTE, &anvil_options())
+        .await
+        .unwrap();
+
+    let quote = QuoteData::from_bytes(TDX_V4_QUOTE).unwrap();
+    assert_eq!(output.quote_version, 4);
+    assert_eq!(output.tee_type, TEE_TYPE_TDX);
+    assert_eq!(output.body().unwrap(), quote.body);
+}
+
+#[tokio::test]
+#[ignore = "needs an anvil node, see TDX_TEST_ANVIL_RPC_URL"]
+async fn submit_quote_in_transaction() {
+    let options = anvil_options();
+    let tdx = Tdx::new();
+    let signer: PrivateKeySigner = ANVIL_PRIVATE_KEY.parse().unwrap();
+
+    let submission = tdx
+        .submit_attestation_report_onchain(TDX_V4_QUOTE, signer, &options)
+        .await
+        .unwrap();
+    let output = tdx
+        .verify_attestation_report_onchain(TDX_V4_QUOTE, &options)
+        .await
+        .unwrap();
+
+    assert!(submission.block_number.is_some());
+    assert_eq!(submission.verified_output, output);
+}
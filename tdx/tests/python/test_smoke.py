@@ -0,0 +1,62 @@
+"""Smoke tests of the Python bindings, run with `make python-test`."""
+
+from pathlib import Path
+
+import pytest
+
+import tdx
+
+TESTDATA = Path(__file__).parents[2] / "examples" / "testdata"
+TEST_PKI = Path(__file__).parents[1] / "data"
+# 2025-01-01, within the validity of the test PKI
+TEST_PKI_TIME = 1_735_689_600
+
+
+def read(path):
+    return path.read_bytes()
+
+
+def test_parse_tdx_quote():
+    quote = tdx.parse_quote(read(TESTDATA / "tdx_v4_quote.bin"))
+    assert quote.version == 4
+    assert quote.tee_type == 0x81
+    assert quote.body_type == "td10"
+    assert len(quote.report_data) == 64
+    assert len(quote.mr_td) == 48
+    assert len(quote.rtmrs) == 4
+    assert quote.mr_enclave is None
+    assert tdx.parse_quote(quote.to_bytes()).to_json() == quote.to_json()
+
+
+def test_parse_sgx_quote():
+    quote = tdx.parse_quote(read(TESTDATA / "sgx_v3_quote.bin"))
+    assert quote.version == 3
+    assert quote.body_type == "sgx"
+    assert len(quote.mr_enclave) == 32
+    assert quote.mr_td is None
+
+
+def test_parse_invalid_quote():
+    with pytest.raises(tdx.TdxError):
+        tdx.parse_quote(b"not a quote")
+
+
+def test_get_fmspc():
+    fmspc, pck_ca = tdx.get_fmspc(read(TESTDATA / "tdx_v4_quote.bin"))
+    assert fmspc == "00806F050000"
+    assert pck_ca == "platform"
+
+
+def test_verify_test_pki_quote():
+    quote = read(TEST_PKI / "test_pki_quote.bin")
+    collaterals = (TEST_PKI / "collaterals.json").read_text()
+    output = tdx.verify(quote, collaterals=collaterals, time=TEST_PKI_TIME)
+    assert output.tcb_status == "UpToDate"
+    assert output.fmspc == "00806F050000"
+    assert output.advisory_ids == []
+
+    with pytest.raises(tdx.ConfigError):
+        tdx.verify(quote, collaterals=collaterals, time=2**64 - 1)
+    # Collaterals expire in 2034
+    with pytest.raises(tdx.TdxError):
+        tdx.verify(quote, collaterals=collaterals, time=TEST_PKI_TIME + 10 * 365 * 86400)
@@ -0,0 +1,128 @@
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// Service configuration, read from a TOML file and overridden by environment variables.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address to listen on. Env: `TDX_VERIFIER_LISTEN`.
+    pub listen: SocketAddr,
+    pub collaterals: CollateralConfig,
+    /// Named appraisal policies, as paths to JSON policy files.
+    pub policies: HashMap<String, PathBuf>,
+    /// Policy applied when a request does not name one. Env: `TDX_VERIFIER_DEFAULT_POLICY`.
+    pub default_policy: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CollateralConfig {
+    /// Env: `TDX_VERIFIER_COLLATERAL_SOURCE`.
+    pub source: CollateralSource,
+    /// Directory of `<FMSPC>.json` collateral files, for `source = "directory"`.
+    /// Env: `TDX_VERIFIER_COLLATERAL_DIR`.
+    pub directory: Option<PathBuf>,
+    /// Base URL of the Intel PCS or a PCCS. Env: `TDX_VERIFIER_PCS_URL`.
+    pub pcs_url: Option<String>,
+    /// Intel PCS API key. Env: `PCS_API_KEY`.
+    pub pcs_api_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollateralSource {
+    /// Automata on-chain PCCS
+    Onchain,
+    /// Intel PCS, or the PCCS at `pcs_url`
+    Pcs,
+    /// Collaterals saved with `tdx collateral save`, for offline verification
+    Directory,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: ([0, 0, 0, 0], 8080).into(),
+            collaterals: CollateralConfig::default(),
+            policies: HashMap::new(),
+            default_policy: None,
+        }
+    }
+}
+
+impl Default for CollateralConfig {
+    fn default() -> Self {
+        CollateralConfig {
+            source: CollateralSource::Onchain,
+            directory: None,
+            pcs_url: None,
+            pcs_api_key: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration from `path` if set, then apply the environment overrides.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let mut config = match path {
+            Some(path) => {
+                let data = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                toml::from_str(&data)
+                    .with_context(|| format!("Failed to parse {}", path.display()))?
+            }
+            None => Config::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.check()?;
+        Ok(config)
+    }
+
+    /// Apply the environment overrides, reading variables with `var`.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> anyhow::Result<()> {
+        if let Some(listen) = var("TDX_VERIFIER_LISTEN") {
+            self.listen = listen
+                .parse()
+                .with_context(|| format!("Invalid TDX_VERIFIER_LISTEN: {listen}"))?;
+        }
+        if let Some(source) = var("TDX_VERIFIER_COLLATERAL_SOURCE") {
+            self.collaterals.source = match source.as_str() {
+                "onchain" => CollateralSource::Onchain,
+                "pcs" => CollateralSource::Pcs,
+                "directory" => CollateralSource::Directory,
+                other => bail!("Invalid TDX_VERIFIER_COLLATERAL_SOURCE: {other}"),
+            };
+        }
+        if let Some(directory) = var("TDX_VERIFIER_COLLATERAL_DIR") {
+            self.collaterals.directory = Some(directory.into());
+        }
+        if let Some(url) = var("TDX_VERIFIER_PCS_URL") {
+            self.collaterals.pcs_url = Some(url);
+        }
+        if let Some(key) = var("PCS_API_KEY") {
+            self.collaterals.pcs_api_key = Some(key);
+        }
+        if let Some(policy) = var("TDX_VERIFIER_DEFAULT_POLICY") {
+            self.default_policy = Some(policy);
+        }
+        Ok(())
+    }
+
+    /// Check that the configuration is complete and consistent.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.collaterals.source == CollateralSource::Directory
+            && self.collaterals.directory.is_none()
+        {
+            bail!("collaterals.directory is required with source = \"directory\"");
+        }
+        if let Some(name) = &self.default_policy {
+            if !self.policies.contains_key(name) {
+                bail!("Default policy {name} is not defined in [policies]");
+            }
+        }
+        Ok(())
+    }
+}
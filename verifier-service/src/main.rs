@@ -0,0 +1,46 @@
+//! HTTP service verifying Intel TDX and SGX DCAP quotes for other services.
+
+mod config;
+mod routes;
+mod state;
+
+use clap::Parser;
+use config::Config;
+use state::AppState;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Parser)]
+#[clap(name = "tdx-verifier", version)]
+struct Args {
+    /// TOML configuration file
+    #[clap(long, env = "TDX_VERIFIER_CONFIG")]
+    config: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+    let config = Config::load(args.config.as_deref())?;
+    let state = AppState::new(&config)?;
+    tracing::info!(
+        "Collateral source: {:?}, policies: {:?}",
+        config.collaterals.source,
+        state.policies.keys().collect::<Vec<_>>()
+    );
+
+    let app = routes::router(Arc::new(state));
+    let listener = tokio::net::TcpListener::bind(config.listen).await?;
+    tracing::info!("Listening on {}", config.listen);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+    Ok(())
+}
+
+async fn shutdown_signal() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        tracing::error!("Failed to listen for shutdown signal: {e}");
+    }
+}
@@ -0,0 +1,429 @@
+use crate::state::AppState;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tdx::collateral::RawCollaterals;
+use tdx::error::TdxError;
+use tdx::policy::PolicyViolation;
+use tdx::utils::{PckCA, TeeType};
+use tdx::verified_output::VerifiedOutput;
+use utoipa::{OpenApi, ToSchema};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "TDX verification service",
+        description = "Verifies Intel TDX and SGX DCAP quotes and serves their collaterals"
+    ),
+    paths(verify, collateral, health, ready),
+    components(schemas(VerifyRequest, VerifyResponse, ErrorResponse))
+)]
+pub struct ApiDoc;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/verify", post(verify))
+        .route("/collateral/{fmspc}", get(collateral))
+        .route("/health", get(health))
+        .route("/ready", get(ready))
+        .route("/openapi.json", get(openapi))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyRequest {
+    /// Hex-encoded raw quote
+    pub quote: String,
+    /// Hex-encoded data the report data of the quote must start with
+    pub report_data: Option<String>,
+    /// Name of the appraisal policy, defaults to the configured default policy
+    pub policy: Option<String>,
+    /// Verification time as a UNIX timestamp in seconds, defaults to now
+    pub time: Option<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VerifyResponse {
+    /// Whether the quote verified and satisfies the policy and report data
+    pub verified: bool,
+    /// Name of the policy the quote was appraised against
+    pub policy: Option<String>,
+    #[schema(value_type = Object)]
+    pub verified_output: VerifiedOutput,
+    #[schema(value_type = Vec<Object>)]
+    pub policy_violations: Vec<PolicyViolation>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CollateralQuery {
+    tee: Option<String>,
+    ca: Option<String>,
+}
+
+pub struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(ErrorResponse { error: self.1 })).into_response()
+    }
+}
+
+impl From<TdxError> for ApiError {
+    fn from(err: TdxError) -> Self {
+        let status = match err {
+            TdxError::ConfigOptions(_) => StatusCode::BAD_REQUEST,
+            TdxError::Http(_) => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::UNPROCESSABLE_ENTITY,
+        };
+        ApiError(status, err.to_string())
+    }
+}
+
+fn bad_request(message: String) -> ApiError {
+    ApiError(StatusCode::BAD_REQUEST, message)
+}
+
+/// Verify a quote and appraise it against a named policy
+#[utoipa::path(
+    post,
+    path = "/verify",
+    request_body = VerifyRequest,
+    responses(
+        (status = 200, description = "Quote verified, see `verified` for the appraisal", body = VerifyResponse),
+        (status = 400, description = "Malformed request or unknown policy", body = ErrorResponse),
+        (status = 422, description = "Quote verification failed", body = ErrorResponse),
+        (status = 502, description = "Collaterals could not be retrieved", body = ErrorResponse)
+    )
+)]
+async fn verify(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<VerifyRequest>,
+) -> Result<Json<VerifyResponse>, ApiError> {
+    let raw_quote = hex::decode(request.quote.trim_start_matches("0x"))
+        .map_err(|e| bad_request(format!("Invalid quote hex: {e}")))?;
+    let report_data = request
+        .report_data
+        .as_deref()
+        .map(|data| hex::decode(data.trim_start_matches("0x")))
+        .transpose()
+        .map_err(|e| bad_request(format!("Invalid report data hex: {e}")))?;
+    if report_data.as_ref().is_some_and(|data| data.len() > 64) {
+        return Err(bad_request("Report data exceeds 64 bytes".to_string()));
+    }
+    let policy_name = request.policy.or_else(|| state.default_policy.clone());
+    let policy = match &policy_name {
+        Some(name) => Some(
+            state
+                .policies
+                .get(name)
+                .ok_or_else(|| bad_request(format!("Unknown policy {name}")))?,
+        ),
+        None => None,
+    };
+    let time = match request.time {
+        Some(secs) => UNIX_EPOCH
+            .checked_add(Duration::from_secs(secs))
+            .ok_or_else(|| bad_request(format!("Invalid verification time {secs}")))?,
+        None => SystemTime::now(),
+    };
+
+    let collaterals = state.collaterals_for_quote(raw_quote.clone()).await?;
+    let output = {
+        let state = state.clone();
+        tokio::task::spawn_blocking(move || {
+            state
+                .tdx
+                .verify_attestation_report_with_collaterals(&raw_quote, &collaterals, time)
+        })
+        .await
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))??
+    };
+
+    let mut violations = match policy {
+        Some(policy) => policy.appraise(&output)?,
+        None => Vec::new(),
+    };
+    if let Some(expected) = report_data {
+        if !output.body()?.report_data().starts_with(&expected) {
+            violations.push(PolicyViolation {
+                field: "report_data".to_string(),
+                reason: "Report data does not match the expected value".to_string(),
+            });
+        }
+    }
+    Ok(Json(VerifyResponse {
+        verified: violations.is_empty(),
+        policy: policy_name,
+        verified_output: output,
+        policy_violations: violations,
+    }))
+}
+
+/// Retrieve the collaterals of an FMSPC
+#[utoipa::path(
+    get,
+    path = "/collateral/{fmspc}",
+    params(
+        ("fmspc" = String, Path, description = "Hex-encoded FMSPC"),
+        ("tee" = Option<String>, Query, description = "`tdx` (default) or `sgx`"),
+        ("ca" = Option<String>, Query, description = "PCK CA: `platform` (default) or `processor`")
+    ),
+    responses(
+        (status = 200, description = "Collaterals as saved by `tdx collateral save`, hex-encoded"),
+        (status = 400, description = "Invalid FMSPC, or unsupported by the collateral source", body = ErrorResponse),
+        (status = 404, description = "No collaterals for this FMSPC", body = ErrorResponse),
+        (status = 502, description = "Collaterals could not be retrieved", body = ErrorResponse)
+    )
+)]
+async fn collateral(
+    State(state): State<Arc<AppState>>,
+    Path(fmspc): Path<String>,
+    Query(query): Query<CollateralQuery>,
+) -> Result<Json<RawCollaterals>, ApiError> {
+    let tee_type = match query.tee.as_deref() {
+        None | Some("tdx") => TeeType::Tdx,
+        Some("sgx") => TeeType::Sgx,
+        Some(other) => return Err(bad_request(format!("Unknown TEE type {other}"))),
+    };
+    let pck_ca = match query.ca.as_deref() {
+        None | Some("platform") => PckCA::Platform,
+        Some("processor") => PckCA::Processor,
+        Some(other) => return Err(bad_request(format!("Unknown PCK CA {other}"))),
+    };
+    tokio::task::spawn_blocking(move || state.collaterals_for_fmspc(&fmspc, tee_type, pck_ca))
+        .await
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(Json)
+        .map_err(|e| match e {
+            TdxError::IO(message) => ApiError(StatusCode::NOT_FOUND, message),
+            e => e.into(),
+        })
+}
+
+/// Liveness probe
+#[utoipa::path(get, path = "/health", responses((status = 200, description = "The service is running")))]
+async fn health() -> &'static str {
+    "ok"
+}
+
+/// Readiness probe
+#[utoipa::path(
+    get,
+    path = "/ready",
+    responses(
+        (status = 200, description = "The service can serve requests"),
+        (status = 503, description = "The collateral source is unavailable")
+    )
+)]
+async fn ready(State(state): State<Arc<AppState>>) -> (StatusCode, &'static str) {
+    if state.is_ready() {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not ready")
+    }
+}
+
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use std::path::PathBuf;
+    use tower::ServiceExt;
+
+    const TDX_V4_QUOTE: &[u8] = include_bytes!("../../tdx/examples/testdata/tdx_v4_quote.bin");
+    /// Collaterals of a test PKI for the FMSPC of the TDX v4 test quote.
+    const COLLATERALS: &[u8] = include_bytes!("../../tdx/tests/data/collaterals.json");
+    /// The TDX v4 test quote re-signed under the test PKI.
+    const TEST_PKI_QUOTE: &[u8] = include_bytes!("../../tdx/tests/data/test_pki_quote.bin");
+    /// 2025-01-01, within the validity of the test PKI.
+    const TEST_PKI_TIME: u64 = 1_735_689_600;
+
+    /// A collateral directory holding the test collaterals, unique to `name`.
+    fn collateral_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tdx-verifier-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("00806F050000.json"), COLLATERALS).unwrap();
+        dir
+    }
+
+    fn app(dir: &std::path::Path) -> Router {
+        // Accepts any quote with an UpToDate TCB, and one requiring an MRTD of zeros
+        let policy = |name: &str, policy: serde_json::Value| {
+            let path = dir.join(format!("{name}.policy"));
+            std::fs::write(&path, policy.to_string()).unwrap();
+            (name.to_string(), path)
+        };
+        let mut config = Config {
+            policies: [
+                policy("default", serde_json::json!({})),
+                policy(
+                    "zero-mrtd",
+                    serde_json::json!({ "mr_td": [hex::encode([0; 48])] }),
+                ),
+            ]
+            .into(),
+            ..Config::default()
+        };
+        config
+            .apply_env(|name| match name {
+                "TDX_VERIFIER_COLLATERAL_SOURCE" => Some("directory".to_string()),
+                "TDX_VERIFIER_COLLATERAL_DIR" => Some(dir.display().to_string()),
+                _ => None,
+            })
+            .unwrap();
+        config.check().unwrap();
+        router(Arc::new(AppState::new(&config).unwrap()))
+    }
+
+    async fn send(app: Router, request: Request<Body>) -> (StatusCode, Vec<u8>) {
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, body.to_vec())
+    }
+
+    async fn get(app: Router, uri: &str) -> (StatusCode, Vec<u8>) {
+        send(app, Request::get(uri).body(Body::empty()).unwrap()).await
+    }
+
+    async fn post_verify(app: Router, body: serde_json::Value) -> (StatusCode, Vec<u8>) {
+        let request = Request::post("/verify")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        send(app, request).await
+    }
+
+    #[tokio::test]
+    async fn serves_collaterals_from_directory() {
+        let dir = collateral_dir("collateral");
+        let (status, body) = get(app(&dir), "/collateral/00806f050000").await;
+        assert_eq!(status, StatusCode::OK);
+        let served: RawCollaterals = serde_json::from_slice(&body).unwrap();
+        let expected: RawCollaterals = serde_json::from_slice(COLLATERALS).unwrap();
+        assert_eq!(served, expected);
+
+        let (status, _) = get(app(&dir), "/collateral/00806f050001").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = get(app(&dir), "/collateral/not-hex").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = get(app(&dir), "/collateral/00806f050000?tee=other").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn reports_readiness_of_directory() {
+        let dir = collateral_dir("ready");
+        let app = app(&dir);
+        assert_eq!(get(app.clone(), "/ready").await.0, StatusCode::OK);
+        assert_eq!(get(app.clone(), "/health").await.0, StatusCode::OK);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(get(app, "/ready").await.0, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_verify_requests() {
+        let dir = collateral_dir("malformed");
+        let quote = hex::encode(TDX_V4_QUOTE);
+        let requests = [
+            serde_json::json!({ "quote": "zz" }),
+            serde_json::json!({ "quote": quote, "report_data": hex::encode([0u8; 65]) }),
+            serde_json::json!({ "quote": quote, "policy": "unknown" }),
+            serde_json::json!({ "quote": quote, "time": u64::MAX }),
+        ];
+        for request in requests {
+            let (status, _) = post_verify(app(&dir), request).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn verifies_against_directory_collaterals() {
+        // The test quote is certified by Intel and the test collaterals by a test PKI, so the
+        // quote is looked up by FMSPC and then fails verification.
+        let dir = collateral_dir("verify");
+        let request = serde_json::json!({ "quote": hex::encode(TDX_V4_QUOTE) });
+        let (status, body) = post_verify(app(&dir), request).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(error["error"].is_string());
+
+        std::fs::remove_file(dir.join("00806F050000.json")).unwrap();
+        let request = serde_json::json!({ "quote": hex::encode(TDX_V4_QUOTE) });
+        let (status, body) = post_verify(app(&dir), request).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(
+            error["error"]
+                .as_str()
+                .unwrap()
+                .contains("No collaterals for FMSPC 00806F050000"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn verifies_test_pki_quote() {
+        let dir = collateral_dir("verified");
+        let request = serde_json::json!({
+            "quote": hex::encode(TEST_PKI_QUOTE),
+            "report_data": hex::encode(&TEST_PKI_QUOTE[568..600]),
+            "policy": "default",
+            "time": TEST_PKI_TIME,
+        });
+        let (status, body) = post_verify(app(&dir), request).await;
+        assert_eq!(status, StatusCode::OK);
+        let response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response["verified"], true, "{response}");
+        assert_eq!(response["policy"], "default");
+        assert_eq!(response["policy_violations"], serde_json::json!([]));
+
+        // Collaterals expire in 2034
+        let request = serde_json::json!({
+            "quote": hex::encode(TEST_PKI_QUOTE),
+            "time": TEST_PKI_TIME + 10 * 365 * 24 * 3600,
+        });
+        let (status, _) = post_verify(app(&dir), request).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn reports_policy_violations() {
+        let dir = collateral_dir("violations");
+        let request = serde_json::json!({
+            "quote": hex::encode(TEST_PKI_QUOTE),
+            "report_data": hex::encode([0xff; 64]),
+            "policy": "zero-mrtd",
+            "time": TEST_PKI_TIME,
+        });
+        let (status, body) = post_verify(app(&dir), request).await;
+        assert_eq!(status, StatusCode::OK);
+        let response: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response["verified"], false, "{response}");
+        let fields: Vec<&str> = response["policy_violations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|violation| violation["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, ["mr_td", "report_data"]);
+    }
+}
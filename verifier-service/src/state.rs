@@ -0,0 +1,129 @@
+use crate::config::{CollateralSource, Config};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tdx::collateral::RawCollaterals;
+use tdx::error::{Result, TdxError};
+use tdx::pcs::PcsClient;
+use tdx::policy::AppraisalPolicy;
+use tdx::quote::QuoteData;
+use tdx::utils::{PckCA, TeeType};
+use tdx::Tdx;
+
+/// Where the service takes collaterals from.
+#[derive(Debug, Clone)]
+pub enum Collaterals {
+    Onchain,
+    Pcs(PcsClient),
+    Directory(PathBuf),
+}
+
+pub struct AppState {
+    pub tdx: Tdx,
+    pub collaterals: Collaterals,
+    pub policies: HashMap<String, AppraisalPolicy>,
+    pub default_policy: Option<String>,
+}
+
+impl AppState {
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        let pcs_client = config.collaterals.pcs_url.as_deref().map(PcsClient::new);
+        let pcs_client = match &config.collaterals.pcs_api_key {
+            Some(key) => Some(pcs_client.unwrap_or_default().with_api_key(key)),
+            None => pcs_client,
+        };
+        let mut tdx = Tdx::new();
+        if let Some(client) = &pcs_client {
+            tdx = tdx.with_pcs_client(client.clone());
+        }
+        let collaterals = match config.collaterals.source {
+            CollateralSource::Onchain => Collaterals::Onchain,
+            CollateralSource::Pcs => Collaterals::Pcs(pcs_client.unwrap_or_default()),
+            CollateralSource::Directory => Collaterals::Directory(
+                config
+                    .collaterals
+                    .directory
+                    .clone()
+                    .expect("checked by Config::load"),
+            ),
+        };
+        let policies = config
+            .policies
+            .iter()
+            .map(|(name, path)| {
+                let policy = AppraisalPolicy::load(path)
+                    .with_context(|| format!("Failed to load policy {name}"))?;
+                Ok((name.clone(), policy))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(AppState {
+            tdx,
+            collaterals,
+            policies,
+            default_policy: config.default_policy.clone(),
+        })
+    }
+
+    /// Retrieve the collaterals to verify `raw_quote`.
+    pub async fn collaterals_for_quote(
+        self: &Arc<Self>,
+        raw_quote: Vec<u8>,
+    ) -> Result<RawCollaterals> {
+        // The on-chain source is async, and resolves PCK certificates that are not embedded in
+        // the quote on a blocking thread itself
+        if let Collaterals::Onchain = self.collaterals {
+            return self.tdx.get_raw_collaterals(&raw_quote).await;
+        }
+        // The PCS client and the directory source block
+        let state = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let quote = QuoteData::from_bytes(&raw_quote)?;
+            let tee_type = TeeType::from_header(quote.header.tee_type)?;
+            let (fmspc, pck_ca) = state.tdx.get_pck_fmspc_and_issuer(&raw_quote)?;
+            state.collaterals_for_fmspc(&fmspc, tee_type, pck_ca)
+        })
+        .await
+        .map_err(|e| TdxError::Anyhow(e.to_string()))?
+    }
+
+    /// Retrieve the collaterals of an FMSPC. Not supported by the on-chain source, which is
+    /// queried by quote.
+    pub fn collaterals_for_fmspc(
+        &self,
+        fmspc: &str,
+        tee_type: TeeType,
+        pck_ca: PckCA,
+    ) -> Result<RawCollaterals> {
+        if fmspc.len() != 12 || hex::decode(fmspc).is_err() {
+            return Err(TdxError::ConfigOptions(format!(
+                "Invalid FMSPC {fmspc}, expected 6 hex-encoded bytes"
+            )));
+        }
+        match &self.collaterals {
+            Collaterals::Onchain => Err(TdxError::ConfigOptions(
+                "The on-chain collateral source is queried by quote, not by FMSPC".to_string(),
+            )),
+            Collaterals::Pcs(client) => client.get_collaterals(tee_type, fmspc, pck_ca),
+            Collaterals::Directory(directory) => {
+                let path = directory.join(format!("{}.json", fmspc.to_uppercase()));
+                if !path.exists() {
+                    return Err(TdxError::IO(format!(
+                        "No collaterals for FMSPC {} in {}",
+                        fmspc.to_uppercase(),
+                        directory.display()
+                    )));
+                }
+                RawCollaterals::load(path)
+            }
+        }
+    }
+
+    /// Whether the service can serve requests: the collateral directory, if any, is readable.
+    pub fn is_ready(&self) -> bool {
+        match &self.collaterals {
+            Collaterals::Directory(directory) => std::fs::read_dir(directory).is_ok(),
+            _ => true,
+        }
+    }
+}